@@ -25,9 +25,9 @@ use crate::math::spot_balance::get_token_amount;
 use crate::math::spot_withdraw::validate_spot_balances;
 use crate::math::{amm, amm_spread, bn, cp_curve, quote_asset::*};
 
-use crate::state::events::CurveRecord;
+use crate::state::events::{CurveRecord, CurveUpdateReason};
 use crate::state::oracle::OraclePriceData;
-use crate::state::perp_market::{PerpMarket, AMM};
+use crate::state::perp_market::{PerpMarket, SpreadModel, AMM};
 use crate::state::spot_market::{SpotBalance, SpotBalanceType, SpotMarket};
 use crate::state::user::User;
 use crate::validate;
@@ -193,9 +193,14 @@ pub fn update_spread_reserves(amm: &mut AMM) -> DriftResult {
     Ok(())
 }
 
-pub fn update_spreads(amm: &mut AMM, reserve_price: u64) -> DriftResult<(u32, u32)> {
+pub fn update_spreads(market: &mut PerpMarket, reserve_price: u64) -> DriftResult<(u32, u32)> {
+    let spread_model = market.spread_model;
+    let volatility_spread_scale = market.volatility_spread_scale;
+    let intensity_spread_scale = market.intensity_spread_scale;
+    let amm = &mut market.amm;
+
     let (long_spread, short_spread) = if amm.curve_update_intensity > 0 {
-        amm_spread::calculate_spread(
+        let (long_spread, short_spread) = amm_spread::calculate_spread(
             amm.base_spread,
             amm.last_oracle_reserve_price_spread_pct,
             amm.last_oracle_conf_pct,
@@ -209,7 +214,25 @@ pub fn update_spreads(amm: &mut AMM, reserve_price: u64) -> DriftResult<(u32, u3
             amm.base_asset_reserve,
             amm.min_base_asset_reserve,
             amm.max_base_asset_reserve,
-        )?
+        )?;
+
+        match spread_model {
+            SpreadModel::Legacy => (long_spread, short_spread),
+            SpreadModel::VolatilityIntensity => amm_spread::calculate_volatility_intensity_spread(
+                long_spread,
+                short_spread,
+                amm.mark_std,
+                amm.oracle_std,
+                amm.long_intensity_volume,
+                amm.short_intensity_volume,
+                reserve_price,
+                volatility_spread_scale,
+                intensity_spread_scale,
+                amm.max_spread
+                    .cast::<u64>()?
+                    .max(amm.last_oracle_reserve_price_spread_pct.unsigned_abs()),
+            )?,
+        }
     } else {
         let half_base_spread = amm.base_spread.safe_div(2)?;
         (half_base_spread, half_base_spread)
@@ -223,7 +246,7 @@ pub fn update_spreads(amm: &mut AMM, reserve_price: u64) -> DriftResult<(u32, u3
     Ok((long_spread, short_spread))
 }
 
-pub fn update_concentration_coef(amm: &mut AMM, scale: u128) -> DriftResult {
+pub fn update_concentration_coef(market: &mut PerpMarket, scale: u128) -> DriftResult {
     validate!(
         scale > 0,
         ErrorCode::InvalidConcentrationCoef,
@@ -240,6 +263,7 @@ pub fn update_concentration_coef(amm: &mut AMM, scale: u128) -> DriftResult {
         "invalid new_concentration_coef",
     )?;
 
+    let amm = &mut market.amm;
     amm.concentration_coef = new_concentration_coef;
 
     let (_, terminal_quote_reserves, terminal_base_reserves) =
@@ -261,8 +285,9 @@ pub fn update_concentration_coef(amm: &mut AMM, scale: u128) -> DriftResult {
     amm.min_base_asset_reserve = min_base_asset_reserve;
 
     let reserve_price_after = amm.reserve_price()?;
-    update_spreads(amm, reserve_price_after)?;
+    update_spreads(market, reserve_price_after)?;
 
+    let amm = &market.amm;
     let (max_bids, max_asks) = amm::calculate_market_open_bids_asks(amm)?;
     validate!(
         max_bids > amm.base_asset_amount_with_amm && max_asks < amm.base_asset_amount_with_amm,
@@ -349,6 +374,7 @@ pub fn formulaic_update_k(
                 total_fee_minus_distributions: market.amm.total_fee_minus_distributions,
                 oracle_price: market.amm.historical_oracle_data.last_oracle_price,
                 fill_record: market.next_fill_record_id as u128,
+                reason: CurveUpdateReason::FormulaicUpdateK,
             });
         }
     }
@@ -619,11 +645,12 @@ pub fn update_pnl_pool_and_user_balance(
 }
 
 pub fn move_price(
-    amm: &mut AMM,
+    market: &mut PerpMarket,
     base_asset_reserve: u128,
     quote_asset_reserve: u128,
     sqrt_k: u128,
 ) -> DriftResult {
+    let amm = &mut market.amm;
     amm.base_asset_reserve = base_asset_reserve;
 
     let k = bn::U256::from(sqrt_k).safe_mul(bn::U256::from(sqrt_k))?;
@@ -651,7 +678,7 @@ pub fn move_price(
     amm.min_base_asset_reserve = min_base_asset_reserve;
 
     let reserve_price_after = amm.reserve_price()?;
-    update_spreads(amm, reserve_price_after)?;
+    update_spreads(market, reserve_price_after)?;
 
     Ok(())
 }
@@ -0,0 +1,288 @@
+use solana_program::msg;
+
+use crate::controller::spot_position::{
+    update_spot_balances_and_cumulative_deposits,
+    update_spot_balances_and_cumulative_deposits_with_limits,
+};
+use crate::error::{DriftResult, ErrorCode};
+use crate::math::casting::Cast;
+use crate::math::constants::QUOTE_SPOT_MARKET_INDEX;
+use crate::math::insurance::{if_shares_to_vault_amount, vault_amount_to_if_shares};
+use crate::math::margin::{
+    calculate_margin_requirement_and_total_collateral, MarginRequirementType,
+};
+use crate::math::safe_math::SafeMath;
+use crate::state::backstop_vault::{BackstopVault, BackstopVaultStake};
+use crate::state::events::{BackstopVaultStakeRecord, StakeAction};
+use crate::state::oracle_map::OracleMap;
+use crate::state::perp_market_map::PerpMarketMap;
+use crate::state::spot_market::{SpotBalanceType, SpotMarket};
+use crate::state::spot_market_map::SpotMarketMap;
+use crate::state::user::User;
+use crate::{emit, validate};
+
+#[cfg(test)]
+mod tests;
+
+// the vault is worth the backstop user's collateral, including pnl on positions it has absorbed
+pub fn calculate_backstop_vault_value(
+    backstop_user: &User,
+    perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
+    oracle_map: &mut OracleMap,
+) -> DriftResult<u64> {
+    let (_, total_collateral, _, _) = calculate_margin_requirement_and_total_collateral(
+        backstop_user,
+        perp_market_map,
+        MarginRequirementType::Maintenance,
+        spot_market_map,
+        oracle_map,
+        None,
+    )?;
+
+    total_collateral.max(0).cast()
+}
+
+pub fn add_backstop_vault_stake(
+    amount: u64,
+    vault_value: u64,
+    backstop_vault: &mut BackstopVault,
+    backstop_vault_stake: &mut BackstopVaultStake,
+    backstop_user: &mut User,
+    quote_spot_market: &mut SpotMarket,
+    now: i64,
+) -> DriftResult {
+    validate!(
+        quote_spot_market.market_index == QUOTE_SPOT_MARKET_INDEX,
+        ErrorCode::InvalidSpotMarketAccount,
+        "backstop vault only accepts quote deposits"
+    )?;
+
+    validate!(
+        !(vault_value == 0 && backstop_vault.total_shares != 0),
+        ErrorCode::InvalidBackstopVault,
+        "Backstop vault value should be non-zero for new stakers to enter"
+    )?;
+
+    let shares_before = backstop_vault_stake.shares;
+    let total_shares_before = backstop_vault.total_shares;
+
+    let n_shares = vault_amount_to_if_shares(amount, backstop_vault.total_shares, vault_value)?;
+
+    // reset cost basis if no shares
+    backstop_vault_stake.cost_basis = if shares_before == 0 {
+        amount.cast()?
+    } else {
+        backstop_vault_stake.cost_basis.safe_add(amount.cast()?)?
+    };
+
+    backstop_vault_stake.increase_shares(n_shares)?;
+    backstop_vault_stake.last_stake_ts = now;
+    backstop_vault.total_shares = backstop_vault.total_shares.safe_add(n_shares)?;
+
+    let position_index = backstop_user.force_get_spot_position_index(QUOTE_SPOT_MARKET_INDEX)?;
+    update_spot_balances_and_cumulative_deposits(
+        amount.cast()?,
+        &SpotBalanceType::Deposit,
+        quote_spot_market,
+        &mut backstop_user.spot_positions[position_index],
+        false,
+        None,
+    )?;
+
+    emit!(BackstopVaultStakeRecord {
+        ts: now,
+        user_authority: backstop_vault_stake.authority,
+        action: StakeAction::Stake,
+        amount,
+        vault_value_before: vault_value,
+        shares_before,
+        total_shares_before,
+        shares_after: backstop_vault_stake.shares,
+        total_shares_after: backstop_vault.total_shares,
+    });
+
+    Ok(())
+}
+
+pub fn request_remove_backstop_vault_stake(
+    n_shares: u128,
+    vault_value: u64,
+    backstop_vault: &BackstopVault,
+    backstop_vault_stake: &mut BackstopVaultStake,
+    now: i64,
+) -> DriftResult {
+    validate!(
+        n_shares > 0 && n_shares <= backstop_vault_stake.shares,
+        ErrorCode::InsufficientBackstopShares,
+        "cant request to remove {} shares, stake has {}",
+        n_shares,
+        backstop_vault_stake.shares
+    )?;
+
+    // value is locked at request time, stakers dont earn on shares waiting out the unstaking period
+    let request_value =
+        if_shares_to_vault_amount(n_shares, backstop_vault.total_shares, vault_value)?;
+
+    validate!(
+        request_value > 0,
+        ErrorCode::InsufficientBackstopShares,
+        "shares {} are worth 0",
+        n_shares
+    )?;
+
+    backstop_vault_stake.last_withdraw_request_shares = n_shares;
+    backstop_vault_stake.last_withdraw_request_value = request_value;
+    backstop_vault_stake.last_withdraw_request_ts = now;
+
+    emit!(BackstopVaultStakeRecord {
+        ts: now,
+        user_authority: backstop_vault_stake.authority,
+        action: StakeAction::UnstakeRequest,
+        amount: request_value,
+        vault_value_before: vault_value,
+        shares_before: backstop_vault_stake.shares,
+        total_shares_before: backstop_vault.total_shares,
+        shares_after: backstop_vault_stake.shares,
+        total_shares_after: backstop_vault.total_shares,
+    });
+
+    Ok(())
+}
+
+pub fn cancel_request_remove_backstop_vault_stake(
+    vault_value: u64,
+    backstop_vault: &mut BackstopVault,
+    backstop_vault_stake: &mut BackstopVaultStake,
+    now: i64,
+) -> DriftResult {
+    let n_shares = backstop_vault_stake.last_withdraw_request_shares;
+
+    validate!(
+        n_shares != 0,
+        ErrorCode::InvalidBackstopUnstake,
+        "No withdraw request in progress"
+    )?;
+
+    let shares_before = backstop_vault_stake.shares;
+    let total_shares_before = backstop_vault.total_shares;
+
+    // any gain on the requested shares since the request is forfeited to the other stakers
+    let amount = if_shares_to_vault_amount(n_shares, backstop_vault.total_shares, vault_value)?;
+    let shares_lost = if amount > backstop_vault_stake.last_withdraw_request_value {
+        let new_n_shares = vault_amount_to_if_shares(
+            backstop_vault_stake.last_withdraw_request_value,
+            backstop_vault.total_shares.safe_sub(n_shares)?,
+            vault_value.safe_sub(backstop_vault_stake.last_withdraw_request_value)?,
+        )?;
+
+        n_shares.saturating_sub(new_n_shares)
+    } else {
+        0
+    };
+
+    backstop_vault_stake.decrease_shares(shares_lost)?;
+    backstop_vault.total_shares = backstop_vault.total_shares.safe_sub(shares_lost)?;
+
+    backstop_vault_stake.last_withdraw_request_shares = 0;
+    backstop_vault_stake.last_withdraw_request_value = 0;
+    backstop_vault_stake.last_withdraw_request_ts = now;
+
+    emit!(BackstopVaultStakeRecord {
+        ts: now,
+        user_authority: backstop_vault_stake.authority,
+        action: StakeAction::UnstakeCancelRequest,
+        amount: 0,
+        vault_value_before: vault_value,
+        shares_before,
+        total_shares_before,
+        shares_after: backstop_vault_stake.shares,
+        total_shares_after: backstop_vault.total_shares,
+    });
+
+    Ok(())
+}
+
+pub fn remove_backstop_vault_stake(
+    vault_value: u64,
+    backstop_vault: &mut BackstopVault,
+    backstop_vault_stake: &mut BackstopVaultStake,
+    backstop_user: &mut User,
+    quote_spot_market: &mut SpotMarket,
+    now: i64,
+) -> DriftResult<u64> {
+    validate!(
+        quote_spot_market.market_index == QUOTE_SPOT_MARKET_INDEX,
+        ErrorCode::InvalidSpotMarketAccount,
+        "backstop vault only pays out quote"
+    )?;
+
+    let n_shares = backstop_vault_stake.last_withdraw_request_shares;
+
+    validate!(
+        n_shares > 0,
+        ErrorCode::InvalidBackstopUnstake,
+        "Must submit withdraw request and wait the unstaking period"
+    )?;
+
+    let time_since_withdraw_request =
+        now.safe_sub(backstop_vault_stake.last_withdraw_request_ts)?;
+
+    validate!(
+        time_since_withdraw_request >= backstop_vault.unstaking_period,
+        ErrorCode::TryingToRemoveLiquidityTooFast,
+        "{} seconds since withdraw request, unstaking period is {}",
+        time_since_withdraw_request,
+        backstop_vault.unstaking_period
+    )?;
+
+    let shares_before = backstop_vault_stake.shares;
+    let total_shares_before = backstop_vault.total_shares;
+
+    let amount = if_shares_to_vault_amount(n_shares, backstop_vault.total_shares, vault_value)?
+        .min(backstop_vault_stake.last_withdraw_request_value);
+
+    validate!(
+        amount > 0,
+        ErrorCode::InsufficientBackstopShares,
+        "shares {} are worth 0",
+        n_shares
+    )?;
+
+    backstop_vault_stake.decrease_shares(n_shares)?;
+    backstop_vault_stake.cost_basis = backstop_vault_stake.cost_basis.safe_sub(amount.cast()?)?;
+    backstop_vault.total_shares = backstop_vault.total_shares.safe_sub(n_shares)?;
+
+    backstop_vault_stake.last_withdraw_request_shares = 0;
+    backstop_vault_stake.last_withdraw_request_value = 0;
+    backstop_vault_stake.last_withdraw_request_ts = now;
+
+    let position_index = backstop_user.force_get_spot_position_index(QUOTE_SPOT_MARKET_INDEX)?;
+    let spot_position = &mut backstop_user.spot_positions[position_index];
+    update_spot_balances_and_cumulative_deposits_with_limits(
+        amount.cast()?,
+        &SpotBalanceType::Borrow,
+        quote_spot_market,
+        spot_position,
+    )?;
+
+    validate!(
+        spot_position.balance_type == SpotBalanceType::Deposit || spot_position.scaled_balance == 0,
+        ErrorCode::InsufficientCollateral,
+        "backstop vault cant borrow to pay out stakers"
+    )?;
+
+    emit!(BackstopVaultStakeRecord {
+        ts: now,
+        user_authority: backstop_vault_stake.authority,
+        action: StakeAction::Unstake,
+        amount,
+        vault_value_before: vault_value,
+        shares_before,
+        total_shares_before,
+        shares_after: backstop_vault_stake.shares,
+        total_shares_after: backstop_vault.total_shares,
+    });
+
+    Ok(amount)
+}
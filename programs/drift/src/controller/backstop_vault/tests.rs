@@ -0,0 +1,334 @@
+use anchor_lang::prelude::Pubkey;
+
+use crate::controller::backstop_vault::*;
+use crate::error::ErrorCode;
+use crate::math::constants::{QUOTE_PRECISION_U64, SPOT_CUMULATIVE_INTEREST_PRECISION};
+use crate::state::perp_market::MarketStatus;
+use crate::state::spot_market::SpotMarket;
+use crate::state::user::User;
+
+fn get_quote_spot_market() -> SpotMarket {
+    SpotMarket {
+        market_index: 0,
+        status: MarketStatus::Active,
+        cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+        cumulative_borrow_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+        decimals: 6,
+        ..SpotMarket::default()
+    }
+}
+
+#[test]
+pub fn stake_and_unstake() {
+    let mut backstop_vault = BackstopVault::default();
+    let mut backstop_user = User::default();
+    let mut spot_market = get_quote_spot_market();
+
+    let mut stake_a = BackstopVaultStake::new(Pubkey::new_unique(), 0);
+    let mut stake_b = BackstopVaultStake::new(Pubkey::new_unique(), 0);
+
+    let amount = 100 * QUOTE_PRECISION_U64;
+    add_backstop_vault_stake(
+        amount,
+        0,
+        &mut backstop_vault,
+        &mut stake_a,
+        &mut backstop_user,
+        &mut spot_market,
+        0,
+    )
+    .unwrap();
+
+    assert_eq!(stake_a.shares, amount as u128);
+    assert_eq!(backstop_vault.total_shares, amount as u128);
+    assert_eq!(
+        backstop_user.spot_positions[0]
+            .get_token_amount(&spot_market)
+            .unwrap(),
+        amount as u128
+    );
+
+    // vault doubled from absorbed liquidations, new stakers get fewer shares per dollar
+    let vault_value = 200 * QUOTE_PRECISION_U64;
+    add_backstop_vault_stake(
+        50 * QUOTE_PRECISION_U64,
+        vault_value,
+        &mut backstop_vault,
+        &mut stake_b,
+        &mut backstop_user,
+        &mut spot_market,
+        0,
+    )
+    .unwrap();
+
+    assert_eq!(stake_b.shares, 25 * QUOTE_PRECISION_U64 as u128);
+    assert_eq!(
+        backstop_vault.total_shares,
+        125 * QUOTE_PRECISION_U64 as u128
+    );
+    assert_eq!(stake_b.cost_basis, 50 * QUOTE_PRECISION_U64 as i64);
+
+    let vault_value = 250 * QUOTE_PRECISION_U64;
+    request_remove_backstop_vault_stake(
+        stake_b.shares,
+        vault_value,
+        &backstop_vault,
+        &mut stake_b,
+        0,
+    )
+    .unwrap();
+    assert_eq!(
+        stake_b.last_withdraw_request_value,
+        50 * QUOTE_PRECISION_U64
+    );
+
+    let amount_out = remove_backstop_vault_stake(
+        vault_value,
+        &mut backstop_vault,
+        &mut stake_b,
+        &mut backstop_user,
+        &mut spot_market,
+        0,
+    )
+    .unwrap();
+
+    assert_eq!(amount_out, 50 * QUOTE_PRECISION_U64);
+    assert_eq!(stake_b.shares, 0);
+    assert_eq!(
+        backstop_vault.total_shares,
+        100 * QUOTE_PRECISION_U64 as u128
+    );
+    assert_eq!(
+        backstop_user.spot_positions[0]
+            .get_token_amount(&spot_market)
+            .unwrap(),
+        100 * QUOTE_PRECISION_U64 as u128
+    );
+}
+
+#[test]
+pub fn cant_remove_more_shares_than_staked() {
+    let mut backstop_vault = BackstopVault::default();
+    let mut backstop_user = User::default();
+    let mut spot_market = get_quote_spot_market();
+
+    let mut stake_a = BackstopVaultStake::new(Pubkey::new_unique(), 0);
+    let mut stake_b = BackstopVaultStake::new(Pubkey::new_unique(), 0);
+
+    let amount = 100 * QUOTE_PRECISION_U64;
+    add_backstop_vault_stake(
+        amount,
+        0,
+        &mut backstop_vault,
+        &mut stake_a,
+        &mut backstop_user,
+        &mut spot_market,
+        0,
+    )
+    .unwrap();
+
+    add_backstop_vault_stake(
+        amount,
+        amount,
+        &mut backstop_vault,
+        &mut stake_b,
+        &mut backstop_user,
+        &mut spot_market,
+        0,
+    )
+    .unwrap();
+
+    let result = request_remove_backstop_vault_stake(
+        stake_a.shares + 1,
+        2 * amount,
+        &backstop_vault,
+        &mut stake_a,
+        0,
+    );
+
+    assert!(result.is_err());
+}
+
+#[test]
+pub fn cant_stake_into_empty_vault_with_shares() {
+    let mut backstop_vault = BackstopVault {
+        total_shares: 100 * QUOTE_PRECISION_U64 as u128,
+        ..BackstopVault::default()
+    };
+    let mut backstop_user = User::default();
+    let mut spot_market = get_quote_spot_market();
+    let mut stake = BackstopVaultStake::new(Pubkey::new_unique(), 0);
+
+    let result = add_backstop_vault_stake(
+        100 * QUOTE_PRECISION_U64,
+        0,
+        &mut backstop_vault,
+        &mut stake,
+        &mut backstop_user,
+        &mut spot_market,
+        0,
+    );
+
+    assert!(result.is_err());
+}
+
+#[test]
+pub fn unstake_waits_out_unstaking_period() {
+    let mut backstop_vault = BackstopVault {
+        unstaking_period: 100,
+        ..BackstopVault::default()
+    };
+    let mut backstop_user = User::default();
+    let mut spot_market = get_quote_spot_market();
+    let mut stake = BackstopVaultStake::new(Pubkey::new_unique(), 0);
+
+    let amount = 100 * QUOTE_PRECISION_U64;
+    add_backstop_vault_stake(
+        amount,
+        0,
+        &mut backstop_vault,
+        &mut stake,
+        &mut backstop_user,
+        &mut spot_market,
+        0,
+    )
+    .unwrap();
+
+    // cant remove without a request
+    let result = remove_backstop_vault_stake(
+        amount,
+        &mut backstop_vault,
+        &mut stake,
+        &mut backstop_user,
+        &mut spot_market,
+        200,
+    );
+    assert_eq!(result, Err(ErrorCode::InvalidBackstopUnstake));
+
+    request_remove_backstop_vault_stake(stake.shares, amount, &backstop_vault, &mut stake, 200)
+        .unwrap();
+    assert_eq!(stake.last_withdraw_request_shares, amount as u128);
+    assert_eq!(stake.last_withdraw_request_ts, 200);
+
+    let result = remove_backstop_vault_stake(
+        amount,
+        &mut backstop_vault,
+        &mut stake,
+        &mut backstop_user,
+        &mut spot_market,
+        299,
+    );
+    assert_eq!(result, Err(ErrorCode::TryingToRemoveLiquidityTooFast));
+
+    // vault gained while the request was pending, staker only gets the value at request time
+    let amount_out = remove_backstop_vault_stake(
+        2 * amount,
+        &mut backstop_vault,
+        &mut stake,
+        &mut backstop_user,
+        &mut spot_market,
+        300,
+    )
+    .unwrap();
+
+    assert_eq!(amount_out, amount);
+    assert_eq!(stake.shares, 0);
+    assert_eq!(stake.last_withdraw_request_shares, 0);
+    assert_eq!(stake.last_withdraw_request_value, 0);
+    assert_eq!(backstop_vault.total_shares, 0);
+}
+
+#[test]
+pub fn unstake_pays_reduced_value_after_loss() {
+    let mut backstop_vault = BackstopVault::default();
+    let mut backstop_user = User::default();
+    let mut spot_market = get_quote_spot_market();
+    let mut stake = BackstopVaultStake::new(Pubkey::new_unique(), 0);
+
+    let amount = 100 * QUOTE_PRECISION_U64;
+    add_backstop_vault_stake(
+        amount,
+        0,
+        &mut backstop_vault,
+        &mut stake,
+        &mut backstop_user,
+        &mut spot_market,
+        0,
+    )
+    .unwrap();
+
+    request_remove_backstop_vault_stake(stake.shares, amount, &backstop_vault, &mut stake, 0)
+        .unwrap();
+
+    // absorbed positions lost value during the unstaking period
+    let amount_out = remove_backstop_vault_stake(
+        amount / 2,
+        &mut backstop_vault,
+        &mut stake,
+        &mut backstop_user,
+        &mut spot_market,
+        0,
+    )
+    .unwrap();
+
+    assert_eq!(amount_out, amount / 2);
+    assert_eq!(stake.shares, 0);
+}
+
+#[test]
+pub fn cancel_request_forfeits_gains() {
+    let mut backstop_vault = BackstopVault::default();
+    let mut backstop_user = User::default();
+    let mut spot_market = get_quote_spot_market();
+    let mut stake_a = BackstopVaultStake::new(Pubkey::new_unique(), 0);
+    let mut stake_b = BackstopVaultStake::new(Pubkey::new_unique(), 0);
+
+    let amount = 100 * QUOTE_PRECISION_U64;
+    add_backstop_vault_stake(
+        amount,
+        0,
+        &mut backstop_vault,
+        &mut stake_a,
+        &mut backstop_user,
+        &mut spot_market,
+        0,
+    )
+    .unwrap();
+    add_backstop_vault_stake(
+        amount,
+        amount,
+        &mut backstop_vault,
+        &mut stake_b,
+        &mut backstop_user,
+        &mut spot_market,
+        0,
+    )
+    .unwrap();
+
+    let result = cancel_request_remove_backstop_vault_stake(
+        2 * amount,
+        &mut backstop_vault,
+        &mut stake_a,
+        0,
+    );
+    assert_eq!(result, Err(ErrorCode::InvalidBackstopUnstake));
+
+    request_remove_backstop_vault_stake(
+        stake_a.shares,
+        2 * amount,
+        &backstop_vault,
+        &mut stake_a,
+        0,
+    )
+    .unwrap();
+
+    // vault value doubled while the request was pending
+    cancel_request_remove_backstop_vault_stake(4 * amount, &mut backstop_vault, &mut stake_a, 0)
+        .unwrap();
+
+    // a keeps shares worth its request value, the rest go to b
+    assert_eq!(stake_a.shares, 33333333);
+    assert_eq!(backstop_vault.total_shares, 133333333);
+    assert_eq!(stake_a.last_withdraw_request_shares, 0);
+    assert_eq!(stake_a.last_withdraw_request_value, 0);
+}
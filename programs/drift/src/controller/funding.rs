@@ -1,24 +1,24 @@
-use std::cmp::{max, min};
-
 use anchor_lang::prelude::*;
 use solana_program::clock::UnixTimestamp;
 
 use crate::controller::amm::formulaic_update_k;
-use crate::controller::position::{
-    get_position_index, update_quote_asset_and_break_even_amount, PositionDirection,
-};
-use crate::error::DriftResult;
+use crate::controller::position::{get_position_index, update_quote_asset_and_break_even_amount};
+use crate::error::{DriftResult, ErrorCode};
 use crate::get_then_update_id;
 use crate::math::amm;
 use crate::math::casting::Cast;
-use crate::math::constants::{FUNDING_RATE_BUFFER, ONE_HOUR_I128, TWENTY_FOUR_HOUR};
-use crate::math::funding::{calculate_funding_payment, calculate_funding_rate_long_short};
+use crate::math::constants::TWENTY_FOUR_HOUR;
+use crate::math::funding::{
+    calculate_funding_payment, calculate_funding_rate_long_short,
+    calculate_funding_rate_with_params,
+};
 use crate::math::helpers::on_the_hour_update;
 use crate::math::safe_math::SafeMath;
 use crate::math::stats::calculate_new_twap;
 
 use crate::math::oracle;
 
+use crate::load_mut;
 use crate::state::events::{FundingPaymentRecord, FundingRateRecord};
 use crate::state::oracle_map::OracleMap;
 use crate::state::perp_market::{PerpMarket, AMM};
@@ -26,6 +26,9 @@ use crate::state::perp_market_map::PerpMarketMap;
 use crate::state::state::OracleGuardRails;
 use crate::state::user::User;
 
+#[cfg(test)]
+mod tests;
+
 pub fn settle_funding_payment(
     user: &mut User,
     user_key: &Pubkey,
@@ -62,6 +65,7 @@ pub fn settle_funding_payment(
         user.update_cumulative_perp_funding(market_funding_payment)?;
 
         let market_position = &mut user.perp_positions[position_index];
+        market_position.update_cumulative_funding(market_funding_payment)?;
 
         emit!(FundingPaymentRecord {
             ts: now,
@@ -82,6 +86,20 @@ pub fn settle_funding_payment(
     Ok(())
 }
 
+pub fn settle_funding_payments_for_users(
+    users: &[AccountLoader<User>],
+    market: &mut PerpMarket,
+    now: UnixTimestamp,
+) -> DriftResult {
+    for user_account_loader in users.iter() {
+        let user_key = user_account_loader.key();
+        let user = &mut load_mut!(user_account_loader)?;
+        settle_funding_payment(user, &user_key, market, now)?;
+    }
+
+    Ok(())
+}
+
 pub fn settle_funding_payments(
     user: &mut User,
     user_key: &Pubkey,
@@ -117,6 +135,7 @@ pub fn settle_funding_payments(
             user.update_cumulative_perp_funding(market_funding_payment)?;
 
             let market_position = &mut user.perp_positions[position_index];
+            market_position.update_cumulative_funding(market_funding_payment)?;
 
             emit!(FundingPaymentRecord {
                 ts: now,
@@ -187,19 +206,7 @@ pub fn update_funding_rate(
 
         // price relates to execution premium / direction
         let (execution_premium_price, execution_premium_direction) =
-            if market.amm.long_spread > market.amm.short_spread {
-                (
-                    market.amm.ask_price(reserve_price)?,
-                    Some(PositionDirection::Long),
-                )
-            } else if market.amm.long_spread < market.amm.short_spread {
-                (
-                    market.amm.bid_price(reserve_price)?,
-                    Some(PositionDirection::Short),
-                )
-            } else {
-                (reserve_price, None)
-            };
+            calculate_execution_premium_price(&market.amm, reserve_price)?;
 
         let sanitize_clamp_denominator = market.get_sanitize_clamp_denominator()?;
         let mid_price_twap = amm::update_mark_twap(
@@ -210,22 +217,13 @@ pub fn update_funding_rate(
             sanitize_clamp_denominator,
         )?;
 
-        let period_adjustment = (24_i128)
-            .safe_mul(ONE_HOUR_I128)?
-            .safe_div(max(ONE_HOUR_I128, market.amm.funding_period as i128))?;
-        // funding period = 1 hour, window = 1 day
-        // low periodicity => quickly updating/settled funding rates => lower funding rate payment per interval
-        let price_spread = mid_price_twap.cast::<i64>()?.safe_sub(oracle_price_twap)?;
-
-        // clamp price divergence to 3% for funding rate calculation
-        let max_price_spread = oracle_price_twap.safe_div(33)?; // 3%
-        let clamped_price_spread = max(-max_price_spread, min(price_spread, max_price_spread));
-
-        let funding_rate = clamped_price_spread
-            .cast::<i128>()?
-            .safe_mul(FUNDING_RATE_BUFFER.cast()?)?
-            .safe_div(period_adjustment.cast()?)?
-            .cast::<i64>()?;
+        let funding_rate = calculate_funding_rate_for_market(
+            market,
+            mid_price_twap,
+            oracle_price_twap,
+            execution_premium_price,
+            oracle_price_data.price,
+        )?;
 
         let (funding_rate_long, funding_rate_short, funding_imbalance_cost) =
             calculate_funding_rate_long_short(market, funding_rate.cast()?)?;
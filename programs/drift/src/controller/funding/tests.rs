@@ -0,0 +1,101 @@
+use anchor_lang::prelude::{AccountLoader, Pubkey};
+use anchor_lang::Owner;
+
+use crate::controller::funding::settle_funding_payments_for_users;
+use crate::create_anchor_account_info;
+use crate::math::constants::{
+    BASE_PRECISION_I128, BASE_PRECISION_I64, FUNDING_RATE_PRECISION_I128,
+    FUNDING_RATE_PRECISION_I64, QUOTE_PRECISION_I64,
+};
+use crate::state::perp_market::{PerpMarket, AMM};
+use crate::state::user::{PerpPosition, User};
+use crate::test_utils::*;
+
+#[test]
+fn settle_funding_for_users() {
+    let mut market = PerpMarket {
+        amm: AMM {
+            cumulative_funding_rate_long: FUNDING_RATE_PRECISION_I128,
+            base_asset_amount_long: BASE_PRECISION_I128,
+            base_asset_amount_with_amm: BASE_PRECISION_I128,
+            quote_asset_amount: -100 * QUOTE_PRECISION_I64 as i128,
+            quote_entry_amount_long: -100 * QUOTE_PRECISION_I64 as i128,
+            quote_break_even_amount_long: -100 * QUOTE_PRECISION_I64 as i128,
+            ..AMM::default_test()
+        },
+        number_of_users_with_base: 1,
+        number_of_users: 1,
+        ..PerpMarket::default_test()
+    };
+
+    let mut user = User {
+        perp_positions: get_positions(PerpPosition {
+            market_index: 0,
+            base_asset_amount: BASE_PRECISION_I64,
+            quote_asset_amount: -100 * QUOTE_PRECISION_I64,
+            quote_entry_amount: -100 * QUOTE_PRECISION_I64,
+            quote_break_even_amount: -100 * QUOTE_PRECISION_I64,
+            ..PerpPosition::default()
+        }),
+        ..User::default()
+    };
+    let user_key = Pubkey::new_unique();
+    create_anchor_account_info!(user, &user_key, User, user_account_info);
+    let user_account_loader: AccountLoader<User> =
+        AccountLoader::try_from(&user_account_info).unwrap();
+
+    // no position in the market, left untouched
+    let mut user_without_position = User::default();
+    let user_without_position_key = Pubkey::new_unique();
+    create_anchor_account_info!(
+        user_without_position,
+        &user_without_position_key,
+        User,
+        user_without_position_account_info
+    );
+    let user_without_position_account_loader: AccountLoader<User> =
+        AccountLoader::try_from(&user_without_position_account_info).unwrap();
+
+    settle_funding_payments_for_users(
+        &[
+            user_account_loader.clone(),
+            user_without_position_account_loader.clone(),
+        ],
+        &mut market,
+        0,
+    )
+    .unwrap();
+
+    // longs pay $1 per base on a 1 unit cumulative funding rate
+    let user = user_account_loader.load().unwrap();
+    assert_eq!(
+        user.perp_positions[0].quote_asset_amount,
+        -101 * QUOTE_PRECISION_I64
+    );
+    assert_eq!(
+        user.perp_positions[0].quote_break_even_amount,
+        -101 * QUOTE_PRECISION_I64
+    );
+    assert_eq!(
+        user.perp_positions[0].quote_entry_amount,
+        -100 * QUOTE_PRECISION_I64
+    );
+    assert_eq!(
+        user.perp_positions[0].last_cumulative_funding_rate,
+        FUNDING_RATE_PRECISION_I64
+    );
+    assert_eq!(
+        user.perp_positions[0].cumulative_funding,
+        -QUOTE_PRECISION_I64
+    );
+    assert_eq!(user.cumulative_perp_funding, -QUOTE_PRECISION_I64);
+
+    let user_without_position = user_without_position_account_loader.load().unwrap();
+    assert!(user_without_position.perp_positions[0].is_available());
+    assert_eq!(user_without_position.cumulative_perp_funding, 0);
+
+    assert_eq!(
+        market.amm.quote_asset_amount,
+        -101 * QUOTE_PRECISION_I64 as i128
+    );
+}
@@ -1,35 +1,40 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{Token, TokenAccount};
 use solana_program::msg;
 
 use crate::controller::spot_balance::{
     update_revenue_pool_balances, update_spot_balances, update_spot_market_cumulative_interest,
 };
-use crate::controller::token::send_from_program_vault;
+use crate::controller::token::{load_token_account, send_from_program_vault};
 use crate::error::DriftResult;
 use crate::error::ErrorCode;
 use crate::math::amm::calculate_net_user_pnl;
 use crate::math::casting::Cast;
 use crate::math::constants::{
     MAX_APR_PER_REVENUE_SETTLE_TO_INSURANCE_FUND_VAULT, ONE_YEAR, PERCENTAGE_PRECISION_U64,
-    SHARE_OF_REVENUE_ALLOCATED_TO_INSURANCE_FUND_VAULT_DENOMINATOR,
+    QUOTE_SPOT_MARKET_INDEX, SHARE_OF_REVENUE_ALLOCATED_TO_INSURANCE_FUND_VAULT_DENOMINATOR,
     SHARE_OF_REVENUE_ALLOCATED_TO_INSURANCE_FUND_VAULT_NUMERATOR,
 };
 use crate::math::helpers::get_proportion_u128;
 use crate::math::helpers::on_the_hour_update;
 use crate::math::insurance::{
-    calculate_if_shares_lost, calculate_rebase_info, if_shares_to_vault_amount,
-    vault_amount_to_if_shares,
+    calculate_if_shares_lost, calculate_if_stake_rewards, calculate_instant_unstake_haircut_shares,
+    calculate_insurance_coverage_ratio, calculate_perp_market_insurance_value,
+    calculate_perp_market_weighted_open_interest, calculate_rebase_info,
+    calculate_reward_per_share_delta, if_shares_to_shares_mint_amount, if_shares_to_vault_amount,
+    shares_mint_amount_to_if_shares, vault_amount_to_if_shares,
 };
 use crate::math::safe_math::SafeMath;
 use crate::math::spot_balance::get_token_amount;
 use crate::math::spot_withdraw::validate_spot_market_vault_amount;
-use crate::state::events::{InsuranceFundRecord, InsuranceFundStakeRecord, StakeAction};
+use crate::state::events::{
+    InsuranceCoverageRecord, InsuranceFundRecord, InsuranceFundRewardRecord,
+    InsuranceFundStakeRecord, StakeAction,
+};
 use crate::state::insurance_fund_stake::InsuranceFundStake;
 use crate::state::perp_market::PerpMarket;
-use crate::state::spot_market::{SpotBalanceType, SpotMarket};
+use crate::state::spot_market::{InsuranceFund, InsuranceFundMarket, SpotBalanceType, SpotMarket};
 use crate::state::state::State;
-use crate::state::user::UserStats;
+use crate::state::user::{MarketType, UserStats};
 use crate::{emit, validate};
 
 #[cfg(test)]
@@ -40,25 +45,27 @@ pub fn add_insurance_fund_stake(
     insurance_vault_amount: u64,
     insurance_fund_stake: &mut InsuranceFundStake,
     user_stats: &mut UserStats,
-    spot_market: &mut SpotMarket,
+    market: &mut impl InsuranceFundMarket,
     now: i64,
-) -> DriftResult {
+) -> DriftResult<u128> {
     validate!(
-        !(insurance_vault_amount == 0 && spot_market.insurance_fund.total_shares != 0),
+        !(insurance_vault_amount == 0 && market.insurance_fund().total_shares != 0),
         ErrorCode::InvalidIFForNewStakes,
         "Insurance Fund balance should be non-zero for new stakers to enter"
     )?;
 
-    apply_rebase_to_insurance_fund(insurance_vault_amount, spot_market)?;
-    apply_rebase_to_insurance_fund_stake(insurance_fund_stake, spot_market)?;
+    apply_rebase_to_insurance_fund(insurance_vault_amount, market)?;
+    update_insurance_fund_rewards(market, now)?;
+    apply_rebase_to_insurance_fund_stake(insurance_fund_stake, market)?;
+    settle_insurance_fund_stake_rewards(insurance_fund_stake, market)?;
 
-    let if_shares_before = insurance_fund_stake.checked_if_shares(spot_market)?;
-    let total_if_shares_before = spot_market.insurance_fund.total_shares;
-    let user_if_shares_before = spot_market.insurance_fund.user_shares;
+    let if_shares_before = insurance_fund_stake.checked_if_shares(market)?;
+    let total_if_shares_before = market.insurance_fund().total_shares;
+    let user_if_shares_before = market.insurance_fund().user_shares;
 
     let n_shares = vault_amount_to_if_shares(
         amount,
-        spot_market.insurance_fund.total_shares,
+        market.insurance_fund().total_shares,
         insurance_vault_amount,
     )?;
 
@@ -69,72 +76,86 @@ pub fn add_insurance_fund_stake(
         insurance_fund_stake.cost_basis.safe_add(amount.cast()?)?
     };
 
-    insurance_fund_stake.increase_if_shares(n_shares, spot_market)?;
+    insurance_fund_stake.increase_if_shares(n_shares, market)?;
 
-    spot_market.insurance_fund.total_shares =
-        spot_market.insurance_fund.total_shares.safe_add(n_shares)?;
+    market.insurance_fund_mut().total_shares =
+        market.insurance_fund().total_shares.safe_add(n_shares)?;
 
-    spot_market.insurance_fund.user_shares =
-        spot_market.insurance_fund.user_shares.safe_add(n_shares)?;
+    market.insurance_fund_mut().user_shares =
+        market.insurance_fund().user_shares.safe_add(n_shares)?;
 
-    if spot_market.market_index == 0 {
+    if is_quote_spot_market(market) {
         user_stats.if_staked_quote_asset_amount = if_shares_to_vault_amount(
-            insurance_fund_stake.checked_if_shares(spot_market)?,
-            spot_market.insurance_fund.total_shares,
+            insurance_fund_stake.checked_if_shares(market)?,
+            market.insurance_fund().total_shares,
             insurance_vault_amount.safe_add(amount)?,
         )?;
     }
 
-    let if_shares_after = insurance_fund_stake.checked_if_shares(spot_market)?;
+    let if_shares_after = insurance_fund_stake.checked_if_shares(market)?;
 
     emit!(InsuranceFundStakeRecord {
         ts: now,
         user_authority: user_stats.authority,
         action: StakeAction::Stake,
         amount,
-        market_index: spot_market.market_index,
+        market_index: market.market_index(),
+        market_type: market.market_type(),
         insurance_vault_amount_before: insurance_vault_amount,
         if_shares_before,
         user_if_shares_before,
         total_if_shares_before,
         if_shares_after,
-        total_if_shares_after: spot_market.insurance_fund.total_shares,
-        user_if_shares_after: spot_market.insurance_fund.user_shares,
+        total_if_shares_after: market.insurance_fund().total_shares,
+        user_if_shares_after: market.insurance_fund().user_shares,
     });
 
-    Ok(())
+    Ok(n_shares)
+}
+
+fn is_quote_spot_market(market: &impl InsuranceFundMarket) -> bool {
+    market.market_type() == MarketType::Spot && market.market_index() == QUOTE_SPOT_MARKET_INDEX
 }
 
 pub fn apply_rebase_to_insurance_fund(
     insurance_fund_vault_balance: u64,
-    spot_market: &mut SpotMarket,
+    market: &mut impl InsuranceFundMarket,
 ) -> DriftResult {
     if insurance_fund_vault_balance != 0
-        && insurance_fund_vault_balance.cast::<u128>()? < spot_market.insurance_fund.total_shares
+        && insurance_fund_vault_balance.cast::<u128>()? < market.insurance_fund().total_shares
     {
         let (expo_diff, rebase_divisor) = calculate_rebase_info(
-            spot_market.insurance_fund.total_shares,
+            market.insurance_fund().total_shares,
             insurance_fund_vault_balance,
         )?;
 
-        spot_market.insurance_fund.total_shares = spot_market
-            .insurance_fund
+        market.insurance_fund_mut().total_shares = market
+            .insurance_fund()
             .total_shares
             .safe_div(rebase_divisor)?;
-        spot_market.insurance_fund.user_shares = spot_market
-            .insurance_fund
+        market.insurance_fund_mut().user_shares = market
+            .insurance_fund()
             .user_shares
             .safe_div(rebase_divisor)?;
-        spot_market.insurance_fund.shares_base = spot_market
-            .insurance_fund
+        market.insurance_fund_extension_mut().tokenized_shares = market
+            .insurance_fund_extension()
+            .tokenized_shares
+            .safe_div(rebase_divisor)?;
+        market.insurance_fund_mut().shares_base = market
+            .insurance_fund()
             .shares_base
             .safe_add(expo_diff.cast::<u128>()?)?;
+        // keep reward_per_share in units of the rebased shares
+        market.insurance_fund_extension_mut().reward_per_share = market
+            .insurance_fund_extension()
+            .reward_per_share
+            .safe_mul(rebase_divisor)?;
 
         msg!("rebasing insurance fund: expo_diff={}", expo_diff);
     }
 
-    if insurance_fund_vault_balance != 0 && spot_market.insurance_fund.total_shares == 0 {
-        spot_market.insurance_fund.total_shares = insurance_fund_vault_balance.cast::<u128>()?;
+    if insurance_fund_vault_balance != 0 && market.insurance_fund().total_shares == 0 {
+        market.insurance_fund_mut().total_shares = insurance_fund_vault_balance.cast::<u128>()?;
     }
 
     Ok(())
@@ -142,37 +163,49 @@ pub fn apply_rebase_to_insurance_fund(
 
 pub fn apply_rebase_to_insurance_fund_stake(
     insurance_fund_stake: &mut InsuranceFundStake,
-    spot_market: &mut SpotMarket,
+    market: &mut impl InsuranceFundMarket,
 ) -> DriftResult {
-    if spot_market.insurance_fund.shares_base != insurance_fund_stake.if_base {
+    if market.insurance_fund().shares_base != insurance_fund_stake.if_base {
         validate!(
-            spot_market.insurance_fund.shares_base > insurance_fund_stake.if_base,
+            market.insurance_fund().shares_base > insurance_fund_stake.if_base,
             ErrorCode::InvalidIFRebase,
             "Rebase expo out of bounds"
         )?;
 
-        let expo_diff = (spot_market.insurance_fund.shares_base - insurance_fund_stake.if_base)
-            .cast::<u32>()?;
+        let expo_diff =
+            (market.insurance_fund().shares_base - insurance_fund_stake.if_base).cast::<u32>()?;
 
         let rebase_divisor = 10_u128.pow(expo_diff);
 
         msg!(
             "rebasing insurance fund stake: base: {} -> {} ",
             insurance_fund_stake.if_base,
-            spot_market.insurance_fund.shares_base,
+            market.insurance_fund().shares_base,
         );
 
-        insurance_fund_stake.if_base = spot_market.insurance_fund.shares_base;
+        insurance_fund_stake.if_base = market.insurance_fund().shares_base;
 
         let old_if_shares = insurance_fund_stake.unchecked_if_shares();
         let new_if_shares = old_if_shares.safe_div(rebase_divisor)?;
 
+        // settle rewards on the pre-rebase shares before they are scaled down
+        let rewards = calculate_if_stake_rewards(
+            old_if_shares,
+            market.insurance_fund_extension().reward_per_share,
+            insurance_fund_stake.reward_per_share_checkpoint,
+            rebase_divisor,
+        )?;
+        insurance_fund_stake.unclaimed_rewards =
+            insurance_fund_stake.unclaimed_rewards.safe_add(rewards)?;
+        insurance_fund_stake.reward_per_share_checkpoint =
+            market.insurance_fund_extension().reward_per_share;
+
         msg!(
             "rebasing insurance fund stake: shares -> {} ",
             new_if_shares
         );
 
-        insurance_fund_stake.update_if_shares(new_if_shares, spot_market)?;
+        insurance_fund_stake.update_if_shares(new_if_shares, market)?;
 
         insurance_fund_stake.last_withdraw_request_shares = insurance_fund_stake
             .last_withdraw_request_shares
@@ -182,42 +215,130 @@ pub fn apply_rebase_to_insurance_fund_stake(
     Ok(())
 }
 
+pub fn update_insurance_fund_rewards(
+    market: &mut impl InsuranceFundMarket,
+    now: i64,
+) -> DriftResult {
+    // tokenized shares have no stake to credit rewards to
+    let staked_user_shares = market
+        .insurance_fund()
+        .user_shares
+        .safe_sub(market.insurance_fund_extension().tokenized_shares)?;
+
+    let insurance_fund_extension = market.insurance_fund_extension_mut();
+
+    let emission_end_ts = now.min(insurance_fund_extension.reward_emission_end_ts);
+    if insurance_fund_extension.reward_emission_rate > 0
+        && emission_end_ts > insurance_fund_extension.last_reward_update_ts
+    {
+        let elapsed = emission_end_ts.safe_sub(insurance_fund_extension.last_reward_update_ts)?;
+
+        let reward_per_share_delta = calculate_reward_per_share_delta(
+            insurance_fund_extension.reward_emission_rate,
+            elapsed,
+            staked_user_shares,
+        )?;
+
+        insurance_fund_extension.reward_per_share = insurance_fund_extension
+            .reward_per_share
+            .safe_add(reward_per_share_delta)?;
+    }
+
+    insurance_fund_extension.last_reward_update_ts =
+        insurance_fund_extension.last_reward_update_ts.max(now);
+
+    Ok(())
+}
+
+pub fn settle_insurance_fund_stake_rewards(
+    insurance_fund_stake: &mut InsuranceFundStake,
+    market: &impl InsuranceFundMarket,
+) -> DriftResult {
+    let rewards = calculate_if_stake_rewards(
+        insurance_fund_stake.checked_if_shares(market)?,
+        market.insurance_fund_extension().reward_per_share,
+        insurance_fund_stake.reward_per_share_checkpoint,
+        1,
+    )?;
+
+    insurance_fund_stake.unclaimed_rewards =
+        insurance_fund_stake.unclaimed_rewards.safe_add(rewards)?;
+    insurance_fund_stake.reward_per_share_checkpoint =
+        market.insurance_fund_extension().reward_per_share;
+
+    Ok(())
+}
+
+pub fn claim_insurance_fund_stake_rewards(
+    insurance_vault_amount: u64,
+    reward_vault_amount: u64,
+    insurance_fund_stake: &mut InsuranceFundStake,
+    market: &mut impl InsuranceFundMarket,
+    now: i64,
+) -> DriftResult<u64> {
+    apply_rebase_to_insurance_fund(insurance_vault_amount, market)?;
+    update_insurance_fund_rewards(market, now)?;
+    apply_rebase_to_insurance_fund_stake(insurance_fund_stake, market)?;
+    settle_insurance_fund_stake_rewards(insurance_fund_stake, market)?;
+
+    let amount = insurance_fund_stake
+        .unclaimed_rewards
+        .min(reward_vault_amount);
+
+    insurance_fund_stake.unclaimed_rewards =
+        insurance_fund_stake.unclaimed_rewards.safe_sub(amount)?;
+
+    emit!(InsuranceFundRewardRecord {
+        ts: now,
+        user_authority: insurance_fund_stake.authority,
+        market_index: market.market_index(),
+        market_type: market.market_type(),
+        amount,
+        reward_per_share: market.insurance_fund_extension().reward_per_share,
+        unclaimed_rewards_after: insurance_fund_stake.unclaimed_rewards,
+    });
+
+    Ok(amount)
+}
+
 pub fn request_remove_insurance_fund_stake(
     n_shares: u128,
     insurance_vault_amount: u64,
     insurance_fund_stake: &mut InsuranceFundStake,
     user_stats: &mut UserStats,
-    spot_market: &mut SpotMarket,
+    market: &mut impl InsuranceFundMarket,
     now: i64,
 ) -> DriftResult {
     msg!("n_shares {}", n_shares);
     insurance_fund_stake.last_withdraw_request_shares = n_shares;
 
-    apply_rebase_to_insurance_fund(insurance_vault_amount, spot_market)?;
-    apply_rebase_to_insurance_fund_stake(insurance_fund_stake, spot_market)?;
+    apply_rebase_to_insurance_fund(insurance_vault_amount, market)?;
+    update_insurance_fund_rewards(market, now)?;
+    apply_rebase_to_insurance_fund_stake(insurance_fund_stake, market)?;
+    settle_insurance_fund_stake_rewards(insurance_fund_stake, market)?;
 
-    let if_shares_before = insurance_fund_stake.checked_if_shares(spot_market)?;
-    let total_if_shares_before = spot_market.insurance_fund.total_shares;
-    let user_if_shares_before = spot_market.insurance_fund.user_shares;
+    let if_shares_before = insurance_fund_stake.checked_if_shares(market)?;
+    let total_if_shares_before = market.insurance_fund().total_shares;
+    let user_if_shares_before = market.insurance_fund().user_shares;
 
     validate!(
         insurance_fund_stake.last_withdraw_request_shares
-            <= insurance_fund_stake.checked_if_shares(spot_market)?,
+            <= insurance_fund_stake.checked_if_shares(market)?,
         ErrorCode::InvalidInsuranceUnstakeSize,
         "last_withdraw_request_shares exceeds if_shares {} > {}",
         insurance_fund_stake.last_withdraw_request_shares,
-        insurance_fund_stake.checked_if_shares(spot_market)?
+        insurance_fund_stake.checked_if_shares(market)?
     )?;
 
     validate!(
-        insurance_fund_stake.if_base == spot_market.insurance_fund.shares_base,
+        insurance_fund_stake.if_base == market.insurance_fund().shares_base,
         ErrorCode::InvalidIFRebase,
         "if stake base != spot market base"
     )?;
 
     insurance_fund_stake.last_withdraw_request_value = if_shares_to_vault_amount(
         insurance_fund_stake.last_withdraw_request_shares,
-        spot_market.insurance_fund.total_shares,
+        market.insurance_fund().total_shares,
         insurance_vault_amount,
     )?
     .min(insurance_vault_amount.saturating_sub(1));
@@ -229,12 +350,12 @@ pub fn request_remove_insurance_fund_stake(
         "Requested withdraw value is not below Insurance Fund balance"
     )?;
 
-    let if_shares_after = insurance_fund_stake.checked_if_shares(spot_market)?;
+    let if_shares_after = insurance_fund_stake.checked_if_shares(market)?;
 
-    if spot_market.market_index == 0 {
+    if is_quote_spot_market(market) {
         user_stats.if_staked_quote_asset_amount = if_shares_to_vault_amount(
-            insurance_fund_stake.checked_if_shares(spot_market)?,
-            spot_market.insurance_fund.total_shares,
+            insurance_fund_stake.checked_if_shares(market)?,
+            market.insurance_fund().total_shares,
             insurance_vault_amount,
         )?;
     }
@@ -244,14 +365,15 @@ pub fn request_remove_insurance_fund_stake(
         user_authority: user_stats.authority,
         action: StakeAction::UnstakeRequest,
         amount: insurance_fund_stake.last_withdraw_request_value,
-        market_index: spot_market.market_index,
+        market_index: market.market_index(),
+        market_type: market.market_type(),
         insurance_vault_amount_before: insurance_vault_amount,
         if_shares_before,
         user_if_shares_before,
         total_if_shares_before,
         if_shares_after,
-        total_if_shares_after: spot_market.insurance_fund.total_shares,
-        user_if_shares_after: spot_market.insurance_fund.user_shares,
+        total_if_shares_after: market.insurance_fund().total_shares,
+        user_if_shares_after: market.insurance_fund().user_shares,
     });
 
     insurance_fund_stake.last_withdraw_request_ts = now;
@@ -263,18 +385,20 @@ pub fn cancel_request_remove_insurance_fund_stake(
     insurance_vault_amount: u64,
     insurance_fund_stake: &mut InsuranceFundStake,
     user_stats: &mut UserStats,
-    spot_market: &mut SpotMarket,
+    market: &mut impl InsuranceFundMarket,
     now: i64,
 ) -> DriftResult {
-    apply_rebase_to_insurance_fund(insurance_vault_amount, spot_market)?;
-    apply_rebase_to_insurance_fund_stake(insurance_fund_stake, spot_market)?;
+    apply_rebase_to_insurance_fund(insurance_vault_amount, market)?;
+    update_insurance_fund_rewards(market, now)?;
+    apply_rebase_to_insurance_fund_stake(insurance_fund_stake, market)?;
+    settle_insurance_fund_stake_rewards(insurance_fund_stake, market)?;
 
-    let if_shares_before = insurance_fund_stake.checked_if_shares(spot_market)?;
-    let total_if_shares_before = spot_market.insurance_fund.total_shares;
-    let user_if_shares_before = spot_market.insurance_fund.user_shares;
+    let if_shares_before = insurance_fund_stake.checked_if_shares(market)?;
+    let total_if_shares_before = market.insurance_fund().total_shares;
+    let user_if_shares_before = market.insurance_fund().user_shares;
 
     validate!(
-        insurance_fund_stake.if_base == spot_market.insurance_fund.shares_base,
+        insurance_fund_stake.if_base == market.insurance_fund().shares_base,
         ErrorCode::InvalidIFRebase,
         "if stake base != spot market base"
     )?;
@@ -286,26 +410,26 @@ pub fn cancel_request_remove_insurance_fund_stake(
     )?;
 
     let if_shares_lost =
-        calculate_if_shares_lost(insurance_fund_stake, spot_market, insurance_vault_amount)?;
+        calculate_if_shares_lost(insurance_fund_stake, market, insurance_vault_amount)?;
 
-    insurance_fund_stake.decrease_if_shares(if_shares_lost, spot_market)?;
+    insurance_fund_stake.decrease_if_shares(if_shares_lost, market)?;
 
-    spot_market.insurance_fund.total_shares = spot_market
-        .insurance_fund
+    market.insurance_fund_mut().total_shares = market
+        .insurance_fund()
         .total_shares
         .safe_sub(if_shares_lost)?;
 
-    spot_market.insurance_fund.user_shares = spot_market
-        .insurance_fund
+    market.insurance_fund_mut().user_shares = market
+        .insurance_fund()
         .user_shares
         .safe_sub(if_shares_lost)?;
 
-    let if_shares_after = insurance_fund_stake.checked_if_shares(spot_market)?;
+    let if_shares_after = insurance_fund_stake.checked_if_shares(market)?;
 
-    if spot_market.market_index == 0 {
+    if is_quote_spot_market(market) {
         user_stats.if_staked_quote_asset_amount = if_shares_to_vault_amount(
             if_shares_after,
-            spot_market.insurance_fund.total_shares,
+            market.insurance_fund().total_shares,
             insurance_vault_amount,
         )?;
     }
@@ -315,14 +439,15 @@ pub fn cancel_request_remove_insurance_fund_stake(
         user_authority: user_stats.authority,
         action: StakeAction::UnstakeCancelRequest,
         amount: 0,
-        market_index: spot_market.market_index,
+        market_index: market.market_index(),
+        market_type: market.market_type(),
         insurance_vault_amount_before: insurance_vault_amount,
         if_shares_before,
         user_if_shares_before,
         total_if_shares_before,
         if_shares_after,
-        total_if_shares_after: spot_market.insurance_fund.total_shares,
-        user_if_shares_after: spot_market.insurance_fund.user_shares,
+        total_if_shares_after: market.insurance_fund().total_shares,
+        user_if_shares_after: market.insurance_fund().user_shares,
     });
 
     insurance_fund_stake.last_withdraw_request_shares = 0;
@@ -336,23 +461,25 @@ pub fn remove_insurance_fund_stake(
     insurance_vault_amount: u64,
     insurance_fund_stake: &mut InsuranceFundStake,
     user_stats: &mut UserStats,
-    spot_market: &mut SpotMarket,
+    market: &mut impl InsuranceFundMarket,
     now: i64,
 ) -> DriftResult<u64> {
     let time_since_withdraw_request =
         now.safe_sub(insurance_fund_stake.last_withdraw_request_ts)?;
 
     validate!(
-        time_since_withdraw_request >= spot_market.insurance_fund.unstaking_period,
+        time_since_withdraw_request >= market.insurance_fund().unstaking_period,
         ErrorCode::TryingToRemoveLiquidityTooFast
     )?;
 
-    apply_rebase_to_insurance_fund(insurance_vault_amount, spot_market)?;
-    apply_rebase_to_insurance_fund_stake(insurance_fund_stake, spot_market)?;
+    apply_rebase_to_insurance_fund(insurance_vault_amount, market)?;
+    update_insurance_fund_rewards(market, now)?;
+    apply_rebase_to_insurance_fund_stake(insurance_fund_stake, market)?;
+    settle_insurance_fund_stake_rewards(insurance_fund_stake, market)?;
 
-    let if_shares_before = insurance_fund_stake.checked_if_shares(spot_market)?;
-    let total_if_shares_before = spot_market.insurance_fund.total_shares;
-    let user_if_shares_before = spot_market.insurance_fund.user_shares;
+    let if_shares_before = insurance_fund_stake.checked_if_shares(market)?;
+    let total_if_shares_before = market.insurance_fund().total_shares;
+    let user_if_shares_before = market.insurance_fund().user_shares;
 
     let n_shares = insurance_fund_stake.last_withdraw_request_shares;
 
@@ -369,38 +496,38 @@ pub fn remove_insurance_fund_stake(
 
     let amount = if_shares_to_vault_amount(
         n_shares,
-        spot_market.insurance_fund.total_shares,
+        market.insurance_fund().total_shares,
         insurance_vault_amount,
     )?;
 
     let _if_shares_lost =
-        calculate_if_shares_lost(insurance_fund_stake, spot_market, insurance_vault_amount)?;
+        calculate_if_shares_lost(insurance_fund_stake, market, insurance_vault_amount)?;
 
     let withdraw_amount = amount.min(insurance_fund_stake.last_withdraw_request_value);
 
-    insurance_fund_stake.decrease_if_shares(n_shares, spot_market)?;
+    insurance_fund_stake.decrease_if_shares(n_shares, market)?;
 
     insurance_fund_stake.cost_basis = insurance_fund_stake
         .cost_basis
         .safe_sub(withdraw_amount.cast()?)?;
 
-    spot_market.insurance_fund.total_shares =
-        spot_market.insurance_fund.total_shares.safe_sub(n_shares)?;
+    market.insurance_fund_mut().total_shares =
+        market.insurance_fund().total_shares.safe_sub(n_shares)?;
 
-    spot_market.insurance_fund.user_shares =
-        spot_market.insurance_fund.user_shares.safe_sub(n_shares)?;
+    market.insurance_fund_mut().user_shares =
+        market.insurance_fund().user_shares.safe_sub(n_shares)?;
 
     // reset insurance_fund_stake withdraw request info
     insurance_fund_stake.last_withdraw_request_shares = 0;
     insurance_fund_stake.last_withdraw_request_value = 0;
     insurance_fund_stake.last_withdraw_request_ts = now;
 
-    let if_shares_after = insurance_fund_stake.checked_if_shares(spot_market)?;
+    let if_shares_after = insurance_fund_stake.checked_if_shares(market)?;
 
-    if spot_market.market_index == 0 {
+    if is_quote_spot_market(market) {
         user_stats.if_staked_quote_asset_amount = if_shares_to_vault_amount(
             if_shares_after,
-            spot_market.insurance_fund.total_shares,
+            market.insurance_fund().total_shares,
             insurance_vault_amount.safe_sub(amount)?,
         )?;
     }
@@ -410,19 +537,291 @@ pub fn remove_insurance_fund_stake(
         user_authority: user_stats.authority,
         action: StakeAction::Unstake,
         amount: withdraw_amount,
-        market_index: spot_market.market_index,
+        market_index: market.market_index(),
+        market_type: market.market_type(),
         insurance_vault_amount_before: insurance_vault_amount,
         if_shares_before,
         user_if_shares_before,
         total_if_shares_before,
         if_shares_after,
-        total_if_shares_after: spot_market.insurance_fund.total_shares,
-        user_if_shares_after: spot_market.insurance_fund.user_shares,
+        total_if_shares_after: market.insurance_fund().total_shares,
+        user_if_shares_after: market.insurance_fund().user_shares,
     });
 
     Ok(withdraw_amount)
 }
 
+// skips the unstaking period, forfeiting instant_unstake_haircut of the shares.
+// the protocol keeps its share of the haircut, the rest is burned for the remaining stakers
+pub fn instant_remove_insurance_fund_stake(
+    n_shares: u128,
+    insurance_vault_amount: u64,
+    insurance_fund_stake: &mut InsuranceFundStake,
+    user_stats: &mut UserStats,
+    market: &mut impl InsuranceFundMarket,
+    now: i64,
+) -> DriftResult<u64> {
+    validate!(
+        market.insurance_fund_extension().instant_unstake_haircut > 0,
+        ErrorCode::InstantIFUnstakeDisabled,
+        "market {} has no instant unstake haircut",
+        market.market_index()
+    )?;
+
+    validate!(
+        insurance_fund_stake.last_withdraw_request_shares == 0
+            && insurance_fund_stake.last_withdraw_request_value == 0,
+        ErrorCode::IFWithdrawRequestInProgress,
+        "withdraw request in progress"
+    )?;
+
+    apply_rebase_to_insurance_fund(insurance_vault_amount, market)?;
+    update_insurance_fund_rewards(market, now)?;
+    apply_rebase_to_insurance_fund_stake(insurance_fund_stake, market)?;
+    settle_insurance_fund_stake_rewards(insurance_fund_stake, market)?;
+
+    let if_shares_before = insurance_fund_stake.checked_if_shares(market)?;
+    let total_if_shares_before = market.insurance_fund().total_shares;
+    let user_if_shares_before = market.insurance_fund().user_shares;
+
+    validate!(
+        n_shares > 0,
+        ErrorCode::IFWithdrawRequestTooSmall,
+        "Requested instant unstake of 0 shares"
+    )?;
+
+    validate!(
+        if_shares_before >= n_shares,
+        ErrorCode::InsufficientIFShares
+    )?;
+
+    let (haircut_shares, protocol_shares) = calculate_instant_unstake_haircut_shares(
+        n_shares,
+        market.insurance_fund_extension().instant_unstake_haircut,
+    )?;
+
+    let amount = if_shares_to_vault_amount(
+        n_shares.safe_sub(haircut_shares)?,
+        market.insurance_fund().total_shares,
+        insurance_vault_amount,
+    )?;
+
+    insurance_fund_stake.decrease_if_shares(n_shares, market)?;
+
+    insurance_fund_stake.cost_basis = insurance_fund_stake.cost_basis.safe_sub(amount.cast()?)?;
+
+    market.insurance_fund_mut().total_shares = market
+        .insurance_fund()
+        .total_shares
+        .safe_sub(n_shares.safe_sub(protocol_shares)?)?;
+
+    market.insurance_fund_mut().user_shares =
+        market.insurance_fund().user_shares.safe_sub(n_shares)?;
+
+    let if_shares_after = insurance_fund_stake.checked_if_shares(market)?;
+
+    if is_quote_spot_market(market) {
+        user_stats.if_staked_quote_asset_amount = if_shares_to_vault_amount(
+            if_shares_after,
+            market.insurance_fund().total_shares,
+            insurance_vault_amount.safe_sub(amount)?,
+        )?;
+    }
+
+    emit!(InsuranceFundStakeRecord {
+        ts: now,
+        user_authority: user_stats.authority,
+        action: StakeAction::InstantUnstake,
+        amount,
+        market_index: market.market_index(),
+        market_type: market.market_type(),
+        insurance_vault_amount_before: insurance_vault_amount,
+        if_shares_before,
+        user_if_shares_before,
+        total_if_shares_before,
+        if_shares_after,
+        total_if_shares_after: market.insurance_fund().total_shares,
+        user_if_shares_after: market.insurance_fund().user_shares,
+    });
+
+    Ok(amount)
+}
+
+pub fn mint_insurance_fund_shares(
+    n_shares: u128,
+    insurance_vault_amount: u64,
+    insurance_fund_stake: &mut InsuranceFundStake,
+    user_stats: &mut UserStats,
+    market: &mut impl InsuranceFundMarket,
+    now: i64,
+) -> DriftResult<u64> {
+    validate!(
+        market.insurance_fund_extension().shares_mint != Pubkey::default(),
+        ErrorCode::InvalidInsuranceFundSharesMint,
+        "market {} has no insurance fund shares mint",
+        market.market_index()
+    )?;
+
+    validate!(
+        insurance_fund_stake.last_withdraw_request_shares == 0
+            && insurance_fund_stake.last_withdraw_request_value == 0,
+        ErrorCode::IFWithdrawRequestInProgress,
+        "withdraw request in progress"
+    )?;
+
+    apply_rebase_to_insurance_fund(insurance_vault_amount, market)?;
+    update_insurance_fund_rewards(market, now)?;
+    apply_rebase_to_insurance_fund_stake(insurance_fund_stake, market)?;
+    settle_insurance_fund_stake_rewards(insurance_fund_stake, market)?;
+
+    let if_shares_before = insurance_fund_stake.checked_if_shares(market)?;
+    let total_if_shares_before = market.insurance_fund().total_shares;
+    let user_if_shares_before = market.insurance_fund().user_shares;
+
+    validate!(
+        n_shares > 0 && if_shares_before >= n_shares,
+        ErrorCode::InsufficientIFShares
+    )?;
+
+    let amount = if_shares_to_shares_mint_amount(
+        n_shares,
+        market.insurance_fund().shares_base,
+        market.insurance_fund_extension().shares_mint_base,
+    )?;
+
+    let value = if_shares_to_vault_amount(
+        n_shares,
+        market.insurance_fund().total_shares,
+        insurance_vault_amount,
+    )?;
+
+    insurance_fund_stake.decrease_if_shares(n_shares, market)?;
+
+    insurance_fund_stake.cost_basis = insurance_fund_stake.cost_basis.safe_sub(value.cast()?)?;
+
+    // shares stay in user_shares, they are only moved out of the stake account
+    market.insurance_fund_extension_mut().tokenized_shares = market
+        .insurance_fund_extension()
+        .tokenized_shares
+        .safe_add(n_shares)?;
+
+    let if_shares_after = insurance_fund_stake.checked_if_shares(market)?;
+
+    if is_quote_spot_market(market) {
+        user_stats.if_staked_quote_asset_amount = if_shares_to_vault_amount(
+            if_shares_after,
+            market.insurance_fund().total_shares,
+            insurance_vault_amount,
+        )?;
+    }
+
+    emit!(InsuranceFundStakeRecord {
+        ts: now,
+        user_authority: user_stats.authority,
+        action: StakeAction::MintShares,
+        amount,
+        market_index: market.market_index(),
+        market_type: market.market_type(),
+        insurance_vault_amount_before: insurance_vault_amount,
+        if_shares_before,
+        user_if_shares_before,
+        total_if_shares_before,
+        if_shares_after,
+        total_if_shares_after: market.insurance_fund().total_shares,
+        user_if_shares_after: market.insurance_fund().user_shares,
+    });
+
+    Ok(amount)
+}
+
+pub fn redeem_insurance_fund_shares(
+    amount: u64,
+    insurance_vault_amount: u64,
+    insurance_fund_stake: &mut InsuranceFundStake,
+    user_stats: &mut UserStats,
+    market: &mut impl InsuranceFundMarket,
+    now: i64,
+) -> DriftResult<u128> {
+    validate!(
+        market.insurance_fund_extension().shares_mint != Pubkey::default(),
+        ErrorCode::InvalidInsuranceFundSharesMint,
+        "market {} has no insurance fund shares mint",
+        market.market_index()
+    )?;
+
+    validate!(
+        insurance_fund_stake.last_withdraw_request_shares == 0
+            && insurance_fund_stake.last_withdraw_request_value == 0,
+        ErrorCode::IFWithdrawRequestInProgress,
+        "withdraw request in progress"
+    )?;
+
+    apply_rebase_to_insurance_fund(insurance_vault_amount, market)?;
+    update_insurance_fund_rewards(market, now)?;
+    apply_rebase_to_insurance_fund_stake(insurance_fund_stake, market)?;
+    settle_insurance_fund_stake_rewards(insurance_fund_stake, market)?;
+
+    let if_shares_before = insurance_fund_stake.checked_if_shares(market)?;
+    let total_if_shares_before = market.insurance_fund().total_shares;
+    let user_if_shares_before = market.insurance_fund().user_shares;
+
+    let n_shares = shares_mint_amount_to_if_shares(
+        amount,
+        market.insurance_fund().shares_base,
+        market.insurance_fund_extension().shares_mint_base,
+    )?;
+
+    validate!(
+        n_shares > 0,
+        ErrorCode::InsufficientIFShares,
+        "{} shares mint tokens is less than one if share",
+        amount
+    )?;
+
+    let value = if_shares_to_vault_amount(
+        n_shares,
+        market.insurance_fund().total_shares,
+        insurance_vault_amount,
+    )?;
+
+    market.insurance_fund_extension_mut().tokenized_shares = market
+        .insurance_fund_extension()
+        .tokenized_shares
+        .safe_sub(n_shares)?;
+
+    insurance_fund_stake.increase_if_shares(n_shares, market)?;
+
+    insurance_fund_stake.cost_basis = insurance_fund_stake.cost_basis.safe_add(value.cast()?)?;
+
+    let if_shares_after = insurance_fund_stake.checked_if_shares(market)?;
+
+    if is_quote_spot_market(market) {
+        user_stats.if_staked_quote_asset_amount = if_shares_to_vault_amount(
+            if_shares_after,
+            market.insurance_fund().total_shares,
+            insurance_vault_amount,
+        )?;
+    }
+
+    emit!(InsuranceFundStakeRecord {
+        ts: now,
+        user_authority: user_stats.authority,
+        action: StakeAction::RedeemShares,
+        amount,
+        market_index: market.market_index(),
+        market_type: market.market_type(),
+        insurance_vault_amount_before: insurance_vault_amount,
+        if_shares_before,
+        user_if_shares_before,
+        total_if_shares_before,
+        if_shares_after,
+        total_if_shares_after: market.insurance_fund().total_shares,
+        user_if_shares_after: market.insurance_fund().user_shares,
+    });
+
+    Ok(n_shares)
+}
+
 pub fn admin_remove_insurance_fund_stake(
     insurance_vault_amount: u64,
     n_shares: u128,
@@ -462,6 +861,7 @@ pub fn admin_remove_insurance_fund_stake(
         action: StakeAction::Unstake,
         amount: withdraw_amount,
         market_index: spot_market.market_index,
+        market_type: MarketType::Spot,
         insurance_vault_amount_before: insurance_vault_amount,
         if_shares_before,
         user_if_shares_before,
@@ -474,14 +874,16 @@ pub fn admin_remove_insurance_fund_stake(
     Ok(withdraw_amount)
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn attempt_settle_revenue_to_insurance_fund<'info>(
-    spot_market_vault: &Account<'info, TokenAccount>,
-    insurance_fund_vault: &Account<'info, TokenAccount>,
+    spot_market_vault: &AccountInfo<'info>,
+    insurance_fund_vault: &AccountInfo<'info>,
     spot_market: &mut SpotMarket,
     now: i64,
-    token_program: &Program<'info, Token>,
+    token_program: &AccountInfo<'info>,
     drift_signer: &AccountInfo<'info>,
     state: &State,
+    mint: &Option<AccountInfo<'info>>,
 ) -> Result<()> {
     let valid_revenue_settle_time = if spot_market.insurance_fund.revenue_settle_period > 0 {
         let time_until_next_update = on_the_hour_update(
@@ -497,8 +899,9 @@ pub fn attempt_settle_revenue_to_insurance_fund<'info>(
 
     let _token_amount = if valid_revenue_settle_time {
         // uses proportion of revenue pool allocated to insurance fund
-        let spot_market_vault_amount = spot_market_vault.amount;
-        let insurance_fund_vault_amount = insurance_fund_vault.amount;
+        let spot_market_vault_amount = load_token_account(spot_market_vault, token_program)?.amount;
+        let insurance_fund_vault_amount =
+            load_token_account(insurance_fund_vault, token_program)?.amount;
 
         let token_amount = settle_revenue_to_insurance_fund(
             spot_market_vault_amount,
@@ -521,6 +924,7 @@ pub fn attempt_settle_revenue_to_insurance_fund<'info>(
                 drift_signer,
                 state.signer_nonce,
                 token_amount.cast()?,
+                mint,
             )?;
         }
 
@@ -568,14 +972,62 @@ pub fn settle_revenue_to_insurance_fund(
         token_amount = depositors_claim.safe_div(2)?;
     }
 
-    if spot_market.insurance_fund.user_shares > 0 {
+    let insurance_fund_token_amount = calculate_revenue_settle_to_insurance_fund(
+        token_amount,
+        insurance_vault_amount,
+        &spot_market.insurance_fund,
+    )?;
+
+    spot_market.insurance_fund.last_revenue_settle_ts = now;
+
+    let total_if_shares_before = spot_market.insurance_fund.total_shares;
+
+    settle_protocol_insurance_fund_shares(
+        insurance_fund_token_amount,
+        insurance_vault_amount,
+        &mut spot_market.insurance_fund,
+    )?;
+
+    update_revenue_pool_balances(
+        insurance_fund_token_amount.cast::<u128>()?,
+        &SpotBalanceType::Borrow,
+        spot_market,
+    )?;
+
+    emit!(InsuranceFundRecord {
+        ts: now,
+        spot_market_index: spot_market.market_index,
+        perp_market_index: 0, // todo: make option?
+        amount: insurance_fund_token_amount.cast()?,
+
+        user_if_factor: spot_market.insurance_fund.user_factor,
+        total_if_factor: spot_market.insurance_fund.total_factor,
+        vault_amount_before: spot_market_vault_amount,
+        insurance_vault_amount_before: insurance_vault_amount,
+        total_if_shares_before,
+        total_if_shares_after: spot_market.insurance_fund.total_shares,
+        insurance_fund_market_type: MarketType::Spot,
+    });
+
+    insurance_fund_token_amount.cast()
+}
+
+// caps the revenue at the max apr for the insurance fund's stakers and takes the insurance fund's share of it
+fn calculate_revenue_settle_to_insurance_fund(
+    token_amount: u128,
+    insurance_vault_amount: u64,
+    insurance_fund: &InsuranceFund,
+) -> DriftResult<u64> {
+    let mut token_amount = token_amount;
+
+    if insurance_fund.user_shares > 0 {
         let capped_apr_amount = insurance_vault_amount
             .safe_mul(MAX_APR_PER_REVENUE_SETTLE_TO_INSURANCE_FUND_VAULT)?
             .safe_div(PERCENTAGE_PRECISION_U64)?
             .safe_div(
                 ONE_YEAR
                     .cast::<u64>()?
-                    .safe_div(spot_market.insurance_fund.revenue_settle_period.cast()?)?
+                    .safe_div(insurance_fund.revenue_settle_period.cast()?)?
                     .max(1),
             )?
             .cast::<u128>()?;
@@ -595,26 +1047,98 @@ pub fn settle_revenue_to_insurance_fund(
         "no amount to settle to insurance fund"
     )?;
 
-    spot_market.insurance_fund.last_revenue_settle_ts = now;
+    Ok(insurance_fund_token_amount)
+}
 
-    let protocol_if_factor = spot_market
-        .insurance_fund
+fn settle_protocol_insurance_fund_shares(
+    insurance_fund_token_amount: u64,
+    insurance_vault_amount: u64,
+    insurance_fund: &mut InsuranceFund,
+) -> DriftResult {
+    let protocol_if_factor = insurance_fund
         .total_factor
-        .safe_sub(spot_market.insurance_fund.user_factor)?;
+        .safe_sub(insurance_fund.user_factor)?;
 
     // give protocol its cut
     let n_shares = vault_amount_to_if_shares(
         insurance_fund_token_amount
             .safe_mul(protocol_if_factor.cast()?)?
-            .safe_div(spot_market.insurance_fund.total_factor.cast()?)?,
-        spot_market.insurance_fund.total_shares,
+            .safe_div(insurance_fund.total_factor.cast()?)?,
+        insurance_fund.total_shares,
         insurance_vault_amount,
     )?;
 
-    let total_if_shares_before = spot_market.insurance_fund.total_shares;
+    insurance_fund.total_shares = insurance_fund.total_shares.safe_add(n_shares)?;
 
-    spot_market.insurance_fund.total_shares =
-        spot_market.insurance_fund.total_shares.safe_add(n_shares)?;
+    Ok(())
+}
+
+// the quote revenue a perp market has swept into the spot market's revenue pool since its last settle
+// funds the perp market's own insurance fund
+pub fn settle_revenue_to_perp_market_insurance_fund(
+    spot_market_vault_amount: u64,
+    insurance_vault_amount: u64,
+    spot_market: &mut SpotMarket,
+    perp_market: &mut PerpMarket,
+    now: i64,
+) -> DriftResult<u64> {
+    update_spot_market_cumulative_interest(spot_market, None, now)?;
+
+    validate!(
+        perp_market.has_insurance_fund(),
+        ErrorCode::InvalidPerpMarketInsuranceFundVault,
+        "perp market {} has no insurance fund",
+        perp_market.market_index
+    )?;
+
+    validate!(
+        perp_market.insurance_fund.revenue_settle_period > 0,
+        ErrorCode::RevenueSettingsCannotSettleToIF,
+        "invalid revenue_settle_period settings on perp market"
+    )?;
+
+    validate!(
+        perp_market.insurance_fund.user_factor <= perp_market.insurance_fund.total_factor,
+        ErrorCode::RevenueSettingsCannotSettleToIF,
+        "invalid if_factor settings on perp market"
+    )?;
+
+    let depositors_claim =
+        validate_spot_market_vault_amount(spot_market, spot_market_vault_amount)?.cast::<u128>()?;
+
+    let revenue_pool_token_amount = get_token_amount(
+        spot_market.revenue_pool.scaled_balance,
+        spot_market,
+        &SpotBalanceType::Deposit,
+    )?;
+
+    let mut token_amount = perp_market
+        .amm
+        .total_fee_withdrawn
+        .saturating_sub(perp_market.insurance_fund_settled_fee_withdrawn)
+        .min(revenue_pool_token_amount);
+
+    if depositors_claim < token_amount {
+        // only allow half of withdraw available when utilization is high
+        token_amount = depositors_claim.safe_div(2)?;
+    }
+
+    let insurance_fund_token_amount = calculate_revenue_settle_to_insurance_fund(
+        token_amount,
+        insurance_vault_amount,
+        &perp_market.insurance_fund,
+    )?;
+
+    perp_market.insurance_fund.last_revenue_settle_ts = now;
+    perp_market.insurance_fund_settled_fee_withdrawn = perp_market.amm.total_fee_withdrawn;
+
+    let total_if_shares_before = perp_market.insurance_fund.total_shares;
+
+    settle_protocol_insurance_fund_shares(
+        insurance_fund_token_amount,
+        insurance_vault_amount,
+        &mut perp_market.insurance_fund,
+    )?;
 
     update_revenue_pool_balances(
         insurance_fund_token_amount.cast::<u128>()?,
@@ -625,27 +1149,25 @@ pub fn settle_revenue_to_insurance_fund(
     emit!(InsuranceFundRecord {
         ts: now,
         spot_market_index: spot_market.market_index,
-        perp_market_index: 0, // todo: make option?
+        perp_market_index: perp_market.market_index,
         amount: insurance_fund_token_amount.cast()?,
-
-        user_if_factor: spot_market.insurance_fund.user_factor,
-        total_if_factor: spot_market.insurance_fund.total_factor,
+        user_if_factor: perp_market.insurance_fund.user_factor,
+        total_if_factor: perp_market.insurance_fund.total_factor,
         vault_amount_before: spot_market_vault_amount,
         insurance_vault_amount_before: insurance_vault_amount,
         total_if_shares_before,
-        total_if_shares_after: spot_market.insurance_fund.total_shares,
+        total_if_shares_after: perp_market.insurance_fund.total_shares,
+        insurance_fund_market_type: MarketType::Perp,
     });
 
-    insurance_fund_token_amount.cast()
+    Ok(insurance_fund_token_amount)
 }
 
-pub fn resolve_perp_pnl_deficit(
-    vault_amount: u64,
-    insurance_vault_amount: u64,
+fn calculate_perp_pnl_deficit_to_resolve(
     spot_market: &mut SpotMarket,
-    market: &mut PerpMarket,
+    market: &PerpMarket,
     now: i64,
-) -> DriftResult<u64> {
+) -> DriftResult<i128> {
     validate!(
         market.amm.total_fee_minus_distributions < 0,
         ErrorCode::NoAmmPerpPnlDeficit,
@@ -668,8 +1190,6 @@ pub fn resolve_perp_pnl_deficit(
 
     update_spot_market_cumulative_interest(spot_market, None, now)?;
 
-    let total_if_shares_before = spot_market.insurance_fund.total_shares;
-
     let excess_user_pnl_imbalance = if market.unrealized_pnl_max_imbalance > 0 {
         let net_unsettled_pnl = calculate_net_user_pnl(
             &market.amm,
@@ -688,17 +1208,125 @@ pub fn resolve_perp_pnl_deficit(
         excess_user_pnl_imbalance
     )?;
 
+    Ok(excess_user_pnl_imbalance)
+}
+
+// the perp market's own insurance fund is drawn first, the spot market's insurance fund covers what remains
+pub fn resolve_perp_pnl_deficit(
+    vault_amount: u64,
+    insurance_vault_amount: u64,
+    perp_market_insurance_vault_amount: Option<u64>,
+    spot_market: &mut SpotMarket,
+    market: &mut PerpMarket,
+    now: i64,
+) -> DriftResult<(u64, u64)> {
+    let excess_user_pnl_imbalance =
+        calculate_perp_pnl_deficit_to_resolve(spot_market, market, now)?;
+
+    let perp_market_insurance_withdraw = match perp_market_insurance_vault_amount {
+        Some(perp_market_insurance_vault_amount) => {
+            resolve_perp_pnl_deficit_from_perp_market_insurance_fund(
+                excess_user_pnl_imbalance,
+                vault_amount,
+                perp_market_insurance_vault_amount,
+                spot_market,
+                market,
+                now,
+            )?
+        }
+        None => 0,
+    };
+
+    let remaining_user_pnl_imbalance =
+        excess_user_pnl_imbalance.safe_sub(perp_market_insurance_withdraw)?;
+
+    let insurance_withdraw = if remaining_user_pnl_imbalance > 0 {
+        resolve_perp_pnl_deficit_from_insurance_fund(
+            remaining_user_pnl_imbalance,
+            perp_market_insurance_withdraw == 0,
+            vault_amount,
+            insurance_vault_amount,
+            spot_market,
+            market,
+            now,
+        )?
+    } else {
+        0
+    };
+
+    Ok((
+        perp_market_insurance_withdraw.cast()?,
+        insurance_withdraw.cast()?,
+    ))
+}
+
+fn resolve_perp_pnl_deficit_from_perp_market_insurance_fund(
+    excess_user_pnl_imbalance: i128,
+    vault_amount: u64,
+    perp_market_insurance_vault_amount: u64,
+    spot_market: &mut SpotMarket,
+    market: &mut PerpMarket,
+    now: i64,
+) -> DriftResult<i128> {
+    // subtract 1 so the insurance vault always remains >= 1
+    let insurance_withdraw = excess_user_pnl_imbalance.min(
+        perp_market_insurance_vault_amount
+            .saturating_sub(1)
+            .cast()?,
+    );
+
+    if insurance_withdraw <= 0 {
+        return Ok(0);
+    }
+
+    market.amm.total_fee_minus_distributions = market
+        .amm
+        .total_fee_minus_distributions
+        .safe_add(insurance_withdraw)?;
+
+    update_spot_balances(
+        insurance_withdraw.cast()?,
+        &SpotBalanceType::Deposit,
+        spot_market,
+        &mut market.pnl_pool,
+        false,
+    )?;
+
+    emit!(InsuranceFundRecord {
+        ts: now,
+        spot_market_index: spot_market.market_index,
+        perp_market_index: market.market_index,
+        amount: -insurance_withdraw.cast()?,
+        user_if_factor: market.insurance_fund.user_factor,
+        total_if_factor: market.insurance_fund.total_factor,
+        vault_amount_before: vault_amount,
+        insurance_vault_amount_before: perp_market_insurance_vault_amount,
+        total_if_shares_before: market.insurance_fund.total_shares,
+        total_if_shares_after: market.insurance_fund.total_shares,
+        insurance_fund_market_type: MarketType::Perp,
+    });
+
+    Ok(insurance_withdraw)
+}
+
+// once the perp market's insurance fund has paid, hitting the spot market's withdraw limits
+// leaves the rest of the deficit unresolved instead of failing
+fn resolve_perp_pnl_deficit_from_insurance_fund(
+    excess_user_pnl_imbalance: i128,
+    insurance_withdraw_required: bool,
+    vault_amount: u64,
+    insurance_vault_amount: u64,
+    spot_market: &mut SpotMarket,
+    market: &mut PerpMarket,
+    now: i64,
+) -> DriftResult<i128> {
+    let total_if_shares_before = spot_market.insurance_fund.total_shares;
+
     let max_revenue_withdraw_per_period = market
         .insurance_claim
         .max_revenue_withdraw_per_period
         .safe_sub(market.insurance_claim.revenue_withdraw_since_last_settle)?
         .cast::<i128>()?;
-    validate!(
-        max_revenue_withdraw_per_period > 0,
-        ErrorCode::MaxRevenueWithdrawPerPeriodReached,
-        "max_revenue_withdraw_per_period={} as already been reached",
-        max_revenue_withdraw_per_period
-    )?;
 
     let max_insurance_withdraw = market
         .insurance_claim
@@ -706,6 +1334,22 @@ pub fn resolve_perp_pnl_deficit(
         .safe_sub(market.insurance_claim.quote_settled_insurance)?
         .cast::<i128>()?;
 
+    let insurance_withdraw = excess_user_pnl_imbalance
+        .min(max_revenue_withdraw_per_period)
+        .min(max_insurance_withdraw)
+        .min(insurance_vault_amount.saturating_sub(1).cast()?);
+
+    if !insurance_withdraw_required && insurance_withdraw <= 0 {
+        return Ok(0);
+    }
+
+    validate!(
+        max_revenue_withdraw_per_period > 0,
+        ErrorCode::MaxRevenueWithdrawPerPeriodReached,
+        "max_revenue_withdraw_per_period={} as already been reached",
+        max_revenue_withdraw_per_period
+    )?;
+
     validate!(
         max_insurance_withdraw > 0,
         ErrorCode::MaxIFWithdrawReached,
@@ -714,11 +1358,6 @@ pub fn resolve_perp_pnl_deficit(
         market.insurance_claim.quote_max_insurance,
     )?;
 
-    let insurance_withdraw = excess_user_pnl_imbalance
-        .min(max_revenue_withdraw_per_period)
-        .min(max_insurance_withdraw)
-        .min(insurance_vault_amount.saturating_sub(1).cast()?);
-
     validate!(
         insurance_withdraw > 0,
         ErrorCode::NoIFWithdrawAvailable,
@@ -772,7 +1411,43 @@ pub fn resolve_perp_pnl_deficit(
         insurance_vault_amount_before: insurance_vault_amount,
         total_if_shares_before,
         total_if_shares_after: spot_market.insurance_fund.total_shares,
+        insurance_fund_market_type: MarketType::Spot,
+    });
+
+    Ok(insurance_withdraw)
+}
+
+pub fn update_perp_market_insurance_coverage(
+    perp_market: &mut PerpMarket,
+    insurance_fund_vault_balance: u64,
+    perp_market_insurance_fund_vault_balance: u64,
+    now: i64,
+) -> DriftResult {
+    let insurance_value = calculate_perp_market_insurance_value(
+        perp_market,
+        insurance_fund_vault_balance,
+        perp_market_insurance_fund_vault_balance,
+    )?;
+
+    let weighted_open_interest = calculate_perp_market_weighted_open_interest(
+        perp_market,
+        perp_market.amm.historical_oracle_data.last_oracle_price,
+    )?;
+
+    let insurance_coverage_ratio =
+        calculate_insurance_coverage_ratio(insurance_value, weighted_open_interest)?;
+
+    perp_market.insurance_coverage_ratio = insurance_coverage_ratio;
+    perp_market.last_insurance_coverage_update_ts = now;
+
+    emit!(InsuranceCoverageRecord {
+        ts: now,
+        perp_market_index: perp_market.market_index,
+        insurance_value,
+        weighted_open_interest,
+        insurance_coverage_ratio,
+        min_insurance_coverage_ratio: perp_market.min_insurance_coverage_ratio,
     });
 
-    insurance_withdraw.cast()
+    Ok(())
 }
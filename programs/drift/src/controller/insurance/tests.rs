@@ -1,8 +1,13 @@
 use anchor_lang::prelude::Pubkey;
 
 use crate::controller::insurance::*;
-use crate::math::constants::{QUOTE_PRECISION, SPOT_CUMULATIVE_INTEREST_PRECISION};
-use crate::state::spot_market::InsuranceFund;
+use crate::math::constants::{
+    IF_REWARD_PER_SHARE_PRECISION, PRICE_PRECISION_I64, QUOTE_PRECISION, QUOTE_PRECISION_I128,
+    QUOTE_PRECISION_U64, SPOT_CUMULATIVE_INTEREST_PRECISION,
+};
+use crate::state::oracle::HistoricalOracleData;
+use crate::state::perp_market::{InsuranceClaim, AMM};
+use crate::state::spot_market::{InsuranceFund, InsuranceFundExtension};
 use crate::state::user::UserStats;
 
 #[test]
@@ -1356,3 +1361,627 @@ pub fn multiple_if_stakes_and_rebase_and_admin_remove() {
     assert_eq!(spot_market.insurance_fund.user_shares, 0);
     assert_eq!(spot_market.insurance_fund.total_shares, 0);
 }
+
+#[test]
+pub fn basic_stake_perp_market_if_test() {
+    let mut if_balance = 0;
+
+    let mut if_stake = InsuranceFundStake {
+        market_type: MarketType::Perp,
+        ..InsuranceFundStake::new(Pubkey::default(), 0, 0)
+    };
+    let mut user_stats = UserStats {
+        number_of_sub_accounts: 0,
+        ..UserStats::default()
+    };
+    let amount = QUOTE_PRECISION as u64; // $1
+    let mut perp_market = PerpMarket {
+        insurance_fund: InsuranceFund {
+            vault: Pubkey::new_unique(),
+            unstaking_period: 0,
+            ..InsuranceFund::default()
+        },
+        ..PerpMarket::default()
+    };
+
+    add_insurance_fund_stake(
+        amount,
+        if_balance,
+        &mut if_stake,
+        &mut user_stats,
+        &mut perp_market,
+        0,
+    )
+    .unwrap();
+    assert_eq!(if_stake.unchecked_if_shares(), amount as u128);
+    assert_eq!(perp_market.insurance_fund.total_shares, amount as u128);
+    assert_eq!(perp_market.insurance_fund.user_shares, amount as u128);
+    // only the quote spot market's insurance fund counts towards staked quote
+    assert_eq!(user_stats.if_staked_quote_asset_amount, 0);
+    if_balance += amount;
+
+    request_remove_insurance_fund_stake(
+        if_stake.unchecked_if_shares(),
+        if_balance,
+        &mut if_stake,
+        &mut user_stats,
+        &mut perp_market,
+        0,
+    )
+    .unwrap();
+
+    let amount_returned = remove_insurance_fund_stake(
+        if_balance,
+        &mut if_stake,
+        &mut user_stats,
+        &mut perp_market,
+        0,
+    )
+    .unwrap();
+    assert_eq!(amount_returned, amount - 1);
+    assert_eq!(if_stake.unchecked_if_shares(), 0);
+    assert_eq!(perp_market.insurance_fund.total_shares, 0);
+    assert_eq!(perp_market.insurance_fund.user_shares, 0);
+}
+
+#[test]
+pub fn cant_use_spot_market_if_stake_for_perp_market() {
+    let mut if_stake = InsuranceFundStake::new(Pubkey::default(), 0, 0);
+    let mut user_stats = UserStats::default();
+    let mut perp_market = PerpMarket {
+        insurance_fund: InsuranceFund {
+            vault: Pubkey::new_unique(),
+            ..InsuranceFund::default()
+        },
+        ..PerpMarket::default()
+    };
+
+    assert_eq!(
+        add_insurance_fund_stake(
+            QUOTE_PRECISION as u64,
+            0,
+            &mut if_stake,
+            &mut user_stats,
+            &mut perp_market,
+            0,
+        ),
+        Err(ErrorCode::InvalidInsuranceFundStakeMarket)
+    );
+
+    let mut spot_market = SpotMarket::default();
+    let mut perp_if_stake = InsuranceFundStake {
+        market_type: MarketType::Perp,
+        ..InsuranceFundStake::new(Pubkey::default(), 0, 0)
+    };
+
+    assert_eq!(
+        add_insurance_fund_stake(
+            QUOTE_PRECISION as u64,
+            0,
+            &mut perp_if_stake,
+            &mut user_stats,
+            &mut spot_market,
+            0,
+        ),
+        Err(ErrorCode::InvalidInsuranceFundStakeMarket)
+    );
+}
+
+#[test]
+pub fn if_stake_rewards_accrue_pro_rata_and_survive_rebase() {
+    let mut if_balance = 0_u64;
+    let amount = 1000 * QUOTE_PRECISION as u64; // $1000
+
+    let mut if_stake_1 = InsuranceFundStake::new(Pubkey::default(), 0, 0);
+    let mut user_stats_1 = UserStats::default();
+    let mut if_stake_2 = InsuranceFundStake::new(Pubkey::default(), 0, 0);
+    let mut user_stats_2 = UserStats::default();
+
+    let mut spot_market = SpotMarket {
+        insurance_fund_extension: InsuranceFundExtension {
+            reward_emission_rate: 100,
+            reward_emission_end_ts: 1000,
+            ..InsuranceFundExtension::default()
+        },
+        ..SpotMarket::default()
+    };
+
+    add_insurance_fund_stake(
+        amount,
+        if_balance,
+        &mut if_stake_1,
+        &mut user_stats_1,
+        &mut spot_market,
+        0,
+    )
+    .unwrap();
+    if_balance += amount;
+
+    // staker 1 earns everything for the first 100 seconds
+    add_insurance_fund_stake(
+        amount,
+        if_balance,
+        &mut if_stake_2,
+        &mut user_stats_2,
+        &mut spot_market,
+        100,
+    )
+    .unwrap();
+    if_balance += amount;
+    assert_eq!(
+        spot_market.insurance_fund_extension.reward_per_share,
+        10_000_000
+    );
+    assert_eq!(if_stake_2.reward_per_share_checkpoint, 10_000_000);
+    assert_eq!(if_stake_2.unclaimed_rewards, 0);
+
+    // then they split emissions evenly
+    let claimed = claim_insurance_fund_stake_rewards(
+        if_balance,
+        u64::MAX,
+        &mut if_stake_1,
+        &mut spot_market,
+        200,
+    )
+    .unwrap();
+    assert_eq!(claimed, 15000);
+    assert_eq!(if_stake_1.unclaimed_rewards, 0);
+
+    let claimed = claim_insurance_fund_stake_rewards(
+        if_balance,
+        u64::MAX,
+        &mut if_stake_2,
+        &mut spot_market,
+        200,
+    )
+    .unwrap();
+    assert_eq!(claimed, 5000);
+
+    // insurance fund drawn down, forcing a rebase
+    if_balance = QUOTE_PRECISION as u64;
+    let claimed = claim_insurance_fund_stake_rewards(
+        if_balance,
+        u64::MAX,
+        &mut if_stake_1,
+        &mut spot_market,
+        300,
+    )
+    .unwrap();
+    assert_eq!(spot_market.insurance_fund.shares_base, 2);
+    assert_eq!(if_stake_1.if_base, 2);
+    assert_eq!(claimed, 5000);
+
+    // staker 2 rebases two periods later and is still owed both
+    let claimed = claim_insurance_fund_stake_rewards(
+        if_balance,
+        u64::MAX,
+        &mut if_stake_2,
+        &mut spot_market,
+        400,
+    )
+    .unwrap();
+    assert_eq!(if_stake_2.if_base, 2);
+    assert_eq!(claimed, 10000);
+
+    // claims are capped by the reward vault balance
+    let claimed = claim_insurance_fund_stake_rewards(
+        if_balance,
+        1000,
+        &mut if_stake_1,
+        &mut spot_market,
+        500,
+    )
+    .unwrap();
+    assert_eq!(claimed, 1000);
+    assert_eq!(if_stake_1.unclaimed_rewards, 9000);
+
+    // no emissions after the end ts
+    let reward_per_share_before = spot_market.insurance_fund_extension.reward_per_share;
+    update_insurance_fund_rewards(&mut spot_market, 5000).unwrap();
+    assert_eq!(
+        spot_market.insurance_fund_extension.reward_per_share - reward_per_share_before,
+        500 * 100 * IF_REWARD_PER_SHARE_PRECISION / 20_000_000
+    );
+}
+
+#[test]
+pub fn mint_and_redeem_if_shares_across_rebase() {
+    let mut if_balance = 0_u64;
+    let amount = 1000 * QUOTE_PRECISION as u64; // $1000
+
+    let mut if_stake = InsuranceFundStake::new(Pubkey::default(), 0, 0);
+    let mut user_stats = UserStats::default();
+    let mut spot_market = SpotMarket::default();
+
+    add_insurance_fund_stake(
+        amount,
+        if_balance,
+        &mut if_stake,
+        &mut user_stats,
+        &mut spot_market,
+        0,
+    )
+    .unwrap();
+    if_balance += amount;
+
+    // no shares mint yet
+    assert!(mint_insurance_fund_shares(
+        1,
+        if_balance,
+        &mut if_stake,
+        &mut user_stats,
+        &mut spot_market,
+        0,
+    )
+    .is_err());
+
+    spot_market.insurance_fund_extension.shares_mint = Pubkey::new_unique();
+
+    // cant mint more than staked
+    assert!(mint_insurance_fund_shares(
+        (amount + 1) as u128,
+        if_balance,
+        &mut if_stake,
+        &mut user_stats,
+        &mut spot_market,
+        0,
+    )
+    .is_err());
+
+    let minted = mint_insurance_fund_shares(
+        400 * QUOTE_PRECISION,
+        if_balance,
+        &mut if_stake,
+        &mut user_stats,
+        &mut spot_market,
+        0,
+    )
+    .unwrap();
+    assert_eq!(minted, 400 * QUOTE_PRECISION as u64);
+    assert_eq!(if_stake.unchecked_if_shares(), 600 * QUOTE_PRECISION);
+    assert_eq!(
+        spot_market.insurance_fund_extension.tokenized_shares,
+        400 * QUOTE_PRECISION
+    );
+    assert_eq!(
+        spot_market.insurance_fund.user_shares,
+        1000 * QUOTE_PRECISION
+    );
+    assert_eq!(
+        spot_market.insurance_fund.total_shares,
+        1000 * QUOTE_PRECISION
+    );
+    assert_eq!(
+        user_stats.if_staked_quote_asset_amount,
+        600 * QUOTE_PRECISION as u64
+    );
+
+    // insurance fund drawn down, forcing a rebase
+    if_balance = QUOTE_PRECISION as u64;
+
+    // less than one if share after the rebase
+    assert!(redeem_insurance_fund_shares(
+        99,
+        if_balance,
+        &mut if_stake,
+        &mut user_stats,
+        &mut spot_market,
+        0,
+    )
+    .is_err());
+    assert_eq!(spot_market.insurance_fund.shares_base, 2);
+    assert_eq!(
+        spot_market.insurance_fund_extension.tokenized_shares,
+        4 * QUOTE_PRECISION
+    );
+
+    let redeemed = redeem_insurance_fund_shares(
+        minted,
+        if_balance,
+        &mut if_stake,
+        &mut user_stats,
+        &mut spot_market,
+        0,
+    )
+    .unwrap();
+    assert_eq!(redeemed, 4 * QUOTE_PRECISION);
+    assert_eq!(if_stake.if_base, 2);
+    assert_eq!(if_stake.unchecked_if_shares(), 10 * QUOTE_PRECISION);
+    assert_eq!(spot_market.insurance_fund_extension.tokenized_shares, 0);
+    assert_eq!(spot_market.insurance_fund.user_shares, 10 * QUOTE_PRECISION);
+    assert_eq!(user_stats.if_staked_quote_asset_amount, if_balance);
+}
+
+#[test]
+pub fn instant_unstake_if_with_haircut() {
+    let mut if_balance = 0_u64;
+    let amount = 1000 * QUOTE_PRECISION as u64; // $1000
+
+    let mut if_stake_1 = InsuranceFundStake::new(Pubkey::default(), 0, 0);
+    let mut user_stats_1 = UserStats::default();
+    let mut if_stake_2 = InsuranceFundStake::new(Pubkey::default(), 0, 0);
+    let mut user_stats_2 = UserStats::default();
+    let mut spot_market = SpotMarket {
+        insurance_fund: InsuranceFund {
+            unstaking_period: 60 * 60 * 24 * 13,
+            ..InsuranceFund::default()
+        },
+        ..SpotMarket::default()
+    };
+
+    add_insurance_fund_stake(
+        amount,
+        if_balance,
+        &mut if_stake_1,
+        &mut user_stats_1,
+        &mut spot_market,
+        0,
+    )
+    .unwrap();
+    if_balance += amount;
+
+    add_insurance_fund_stake(
+        amount,
+        if_balance,
+        &mut if_stake_2,
+        &mut user_stats_2,
+        &mut spot_market,
+        0,
+    )
+    .unwrap();
+    if_balance += amount;
+
+    // disabled by default
+    assert!(instant_remove_insurance_fund_stake(
+        if_stake_1.unchecked_if_shares(),
+        if_balance,
+        &mut if_stake_1,
+        &mut user_stats_1,
+        &mut spot_market,
+        0,
+    )
+    .is_err());
+
+    spot_market.insurance_fund_extension.instant_unstake_haircut = 100_000; // 10%
+
+    // cant combine with a pending withdraw request
+    request_remove_insurance_fund_stake(
+        if_stake_2.unchecked_if_shares(),
+        if_balance,
+        &mut if_stake_2,
+        &mut user_stats_2,
+        &mut spot_market,
+        0,
+    )
+    .unwrap();
+    assert!(instant_remove_insurance_fund_stake(
+        if_stake_2.unchecked_if_shares(),
+        if_balance,
+        &mut if_stake_2,
+        &mut user_stats_2,
+        &mut spot_market,
+        0,
+    )
+    .is_err());
+    cancel_request_remove_insurance_fund_stake(
+        if_balance,
+        &mut if_stake_2,
+        &mut user_stats_2,
+        &mut spot_market,
+        0,
+    )
+    .unwrap();
+
+    let amount_out = instant_remove_insurance_fund_stake(
+        if_stake_1.unchecked_if_shares(),
+        if_balance,
+        &mut if_stake_1,
+        &mut user_stats_1,
+        &mut spot_market,
+        0,
+    )
+    .unwrap();
+    assert_eq!(amount_out, 900 * QUOTE_PRECISION as u64);
+    if_balance -= amount_out;
+
+    assert_eq!(if_stake_1.unchecked_if_shares(), 0);
+    assert_eq!(user_stats_1.if_staked_quote_asset_amount, 0);
+
+    // half the haircut stays with the protocol, the rest is burned
+    assert_eq!(
+        spot_market.insurance_fund.user_shares,
+        1000 * QUOTE_PRECISION
+    );
+    assert_eq!(
+        spot_market.insurance_fund.total_shares,
+        1050 * QUOTE_PRECISION
+    );
+
+    let remaining_staker_value = if_shares_to_vault_amount(
+        if_stake_2.unchecked_if_shares(),
+        spot_market.insurance_fund.total_shares,
+        if_balance,
+    )
+    .unwrap();
+    assert_eq!(remaining_staker_value, 1047619047);
+}
+
+fn perp_market_with_pnl_deficit() -> PerpMarket {
+    PerpMarket {
+        amm: AMM {
+            quote_asset_amount: 200 * QUOTE_PRECISION_I128,
+            total_fee_minus_distributions: -200 * QUOTE_PRECISION_I128,
+            historical_oracle_data: HistoricalOracleData {
+                last_oracle_price: PRICE_PRECISION_I64,
+                ..HistoricalOracleData::default()
+            },
+            ..AMM::default()
+        },
+        unrealized_pnl_max_imbalance: 50 * QUOTE_PRECISION_U64,
+        insurance_claim: InsuranceClaim {
+            max_revenue_withdraw_per_period: 1000 * QUOTE_PRECISION_U64,
+            quote_max_insurance: 1000 * QUOTE_PRECISION_U64,
+            ..InsuranceClaim::default()
+        },
+        insurance_fund: InsuranceFund {
+            vault: Pubkey::new_unique(),
+            ..InsuranceFund::default()
+        },
+        ..PerpMarket::default()
+    }
+}
+
+fn quote_spot_market(now: i64) -> SpotMarket {
+    SpotMarket {
+        market_index: 0,
+        decimals: 6,
+        cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+        cumulative_borrow_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+        last_interest_ts: now as u64,
+        ..SpotMarket::default()
+    }
+}
+
+#[test]
+pub fn quote_insurance_fund_covers_what_perp_market_insurance_fund_cant() {
+    let now = 100;
+    let mut perp_market = perp_market_with_pnl_deficit();
+    let mut spot_market = quote_spot_market(now);
+
+    let (perp_market_pay_from_insurance, pay_from_insurance) = resolve_perp_pnl_deficit(
+        0,
+        1000 * QUOTE_PRECISION_U64,
+        Some(101 * QUOTE_PRECISION_U64),
+        &mut spot_market,
+        &mut perp_market,
+        now,
+    )
+    .unwrap();
+
+    assert_eq!(perp_market_pay_from_insurance, 100 * QUOTE_PRECISION_U64);
+    assert_eq!(pay_from_insurance, 50 * QUOTE_PRECISION_U64);
+    assert_eq!(
+        perp_market.amm.total_fee_minus_distributions,
+        -50 * QUOTE_PRECISION_I128
+    );
+    assert_eq!(
+        perp_market.insurance_claim.quote_settled_insurance,
+        50 * QUOTE_PRECISION_U64
+    );
+    assert_eq!(
+        get_token_amount(
+            perp_market.pnl_pool.scaled_balance,
+            &spot_market,
+            &SpotBalanceType::Deposit
+        )
+        .unwrap(),
+        150 * QUOTE_PRECISION
+    );
+}
+
+#[test]
+pub fn perp_market_insurance_fund_covers_deficit_without_quote_insurance_fund_limits() {
+    let now = 100;
+    let mut perp_market = PerpMarket {
+        insurance_claim: InsuranceClaim::default(),
+        ..perp_market_with_pnl_deficit()
+    };
+    let mut spot_market = quote_spot_market(now);
+
+    let (perp_market_pay_from_insurance, pay_from_insurance) = resolve_perp_pnl_deficit(
+        0,
+        1000 * QUOTE_PRECISION_U64,
+        Some(1000 * QUOTE_PRECISION_U64),
+        &mut spot_market,
+        &mut perp_market,
+        now,
+    )
+    .unwrap();
+
+    assert_eq!(perp_market_pay_from_insurance, 150 * QUOTE_PRECISION_U64);
+    assert_eq!(pay_from_insurance, 0);
+    assert_eq!(perp_market.insurance_claim.quote_settled_insurance, 0);
+
+    // quote insurance fund limits still apply when there is no perp market insurance fund
+    let mut perp_market = PerpMarket {
+        insurance_claim: InsuranceClaim::default(),
+        ..perp_market_with_pnl_deficit()
+    };
+    let mut spot_market = quote_spot_market(now);
+
+    let result = resolve_perp_pnl_deficit(
+        0,
+        1000 * QUOTE_PRECISION_U64,
+        None,
+        &mut spot_market,
+        &mut perp_market,
+        now,
+    );
+    assert_eq!(result, Err(ErrorCode::MaxRevenueWithdrawPerPeriodReached));
+}
+
+#[test]
+pub fn settle_perp_market_revenue_to_perp_market_insurance_fund() {
+    let now = 3600;
+    let mut spot_market = quote_spot_market(now);
+    update_revenue_pool_balances(
+        100 * QUOTE_PRECISION,
+        &SpotBalanceType::Deposit,
+        &mut spot_market,
+    )
+    .unwrap();
+
+    let mut perp_market = PerpMarket {
+        amm: AMM {
+            total_fee_withdrawn: 60 * QUOTE_PRECISION,
+            ..AMM::default()
+        },
+        insurance_fund: InsuranceFund {
+            vault: Pubkey::new_unique(),
+            total_factor: 1,
+            revenue_settle_period: 3600,
+            ..InsuranceFund::default()
+        },
+        insurance_fund_settled_fee_withdrawn: 20 * QUOTE_PRECISION,
+        ..PerpMarket::default()
+    };
+
+    let settle_amount = settle_revenue_to_perp_market_insurance_fund(
+        100 * QUOTE_PRECISION_U64,
+        0,
+        &mut spot_market,
+        &mut perp_market,
+        now,
+    )
+    .unwrap();
+
+    // only the fees swept since the last settle go to the perp market's insurance fund
+    assert_eq!(settle_amount, 40 * QUOTE_PRECISION_U64);
+    assert_eq!(
+        get_token_amount(
+            spot_market.revenue_pool.scaled_balance,
+            &spot_market,
+            &SpotBalanceType::Deposit
+        )
+        .unwrap(),
+        60 * QUOTE_PRECISION
+    );
+    assert_eq!(
+        perp_market.insurance_fund_settled_fee_withdrawn,
+        60 * QUOTE_PRECISION
+    );
+    assert_eq!(
+        perp_market.insurance_fund.total_shares,
+        40 * QUOTE_PRECISION
+    );
+    assert_eq!(perp_market.insurance_fund.last_revenue_settle_ts, now);
+
+    let result = settle_revenue_to_perp_market_insurance_fund(
+        60 * QUOTE_PRECISION_U64,
+        40 * QUOTE_PRECISION_U64,
+        &mut spot_market,
+        &mut perp_market,
+        now + 3600,
+    );
+    assert_eq!(result, Err(ErrorCode::NoRevenueToSettleToIF));
+}
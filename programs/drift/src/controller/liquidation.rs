@@ -6,8 +6,10 @@ use solana_program::msg;
 use crate::controller::funding::settle_funding_payment;
 use crate::controller::lp::burn_lp_shares;
 use crate::controller::orders;
+use crate::controller::orders::validate_market_within_price_band;
 use crate::controller::position::{
-    get_position_index, update_position_and_market, update_quote_asset_amount,
+    get_position_index, update_lp_market_position, update_position_and_market,
+    update_position_with_base_asset_amount, update_quote_asset_amount,
     update_quote_asset_and_break_even_amount, PositionDirection,
 };
 use crate::controller::repeg::update_amm_and_check_validity;
@@ -18,6 +20,7 @@ use crate::controller::spot_balance::{
 use crate::controller::spot_position::update_spot_balances_and_cumulative_deposits;
 use crate::error::{DriftResult, ErrorCode};
 use crate::get_then_update_id;
+use crate::math::amm;
 use crate::math::bankruptcy::is_user_bankrupt;
 use crate::math::casting::Cast;
 use crate::math::constants::{
@@ -40,11 +43,14 @@ use crate::math::oracle::DriftAction;
 use crate::math::orders::{get_position_delta_for_fill, standardize_base_asset_amount};
 use crate::math::position::calculate_base_asset_value_with_oracle_price;
 use crate::math::safe_math::SafeMath;
+use crate::state::backstop_vault::BackstopVault;
 use crate::state::events::{
-    LiquidateBorrowForPerpPnlRecord, LiquidatePerpPnlForDepositRecord, LiquidatePerpRecord,
-    LiquidateSpotRecord, LiquidationRecord, LiquidationType, OrderAction, OrderActionExplanation,
-    OrderActionRecord, OrderRecord, PerpBankruptcyRecord, SpotBankruptcyRecord,
+    LPAction, LPRecord, LiquidateBorrowForPerpPnlRecord, LiquidatePerpPnlForDepositRecord,
+    LiquidatePerpRecord, LiquidateSpotRecord, LiquidationRecord, LiquidationType, OrderAction,
+    OrderActionExplanation, OrderActionRecord, OrderRecord, PerpBankruptcyRecord,
+    SpotBankruptcyRecord,
 };
+use crate::state::liquidation_history::{LiquidationHistoryRecord, UserLiquidationHistory};
 use crate::state::oracle_map::OracleMap;
 use crate::state::perp_market::MarketStatus;
 use crate::state::perp_market_map::PerpMarketMap;
@@ -53,6 +59,7 @@ use crate::state::spot_market_map::SpotMarketMap;
 use crate::state::state::State;
 use crate::state::user::{MarketType, Order, OrderStatus, OrderType, User, UserStats};
 use crate::validate;
+use crate::validation::perp_market::validate_amm_account_for_fill;
 
 #[cfg(test)]
 mod tests;
@@ -73,17 +80,651 @@ pub fn liquidate_perp(
     slot: u64,
     now: i64,
     state: &State,
+    liquidation_history: Option<&mut UserLiquidationHistory>,
 ) -> DriftResult {
-    let liquidation_margin_buffer_ratio = state.liquidation_margin_buffer_ratio;
+    validate!(
+        !liquidator.is_bankrupt,
+        ErrorCode::UserBankrupt,
+        "liquidator bankrupt",
+    )?;
+
+    liquidator
+        .force_get_perp_position_mut(market_index)
+        .map_err(|e| {
+            msg!(
+                "Liquidator has no available positions to take on perp position in market {}",
+                market_index
+            );
+            e
+        })?;
+
+    // Settle liquidator's funding payments so that collateral is up to date
+    settle_funding_payment(
+        liquidator,
+        liquidator_key,
+        perp_market_map.get_ref_mut(&market_index)?.deref_mut(),
+        now,
+    )?;
+
+    let PerpLiquidation {
+        liquidation_id,
+        margin_requirement,
+        total_collateral,
+        canceled_order_ids,
+        position_index,
+        oracle_price,
+        lp_shares,
+        user_base_asset_amount,
+        base_asset_amount_to_cover_margin_shortage,
+        liquidation_fee,
+        if_liquidation_fee,
+    } = match start_perp_liquidation(
+        market_index,
+        user,
+        user_key,
+        liquidator_key,
+        perp_market_map,
+        spot_market_map,
+        oracle_map,
+        slot,
+        now,
+        state,
+        LiquidationType::LiquidatePerp,
+    )? {
+        Some(perp_liquidation) => perp_liquidation,
+        None => return Ok(()),
+    };
+
+    validate!(
+        liquidator_max_base_asset_amount != 0,
+        ErrorCode::InvalidBaseAssetAmountForLiquidatePerp,
+        "liquidator_max_base_asset_amount cant be 0"
+    )?;
+
+    let base_asset_amount = user_base_asset_amount
+        .min(liquidator_max_base_asset_amount)
+        .min(base_asset_amount_to_cover_margin_shortage);
+    let base_asset_amount = standardize_base_asset_amount(
+        base_asset_amount,
+        perp_market_map.get_ref(&market_index)?.amm.order_step_size,
+    )?;
+
+    // Make sure liquidator enters at better than limit price
+    if let Some(limit_price) = limit_price {
+        match user.perp_positions[position_index].get_direction() {
+            PositionDirection::Long => validate!(
+                oracle_price <= limit_price.cast()?,
+                ErrorCode::LiquidationDoesntSatisfyLimitPrice,
+                "limit price ({}) > oracle price ({})",
+                limit_price,
+                oracle_price
+            )?,
+            PositionDirection::Short => validate!(
+                oracle_price >= limit_price.cast()?,
+                ErrorCode::LiquidationDoesntSatisfyLimitPrice,
+                "limit price ({}) < oracle price ({})",
+                limit_price,
+                oracle_price
+            )?,
+        }
+    }
+
+    let base_asset_value =
+        calculate_base_asset_value_with_oracle_price(base_asset_amount.cast()?, oracle_price)?
+            .cast::<u64>()?;
+
+    let liquidator_fee =
+        -calculate_perp_liquidation_fee(base_asset_value, liquidation_fee)?.cast::<i64>()?;
+
+    let if_fee =
+        -calculate_perp_liquidation_fee(base_asset_value, if_liquidation_fee)?.cast::<i64>()?;
+
+    user_stats.update_taker_volume_30d(base_asset_value, now)?;
+    liquidator_stats.update_maker_volume_30d(base_asset_value, now)?;
+
+    let user_position_delta = get_position_delta_for_fill(
+        base_asset_amount,
+        base_asset_value,
+        user.perp_positions[position_index].get_direction_to_close(),
+    )?;
+
+    let liquidator_position_delta = get_position_delta_for_fill(
+        base_asset_amount,
+        base_asset_value,
+        user.perp_positions[position_index].get_direction(),
+    )?;
+
+    let (
+        user_existing_position_direction,
+        user_position_direction_to_close,
+        liquidator_existing_position_direction,
+    ) = {
+        let mut market = perp_market_map.get_ref_mut(&market_index)?;
+
+        let user_position = user.get_perp_position_mut(market_index).unwrap();
+        let user_existing_position_direction = user_position.get_direction();
+        let user_position_direction_to_close = user_position.get_direction_to_close();
+        update_position_and_market(user_position, &mut market, &user_position_delta)?;
+        update_quote_asset_and_break_even_amount(user_position, &mut market, liquidator_fee)?;
+        update_quote_asset_and_break_even_amount(user_position, &mut market, if_fee)?;
+
+        let liquidator_position = liquidator
+            .force_get_perp_position_mut(market_index)
+            .unwrap();
+        let liquidator_existing_position_direction = liquidator_position.get_direction();
+        update_position_and_market(liquidator_position, &mut market, &liquidator_position_delta)?;
+        update_quote_asset_and_break_even_amount(
+            liquidator_position,
+            &mut market,
+            -liquidator_fee,
+        )?;
+
+        market.amm.total_liquidation_fee = market
+            .amm
+            .total_liquidation_fee
+            .safe_add(if_fee.unsigned_abs().cast()?)?;
+
+        (
+            user_existing_position_direction,
+            user_position_direction_to_close,
+            liquidator_existing_position_direction,
+        )
+    };
+
+    if base_asset_amount >= base_asset_amount_to_cover_margin_shortage {
+        user.is_being_liquidated = false;
+    } else {
+        user.is_bankrupt = is_user_bankrupt(user);
+    }
+
+    let liquidator_meets_initial_margin_requirement =
+        meets_initial_margin_requirement(liquidator, perp_market_map, spot_market_map, oracle_map)?;
+
+    validate!(
+        liquidator_meets_initial_margin_requirement,
+        ErrorCode::InsufficientCollateral,
+        "Liquidator doesnt have enough collateral to take over perp position"
+    )?;
+
+    // get ids for order fills
+    let user_order_id = get_then_update_id!(user, next_order_id);
+    let liquidator_order_id = get_then_update_id!(liquidator, next_order_id);
+    let fill_record_id = {
+        let mut market = perp_market_map.get_ref_mut(&market_index)?;
+        get_then_update_id!(market, next_fill_record_id)
+    };
+
+    let user_order = Order {
+        slot,
+        base_asset_amount,
+        order_id: user_order_id,
+        market_index,
+        status: OrderStatus::Open,
+        order_type: OrderType::Market,
+        market_type: MarketType::Perp,
+        direction: user_position_direction_to_close,
+        existing_position_direction: user_existing_position_direction,
+        ..Order::default()
+    };
+
+    emit!(OrderRecord {
+        ts: now,
+        user: *user_key,
+        order: user_order
+    });
+
+    let liquidator_order = Order {
+        slot,
+        price: if let Some(price) = limit_price {
+            price
+        } else {
+            0
+        },
+        base_asset_amount,
+        order_id: liquidator_order_id,
+        market_index,
+        status: OrderStatus::Open,
+        order_type: if limit_price.is_some() {
+            OrderType::Limit
+        } else {
+            OrderType::Market
+        },
+        market_type: MarketType::Perp,
+        direction: user_existing_position_direction,
+        existing_position_direction: liquidator_existing_position_direction,
+        ..Order::default()
+    };
+
+    emit!(OrderRecord {
+        ts: now,
+        user: *liquidator_key,
+        order: liquidator_order
+    });
+
+    let fill_record = OrderActionRecord {
+        ts: now,
+        action: OrderAction::Fill,
+        action_explanation: OrderActionExplanation::Liquidation,
+        market_index,
+        market_type: MarketType::Perp,
+        filler: None,
+        filler_reward: None,
+        fill_record_id: Some(fill_record_id),
+        base_asset_amount_filled: Some(base_asset_amount),
+        quote_asset_amount_filled: Some(base_asset_value),
+        taker_fee: Some(
+            liquidator_fee
+                .unsigned_abs()
+                .safe_add(if_fee.unsigned_abs())?,
+        ),
+        maker_fee: Some(liquidator_fee),
+        referrer_reward: None,
+        quote_asset_amount_surplus: None,
+        spot_fulfillment_method_fee: None,
+        taker: Some(*user_key),
+        taker_order_id: Some(user_order_id),
+        taker_order_direction: Some(user_position_direction_to_close),
+        taker_order_base_asset_amount: Some(base_asset_amount),
+        taker_order_cumulative_base_asset_amount_filled: Some(base_asset_amount),
+        taker_order_cumulative_quote_asset_amount_filled: Some(base_asset_value),
+        maker: Some(*liquidator_key),
+        maker_order_id: Some(liquidator_order_id),
+        maker_order_direction: Some(user_existing_position_direction),
+        maker_order_base_asset_amount: Some(base_asset_amount),
+        maker_order_cumulative_base_asset_amount_filled: Some(base_asset_amount),
+        maker_order_cumulative_quote_asset_amount_filled: Some(base_asset_value),
+        oracle_price,
+    };
+    emit!(fill_record);
+
+    emit!(LiquidationRecord {
+        ts: now,
+        liquidation_id,
+        liquidation_type: LiquidationType::LiquidatePerp,
+        user: *user_key,
+        liquidator: *liquidator_key,
+        margin_requirement,
+        total_collateral,
+        bankrupt: user.is_bankrupt,
+        canceled_order_ids,
+        liquidate_perp: LiquidatePerpRecord {
+            market_index,
+            oracle_price,
+            base_asset_amount: user_position_delta.base_asset_amount,
+            quote_asset_amount: user_position_delta.quote_asset_amount,
+            lp_shares,
+            user_order_id,
+            liquidator_order_id,
+            fill_record_id,
+            liquidator_fee: liquidator_fee.abs().cast()?,
+            if_fee: if_fee.abs().cast()?,
+        },
+        ..LiquidationRecord::default()
+    });
+
+    record_liquidation_history(
+        liquidation_history,
+        user,
+        LiquidationHistoryRecord {
+            ts: now,
+            slot,
+            price: oracle_price,
+            amount: base_asset_amount,
+            fee: liquidator_fee
+                .unsigned_abs()
+                .safe_add(if_fee.unsigned_abs())?,
+            market_index,
+            liquidation_type: LiquidationType::LiquidatePerp,
+            ..LiquidationHistoryRecord::default()
+        },
+    )?;
+
+    Ok(())
+}
+
+pub fn liquidate_perp_with_amm(
+    market_index: u16,
+    user: &mut User,
+    user_key: &Pubkey,
+    user_stats: &mut UserStats,
+    filler: &mut User,
+    filler_key: &Pubkey,
+    perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
+    oracle_map: &mut OracleMap,
+    slot: u64,
+    now: i64,
+    state: &State,
+    liquidation_history: Option<&mut UserLiquidationHistory>,
+) -> DriftResult {
+    {
+        let market = perp_market_map.get_ref(&market_index)?;
+        validate!(
+            !matches!(
+                market.status,
+                MarketStatus::AmmPaused | MarketStatus::Settlement | MarketStatus::Delisted
+            ),
+            ErrorCode::MarketActionPaused,
+            "amm unavailable to liquidate perp market {}",
+            market_index
+        )?;
+    }
+
+    let PerpLiquidation {
+        liquidation_id,
+        margin_requirement,
+        total_collateral,
+        canceled_order_ids,
+        position_index,
+        oracle_price,
+        lp_shares,
+        user_base_asset_amount,
+        base_asset_amount_to_cover_margin_shortage,
+        liquidation_fee,
+        if_liquidation_fee,
+    } = match start_perp_liquidation(
+        market_index,
+        user,
+        user_key,
+        filler_key,
+        perp_market_map,
+        spot_market_map,
+        oracle_map,
+        slot,
+        now,
+        state,
+        LiquidationType::LiquidatePerpWithAmm,
+    )? {
+        Some(perp_liquidation) => perp_liquidation,
+        None => return Ok(()),
+    };
+
+    let base_asset_amount = standardize_base_asset_amount(
+        user_base_asset_amount.min(base_asset_amount_to_cover_margin_shortage),
+        perp_market_map.get_ref(&market_index)?.amm.order_step_size,
+    )?;
+
+    validate!(
+        base_asset_amount != 0,
+        ErrorCode::InvalidBaseAssetAmountForLiquidatePerp,
+        "base asset amount to liquidate rounds down to 0"
+    )?;
+
+    let (
+        user_existing_position_direction,
+        user_position_direction_to_close,
+        quote_asset_amount,
+        user_position_delta,
+        liquidator_fee,
+        filler_reward,
+        if_fee,
+    ) = {
+        let mut market = perp_market_map.get_ref_mut(&market_index)?;
+
+        let user_existing_position_direction = user.perp_positions[position_index].get_direction();
+        let user_position_direction_to_close =
+            user.perp_positions[position_index].get_direction_to_close();
+
+        validate_amm_account_for_fill(&market.amm, user_position_direction_to_close)?;
+
+        // dont let the amm take on the position if it is already outside the price band
+        validate_market_within_price_band(&market, state, false, None)?;
+
+        let reserve_price_before = market.amm.reserve_price()?;
+        let oracle_reserve_price_spread_pct_before =
+            amm::calculate_oracle_twap_5min_mark_spread_pct(
+                &market.amm,
+                Some(reserve_price_before),
+            )?;
+
+        let market_side_price = match user_position_direction_to_close {
+            PositionDirection::Long => market.amm.ask_price(reserve_price_before)?,
+            PositionDirection::Short => market.amm.bid_price(reserve_price_before)?,
+        };
+
+        let sanitize_clamp_denominator = market.get_sanitize_clamp_denominator()?;
+        amm::update_mark_twap(
+            &mut market.amm,
+            now,
+            Some(market_side_price),
+            Some(user_position_direction_to_close),
+            sanitize_clamp_denominator,
+        )?;
+
+        let (quote_asset_amount, quote_asset_amount_surplus, _) =
+            update_position_with_base_asset_amount(
+                base_asset_amount,
+                user_position_direction_to_close,
+                &mut market,
+                user,
+                position_index,
+                None,
+            )?;
+
+        // nor push it outside the band with the fill
+        validate_market_within_price_band(
+            &market,
+            state,
+            false,
+            Some(oracle_reserve_price_spread_pct_before),
+        )?;
+
+        let user_position_delta = get_position_delta_for_fill(
+            base_asset_amount,
+            quote_asset_amount,
+            user_position_direction_to_close,
+        )?;
+
+        if market.amm.user_lp_shares > 0 {
+            update_lp_market_position(&mut market, &user_position_delta, 0)?;
+
+            let (new_terminal_quote_reserve, new_terminal_base_reserve) =
+                amm::calculate_terminal_reserves(&market.amm)?;
+            market.amm.terminal_quote_asset_reserve = new_terminal_quote_reserve;
+
+            let (min_base_asset_reserve, max_base_asset_reserve) = amm::calculate_bid_ask_bounds(
+                market.amm.concentration_coef,
+                new_terminal_base_reserve,
+            )?;
+            market.amm.min_base_asset_reserve = min_base_asset_reserve;
+            market.amm.max_base_asset_reserve = max_base_asset_reserve;
+        }
+
+        let liquidator_fee = calculate_perp_liquidation_fee(quote_asset_amount, liquidation_fee)?;
+        let if_fee = calculate_perp_liquidation_fee(quote_asset_amount, if_liquidation_fee)?;
+
+        // the amm is the liquidator, the liquidator fee less the filler reward goes to the insurance fund
+        let filler_reward_structure = &state.perp_fee_structure.filler_reward_structure;
+        let filler_reward = liquidator_fee
+            .safe_mul(filler_reward_structure.reward_numerator.cast()?)?
+            .safe_div(filler_reward_structure.reward_denominator.cast()?)?
+            .min(liquidator_fee);
+        let amm_fee = liquidator_fee.safe_sub(filler_reward)?;
+
+        update_quote_asset_and_break_even_amount(
+            &mut user.perp_positions[position_index],
+            &mut market,
+            -liquidator_fee.safe_add(if_fee)?.cast::<i64>()?,
+        )?;
+
+        if filler_reward > 0 {
+            let filler_position = filler.force_get_perp_position_mut(market_index)?;
+            update_quote_asset_amount(filler_position, &mut market, filler_reward.cast()?)?;
+        }
+
+        // same as liquidate_perp, the if fee is swept from the fee pool to the insurance fund.
+        // the amm keeps no share of the liquidator fee, so it is swept alongside the if fee
+        market.amm.total_liquidation_fee = market
+            .amm
+            .total_liquidation_fee
+            .safe_add(if_fee.safe_add(amm_fee)?.cast()?)?;
+
+        let amm_revenue = quote_asset_amount_surplus;
+        market.amm.total_fee = market.amm.total_fee.safe_add(amm_revenue.cast()?)?;
+        market.amm.total_mm_fee = market.amm.total_mm_fee.safe_add(amm_revenue.cast()?)?;
+        market.amm.total_fee_minus_distributions = market
+            .amm
+            .total_fee_minus_distributions
+            .safe_add(amm_revenue.cast()?)?;
+        market.amm.net_revenue_since_last_funding = market
+            .amm
+            .net_revenue_since_last_funding
+            .safe_add(amm_revenue)?;
+
+        (
+            user_existing_position_direction,
+            user_position_direction_to_close,
+            quote_asset_amount,
+            user_position_delta,
+            liquidator_fee,
+            filler_reward,
+            if_fee,
+        )
+    };
+
+    user_stats.update_taker_volume_30d(quote_asset_amount, now)?;
+
+    if base_asset_amount >= base_asset_amount_to_cover_margin_shortage {
+        user.is_being_liquidated = false;
+    } else {
+        user.is_bankrupt = is_user_bankrupt(user);
+    }
+
+    let user_order_id = get_then_update_id!(user, next_order_id);
+    let fill_record_id = {
+        let mut market = perp_market_map.get_ref_mut(&market_index)?;
+        get_then_update_id!(market, next_fill_record_id)
+    };
 
-    validate!(!user.is_bankrupt, ErrorCode::UserBankrupt, "user bankrupt",)?;
+    let user_order = Order {
+        slot,
+        base_asset_amount,
+        order_id: user_order_id,
+        market_index,
+        status: OrderStatus::Open,
+        order_type: OrderType::Market,
+        market_type: MarketType::Perp,
+        direction: user_position_direction_to_close,
+        existing_position_direction: user_existing_position_direction,
+        ..Order::default()
+    };
 
-    validate!(
-        !liquidator.is_bankrupt,
-        ErrorCode::UserBankrupt,
-        "liquidator bankrupt",
+    emit!(OrderRecord {
+        ts: now,
+        user: *user_key,
+        order: user_order
+    });
+
+    let fill_record = OrderActionRecord {
+        ts: now,
+        action: OrderAction::Fill,
+        action_explanation: OrderActionExplanation::Liquidation,
+        market_index,
+        market_type: MarketType::Perp,
+        filler: Some(*filler_key),
+        filler_reward: Some(filler_reward),
+        fill_record_id: Some(fill_record_id),
+        base_asset_amount_filled: Some(base_asset_amount),
+        quote_asset_amount_filled: Some(quote_asset_amount),
+        taker_fee: Some(liquidator_fee.safe_add(if_fee)?),
+        maker_fee: None,
+        referrer_reward: None,
+        quote_asset_amount_surplus: None,
+        spot_fulfillment_method_fee: None,
+        taker: Some(*user_key),
+        taker_order_id: Some(user_order_id),
+        taker_order_direction: Some(user_position_direction_to_close),
+        taker_order_base_asset_amount: Some(base_asset_amount),
+        taker_order_cumulative_base_asset_amount_filled: Some(base_asset_amount),
+        taker_order_cumulative_quote_asset_amount_filled: Some(quote_asset_amount),
+        maker: None,
+        maker_order_id: None,
+        maker_order_direction: None,
+        maker_order_base_asset_amount: None,
+        maker_order_cumulative_base_asset_amount_filled: None,
+        maker_order_cumulative_quote_asset_amount_filled: None,
+        oracle_price,
+    };
+    emit!(fill_record);
+
+    emit!(LiquidationRecord {
+        ts: now,
+        liquidation_id,
+        liquidation_type: LiquidationType::LiquidatePerpWithAmm,
+        user: *user_key,
+        liquidator: *filler_key,
+        margin_requirement,
+        total_collateral,
+        bankrupt: user.is_bankrupt,
+        canceled_order_ids,
+        liquidate_perp: LiquidatePerpRecord {
+            market_index,
+            oracle_price,
+            base_asset_amount: user_position_delta.base_asset_amount,
+            quote_asset_amount: user_position_delta.quote_asset_amount,
+            lp_shares,
+            user_order_id,
+            liquidator_order_id: 0,
+            fill_record_id,
+            liquidator_fee,
+            if_fee,
+        },
+        ..LiquidationRecord::default()
+    });
+
+    record_liquidation_history(
+        liquidation_history,
+        user,
+        LiquidationHistoryRecord {
+            ts: now,
+            slot,
+            price: oracle_price,
+            amount: base_asset_amount,
+            fee: liquidator_fee.safe_add(if_fee)?,
+            market_index,
+            liquidation_type: LiquidationType::LiquidatePerpWithAmm,
+            ..LiquidationHistoryRecord::default()
+        },
     )?;
 
+    Ok(())
+}
+
+// a perp liquidation that still has base to transfer after orders were canceled and lp shares burned
+struct PerpLiquidation {
+    liquidation_id: u16,
+    margin_requirement: u128,
+    total_collateral: i128,
+    canceled_order_ids: Vec<u32>,
+    position_index: usize,
+    oracle_price: i64,
+    lp_shares: u64,
+    user_base_asset_amount: u64,
+    base_asset_amount_to_cover_margin_shortage: u64,
+    liquidation_fee: u32,
+    if_liquidation_fee: u32,
+}
+
+// shared start of liquidate_perp and liquidate_perp_with_amm. returns None once there is nothing left
+// to transfer, either because the user exited liquidation or has no base
+#[allow(clippy::too_many_arguments)]
+fn start_perp_liquidation(
+    market_index: u16,
+    user: &mut User,
+    user_key: &Pubkey,
+    liquidator_key: &Pubkey,
+    perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
+    oracle_map: &mut OracleMap,
+    slot: u64,
+    now: i64,
+    state: &State,
+    liquidation_type: LiquidationType,
+) -> DriftResult<Option<PerpLiquidation>> {
+    let liquidation_margin_buffer_ratio = state.liquidation_margin_buffer_ratio;
+
+    validate!(!user.is_bankrupt, ErrorCode::UserBankrupt, "user bankrupt",)?;
+
     user.get_perp_position(market_index).map_err(|e| {
         msg!(
             "User does not have a position for perp market {}",
@@ -92,16 +733,6 @@ pub fn liquidate_perp(
         e
     })?;
 
-    liquidator
-        .force_get_perp_position_mut(market_index)
-        .map_err(|e| {
-            msg!(
-                "Liquidator has no available positions to take on perp position in market {}",
-                market_index
-            );
-            e
-        })?;
-
     // Settle user's funding payments so that collateral is up to date
     settle_funding_payment(
         user,
@@ -110,14 +741,6 @@ pub fn liquidate_perp(
         now,
     )?;
 
-    // Settle user's funding payments so that collateral is up to date
-    settle_funding_payment(
-        liquidator,
-        liquidator_key,
-        perp_market_map.get_ref_mut(&market_index)?.deref_mut(),
-        now,
-    )?;
-
     let (margin_requirement, total_collateral, margin_requirement_plus_buffer, _) =
         calculate_margin_requirement_and_total_collateral(
             user,
@@ -134,10 +757,10 @@ pub fn liquidate_perp(
         && total_collateral >= margin_requirement_plus_buffer.cast()?
     {
         user.is_being_liquidated = false;
-        return Ok(());
+        return Ok(None);
     }
 
-    let liquidation_id = set_being_liquidated_and_get_liquidation_id(user)?;
+    let liquidation_id = set_being_liquidated_and_get_liquidation_id(user, slot)?;
 
     let position_index = get_position_index(&user.perp_positions, market_index)?;
     validate!(
@@ -185,12 +808,25 @@ pub fn liquidate_perp(
     // burning lp shares = removing open bids/asks
     let lp_shares = user.perp_positions[position_index].lp_shares;
     if lp_shares > 0 {
-        burn_lp_shares(
+        // burning settles the lp's unsettled position before the shares are removed
+        let (position_delta, pnl, fee) = burn_lp_shares(
             &mut user.perp_positions[position_index],
             perp_market_map.get_ref_mut(&market_index)?.deref_mut(),
             lp_shares,
             oracle_price,
         )?;
+
+        emit!(LPRecord {
+            ts: now,
+            action: LPAction::RemoveLiquidityDerisk,
+            user: *user_key,
+            n_shares: lp_shares,
+            market_index,
+            delta_base_asset_amount: position_delta.base_asset_amount,
+            delta_quote_asset_amount: position_delta.quote_asset_amount,
+            pnl,
+            fee,
+        });
     }
 
     // check if user exited liquidation territory
@@ -212,7 +848,7 @@ pub fn liquidate_perp(
                 emit!(LiquidationRecord {
                     ts: now,
                     liquidation_id,
-                    liquidation_type: LiquidationType::LiquidatePerp,
+                    liquidation_type,
                     user: *user_key,
                     liquidator: *liquidator_key,
                     margin_requirement,
@@ -229,7 +865,7 @@ pub fn liquidate_perp(
                 });
 
                 user.is_being_liquidated = false;
-                return Ok(());
+                return Ok(None);
             }
 
             (
@@ -241,278 +877,65 @@ pub fn liquidate_perp(
         };
 
     if user.perp_positions[position_index].base_asset_amount == 0 {
-        msg!("User has no base asset amount");
-        return Ok(());
-    }
-
-    validate!(
-        liquidator_max_base_asset_amount != 0,
-        ErrorCode::InvalidBaseAssetAmountForLiquidatePerp,
-        "liquidator_max_base_asset_amount cant be 0"
-    )?;
-
-    let user_base_asset_amount = user.perp_positions[position_index]
-        .base_asset_amount
-        .unsigned_abs();
-
-    let worst_case_base_asset_amount =
-        user.perp_positions[position_index].worst_case_base_asset_amount()?;
-
-    let margin_ratio = perp_market_map.get_ref(&market_index)?.get_margin_ratio(
-        worst_case_base_asset_amount.unsigned_abs(),
-        MarginRequirementType::Maintenance,
-    )?;
-
-    let margin_ratio_with_buffer = margin_ratio.safe_add(liquidation_margin_buffer_ratio)?;
-
-    let margin_shortage = intermediate_margin_requirement_with_buffer
-        .cast::<i128>()?
-        .safe_sub(intermediate_total_collateral)?
-        .unsigned_abs();
-
-    let market = perp_market_map.get_ref(&market_index)?;
-    let liquidation_fee = market.liquidator_fee;
-    let if_liquidation_fee = market.if_liquidation_fee;
-    let base_asset_amount_to_cover_margin_shortage = standardize_base_asset_amount(
-        calculate_base_asset_amount_to_cover_margin_shortage(
-            margin_shortage,
-            margin_ratio_with_buffer,
-            liquidation_fee,
-            if_liquidation_fee,
-            oracle_price,
-        )?,
-        market.amm.order_step_size,
-    )?;
-    drop(market);
-
-    let base_asset_amount = user_base_asset_amount
-        .min(liquidator_max_base_asset_amount)
-        .min(base_asset_amount_to_cover_margin_shortage);
-    let base_asset_amount = standardize_base_asset_amount(
-        base_asset_amount,
-        perp_market_map.get_ref(&market_index)?.amm.order_step_size,
-    )?;
-
-    // Make sure liquidator enters at better than limit price
-    if let Some(limit_price) = limit_price {
-        match user.perp_positions[position_index].get_direction() {
-            PositionDirection::Long => validate!(
-                oracle_price <= limit_price.cast()?,
-                ErrorCode::LiquidationDoesntSatisfyLimitPrice,
-                "limit price ({}) > oracle price ({})",
-                limit_price,
-                oracle_price
-            )?,
-            PositionDirection::Short => validate!(
-                oracle_price >= limit_price.cast()?,
-                ErrorCode::LiquidationDoesntSatisfyLimitPrice,
-                "limit price ({}) < oracle price ({})",
-                limit_price,
-                oracle_price
-            )?,
-        }
-    }
-
-    let base_asset_value =
-        calculate_base_asset_value_with_oracle_price(base_asset_amount.cast()?, oracle_price)?
-            .cast::<u64>()?;
-
-    let liquidator_fee = -base_asset_value
-        .cast::<u128>()?
-        .safe_mul(liquidation_fee.cast()?)?
-        .safe_div(LIQUIDATION_FEE_PRECISION_U128)?
-        .cast::<i64>()?;
-
-    let if_fee = -base_asset_value
-        .cast::<u128>()?
-        .safe_mul(if_liquidation_fee.cast()?)?
-        .safe_div(LIQUIDATION_FEE_PRECISION_U128)?
-        .cast::<i64>()?;
-
-    user_stats.update_taker_volume_30d(base_asset_value, now)?;
-    liquidator_stats.update_maker_volume_30d(base_asset_value, now)?;
-
-    let user_position_delta = get_position_delta_for_fill(
-        base_asset_amount,
-        base_asset_value,
-        user.perp_positions[position_index].get_direction_to_close(),
-    )?;
-
-    let liquidator_position_delta = get_position_delta_for_fill(
-        base_asset_amount,
-        base_asset_value,
-        user.perp_positions[position_index].get_direction(),
-    )?;
-
-    let (
-        user_existing_position_direction,
-        user_position_direction_to_close,
-        liquidator_existing_position_direction,
-    ) = {
-        let mut market = perp_market_map.get_ref_mut(&market_index)?;
-
-        let user_position = user.get_perp_position_mut(market_index).unwrap();
-        let user_existing_position_direction = user_position.get_direction();
-        let user_position_direction_to_close = user_position.get_direction_to_close();
-        update_position_and_market(user_position, &mut market, &user_position_delta)?;
-        update_quote_asset_and_break_even_amount(user_position, &mut market, liquidator_fee)?;
-        update_quote_asset_and_break_even_amount(user_position, &mut market, if_fee)?;
-
-        let liquidator_position = liquidator
-            .force_get_perp_position_mut(market_index)
-            .unwrap();
-        let liquidator_existing_position_direction = liquidator_position.get_direction();
-        update_position_and_market(liquidator_position, &mut market, &liquidator_position_delta)?;
-        update_quote_asset_and_break_even_amount(
-            liquidator_position,
-            &mut market,
-            -liquidator_fee,
-        )?;
-
-        market.amm.total_liquidation_fee = market
-            .amm
-            .total_liquidation_fee
-            .safe_add(if_fee.unsigned_abs().cast()?)?;
-
-        (
-            user_existing_position_direction,
-            user_position_direction_to_close,
-            liquidator_existing_position_direction,
-        )
-    };
-
-    if base_asset_amount >= base_asset_amount_to_cover_margin_shortage {
-        user.is_being_liquidated = false;
-    } else {
-        user.is_bankrupt = is_user_bankrupt(user);
-    }
-
-    let liquidator_meets_initial_margin_requirement =
-        meets_initial_margin_requirement(liquidator, perp_market_map, spot_market_map, oracle_map)?;
-
-    validate!(
-        liquidator_meets_initial_margin_requirement,
-        ErrorCode::InsufficientCollateral,
-        "Liquidator doesnt have enough collateral to take over perp position"
-    )?;
+        msg!("User has no base asset amount");
+        return Ok(None);
+    }
 
-    // get ids for order fills
-    let user_order_id = get_then_update_id!(user, next_order_id);
-    let liquidator_order_id = get_then_update_id!(liquidator, next_order_id);
-    let fill_record_id = {
-        let mut market = perp_market_map.get_ref_mut(&market_index)?;
-        get_then_update_id!(market, next_fill_record_id)
-    };
+    let user_base_asset_amount = user.perp_positions[position_index]
+        .base_asset_amount
+        .unsigned_abs();
 
-    let user_order = Order {
-        slot,
-        base_asset_amount,
-        order_id: user_order_id,
-        market_index,
-        status: OrderStatus::Open,
-        order_type: OrderType::Market,
-        market_type: MarketType::Perp,
-        direction: user_position_direction_to_close,
-        existing_position_direction: user_existing_position_direction,
-        ..Order::default()
-    };
+    let worst_case_base_asset_amount =
+        user.perp_positions[position_index].worst_case_base_asset_amount()?;
 
-    emit!(OrderRecord {
-        ts: now,
-        user: *user_key,
-        order: user_order
-    });
+    let margin_ratio = perp_market_map.get_ref(&market_index)?.get_margin_ratio(
+        worst_case_base_asset_amount.unsigned_abs(),
+        MarginRequirementType::Maintenance,
+    )?;
 
-    let liquidator_order = Order {
-        slot,
-        price: if let Some(price) = limit_price {
-            price
-        } else {
-            0
-        },
-        base_asset_amount,
-        order_id: liquidator_order_id,
-        market_index,
-        status: OrderStatus::Open,
-        order_type: if limit_price.is_some() {
-            OrderType::Limit
-        } else {
-            OrderType::Market
-        },
-        market_type: MarketType::Perp,
-        direction: user_existing_position_direction,
-        existing_position_direction: liquidator_existing_position_direction,
-        ..Order::default()
-    };
+    let margin_ratio_with_buffer = margin_ratio.safe_add(liquidation_margin_buffer_ratio)?;
 
-    emit!(OrderRecord {
-        ts: now,
-        user: *liquidator_key,
-        order: liquidator_order
-    });
+    let margin_shortage = intermediate_margin_requirement_with_buffer
+        .cast::<i128>()?
+        .safe_sub(intermediate_total_collateral)?
+        .unsigned_abs();
 
-    let fill_record = OrderActionRecord {
-        ts: now,
-        action: OrderAction::Fill,
-        action_explanation: OrderActionExplanation::Liquidation,
-        market_index,
-        market_type: MarketType::Perp,
-        filler: None,
-        filler_reward: None,
-        fill_record_id: Some(fill_record_id),
-        base_asset_amount_filled: Some(base_asset_amount),
-        quote_asset_amount_filled: Some(base_asset_value),
-        taker_fee: Some(
-            liquidator_fee
-                .unsigned_abs()
-                .safe_add(if_fee.unsigned_abs())?,
-        ),
-        maker_fee: Some(liquidator_fee),
-        referrer_reward: None,
-        quote_asset_amount_surplus: None,
-        spot_fulfillment_method_fee: None,
-        taker: Some(*user_key),
-        taker_order_id: Some(user_order_id),
-        taker_order_direction: Some(user_position_direction_to_close),
-        taker_order_base_asset_amount: Some(base_asset_amount),
-        taker_order_cumulative_base_asset_amount_filled: Some(base_asset_amount),
-        taker_order_cumulative_quote_asset_amount_filled: Some(base_asset_value),
-        maker: Some(*liquidator_key),
-        maker_order_id: Some(liquidator_order_id),
-        maker_order_direction: Some(user_existing_position_direction),
-        maker_order_base_asset_amount: Some(base_asset_amount),
-        maker_order_cumulative_base_asset_amount_filled: Some(base_asset_amount),
-        maker_order_cumulative_quote_asset_amount_filled: Some(base_asset_value),
-        oracle_price,
-    };
-    emit!(fill_record);
+    let market = perp_market_map.get_ref(&market_index)?;
+    let liquidation_fee =
+        market.get_liquidator_fee(user.get_slots_since_liquidation_start(slot)?)?;
+    let if_liquidation_fee = market.if_liquidation_fee;
+    let base_asset_amount_to_cover_margin_shortage = standardize_base_asset_amount(
+        calculate_base_asset_amount_to_cover_margin_shortage(
+            margin_shortage,
+            margin_ratio_with_buffer,
+            liquidation_fee,
+            if_liquidation_fee,
+            oracle_price,
+        )?,
+        market.amm.order_step_size,
+    )?;
 
-    emit!(LiquidationRecord {
-        ts: now,
+    Ok(Some(PerpLiquidation {
         liquidation_id,
-        liquidation_type: LiquidationType::LiquidatePerp,
-        user: *user_key,
-        liquidator: *liquidator_key,
         margin_requirement,
         total_collateral,
-        bankrupt: user.is_bankrupt,
         canceled_order_ids,
-        liquidate_perp: LiquidatePerpRecord {
-            market_index,
-            oracle_price,
-            base_asset_amount: user_position_delta.base_asset_amount,
-            quote_asset_amount: user_position_delta.quote_asset_amount,
-            lp_shares,
-            user_order_id,
-            liquidator_order_id,
-            fill_record_id,
-            liquidator_fee: liquidator_fee.abs().cast()?,
-            if_fee: if_fee.abs().cast()?,
-        },
-        ..LiquidationRecord::default()
-    });
+        position_index,
+        oracle_price,
+        lp_shares,
+        user_base_asset_amount,
+        base_asset_amount_to_cover_margin_shortage,
+        liquidation_fee,
+        if_liquidation_fee,
+    }))
+}
 
-    Ok(())
+fn calculate_perp_liquidation_fee(quote_asset_amount: u64, fee: u32) -> DriftResult<u64> {
+    quote_asset_amount
+        .cast::<u128>()?
+        .safe_mul(fee.cast()?)?
+        .safe_div(LIQUIDATION_FEE_PRECISION_U128)?
+        .cast::<u64>()
 }
 
 pub fn liquidate_spot(
@@ -529,6 +952,7 @@ pub fn liquidate_spot(
     now: i64,
     slot: u64,
     liquidation_margin_buffer_ratio: u32,
+    liquidation_history: Option<&mut UserLiquidationHistory>,
 ) -> DriftResult {
     validate!(!user.is_bankrupt, ErrorCode::UserBankrupt, "user bankrupt",)?;
 
@@ -570,6 +994,8 @@ pub fn liquidate_spot(
             e
         })?;
 
+    let slots_since_liquidation_start = user.get_slots_since_liquidation_start(slot)?;
+
     let (asset_amount, asset_price, asset_decimals, asset_weight, asset_liquidation_multiplier) = {
         let mut asset_market = spot_market_map.get_ref_mut(&asset_market_index)?;
         let (asset_price_data, validity_guard_rails) =
@@ -600,7 +1026,7 @@ pub fn liquidate_spot(
             asset_market.decimals,
             asset_market.maintenance_asset_weight,
             calculate_liquidation_multiplier(
-                asset_market.liquidator_fee,
+                asset_market.get_liquidator_fee(slots_since_liquidation_start)?,
                 LiquidationMultiplierType::Premium,
             )?,
         )
@@ -644,7 +1070,7 @@ pub fn liquidate_spot(
             liability_market.decimals,
             liability_market.maintenance_liability_weight,
             calculate_liquidation_multiplier(
-                liability_market.liquidator_fee,
+                liability_market.get_liquidator_fee(slots_since_liquidation_start)?,
                 LiquidationMultiplierType::Discount,
             )?,
             liability_market.if_liquidation_fee,
@@ -670,7 +1096,7 @@ pub fn liquidate_spot(
         return Ok(());
     }
 
-    let liquidation_id = set_being_liquidated_and_get_liquidation_id(user)?;
+    let liquidation_id = set_being_liquidated_and_get_liquidation_id(user, slot)?;
 
     let canceled_order_ids = orders::cancel_orders(
         user,
@@ -874,6 +1300,148 @@ pub fn liquidate_spot(
         ..LiquidationRecord::default()
     });
 
+    record_liquidation_history(
+        liquidation_history,
+        user,
+        LiquidationHistoryRecord {
+            ts: now,
+            slot,
+            price: liability_price,
+            amount: liability_transfer.cast()?,
+            fee: if_fee.cast()?,
+            market_index: liability_market_index,
+            liquidation_type: LiquidationType::LiquidateSpot,
+            ..LiquidationHistoryRecord::default()
+        },
+    )?;
+
+    Ok(())
+}
+
+pub fn liquidate_perp_with_backstop(
+    market_index: u16,
+    liquidator_max_base_asset_amount: u64,
+    limit_price: Option<u64>,
+    user: &mut User,
+    user_key: &Pubkey,
+    user_stats: &mut UserStats,
+    backstop_vault: &BackstopVault,
+    backstop_user: &mut User,
+    backstop_user_key: &Pubkey,
+    backstop_user_stats: &mut UserStats,
+    perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
+    oracle_map: &mut OracleMap,
+    slot: u64,
+    now: i64,
+    state: &State,
+    liquidation_history: Option<&mut UserLiquidationHistory>,
+) -> DriftResult {
+    if !user.is_being_liquidated {
+        return flag_user_for_backstop_liquidation(
+            user,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            slot,
+        );
+    }
+
+    backstop_vault.validate_can_liquidate(user, slot)?;
+
+    liquidate_perp(
+        market_index,
+        liquidator_max_base_asset_amount,
+        limit_price,
+        user,
+        user_key,
+        user_stats,
+        backstop_user,
+        backstop_user_key,
+        backstop_user_stats,
+        perp_market_map,
+        spot_market_map,
+        oracle_map,
+        slot,
+        now,
+        state,
+        liquidation_history,
+    )
+}
+
+pub fn liquidate_spot_with_backstop(
+    asset_market_index: u16,
+    liability_market_index: u16,
+    liquidator_max_liability_transfer: u128,
+    user: &mut User,
+    user_key: &Pubkey,
+    backstop_vault: &BackstopVault,
+    backstop_user: &mut User,
+    backstop_user_key: &Pubkey,
+    perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
+    oracle_map: &mut OracleMap,
+    now: i64,
+    slot: u64,
+    liquidation_margin_buffer_ratio: u32,
+    liquidation_history: Option<&mut UserLiquidationHistory>,
+) -> DriftResult {
+    if !user.is_being_liquidated {
+        return flag_user_for_backstop_liquidation(
+            user,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            slot,
+        );
+    }
+
+    backstop_vault.validate_can_liquidate(user, slot)?;
+
+    liquidate_spot(
+        asset_market_index,
+        liability_market_index,
+        liquidator_max_liability_transfer,
+        user,
+        user_key,
+        backstop_user,
+        backstop_user_key,
+        perp_market_map,
+        spot_market_map,
+        oracle_map,
+        now,
+        slot,
+        liquidation_margin_buffer_ratio,
+        liquidation_history,
+    )
+}
+
+// starts the backstop delay for an undercollateralized user, giving external liquidators the first chance
+fn flag_user_for_backstop_liquidation(
+    user: &mut User,
+    perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
+    oracle_map: &mut OracleMap,
+    slot: u64,
+) -> DriftResult {
+    validate!(!user.is_bankrupt, ErrorCode::UserBankrupt, "user bankrupt",)?;
+
+    let (margin_requirement, total_collateral, _, _) =
+        calculate_margin_requirement_and_total_collateral(
+            user,
+            perp_market_map,
+            MarginRequirementType::Maintenance,
+            spot_market_map,
+            oracle_map,
+            None,
+        )?;
+
+    if total_collateral >= margin_requirement.cast()? {
+        return Err(ErrorCode::SufficientCollateral);
+    }
+
+    set_being_liquidated_and_get_liquidation_id(user, slot)?;
+
     Ok(())
 }
 
@@ -891,6 +1459,7 @@ pub fn liquidate_borrow_for_perp_pnl(
     now: i64,
     slot: u64,
     liquidation_margin_buffer_ratio: u32,
+    liquidation_history: Option<&mut UserLiquidationHistory>,
 ) -> DriftResult {
     // liquidator takes over a user borrow in exchange for that user's positive perpetual pnl
     // can only be done once a user's perpetual position size is 0
@@ -949,6 +1518,8 @@ pub fn liquidate_borrow_for_perp_pnl(
         now,
     )?;
 
+    let slots_since_liquidation_start = user.get_slots_since_liquidation_start(slot)?;
+
     let (pnl, quote_price, quote_decimals, pnl_asset_weight, pnl_liquidation_multiplier) = {
         let user_position = user.get_perp_position(perp_market_index).unwrap();
 
@@ -988,7 +1559,7 @@ pub fn liquidate_borrow_for_perp_pnl(
             6_u32,
             pnl_asset_weight,
             calculate_liquidation_multiplier(
-                market.liquidator_fee,
+                market.get_liquidator_fee(slots_since_liquidation_start)?,
                 LiquidationMultiplierType::Premium,
             )?,
         )
@@ -1029,7 +1600,7 @@ pub fn liquidate_borrow_for_perp_pnl(
             liability_market.decimals,
             liability_market.maintenance_liability_weight,
             calculate_liquidation_multiplier(
-                liability_market.liquidator_fee,
+                liability_market.get_liquidator_fee(slots_since_liquidation_start)?,
                 LiquidationMultiplierType::Discount,
             )?,
         )
@@ -1054,7 +1625,7 @@ pub fn liquidate_borrow_for_perp_pnl(
         return Ok(());
     }
 
-    let liquidation_id = set_being_liquidated_and_get_liquidation_id(user)?;
+    let liquidation_id = set_being_liquidated_and_get_liquidation_id(user, slot)?;
 
     let canceled_order_ids = orders::cancel_orders(
         user,
@@ -1243,6 +1814,21 @@ pub fn liquidate_borrow_for_perp_pnl(
         ..LiquidationRecord::default()
     });
 
+    record_liquidation_history(
+        liquidation_history,
+        user,
+        LiquidationHistoryRecord {
+            ts: now,
+            slot,
+            price: liability_price,
+            amount: liability_transfer.cast()?,
+            fee: 0,
+            market_index: liability_market_index,
+            liquidation_type: LiquidationType::LiquidateBorrowForPerpPnl,
+            ..LiquidationHistoryRecord::default()
+        },
+    )?;
+
     Ok(())
 }
 
@@ -1260,6 +1846,7 @@ pub fn liquidate_perp_pnl_for_deposit(
     now: i64,
     slot: u64,
     liquidation_margin_buffer_ratio: u32,
+    liquidation_history: Option<&mut UserLiquidationHistory>,
 ) -> DriftResult {
     // liquidator takes over remaining negative perpetual pnl in exchange for a user deposit
     // can only be done once the perpetual position's size is 0
@@ -1317,6 +1904,8 @@ pub fn liquidate_perp_pnl_for_deposit(
         now,
     )?;
 
+    let slots_since_liquidation_start = user.get_slots_since_liquidation_start(slot)?;
+
     let (asset_amount, asset_price, asset_decimals, asset_weight, asset_liquidation_multiplier) = {
         let mut asset_market = spot_market_map.get_ref_mut(&asset_market_index)?;
         let (asset_price_data, validity_guard_rails) =
@@ -1347,7 +1936,7 @@ pub fn liquidate_perp_pnl_for_deposit(
             asset_market.decimals,
             asset_market.maintenance_asset_weight,
             calculate_liquidation_multiplier(
-                asset_market.liquidator_fee,
+                asset_market.get_liquidator_fee(slots_since_liquidation_start)?,
                 LiquidationMultiplierType::Premium,
             )?,
         )
@@ -1395,7 +1984,7 @@ pub fn liquidate_perp_pnl_for_deposit(
             6_u32,
             SPOT_WEIGHT_PRECISION,
             calculate_liquidation_multiplier(
-                market.liquidator_fee,
+                market.get_liquidator_fee(slots_since_liquidation_start)?,
                 LiquidationMultiplierType::Discount,
             )?,
         )
@@ -1420,7 +2009,7 @@ pub fn liquidate_perp_pnl_for_deposit(
         return Ok(());
     }
 
-    let liquidation_id = set_being_liquidated_and_get_liquidation_id(user)?;
+    let liquidation_id = set_being_liquidated_and_get_liquidation_id(user, slot)?;
 
     let canceled_order_ids = orders::cancel_orders(
         user,
@@ -1609,13 +2198,46 @@ pub fn liquidate_perp_pnl_for_deposit(
         ..LiquidationRecord::default()
     });
 
+    record_liquidation_history(
+        liquidation_history,
+        user,
+        LiquidationHistoryRecord {
+            ts: now,
+            slot,
+            price: market_oracle_price,
+            amount: pnl_transfer.cast()?,
+            fee: 0,
+            market_index: perp_market_index,
+            liquidation_type: LiquidationType::LiquidatePerpPnlForDeposit,
+            ..LiquidationHistoryRecord::default()
+        },
+    )?;
+
+    Ok(())
+}
+
+pub fn record_liquidation_history(
+    liquidation_history: Option<&mut UserLiquidationHistory>,
+    user: &mut User,
+    record: LiquidationHistoryRecord,
+) -> DriftResult {
+    if let Some(liquidation_history) = liquidation_history {
+        let now = record.ts;
+        liquidation_history.add_record(LiquidationHistoryRecord {
+            liquidation_start_slot: user.liquidation_start_slot,
+            ..record
+        })?;
+        user.liquidation_margin_ratio = liquidation_history.get_liquidation_margin_ratio(now)?;
+    }
+
     Ok(())
 }
 
-pub fn set_being_liquidated_and_get_liquidation_id(user: &mut User) -> DriftResult<u16> {
+pub fn set_being_liquidated_and_get_liquidation_id(user: &mut User, slot: u64) -> DriftResult<u16> {
     let liquidation_id = if user.is_being_liquidated {
         user.next_liquidation_id.safe_sub(1)?
     } else {
+        user.liquidation_start_slot = slot;
         get_then_update_id!(user, next_liquidation_id)
     };
     user.is_being_liquidated = true;
@@ -1634,7 +2256,8 @@ pub fn resolve_perp_bankruptcy(
     oracle_map: &mut OracleMap,
     now: i64,
     insurance_fund_vault_balance: u64,
-) -> DriftResult<u64> {
+    perp_market_insurance_fund_vault_balance: u64,
+) -> DriftResult<(u64, u64)> {
     validate!(
         user.is_bankrupt,
         ErrorCode::UserNotBankrupt,
@@ -1683,11 +2306,22 @@ pub fn resolve_perp_bankruptcy(
             None,
         )?;
 
-    // spot market's insurance fund draw attempt here (before social loss)
+    // perp market's own insurance fund is drawn first, then the spot market's insurance fund (before social loss)
     // subtract 1 from available insurance_fund_vault_balance so deposits in insurance vault always remains >= 1
 
-    let if_payment = {
+    let (perp_market_if_payment, if_payment) = {
         let mut perp_market = perp_market_map.get_ref_mut(&market_index)?;
+
+        let perp_market_if_payment = if perp_market.has_insurance_fund() {
+            loss.unsigned_abs().min(
+                perp_market_insurance_fund_vault_balance
+                    .saturating_sub(1)
+                    .cast()?,
+            )
+        } else {
+            0
+        };
+
         let max_insurance_withdraw = perp_market
             .insurance_claim
             .quote_max_insurance
@@ -1696,6 +2330,7 @@ pub fn resolve_perp_bankruptcy(
 
         let if_payment = loss
             .unsigned_abs()
+            .safe_sub(perp_market_if_payment)?
             .min(insurance_fund_vault_balance.saturating_sub(1).cast()?)
             .min(max_insurance_withdraw);
 
@@ -1710,17 +2345,19 @@ pub fn resolve_perp_bankruptcy(
         update_spot_market_cumulative_interest(spot_market, Some(oracle_price_data), now)?;
 
         update_spot_balances(
-            if_payment,
+            perp_market_if_payment.safe_add(if_payment)?,
             &SpotBalanceType::Deposit,
             spot_market,
             &mut perp_market.pnl_pool,
             false,
         )?;
 
-        if_payment
+        (perp_market_if_payment, if_payment)
     };
 
-    let loss_to_socialize = loss.safe_add(if_payment.cast::<i128>()?)?;
+    let loss_to_socialize = loss
+        .safe_add(perp_market_if_payment.cast::<i128>()?)?
+        .safe_add(if_payment.cast::<i128>()?)?;
 
     let cumulative_funding_rate_delta = calculate_funding_rate_deltas_to_resolve_bankruptcy(
         loss_to_socialize,
@@ -1775,6 +2412,7 @@ pub fn resolve_perp_bankruptcy(
         perp_bankruptcy: PerpBankruptcyRecord {
             market_index,
             if_payment,
+            perp_market_if_payment,
             pnl: loss,
             clawback_user: None,
             clawback_user_payment: None,
@@ -1783,7 +2421,7 @@ pub fn resolve_perp_bankruptcy(
         ..LiquidationRecord::default()
     });
 
-    if_payment.cast()
+    Ok((perp_market_if_payment.cast()?, if_payment.cast()?))
 }
 
 pub fn resolve_spot_bankruptcy(
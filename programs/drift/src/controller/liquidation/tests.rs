@@ -12,9 +12,10 @@ pub mod liquidate_perp {
     use crate::error::ErrorCode;
     use crate::math::constants::{
         AMM_RESERVE_PRECISION, BASE_PRECISION_I128, BASE_PRECISION_I64, BASE_PRECISION_U64,
-        LIQUIDATION_FEE_PRECISION, MARGIN_PRECISION, MARGIN_PRECISION_U128, PEG_PRECISION,
-        PRICE_PRECISION_U64, QUOTE_PRECISION, QUOTE_PRECISION_I128, QUOTE_PRECISION_I64,
-        SPOT_BALANCE_PRECISION_U64, SPOT_CUMULATIVE_INTEREST_PRECISION, SPOT_WEIGHT_PRECISION,
+        LIQUIDATION_FEE_PRECISION, MARGIN_PRECISION, MARGIN_PRECISION_U128, MAX_SPOT_POSITIONS,
+        PEG_PRECISION, PRICE_PRECISION_U64, QUOTE_PRECISION, QUOTE_PRECISION_I128,
+        QUOTE_PRECISION_I64, SPOT_BALANCE_PRECISION_U64, SPOT_CUMULATIVE_INTEREST_PRECISION,
+        SPOT_WEIGHT_PRECISION,
     };
     use crate::math::liquidation::is_user_being_liquidated;
     use crate::math::margin::{
@@ -111,7 +112,7 @@ pub mod liquidate_perp {
                 open_bids: BASE_PRECISION_I64,
                 ..PerpPosition::default()
             }),
-            spot_positions: [SpotPosition::default(); 8],
+            spot_positions: [SpotPosition::default(); MAX_SPOT_POSITIONS as usize],
 
             ..User::default()
         };
@@ -151,6 +152,7 @@ pub mod liquidate_perp {
             slot,
             now,
             &state,
+            None,
         )
         .unwrap();
 
@@ -253,7 +255,7 @@ pub mod liquidate_perp {
                 open_asks: -BASE_PRECISION_I64,
                 ..PerpPosition::default()
             }),
-            spot_positions: [SpotPosition::default(); 8],
+            spot_positions: [SpotPosition::default(); MAX_SPOT_POSITIONS as usize],
 
             ..User::default()
         };
@@ -293,6 +295,7 @@ pub mod liquidate_perp {
             slot,
             now,
             &state,
+            None,
         )
         .unwrap();
 
@@ -437,6 +440,7 @@ pub mod liquidate_perp {
             slot,
             now,
             &state,
+            None,
         )
         .unwrap();
 
@@ -525,7 +529,7 @@ pub mod liquidate_perp {
                 open_bids: BASE_PRECISION_I64,
                 ..PerpPosition::default()
             }),
-            spot_positions: [SpotPosition::default(); 8],
+            spot_positions: [SpotPosition::default(); MAX_SPOT_POSITIONS as usize],
 
             ..User::default()
         };
@@ -565,6 +569,7 @@ pub mod liquidate_perp {
             slot,
             now,
             &state,
+            None,
         )
         .unwrap();
 
@@ -712,6 +717,7 @@ pub mod liquidate_perp {
             slot,
             now,
             &state,
+            None,
         )
         .unwrap();
 
@@ -871,83 +877,959 @@ pub mod liquidate_perp {
             market_to_edit.imf_factor *= 10;
         }
 
-        let (margin_req2, _, _, _) = calculate_margin_requirement_and_total_collateral(
-            &user,
-            &perp_market_map,
-            MarginRequirementType::Maintenance,
-            &spot_market_map,
-            &mut oracle_map,
-            None,
-        )
-        .unwrap();
-        assert_eq!(margin_req2, 1_049_604_950_000);
-        assert!(is_user_being_liquidated(
-            &user,
-            &perp_market_map,
-            &spot_market_map,
-            &mut oracle_map,
-            0
-        )
-        .unwrap());
+        let (margin_req2, _, _, _) = calculate_margin_requirement_and_total_collateral(
+            &user,
+            &perp_market_map,
+            MarginRequirementType::Maintenance,
+            &spot_market_map,
+            &mut oracle_map,
+            None,
+        )
+        .unwrap();
+        assert_eq!(margin_req2, 1_049_604_950_000);
+        assert!(is_user_being_liquidated(
+            &user,
+            &perp_market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            0
+        )
+        .unwrap());
+
+        let mut liquidator = User {
+            spot_positions: get_spot_positions(SpotPosition {
+                market_index: 0,
+                balance_type: SpotBalanceType::Deposit,
+                scaled_balance: 50 * SPOT_BALANCE_PRECISION_U64,
+                ..SpotPosition::default()
+            }),
+            ..User::default()
+        };
+
+        let user_key = Pubkey::default();
+        let liquidator_key = Pubkey::default();
+
+        let mut user_stats = UserStats::default();
+        let mut liquidator_stats = UserStats::default();
+        let state = State {
+            liquidation_margin_buffer_ratio: 10,
+            ..Default::default()
+        };
+        liquidate_perp(
+            0,
+            BASE_PRECISION_U64,
+            None,
+            &mut user,
+            &user_key,
+            &mut user_stats,
+            &mut liquidator,
+            &liquidator_key,
+            &mut liquidator_stats,
+            &perp_market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            slot,
+            now,
+            &state,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(user.perp_positions[0].base_asset_amount, 9999000000000);
+        assert_eq!(user.perp_positions[0].quote_asset_amount, -1499902000000);
+        assert_eq!(user.perp_positions[0].open_orders, 0);
+        assert_eq!(user.perp_positions[0].open_bids, 0);
+
+        assert_eq!(
+            liquidator.perp_positions[0].base_asset_amount,
+            BASE_PRECISION_I64
+        );
+        assert_eq!(
+            liquidator.perp_positions[0].quote_asset_amount,
+            -99 * QUOTE_PRECISION_I64
+        );
+
+        let market_after = perp_market_map.get_ref(&0).unwrap();
+        assert_eq!(market_after.amm.total_liquidation_fee, QUOTE_PRECISION);
+    }
+
+    #[test]
+    pub fn fail_liquidating_long_perp_due_to_limit_price() {
+        let now = 0_i64;
+        let slot = 0_u64;
+
+        let mut oracle_price = get_pyth_price(100, 6);
+        let oracle_price_key =
+            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let pyth_program = crate::ids::pyth_program::id();
+        create_account_info!(
+            oracle_price,
+            &oracle_price_key,
+            &pyth_program,
+            oracle_account_info
+        );
+        let mut oracle_map = OracleMap::load_one(&oracle_account_info, slot, None).unwrap();
+
+        let mut market = PerpMarket {
+            amm: AMM {
+                base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                bid_base_asset_reserve: 101 * AMM_RESERVE_PRECISION,
+                bid_quote_asset_reserve: 99 * AMM_RESERVE_PRECISION,
+                ask_base_asset_reserve: 99 * AMM_RESERVE_PRECISION,
+                ask_quote_asset_reserve: 101 * AMM_RESERVE_PRECISION,
+                sqrt_k: 100 * AMM_RESERVE_PRECISION,
+                peg_multiplier: 100 * PEG_PRECISION,
+                max_slippage_ratio: 50,
+                max_fill_reserve_fraction: 100,
+                order_step_size: 10000000,
+                quote_asset_amount: -150 * QUOTE_PRECISION_I128,
+                base_asset_amount_with_amm: BASE_PRECISION_I128,
+                oracle: oracle_price_key,
+                historical_oracle_data: HistoricalOracleData::default_price(oracle_price.agg.price),
+                ..AMM::default()
+            },
+            margin_ratio_initial: 1000,
+            margin_ratio_maintenance: 500,
+            number_of_users_with_base: 1,
+            status: MarketStatus::Initialized,
+            liquidator_fee: LIQUIDATION_FEE_PRECISION / 100,
+            if_liquidation_fee: LIQUIDATION_FEE_PRECISION / 100,
+            ..PerpMarket::default()
+        };
+        create_anchor_account_info!(market, PerpMarket, market_account_info);
+        let perp_market_map = PerpMarketMap::load_one(&market_account_info, true).unwrap();
+
+        let mut spot_market = SpotMarket {
+            market_index: 0,
+            oracle_source: OracleSource::QuoteAsset,
+            cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            decimals: 6,
+            initial_asset_weight: SPOT_WEIGHT_PRECISION,
+            ..SpotMarket::default()
+        };
+        create_anchor_account_info!(spot_market, SpotMarket, spot_market_account_info);
+        let spot_market_map = SpotMarketMap::load_one(&spot_market_account_info, true).unwrap();
+
+        let mut user = User {
+            orders: get_orders(Order {
+                market_index: 0,
+                status: OrderStatus::Open,
+                order_type: OrderType::Limit,
+                direction: PositionDirection::Long,
+                base_asset_amount: BASE_PRECISION_U64,
+                slot: 0,
+                ..Order::default()
+            }),
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                base_asset_amount: BASE_PRECISION_I64,
+                quote_asset_amount: -150 * QUOTE_PRECISION_I64,
+                quote_entry_amount: -150 * QUOTE_PRECISION_I64,
+                quote_break_even_amount: -150 * QUOTE_PRECISION_I64,
+                open_orders: 1,
+                open_bids: BASE_PRECISION_I64,
+                ..PerpPosition::default()
+            }),
+            spot_positions: [SpotPosition::default(); MAX_SPOT_POSITIONS as usize],
+
+            ..User::default()
+        };
+
+        let mut liquidator = User {
+            spot_positions: get_spot_positions(SpotPosition {
+                market_index: 0,
+                balance_type: SpotBalanceType::Deposit,
+                scaled_balance: 50 * SPOT_BALANCE_PRECISION_U64,
+                ..SpotPosition::default()
+            }),
+            ..User::default()
+        };
+
+        let user_key = Pubkey::default();
+        let liquidator_key = Pubkey::default();
+
+        let mut user_stats = UserStats::default();
+        let mut liquidator_stats = UserStats::default();
+        let state = State {
+            liquidation_margin_buffer_ratio: 10,
+            ..Default::default()
+        };
+
+        let result = liquidate_perp(
+            0,
+            BASE_PRECISION_U64,
+            Some(50 * PRICE_PRECISION_U64),
+            &mut user,
+            &user_key,
+            &mut user_stats,
+            &mut liquidator,
+            &liquidator_key,
+            &mut liquidator_stats,
+            &perp_market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            slot,
+            now,
+            &state,
+            None,
+        );
+
+        assert_eq!(result, Err(ErrorCode::LiquidationDoesntSatisfyLimitPrice));
+    }
+
+    #[test]
+    pub fn fail_liquidating_short_perp_due_to_limit_price() {
+        let now = 0_i64;
+        let slot = 0_u64;
+
+        let mut oracle_price = get_pyth_price(100, 6);
+        let oracle_price_key =
+            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let pyth_program = crate::ids::pyth_program::id();
+        create_account_info!(
+            oracle_price,
+            &oracle_price_key,
+            &pyth_program,
+            oracle_account_info
+        );
+        let mut oracle_map = OracleMap::load_one(&oracle_account_info, slot, None).unwrap();
+
+        let mut market = PerpMarket {
+            amm: AMM {
+                base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                bid_base_asset_reserve: 101 * AMM_RESERVE_PRECISION,
+                bid_quote_asset_reserve: 99 * AMM_RESERVE_PRECISION,
+                ask_base_asset_reserve: 99 * AMM_RESERVE_PRECISION,
+                ask_quote_asset_reserve: 101 * AMM_RESERVE_PRECISION,
+                sqrt_k: 100 * AMM_RESERVE_PRECISION,
+                peg_multiplier: 100 * PEG_PRECISION,
+                max_slippage_ratio: 50,
+                max_fill_reserve_fraction: 100,
+                order_step_size: 10000000,
+                quote_asset_amount: 50 * QUOTE_PRECISION_I128,
+                base_asset_amount_with_amm: BASE_PRECISION_I128,
+                oracle: oracle_price_key,
+                historical_oracle_data: HistoricalOracleData::default_price(oracle_price.agg.price),
+                ..AMM::default()
+            },
+            margin_ratio_initial: 1000,
+            margin_ratio_maintenance: 500,
+            number_of_users_with_base: 1,
+            status: MarketStatus::Initialized,
+            liquidator_fee: LIQUIDATION_FEE_PRECISION / 100,
+            if_liquidation_fee: LIQUIDATION_FEE_PRECISION / 100,
+            ..PerpMarket::default()
+        };
+        create_anchor_account_info!(market, PerpMarket, market_account_info);
+        let perp_market_map = PerpMarketMap::load_one(&market_account_info, true).unwrap();
+
+        let mut spot_market = SpotMarket {
+            market_index: 0,
+            oracle_source: OracleSource::QuoteAsset,
+            cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            decimals: 6,
+            initial_asset_weight: SPOT_WEIGHT_PRECISION,
+            ..SpotMarket::default()
+        };
+        create_anchor_account_info!(spot_market, SpotMarket, spot_market_account_info);
+        let spot_market_map = SpotMarketMap::load_one(&spot_market_account_info, true).unwrap();
+
+        let mut user = User {
+            orders: get_orders(Order {
+                market_index: 0,
+                status: OrderStatus::Open,
+                order_type: OrderType::Limit,
+                direction: PositionDirection::Short,
+                base_asset_amount: BASE_PRECISION_U64,
+                slot: 0,
+                ..Order::default()
+            }),
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                base_asset_amount: -BASE_PRECISION_I64,
+                quote_asset_amount: 50 * QUOTE_PRECISION_I64,
+                quote_entry_amount: 50 * QUOTE_PRECISION_I64,
+                quote_break_even_amount: 50 * QUOTE_PRECISION_I64,
+                open_orders: 1,
+                open_asks: -BASE_PRECISION_I64,
+                ..PerpPosition::default()
+            }),
+            spot_positions: [SpotPosition::default(); MAX_SPOT_POSITIONS as usize],
+
+            ..User::default()
+        };
+
+        let mut liquidator = User {
+            spot_positions: get_spot_positions(SpotPosition {
+                market_index: 0,
+                balance_type: SpotBalanceType::Deposit,
+                scaled_balance: 50 * SPOT_BALANCE_PRECISION_U64,
+                ..SpotPosition::default()
+            }),
+            ..User::default()
+        };
+
+        let user_key = Pubkey::default();
+        let liquidator_key = Pubkey::default();
+
+        let mut user_stats = UserStats::default();
+        let mut liquidator_stats = UserStats::default();
+        let state = State {
+            liquidation_margin_buffer_ratio: 10,
+            ..Default::default()
+        };
+
+        let result = liquidate_perp(
+            0,
+            BASE_PRECISION_U64,
+            Some(150 * PRICE_PRECISION_U64),
+            &mut user,
+            &user_key,
+            &mut user_stats,
+            &mut liquidator,
+            &liquidator_key,
+            &mut liquidator_stats,
+            &perp_market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            slot,
+            now,
+            &state,
+            None,
+        );
+
+        assert_eq!(result, Err(ErrorCode::LiquidationDoesntSatisfyLimitPrice));
+    }
+
+    #[test]
+    pub fn successful_liquidation_burns_lp_shares() {
+        let now = 0_i64;
+        let slot = 0_u64;
+
+        let mut oracle_price = get_pyth_price(100, 6);
+        let oracle_price_key =
+            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let pyth_program = crate::ids::pyth_program::id();
+        create_account_info!(
+            oracle_price,
+            &oracle_price_key,
+            &pyth_program,
+            oracle_account_info
+        );
+        let mut oracle_map = OracleMap::load_one(&oracle_account_info, slot, None).unwrap();
+
+        let mut market = PerpMarket {
+            amm: AMM {
+                base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                bid_base_asset_reserve: 101 * AMM_RESERVE_PRECISION,
+                bid_quote_asset_reserve: 99 * AMM_RESERVE_PRECISION,
+                ask_base_asset_reserve: 99 * AMM_RESERVE_PRECISION,
+                ask_quote_asset_reserve: 101 * AMM_RESERVE_PRECISION,
+                sqrt_k: 100 * AMM_RESERVE_PRECISION,
+                user_lp_shares: AMM_RESERVE_PRECISION,
+                peg_multiplier: 100 * PEG_PRECISION,
+                max_slippage_ratio: 50,
+                max_fill_reserve_fraction: 100,
+                order_step_size: 10000000,
+                quote_asset_amount: -150 * QUOTE_PRECISION_I128,
+                base_asset_amount_with_amm: BASE_PRECISION_I128,
+                base_asset_amount_long: BASE_PRECISION_I128,
+                oracle: oracle_price_key,
+                historical_oracle_data: HistoricalOracleData::default_price(oracle_price.agg.price),
+                ..AMM::default()
+            },
+            margin_ratio_initial: 1000,
+            margin_ratio_maintenance: 500,
+            number_of_users_with_base: 1,
+            status: MarketStatus::Initialized,
+            liquidator_fee: LIQUIDATION_FEE_PRECISION / 100,
+            if_liquidation_fee: LIQUIDATION_FEE_PRECISION / 100,
+            ..PerpMarket::default()
+        };
+        create_anchor_account_info!(market, PerpMarket, market_account_info);
+        let perp_market_map = PerpMarketMap::load_one(&market_account_info, true).unwrap();
+
+        let mut spot_market = SpotMarket {
+            market_index: 0,
+            oracle_source: OracleSource::QuoteAsset,
+            cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            decimals: 6,
+            initial_asset_weight: SPOT_WEIGHT_PRECISION,
+            ..SpotMarket::default()
+        };
+        create_anchor_account_info!(spot_market, SpotMarket, spot_market_account_info);
+        let spot_market_map = SpotMarketMap::load_one(&spot_market_account_info, true).unwrap();
+
+        let mut user = User {
+            orders: get_orders(Order::default()),
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                base_asset_amount: BASE_PRECISION_I64,
+                quote_asset_amount: -150 * QUOTE_PRECISION_I64,
+                quote_entry_amount: -150 * QUOTE_PRECISION_I64,
+                quote_break_even_amount: -150 * QUOTE_PRECISION_I64,
+                lp_shares: BASE_PRECISION_U64,
+                ..PerpPosition::default()
+            }),
+            spot_positions: [SpotPosition::default(); MAX_SPOT_POSITIONS as usize],
+
+            ..User::default()
+        };
+
+        let mut liquidator = User {
+            spot_positions: get_spot_positions(SpotPosition {
+                market_index: 0,
+                balance_type: SpotBalanceType::Deposit,
+                scaled_balance: 50 * SPOT_BALANCE_PRECISION_U64,
+                ..SpotPosition::default()
+            }),
+            ..User::default()
+        };
+
+        let user_key = Pubkey::default();
+        let liquidator_key = Pubkey::default();
+
+        let mut user_stats = UserStats::default();
+        let mut liquidator_stats = UserStats::default();
+        let state = State {
+            liquidation_margin_buffer_ratio: 10,
+            ..Default::default()
+        };
+        liquidate_perp(
+            0,
+            BASE_PRECISION_U64,
+            None,
+            &mut user,
+            &user_key,
+            &mut user_stats,
+            &mut liquidator,
+            &liquidator_key,
+            &mut liquidator_stats,
+            &perp_market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            slot,
+            now,
+            &state,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(user.perp_positions[0].lp_shares, 0);
+        assert_eq!(user.perp_positions[0].base_asset_amount, 0);
+        assert_eq!(
+            liquidator.perp_positions[0].base_asset_amount,
+            BASE_PRECISION_I64
+        );
+
+        let market_after = perp_market_map.get_ref(&0).unwrap();
+        assert_eq!(market_after.amm.user_lp_shares, 0);
+        assert_eq!(market_after.amm.sqrt_k, 99 * AMM_RESERVE_PRECISION);
+    }
+}
+
+pub mod liquidate_perp_with_amm {
+    use crate::state::state::State;
+    use std::str::FromStr;
+
+    use anchor_lang::Owner;
+    use solana_program::pubkey::Pubkey;
+
+    use crate::controller::liquidation::liquidate_perp_with_amm;
+    use crate::create_account_info;
+    use crate::create_anchor_account_info;
+    use crate::error::ErrorCode;
+    use crate::math::constants::{
+        AMM_RESERVE_PRECISION, BASE_PRECISION_I128, BASE_PRECISION_I64, BASE_PRECISION_U64,
+        LIQUIDATION_FEE_PRECISION, MAX_SPOT_POSITIONS, PEG_PRECISION, QUOTE_PRECISION_I128,
+        QUOTE_PRECISION_I64, SPOT_CUMULATIVE_INTEREST_PRECISION, SPOT_WEIGHT_PRECISION,
+    };
+    use crate::state::oracle::{HistoricalOracleData, OracleSource};
+    use crate::state::oracle_map::OracleMap;
+    use crate::state::perp_market::{MarketStatus, PerpMarket, AMM};
+    use crate::state::perp_market_map::PerpMarketMap;
+    use crate::state::spot_market::SpotMarket;
+    use crate::state::spot_market_map::SpotMarketMap;
+    use crate::state::user::{Order, PerpPosition, SpotPosition, User, UserStats};
+    use crate::test_utils::*;
+    use crate::test_utils::{get_orders, get_positions, get_pyth_price};
+
+    #[test]
+    pub fn successful_liquidation_long_perp() {
+        let now = 0_i64;
+        let slot = 0_u64;
+
+        let mut oracle_price = get_pyth_price(100, 6);
+        let oracle_price_key =
+            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let pyth_program = crate::ids::pyth_program::id();
+        create_account_info!(
+            oracle_price,
+            &oracle_price_key,
+            &pyth_program,
+            oracle_account_info
+        );
+        let mut oracle_map = OracleMap::load_one(&oracle_account_info, slot, None).unwrap();
+
+        let mut market = PerpMarket {
+            amm: AMM {
+                base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                bid_base_asset_reserve: 101 * AMM_RESERVE_PRECISION,
+                bid_quote_asset_reserve: 99 * AMM_RESERVE_PRECISION,
+                ask_base_asset_reserve: 99 * AMM_RESERVE_PRECISION,
+                ask_quote_asset_reserve: 101 * AMM_RESERVE_PRECISION,
+                min_base_asset_reserve: 50 * AMM_RESERVE_PRECISION,
+                max_base_asset_reserve: 200 * AMM_RESERVE_PRECISION,
+                sqrt_k: 100 * AMM_RESERVE_PRECISION,
+                peg_multiplier: 100 * PEG_PRECISION,
+                max_slippage_ratio: 50,
+                max_fill_reserve_fraction: 100,
+                order_step_size: 10000000,
+                quote_asset_amount: -150 * QUOTE_PRECISION_I128,
+                base_asset_amount_with_amm: BASE_PRECISION_I128,
+                base_asset_amount_long: BASE_PRECISION_I128,
+                oracle: oracle_price_key,
+                historical_oracle_data: HistoricalOracleData::default_price(oracle_price.agg.price),
+                ..AMM::default()
+            },
+            margin_ratio_initial: 1000,
+            margin_ratio_maintenance: 500,
+            number_of_users_with_base: 1,
+            status: MarketStatus::Active,
+            liquidator_fee: LIQUIDATION_FEE_PRECISION / 100,
+            if_liquidation_fee: LIQUIDATION_FEE_PRECISION / 100,
+            ..PerpMarket::default()
+        };
+        create_anchor_account_info!(market, PerpMarket, market_account_info);
+        let perp_market_map = PerpMarketMap::load_one(&market_account_info, true).unwrap();
+
+        let mut spot_market = SpotMarket {
+            market_index: 0,
+            oracle_source: OracleSource::QuoteAsset,
+            cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            decimals: 6,
+            initial_asset_weight: SPOT_WEIGHT_PRECISION,
+            ..SpotMarket::default()
+        };
+        create_anchor_account_info!(spot_market, SpotMarket, spot_market_account_info);
+        let spot_market_map = SpotMarketMap::load_one(&spot_market_account_info, true).unwrap();
+
+        let mut user = User {
+            orders: get_orders(Order::default()),
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                base_asset_amount: BASE_PRECISION_I64,
+                quote_asset_amount: -150 * QUOTE_PRECISION_I64,
+                quote_entry_amount: -150 * QUOTE_PRECISION_I64,
+                quote_break_even_amount: -150 * QUOTE_PRECISION_I64,
+                ..PerpPosition::default()
+            }),
+            spot_positions: [SpotPosition::default(); MAX_SPOT_POSITIONS as usize],
+
+            ..User::default()
+        };
+
+        let mut filler = User::default();
+
+        let user_key = Pubkey::default();
+        let filler_key = Pubkey::new_unique();
+
+        let mut user_stats = UserStats::default();
+        let state = State {
+            liquidation_margin_buffer_ratio: 10,
+            ..Default::default()
+        };
+        liquidate_perp_with_amm(
+            0,
+            &mut user,
+            &user_key,
+            &mut user_stats,
+            &mut filler,
+            &filler_key,
+            &perp_market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            slot,
+            now,
+            &state,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(user.perp_positions[0].base_asset_amount, 0);
+        assert_eq!(filler.perp_positions[0].base_asset_amount, 0);
+        assert!(filler.perp_positions[0].quote_asset_amount > 0);
+
+        let market_after = perp_market_map.get_ref(&0).unwrap();
+        assert_eq!(market_after.amm.base_asset_amount_with_amm, 0);
+        assert_eq!(market_after.amm.base_asset_amount_long, 0);
+        assert!(market_after.amm.base_asset_reserve > 100 * AMM_RESERVE_PRECISION);
+        assert!(market_after.amm.total_liquidation_fee > 0);
+    }
+
+    #[test]
+    pub fn fees_split_between_filler_and_insurance_fund() {
+        let now = 0_i64;
+        let slot = 0_u64;
+
+        let mut oracle_price = get_pyth_price(100, 6);
+        let oracle_price_key =
+            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let pyth_program = crate::ids::pyth_program::id();
+        create_account_info!(
+            oracle_price,
+            &oracle_price_key,
+            &pyth_program,
+            oracle_account_info
+        );
+        let mut oracle_map = OracleMap::load_one(&oracle_account_info, slot, None).unwrap();
+
+        let mut market = PerpMarket {
+            amm: AMM {
+                base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                bid_base_asset_reserve: 101 * AMM_RESERVE_PRECISION,
+                bid_quote_asset_reserve: 99 * AMM_RESERVE_PRECISION,
+                ask_base_asset_reserve: 99 * AMM_RESERVE_PRECISION,
+                ask_quote_asset_reserve: 101 * AMM_RESERVE_PRECISION,
+                min_base_asset_reserve: 50 * AMM_RESERVE_PRECISION,
+                max_base_asset_reserve: 200 * AMM_RESERVE_PRECISION,
+                sqrt_k: 100 * AMM_RESERVE_PRECISION,
+                peg_multiplier: 100 * PEG_PRECISION,
+                max_slippage_ratio: 50,
+                max_fill_reserve_fraction: 100,
+                order_step_size: 10000000,
+                quote_asset_amount: -150 * QUOTE_PRECISION_I128,
+                base_asset_amount_with_amm: BASE_PRECISION_I128,
+                base_asset_amount_long: BASE_PRECISION_I128,
+                oracle: oracle_price_key,
+                historical_oracle_data: HistoricalOracleData::default_price(oracle_price.agg.price),
+                ..AMM::default()
+            },
+            margin_ratio_initial: 1000,
+            margin_ratio_maintenance: 500,
+            number_of_users_with_base: 1,
+            status: MarketStatus::Active,
+            liquidator_fee: LIQUIDATION_FEE_PRECISION / 100,
+            if_liquidation_fee: LIQUIDATION_FEE_PRECISION / 100,
+            ..PerpMarket::default()
+        };
+        create_anchor_account_info!(market, PerpMarket, market_account_info);
+        let perp_market_map = PerpMarketMap::load_one(&market_account_info, true).unwrap();
+
+        let mut spot_market = SpotMarket {
+            market_index: 0,
+            oracle_source: OracleSource::QuoteAsset,
+            cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            decimals: 6,
+            initial_asset_weight: SPOT_WEIGHT_PRECISION,
+            ..SpotMarket::default()
+        };
+        create_anchor_account_info!(spot_market, SpotMarket, spot_market_account_info);
+        let spot_market_map = SpotMarketMap::load_one(&spot_market_account_info, true).unwrap();
+
+        let mut user = User {
+            orders: get_orders(Order::default()),
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                base_asset_amount: BASE_PRECISION_I64,
+                quote_asset_amount: -150 * QUOTE_PRECISION_I64,
+                quote_entry_amount: -150 * QUOTE_PRECISION_I64,
+                quote_break_even_amount: -150 * QUOTE_PRECISION_I64,
+                ..PerpPosition::default()
+            }),
+            spot_positions: [SpotPosition::default(); MAX_SPOT_POSITIONS as usize],
+
+            ..User::default()
+        };
+
+        let mut filler = User::default();
+
+        let user_key = Pubkey::default();
+        let filler_key = Pubkey::new_unique();
+
+        let mut user_stats = UserStats::default();
+        let state = State {
+            liquidation_margin_buffer_ratio: 10,
+            ..Default::default()
+        };
+        liquidate_perp_with_amm(
+            0,
+            &mut user,
+            &user_key,
+            &mut user_stats,
+            &mut filler,
+            &filler_key,
+            &perp_market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            slot,
+            now,
+            &state,
+            None,
+        )
+        .unwrap();
+
+        let market_after = perp_market_map.get_ref(&0).unwrap();
+        // liquidator and if fee are both 1%, the filler gets 10% of the liquidator fee
+        let filler_reward = filler.perp_positions[0].quote_asset_amount;
+        assert!(filler_reward > 0);
+        // the rest of the liquidator fee goes to the insurance fund with the if fee
+        assert_eq!(
+            market_after.amm.total_liquidation_fee,
+            (filler_reward as u128) * 19
+        );
+    }
+
+    #[test]
+    pub fn cant_liquidate_against_paused_amm() {
+        let now = 0_i64;
+        let slot = 0_u64;
+
+        let mut oracle_price = get_pyth_price(100, 6);
+        let oracle_price_key =
+            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let pyth_program = crate::ids::pyth_program::id();
+        create_account_info!(
+            oracle_price,
+            &oracle_price_key,
+            &pyth_program,
+            oracle_account_info
+        );
+        let mut oracle_map = OracleMap::load_one(&oracle_account_info, slot, None).unwrap();
+
+        let mut market = PerpMarket {
+            amm: AMM {
+                base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                bid_base_asset_reserve: 101 * AMM_RESERVE_PRECISION,
+                bid_quote_asset_reserve: 99 * AMM_RESERVE_PRECISION,
+                ask_base_asset_reserve: 99 * AMM_RESERVE_PRECISION,
+                ask_quote_asset_reserve: 101 * AMM_RESERVE_PRECISION,
+                min_base_asset_reserve: 50 * AMM_RESERVE_PRECISION,
+                max_base_asset_reserve: 200 * AMM_RESERVE_PRECISION,
+                sqrt_k: 100 * AMM_RESERVE_PRECISION,
+                peg_multiplier: 100 * PEG_PRECISION,
+                max_slippage_ratio: 50,
+                max_fill_reserve_fraction: 100,
+                order_step_size: 10000000,
+                quote_asset_amount: -150 * QUOTE_PRECISION_I128,
+                base_asset_amount_with_amm: BASE_PRECISION_I128,
+                base_asset_amount_long: BASE_PRECISION_I128,
+                oracle: oracle_price_key,
+                historical_oracle_data: HistoricalOracleData::default_price(oracle_price.agg.price),
+                ..AMM::default()
+            },
+            margin_ratio_initial: 1000,
+            margin_ratio_maintenance: 500,
+            number_of_users_with_base: 1,
+            status: MarketStatus::AmmPaused,
+            liquidator_fee: LIQUIDATION_FEE_PRECISION / 100,
+            if_liquidation_fee: LIQUIDATION_FEE_PRECISION / 100,
+            ..PerpMarket::default()
+        };
+        create_anchor_account_info!(market, PerpMarket, market_account_info);
+        let perp_market_map = PerpMarketMap::load_one(&market_account_info, true).unwrap();
+
+        let mut spot_market = SpotMarket {
+            market_index: 0,
+            oracle_source: OracleSource::QuoteAsset,
+            cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            decimals: 6,
+            initial_asset_weight: SPOT_WEIGHT_PRECISION,
+            ..SpotMarket::default()
+        };
+        create_anchor_account_info!(spot_market, SpotMarket, spot_market_account_info);
+        let spot_market_map = SpotMarketMap::load_one(&spot_market_account_info, true).unwrap();
+
+        let mut user = User {
+            orders: get_orders(Order::default()),
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                base_asset_amount: BASE_PRECISION_I64,
+                quote_asset_amount: -150 * QUOTE_PRECISION_I64,
+                quote_entry_amount: -150 * QUOTE_PRECISION_I64,
+                quote_break_even_amount: -150 * QUOTE_PRECISION_I64,
+                ..PerpPosition::default()
+            }),
+            spot_positions: [SpotPosition::default(); MAX_SPOT_POSITIONS as usize],
+
+            ..User::default()
+        };
+
+        let mut filler = User::default();
+
+        let user_key = Pubkey::default();
+        let filler_key = Pubkey::new_unique();
+
+        let mut user_stats = UserStats::default();
+        let state = State {
+            liquidation_margin_buffer_ratio: 10,
+            ..Default::default()
+        };
+        let result = liquidate_perp_with_amm(
+            0,
+            &mut user,
+            &user_key,
+            &mut user_stats,
+            &mut filler,
+            &filler_key,
+            &perp_market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            slot,
+            now,
+            &state,
+            None,
+        );
+
+        assert_eq!(result, Err(ErrorCode::MarketActionPaused));
+        assert_eq!(user.perp_positions[0].base_asset_amount, BASE_PRECISION_I64);
+        assert!(!user.is_being_liquidated);
+    }
+
+    #[test]
+    pub fn fails_when_base_asset_amount_rounds_to_zero() {
+        let now = 0_i64;
+        let slot = 0_u64;
+
+        let mut oracle_price = get_pyth_price(100, 6);
+        let oracle_price_key =
+            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let pyth_program = crate::ids::pyth_program::id();
+        create_account_info!(
+            oracle_price,
+            &oracle_price_key,
+            &pyth_program,
+            oracle_account_info
+        );
+        let mut oracle_map = OracleMap::load_one(&oracle_account_info, slot, None).unwrap();
+
+        let mut market = PerpMarket {
+            amm: AMM {
+                base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                bid_base_asset_reserve: 101 * AMM_RESERVE_PRECISION,
+                bid_quote_asset_reserve: 99 * AMM_RESERVE_PRECISION,
+                ask_base_asset_reserve: 99 * AMM_RESERVE_PRECISION,
+                ask_quote_asset_reserve: 101 * AMM_RESERVE_PRECISION,
+                min_base_asset_reserve: 50 * AMM_RESERVE_PRECISION,
+                max_base_asset_reserve: 200 * AMM_RESERVE_PRECISION,
+                sqrt_k: 100 * AMM_RESERVE_PRECISION,
+                peg_multiplier: 100 * PEG_PRECISION,
+                max_slippage_ratio: 50,
+                max_fill_reserve_fraction: 100,
+                order_step_size: 2 * BASE_PRECISION_U64,
+                quote_asset_amount: -150 * QUOTE_PRECISION_I128,
+                base_asset_amount_with_amm: BASE_PRECISION_I128,
+                base_asset_amount_long: BASE_PRECISION_I128,
+                oracle: oracle_price_key,
+                historical_oracle_data: HistoricalOracleData::default_price(oracle_price.agg.price),
+                ..AMM::default()
+            },
+            margin_ratio_initial: 1000,
+            margin_ratio_maintenance: 500,
+            number_of_users_with_base: 1,
+            status: MarketStatus::Active,
+            liquidator_fee: LIQUIDATION_FEE_PRECISION / 100,
+            if_liquidation_fee: LIQUIDATION_FEE_PRECISION / 100,
+            ..PerpMarket::default()
+        };
+        create_anchor_account_info!(market, PerpMarket, market_account_info);
+        let perp_market_map = PerpMarketMap::load_one(&market_account_info, true).unwrap();
+
+        let mut spot_market = SpotMarket {
+            market_index: 0,
+            oracle_source: OracleSource::QuoteAsset,
+            cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            decimals: 6,
+            initial_asset_weight: SPOT_WEIGHT_PRECISION,
+            ..SpotMarket::default()
+        };
+        create_anchor_account_info!(spot_market, SpotMarket, spot_market_account_info);
+        let spot_market_map = SpotMarketMap::load_one(&spot_market_account_info, true).unwrap();
 
-        let mut liquidator = User {
-            spot_positions: get_spot_positions(SpotPosition {
+        let mut user = User {
+            orders: get_orders(Order::default()),
+            perp_positions: get_positions(PerpPosition {
                 market_index: 0,
-                balance_type: SpotBalanceType::Deposit,
-                scaled_balance: 50 * SPOT_BALANCE_PRECISION_U64,
-                ..SpotPosition::default()
+                base_asset_amount: BASE_PRECISION_I64,
+                quote_asset_amount: -150 * QUOTE_PRECISION_I64,
+                quote_entry_amount: -150 * QUOTE_PRECISION_I64,
+                quote_break_even_amount: -150 * QUOTE_PRECISION_I64,
+                ..PerpPosition::default()
             }),
+            spot_positions: [SpotPosition::default(); MAX_SPOT_POSITIONS as usize],
+
             ..User::default()
         };
 
+        let mut filler = User::default();
+
         let user_key = Pubkey::default();
-        let liquidator_key = Pubkey::default();
+        let filler_key = Pubkey::new_unique();
 
         let mut user_stats = UserStats::default();
-        let mut liquidator_stats = UserStats::default();
         let state = State {
             liquidation_margin_buffer_ratio: 10,
             ..Default::default()
         };
-        liquidate_perp(
+        let result = liquidate_perp_with_amm(
             0,
-            BASE_PRECISION_U64,
-            None,
             &mut user,
             &user_key,
             &mut user_stats,
-            &mut liquidator,
-            &liquidator_key,
-            &mut liquidator_stats,
+            &mut filler,
+            &filler_key,
             &perp_market_map,
             &spot_market_map,
             &mut oracle_map,
             slot,
             now,
             &state,
-        )
-        .unwrap();
-
-        assert_eq!(user.perp_positions[0].base_asset_amount, 9999000000000);
-        assert_eq!(user.perp_positions[0].quote_asset_amount, -1499902000000);
-        assert_eq!(user.perp_positions[0].open_orders, 0);
-        assert_eq!(user.perp_positions[0].open_bids, 0);
-
-        assert_eq!(
-            liquidator.perp_positions[0].base_asset_amount,
-            BASE_PRECISION_I64
+            None,
         );
+
         assert_eq!(
-            liquidator.perp_positions[0].quote_asset_amount,
-            -99 * QUOTE_PRECISION_I64
+            result,
+            Err(ErrorCode::InvalidBaseAssetAmountForLiquidatePerp)
         );
-
-        let market_after = perp_market_map.get_ref(&0).unwrap();
-        assert_eq!(market_after.amm.total_liquidation_fee, QUOTE_PRECISION);
     }
+}
+
+pub mod liquidate_perp_with_backstop {
+    use crate::state::state::State;
+    use std::str::FromStr;
+
+    use anchor_lang::Owner;
+    use solana_program::pubkey::Pubkey;
+
+    use crate::controller::liquidation::liquidate_perp_with_backstop;
+    use crate::create_account_info;
+    use crate::create_anchor_account_info;
+    use crate::error::ErrorCode;
+    use crate::math::constants::{
+        AMM_RESERVE_PRECISION, BASE_PRECISION_I128, BASE_PRECISION_I64, BASE_PRECISION_U64,
+        LIQUIDATION_FEE_PRECISION, PEG_PRECISION, QUOTE_PRECISION_I128, QUOTE_PRECISION_I64,
+        SPOT_BALANCE_PRECISION_U64, SPOT_CUMULATIVE_INTEREST_PRECISION, SPOT_WEIGHT_PRECISION,
+    };
+    use crate::state::backstop_vault::BackstopVault;
+    use crate::state::oracle::{HistoricalOracleData, OracleSource};
+    use crate::state::oracle_map::OracleMap;
+    use crate::state::perp_market::{MarketStatus, PerpMarket, AMM};
+    use crate::state::perp_market_map::PerpMarketMap;
+    use crate::state::spot_market::{SpotBalanceType, SpotMarket};
+    use crate::state::spot_market_map::SpotMarketMap;
+    use crate::state::user::{PerpPosition, SpotPosition, User, UserStats};
+    use crate::test_utils::*;
+    use crate::test_utils::{get_positions, get_pyth_price, get_spot_positions};
 
     #[test]
-    pub fn fail_liquidating_long_perp_due_to_limit_price() {
+    pub fn backstop_absorbs_position_after_delay() {
         let now = 0_i64;
         let slot = 0_u64;
 
@@ -1005,31 +1887,18 @@ pub mod liquidate_perp {
         let spot_market_map = SpotMarketMap::load_one(&spot_market_account_info, true).unwrap();
 
         let mut user = User {
-            orders: get_orders(Order {
-                market_index: 0,
-                status: OrderStatus::Open,
-                order_type: OrderType::Limit,
-                direction: PositionDirection::Long,
-                base_asset_amount: BASE_PRECISION_U64,
-                slot: 0,
-                ..Order::default()
-            }),
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 base_asset_amount: BASE_PRECISION_I64,
                 quote_asset_amount: -150 * QUOTE_PRECISION_I64,
                 quote_entry_amount: -150 * QUOTE_PRECISION_I64,
                 quote_break_even_amount: -150 * QUOTE_PRECISION_I64,
-                open_orders: 1,
-                open_bids: BASE_PRECISION_I64,
                 ..PerpPosition::default()
             }),
-            spot_positions: [SpotPosition::default(); 8],
-
             ..User::default()
         };
 
-        let mut liquidator = User {
+        let mut backstop_user = User {
             spot_positions: get_spot_positions(SpotPosition {
                 market_index: 0,
                 balance_type: SpotBalanceType::Deposit,
@@ -1039,40 +1908,105 @@ pub mod liquidate_perp {
             ..User::default()
         };
 
+        let backstop_vault = BackstopVault {
+            liquidation_delay_slots: 10,
+            ..BackstopVault::default()
+        };
+
         let user_key = Pubkey::default();
-        let liquidator_key = Pubkey::default();
+        let backstop_user_key = Pubkey::default();
 
         let mut user_stats = UserStats::default();
-        let mut liquidator_stats = UserStats::default();
+        let mut backstop_user_stats = UserStats::default();
         let state = State {
             liquidation_margin_buffer_ratio: 10,
             ..Default::default()
         };
 
-        let result = liquidate_perp(
+        // first call only flags the user, giving external liquidators the delay to step in
+        liquidate_perp_with_backstop(
             0,
             BASE_PRECISION_U64,
-            Some(50 * PRICE_PRECISION_U64),
+            None,
             &mut user,
             &user_key,
             &mut user_stats,
-            &mut liquidator,
-            &liquidator_key,
-            &mut liquidator_stats,
+            &backstop_vault,
+            &mut backstop_user,
+            &backstop_user_key,
+            &mut backstop_user_stats,
             &perp_market_map,
             &spot_market_map,
             &mut oracle_map,
             slot,
             now,
             &state,
+            None,
+        )
+        .unwrap();
+
+        assert!(user.is_being_liquidated);
+        assert_eq!(user.liquidation_start_slot, slot);
+        assert_eq!(user.perp_positions[0].base_asset_amount, BASE_PRECISION_I64);
+        assert_eq!(backstop_user.perp_positions[0].base_asset_amount, 0);
+
+        let result = liquidate_perp_with_backstop(
+            0,
+            BASE_PRECISION_U64,
+            None,
+            &mut user,
+            &user_key,
+            &mut user_stats,
+            &backstop_vault,
+            &mut backstop_user,
+            &backstop_user_key,
+            &mut backstop_user_stats,
+            &perp_market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            slot + 9,
+            now,
+            &state,
+            None,
         );
 
-        assert_eq!(result, Err(ErrorCode::LiquidationDoesntSatisfyLimitPrice));
+        assert_eq!(result, Err(ErrorCode::BackstopLiquidationNotAvailable));
+        assert_eq!(user.perp_positions[0].base_asset_amount, BASE_PRECISION_I64);
+
+        liquidate_perp_with_backstop(
+            0,
+            BASE_PRECISION_U64,
+            None,
+            &mut user,
+            &user_key,
+            &mut user_stats,
+            &backstop_vault,
+            &mut backstop_user,
+            &backstop_user_key,
+            &mut backstop_user_stats,
+            &perp_market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            slot + 10,
+            now,
+            &state,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(user.perp_positions[0].base_asset_amount, 0);
+        assert_eq!(
+            backstop_user.perp_positions[0].base_asset_amount,
+            BASE_PRECISION_I64
+        );
+        assert_eq!(
+            backstop_user.perp_positions[0].quote_asset_amount,
+            -99 * QUOTE_PRECISION_I64
+        );
     }
 
     #[test]
-    pub fn fail_liquidating_short_perp_due_to_limit_price() {
-        let now = 0_i64;
+    pub fn cant_flag_user_with_sufficient_collateral() {
         let slot = 0_u64;
 
         let mut oracle_price = get_pyth_price(100, 6);
@@ -1091,27 +2025,16 @@ pub mod liquidate_perp {
             amm: AMM {
                 base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
                 quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
-                bid_base_asset_reserve: 101 * AMM_RESERVE_PRECISION,
-                bid_quote_asset_reserve: 99 * AMM_RESERVE_PRECISION,
-                ask_base_asset_reserve: 99 * AMM_RESERVE_PRECISION,
-                ask_quote_asset_reserve: 101 * AMM_RESERVE_PRECISION,
                 sqrt_k: 100 * AMM_RESERVE_PRECISION,
                 peg_multiplier: 100 * PEG_PRECISION,
-                max_slippage_ratio: 50,
-                max_fill_reserve_fraction: 100,
                 order_step_size: 10000000,
-                quote_asset_amount: 50 * QUOTE_PRECISION_I128,
-                base_asset_amount_with_amm: BASE_PRECISION_I128,
                 oracle: oracle_price_key,
                 historical_oracle_data: HistoricalOracleData::default_price(oracle_price.agg.price),
                 ..AMM::default()
             },
             margin_ratio_initial: 1000,
             margin_ratio_maintenance: 500,
-            number_of_users_with_base: 1,
             status: MarketStatus::Initialized,
-            liquidator_fee: LIQUIDATION_FEE_PRECISION / 100,
-            if_liquidation_fee: LIQUIDATION_FEE_PRECISION / 100,
             ..PerpMarket::default()
         };
         create_anchor_account_info!(market, PerpMarket, market_account_info);
@@ -1129,31 +2052,12 @@ pub mod liquidate_perp {
         let spot_market_map = SpotMarketMap::load_one(&spot_market_account_info, true).unwrap();
 
         let mut user = User {
-            orders: get_orders(Order {
-                market_index: 0,
-                status: OrderStatus::Open,
-                order_type: OrderType::Limit,
-                direction: PositionDirection::Short,
-                base_asset_amount: BASE_PRECISION_U64,
-                slot: 0,
-                ..Order::default()
-            }),
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
-                base_asset_amount: -BASE_PRECISION_I64,
-                quote_asset_amount: 50 * QUOTE_PRECISION_I64,
-                quote_entry_amount: 50 * QUOTE_PRECISION_I64,
-                quote_break_even_amount: 50 * QUOTE_PRECISION_I64,
-                open_orders: 1,
-                open_asks: -BASE_PRECISION_I64,
+                base_asset_amount: BASE_PRECISION_I64,
+                quote_asset_amount: -100 * QUOTE_PRECISION_I64,
                 ..PerpPosition::default()
             }),
-            spot_positions: [SpotPosition::default(); 8],
-
-            ..User::default()
-        };
-
-        let mut liquidator = User {
             spot_positions: get_spot_positions(SpotPosition {
                 market_index: 0,
                 balance_type: SpotBalanceType::Deposit,
@@ -1163,35 +2067,31 @@ pub mod liquidate_perp {
             ..User::default()
         };
 
-        let user_key = Pubkey::default();
-        let liquidator_key = Pubkey::default();
-
-        let mut user_stats = UserStats::default();
-        let mut liquidator_stats = UserStats::default();
-        let state = State {
-            liquidation_margin_buffer_ratio: 10,
-            ..Default::default()
-        };
+        let mut backstop_user = User::default();
+        let backstop_vault = BackstopVault::default();
 
-        let result = liquidate_perp(
+        let result = liquidate_perp_with_backstop(
             0,
             BASE_PRECISION_U64,
-            Some(150 * PRICE_PRECISION_U64),
+            None,
             &mut user,
-            &user_key,
-            &mut user_stats,
-            &mut liquidator,
-            &liquidator_key,
-            &mut liquidator_stats,
+            &Pubkey::default(),
+            &mut UserStats::default(),
+            &backstop_vault,
+            &mut backstop_user,
+            &Pubkey::default(),
+            &mut UserStats::default(),
             &perp_market_map,
             &spot_market_map,
             &mut oracle_map,
             slot,
-            now,
-            &state,
+            0,
+            &State::default(),
+            None,
         );
 
-        assert_eq!(result, Err(ErrorCode::LiquidationDoesntSatisfyLimitPrice));
+        assert_eq!(result, Err(ErrorCode::SufficientCollateral));
+        assert!(!user.is_being_liquidated);
     }
 }
 
@@ -1206,8 +2106,9 @@ pub mod liquidate_spot {
     use crate::create_account_info;
     use crate::create_anchor_account_info;
     use crate::math::constants::{
-        LIQUIDATION_FEE_PRECISION, MARGIN_PRECISION, MARGIN_PRECISION_U128, SPOT_BALANCE_PRECISION,
-        SPOT_BALANCE_PRECISION_U64, SPOT_CUMULATIVE_INTEREST_PRECISION, SPOT_WEIGHT_PRECISION,
+        LIQUIDATION_FEE_PRECISION, MARGIN_PRECISION, MARGIN_PRECISION_U128, MAX_OPEN_ORDERS,
+        MAX_PERP_POSITIONS, MAX_SPOT_POSITIONS, SPOT_BALANCE_PRECISION, SPOT_BALANCE_PRECISION_U64,
+        SPOT_CUMULATIVE_INTEREST_PRECISION, SPOT_WEIGHT_PRECISION,
     };
     use crate::math::margin::{
         calculate_margin_requirement_and_total_collateral, MarginRequirementType,
@@ -1282,7 +2183,7 @@ pub mod liquidate_spot {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -1296,8 +2197,8 @@ pub mod liquidate_spot {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); MAX_OPEN_ORDERS as usize],
+            perp_positions: [PerpPosition::default(); MAX_PERP_POSITIONS as usize],
             spot_positions,
             ..User::default()
         };
@@ -1329,6 +2230,7 @@ pub mod liquidate_spot {
             now,
             slot,
             10,
+            None,
         )
         .unwrap();
 
@@ -1406,7 +2308,7 @@ pub mod liquidate_spot {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_market = [SpotPosition::default(); 8];
+        let mut spot_market = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         spot_market[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -1420,8 +2322,8 @@ pub mod liquidate_spot {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); MAX_OPEN_ORDERS as usize],
+            perp_positions: [PerpPosition::default(); MAX_PERP_POSITIONS as usize],
             spot_positions: spot_market,
             ..User::default()
         };
@@ -1454,6 +2356,7 @@ pub mod liquidate_spot {
             now,
             slot,
             10,
+            None,
         )
         .is_err());
 
@@ -1479,6 +2382,7 @@ pub mod liquidate_spot {
             now,
             slot,
             10,
+            None,
         )
         .unwrap();
 
@@ -1557,7 +2461,7 @@ pub mod liquidate_spot {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -1571,8 +2475,8 @@ pub mod liquidate_spot {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); MAX_OPEN_ORDERS as usize],
+            perp_positions: [PerpPosition::default(); MAX_PERP_POSITIONS as usize],
             spot_positions,
             ..User::default()
         };
@@ -1606,6 +2510,7 @@ pub mod liquidate_spot {
             now,
             slot,
             liquidation_buffer, // 2%
+            None,
         )
         .unwrap();
 
@@ -1709,9 +2614,9 @@ pub mod liquidate_borrow_for_perp_pnl {
     use crate::create_anchor_account_info;
     use crate::math::constants::{
         AMM_RESERVE_PRECISION, BASE_PRECISION_I128, LIQUIDATION_FEE_PRECISION, MARGIN_PRECISION,
-        MARGIN_PRECISION_U128, PEG_PRECISION, QUOTE_PRECISION_I128, QUOTE_PRECISION_I64,
-        SPOT_BALANCE_PRECISION, SPOT_BALANCE_PRECISION_U64, SPOT_CUMULATIVE_INTEREST_PRECISION,
-        SPOT_WEIGHT_PRECISION,
+        MARGIN_PRECISION_U128, MAX_OPEN_ORDERS, MAX_SPOT_POSITIONS, PEG_PRECISION,
+        QUOTE_PRECISION_I128, QUOTE_PRECISION_I64, SPOT_BALANCE_PRECISION,
+        SPOT_BALANCE_PRECISION_U64, SPOT_CUMULATIVE_INTEREST_PRECISION, SPOT_WEIGHT_PRECISION,
     };
     use crate::math::margin::{
         calculate_margin_requirement_and_total_collateral, MarginRequirementType,
@@ -1815,7 +2720,7 @@ pub mod liquidate_borrow_for_perp_pnl {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         spot_positions[0] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Borrow,
@@ -1823,7 +2728,7 @@ pub mod liquidate_borrow_for_perp_pnl {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); MAX_OPEN_ORDERS as usize],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 quote_asset_amount: 100 * QUOTE_PRECISION_I64,
@@ -1860,6 +2765,7 @@ pub mod liquidate_borrow_for_perp_pnl {
             now,
             slot,
             10,
+            None,
         )
         .unwrap();
 
@@ -1962,7 +2868,7 @@ pub mod liquidate_borrow_for_perp_pnl {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         spot_positions[0] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Borrow,
@@ -1970,7 +2876,7 @@ pub mod liquidate_borrow_for_perp_pnl {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); MAX_OPEN_ORDERS as usize],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 quote_asset_amount: 105 * QUOTE_PRECISION_I64,
@@ -2008,6 +2914,7 @@ pub mod liquidate_borrow_for_perp_pnl {
             now,
             slot,
             liquidation_buffer,
+            None,
         )
         .unwrap();
 
@@ -2146,7 +3053,7 @@ pub mod liquidate_borrow_for_perp_pnl {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         spot_positions[0] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Borrow,
@@ -2154,7 +3061,7 @@ pub mod liquidate_borrow_for_perp_pnl {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); MAX_OPEN_ORDERS as usize],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 quote_asset_amount: 80 * QUOTE_PRECISION_I64,
@@ -2191,6 +3098,7 @@ pub mod liquidate_borrow_for_perp_pnl {
             now,
             slot,
             10,
+            None,
         )
         .unwrap();
 
@@ -2217,8 +3125,9 @@ pub mod liquidate_perp_pnl_for_deposit {
     use crate::create_anchor_account_info;
     use crate::math::constants::{
         AMM_RESERVE_PRECISION, BASE_PRECISION_I128, LIQUIDATION_FEE_PRECISION, MARGIN_PRECISION,
-        PEG_PRECISION, QUOTE_PRECISION_I128, QUOTE_PRECISION_I64, SPOT_BALANCE_PRECISION,
-        SPOT_BALANCE_PRECISION_U64, SPOT_CUMULATIVE_INTEREST_PRECISION, SPOT_WEIGHT_PRECISION,
+        MAX_OPEN_ORDERS, MAX_SPOT_POSITIONS, PEG_PRECISION, QUOTE_PRECISION_I128,
+        QUOTE_PRECISION_I64, SPOT_BALANCE_PRECISION, SPOT_BALANCE_PRECISION_U64,
+        SPOT_CUMULATIVE_INTEREST_PRECISION, SPOT_WEIGHT_PRECISION,
     };
     use crate::state::oracle::HistoricalOracleData;
     use crate::state::oracle::OracleSource;
@@ -2318,7 +3227,7 @@ pub mod liquidate_perp_pnl_for_deposit {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         spot_positions[0] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -2326,7 +3235,7 @@ pub mod liquidate_perp_pnl_for_deposit {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); MAX_OPEN_ORDERS as usize],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 quote_asset_amount: -100 * QUOTE_PRECISION_I64,
@@ -2363,6 +3272,7 @@ pub mod liquidate_perp_pnl_for_deposit {
             now,
             slot,
             10,
+            None,
         )
         .unwrap();
 
@@ -2465,7 +3375,7 @@ pub mod liquidate_perp_pnl_for_deposit {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         spot_positions[0] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -2473,7 +3383,7 @@ pub mod liquidate_perp_pnl_for_deposit {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); MAX_OPEN_ORDERS as usize],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 quote_asset_amount: -91 * QUOTE_PRECISION_I64,
@@ -2510,6 +3420,7 @@ pub mod liquidate_perp_pnl_for_deposit {
             now,
             slot,
             MARGIN_PRECISION as u32 / 50,
+            None,
         )
         .unwrap();
 
@@ -2614,7 +3525,7 @@ pub mod liquidate_perp_pnl_for_deposit {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         spot_positions[0] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -2622,7 +3533,7 @@ pub mod liquidate_perp_pnl_for_deposit {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); MAX_OPEN_ORDERS as usize],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 quote_asset_amount: -150 * QUOTE_PRECISION_I64,
@@ -2659,6 +3570,7 @@ pub mod liquidate_perp_pnl_for_deposit {
             now,
             slot,
             10,
+            None,
         )
         .unwrap();
 
@@ -2688,8 +3600,9 @@ pub mod resolve_perp_bankruptcy {
     use crate::math::constants::{
         AMM_RESERVE_PRECISION, BASE_PRECISION_I128, BASE_PRECISION_I64, BASE_PRECISION_U64,
         FUNDING_RATE_PRECISION_I128, FUNDING_RATE_PRECISION_I64, LIQUIDATION_FEE_PRECISION,
-        PEG_PRECISION, QUOTE_PRECISION_I128, QUOTE_PRECISION_I64, SPOT_BALANCE_PRECISION_U64,
-        SPOT_CUMULATIVE_INTEREST_PRECISION, SPOT_WEIGHT_PRECISION,
+        MAX_OPEN_ORDERS, MAX_SPOT_POSITIONS, PEG_PRECISION, QUOTE_PRECISION_I128,
+        QUOTE_PRECISION_I64, SPOT_BALANCE_PRECISION_U64, SPOT_CUMULATIVE_INTEREST_PRECISION,
+        SPOT_WEIGHT_PRECISION,
     };
     use crate::state::oracle::OracleSource;
     use crate::state::oracle_map::OracleMap;
@@ -2781,7 +3694,7 @@ pub mod resolve_perp_bankruptcy {
                 open_bids: BASE_PRECISION_I64,
                 ..PerpPosition::default()
             }),
-            spot_positions: [SpotPosition::default(); 8],
+            spot_positions: [SpotPosition::default(); MAX_SPOT_POSITIONS as usize],
             is_bankrupt: true,
             is_being_liquidated: false,
             next_liquidation_id: 2,
@@ -2824,6 +3737,7 @@ pub mod resolve_perp_bankruptcy {
             &mut oracle_map,
             now,
             0,
+            0,
         )
         .unwrap();
 
@@ -2831,7 +3745,7 @@ pub mod resolve_perp_bankruptcy {
         assert_eq!(expected_market, market_map.get_ref(&0).unwrap().clone());
 
         let mut affected_long_user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); MAX_OPEN_ORDERS as usize],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 base_asset_amount: 5 * BASE_PRECISION_I64,
@@ -2842,7 +3756,7 @@ pub mod resolve_perp_bankruptcy {
                 last_cumulative_funding_rate: 1000 * FUNDING_RATE_PRECISION_I64,
                 ..PerpPosition::default()
             }),
-            spot_positions: [SpotPosition::default(); 8],
+            spot_positions: [SpotPosition::default(); MAX_SPOT_POSITIONS as usize],
             ..User::default()
         };
 
@@ -2869,7 +3783,7 @@ pub mod resolve_perp_bankruptcy {
         assert_eq!(expected_affected_long_user, affected_long_user);
 
         let mut affected_short_user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); MAX_OPEN_ORDERS as usize],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 base_asset_amount: -5 * BASE_PRECISION_I64,
@@ -2880,7 +3794,7 @@ pub mod resolve_perp_bankruptcy {
                 last_cumulative_funding_rate: -1000 * FUNDING_RATE_PRECISION_I64,
                 ..PerpPosition::default()
             }),
-            spot_positions: [SpotPosition::default(); 8],
+            spot_positions: [SpotPosition::default(); MAX_SPOT_POSITIONS as usize],
             ..User::default()
         };
 
@@ -2920,8 +3834,8 @@ pub mod resolve_spot_bankruptcy {
     use crate::create_anchor_account_info;
     use crate::math::constants::{
         AMM_RESERVE_PRECISION, BASE_PRECISION_I128, BASE_PRECISION_U64,
-        FUNDING_RATE_PRECISION_I128, LIQUIDATION_FEE_PRECISION, PEG_PRECISION, QUOTE_PRECISION,
-        QUOTE_PRECISION_I128, QUOTE_PRECISION_I64, SPOT_BALANCE_PRECISION,
+        FUNDING_RATE_PRECISION_I128, LIQUIDATION_FEE_PRECISION, MAX_PERP_POSITIONS, PEG_PRECISION,
+        QUOTE_PRECISION, QUOTE_PRECISION_I128, QUOTE_PRECISION_I64, SPOT_BALANCE_PRECISION,
         SPOT_BALANCE_PRECISION_U64, SPOT_CUMULATIVE_INTEREST_PRECISION, SPOT_WEIGHT_PRECISION,
     };
     use crate::math::spot_balance::get_token_amount;
@@ -3007,7 +3921,7 @@ pub mod resolve_spot_bankruptcy {
                 slot: 0,
                 ..Order::default()
             }),
-            perp_positions: [PerpPosition::default(); 8],
+            perp_positions: [PerpPosition::default(); MAX_PERP_POSITIONS as usize],
             spot_positions: get_spot_positions(SpotPosition {
                 market_index: 0,
                 scaled_balance: 100 * SPOT_BALANCE_PRECISION_U64,
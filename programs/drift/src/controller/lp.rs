@@ -1,26 +1,35 @@
-use anchor_lang::prelude::{msg, Pubkey};
+use anchor_lang::prelude::{msg, AccountLoader, Clock, Key, Pubkey};
 
 use crate::bn::U192;
 use crate::controller;
 use crate::controller::position::PositionDelta;
-use crate::controller::position::{update_position_and_market, update_quote_asset_amount};
+use crate::controller::position::{
+    update_position_and_market, update_quote_asset_amount, update_quote_asset_and_break_even_amount,
+};
 use crate::emit;
 use crate::error::{DriftResult, ErrorCode};
-use crate::get_struct_values;
+use crate::instructions::{LPSharesParams, OrderParams};
 use crate::math::casting::Cast;
+use crate::math::constants::MAX_PERP_POSITIONS;
 use crate::math::cp_curve::{get_update_k_result, update_k};
-use crate::math::lp::calculate_settle_lp_metrics;
+use crate::math::lp::{
+    calculate_lp_hedge_base_asset_amount, calculate_lp_hedge_limit_price,
+    calculate_settle_lp_metrics, get_lp_per_share_accumulators, is_lp_position_active,
+};
+use crate::math::margin::meets_initial_margin_requirement;
 use crate::math::position::calculate_base_asset_value_with_oracle_price;
 use crate::math::safe_math::SafeMath;
 
 use crate::state::events::{LPAction, LPRecord};
 use crate::state::oracle_map::OracleMap;
-use crate::state::perp_market::PerpMarket;
+use crate::state::perp_market::{MarketStatus, PerpMarket};
 use crate::state::perp_market_map::PerpMarketMap;
+use crate::state::spot_market_map::SpotMarketMap;
 use crate::state::state::State;
 use crate::state::user::PerpPosition;
-use crate::state::user::User;
+use crate::state::user::{MarketType, OrderType, User, UserStats};
 use crate::validate;
+use crate::{load, load_mut};
 use anchor_lang::prelude::Account;
 
 #[cfg(test)]
@@ -31,28 +40,32 @@ pub fn mint_lp_shares(
     market: &mut PerpMarket,
     n_shares: u64,
 ) -> DriftResult<()> {
-    let amm = market.amm;
-
-    let (sqrt_k,) = get_struct_values!(amm, sqrt_k);
-
     if position.lp_shares > 0 {
         settle_lp_position(position, market)?;
     } else {
-        position.last_net_base_asset_amount_per_lp = amm.base_asset_amount_per_lp.cast()?;
-        position.last_net_quote_asset_amount_per_lp = amm.quote_asset_amount_per_lp.cast()?;
+        if position.has_lp_range() {
+            market.force_get_lp_range_mut(
+                position.lp_min_reserve_price,
+                position.lp_max_reserve_price,
+            )?;
+        }
+
+        update_lp_per_share_checkpoints(position, market)?;
     }
 
     // add share balance
     position.lp_shares = position.lp_shares.safe_add(n_shares)?;
 
-    // update market state
-    let new_sqrt_k = sqrt_k.safe_add(n_shares.cast()?)?;
-    let new_sqrt_k_u192 = U192::from(new_sqrt_k);
-
-    let update_k_result = get_update_k_result(market, new_sqrt_k_u192, true)?;
-    update_k(market, &update_k_result)?;
+    // shares of an inactive range join sqrt_k once the reserve price is back in range
+    if position.has_lp_range() {
+        let lp_range = market
+            .get_lp_range_mut(position.lp_min_reserve_price, position.lp_max_reserve_price)?;
+        lp_range.lp_shares = lp_range.lp_shares.safe_add(n_shares.cast()?)?;
+    }
 
-    market.amm.user_lp_shares = market.amm.user_lp_shares.safe_add(n_shares.cast()?)?;
+    if is_lp_position_active(market, position)? {
+        add_lp_shares_to_amm(market, n_shares.cast()?)?;
+    }
 
     crate::validation::perp_market::validate_perp_market(market)?;
     crate::validation::position::validate_perp_position_with_perp_market(position, market)?;
@@ -63,8 +76,8 @@ pub fn mint_lp_shares(
 pub fn settle_lp_position(
     position: &mut PerpPosition,
     market: &mut PerpMarket,
-) -> DriftResult<(PositionDelta, i64)> {
-    let mut lp_metrics = calculate_settle_lp_metrics(&market.amm, position)?;
+) -> DriftResult<(PositionDelta, i64, i64)> {
+    let mut lp_metrics = calculate_settle_lp_metrics(market, position)?;
 
     position.remainder_base_asset_amount = position
         .remainder_base_asset_amount
@@ -91,6 +104,14 @@ pub fn settle_lp_position(
 
     let pnl = update_position_and_market(position, market, &position_delta)?;
 
+    position.lp_base_asset_amount = position
+        .lp_base_asset_amount
+        .safe_add(position_delta.base_asset_amount)?;
+
+    // fees are credited on top of the inventory delta so they don't show up as pnl
+    let fee: i64 = lp_metrics.fee_amount.cast()?;
+    update_quote_asset_and_break_even_amount(position, market, fee)?;
+
     // todo: name for this is confusing, but adding is correct as is
     // definition: net position of users in the market that has the LP as a counterparty (which have NOT settled)
     market.amm.base_asset_amount_with_unsettled_lp = market
@@ -98,13 +119,150 @@ pub fn settle_lp_position(
         .base_asset_amount_with_unsettled_lp
         .safe_add(lp_metrics.base_asset_amount)?;
 
-    position.last_net_base_asset_amount_per_lp = market.amm.base_asset_amount_per_lp.cast()?;
-    position.last_net_quote_asset_amount_per_lp = market.amm.quote_asset_amount_per_lp.cast()?;
+    update_lp_per_share_checkpoints(position, market)?;
+
+    update_lp_ranges(market)?;
 
     crate::validation::perp_market::validate_perp_market(market)?;
     crate::validation::position::validate_perp_position_with_perp_market(position, market)?;
 
-    Ok((position_delta, pnl))
+    Ok((position_delta, pnl, fee))
+}
+
+fn update_lp_per_share_checkpoints(
+    position: &mut PerpPosition,
+    market: &PerpMarket,
+) -> DriftResult {
+    let (base_asset_amount_per_lp, quote_asset_amount_per_lp, fee_per_lp) =
+        get_lp_per_share_accumulators(market, position)?;
+
+    position.last_net_base_asset_amount_per_lp = base_asset_amount_per_lp.cast()?;
+    position.last_net_quote_asset_amount_per_lp = quote_asset_amount_per_lp.cast()?;
+    position.last_fee_per_lp = fee_per_lp.cast()?;
+
+    Ok(())
+}
+
+fn add_lp_shares_to_amm(market: &mut PerpMarket, n_shares: u128) -> DriftResult {
+    let new_sqrt_k = market.amm.sqrt_k.safe_add(n_shares)?;
+    let new_sqrt_k_u192 = U192::from(new_sqrt_k);
+
+    let update_k_result = get_update_k_result(market, new_sqrt_k_u192, true)?;
+    update_k(market, &update_k_result)?;
+
+    market.amm.user_lp_shares = market.amm.user_lp_shares.safe_add(n_shares)?;
+
+    Ok(())
+}
+
+fn remove_lp_shares_from_amm(market: &mut PerpMarket, n_shares: u128) -> DriftResult {
+    market.amm.user_lp_shares = market.amm.user_lp_shares.safe_sub(n_shares)?;
+
+    let new_sqrt_k = market.amm.sqrt_k.safe_sub(n_shares)?;
+    let new_sqrt_k_u192 = U192::from(new_sqrt_k);
+
+    let update_k_result = get_update_k_result(market, new_sqrt_k_u192, false)?;
+    update_k(market, &update_k_result)?;
+
+    Ok(())
+}
+
+/// Moves each lp range's shares in or out of sqrt_k when the reserve price crosses the range.
+/// Ranges are tracked by the market, so every ranged lp stops taking inventory at the crossing
+/// without having to settle. A range stays active while removing its shares would leave the amm
+/// unable to cover its imbalance.
+pub fn update_lp_ranges(market: &mut PerpMarket) -> DriftResult {
+    let reserve_price = market.amm.reserve_price()?;
+
+    for lp_range_index in 0..market.lp_ranges.len() {
+        let lp_range = market.lp_ranges[lp_range_index];
+
+        // ranges without shares are reset when taken over, see force_get_lp_range_mut
+        if lp_range.is_available() {
+            continue;
+        }
+
+        let in_range = lp_range.is_reserve_price_in_range(reserve_price);
+        if in_range == lp_range.active {
+            continue;
+        }
+
+        if in_range {
+            add_lp_shares_to_amm(market, lp_range.lp_shares)?;
+        } else {
+            let new_sqrt_k = market.amm.sqrt_k.safe_sub(lp_range.lp_shares)?;
+            if market.amm.base_asset_amount_with_amm.unsigned_abs() > new_sqrt_k {
+                msg!(
+                    "lp range {} - {} stays active, amm imbalance too large to remove its shares",
+                    lp_range.min_reserve_price,
+                    lp_range.max_reserve_price
+                );
+                continue;
+            }
+
+            remove_lp_shares_from_amm(market, lp_range.lp_shares)?;
+        }
+
+        market.lp_ranges[lp_range_index].active = in_range;
+    }
+
+    Ok(())
+}
+
+pub fn update_lp_range(
+    position: &mut PerpPosition,
+    market: &mut PerpMarket,
+    min_reserve_price: u64,
+    max_reserve_price: u64,
+) -> DriftResult<(PositionDelta, i64, i64)> {
+    validate!(
+        position.lp_shares > 0,
+        ErrorCode::InvalidLPRange,
+        "position has no lp shares in market {}",
+        market.market_index
+    )?;
+
+    validate!(
+        min_reserve_price == 0 || max_reserve_price == 0 || min_reserve_price < max_reserve_price,
+        ErrorCode::InvalidLPRange,
+        "min_reserve_price {} >= max_reserve_price {}",
+        min_reserve_price,
+        max_reserve_price
+    )?;
+
+    // settle what was earned under the old range first
+    let (position_delta, pnl, fee) = settle_lp_position(position, market)?;
+
+    let lp_shares = position.lp_shares.cast::<u128>()?;
+    let was_active = is_lp_position_active(market, position)?;
+
+    if position.has_lp_range() {
+        let lp_range = market
+            .get_lp_range_mut(position.lp_min_reserve_price, position.lp_max_reserve_price)?;
+        lp_range.lp_shares = lp_range.lp_shares.safe_sub(lp_shares)?;
+    }
+
+    position.lp_min_reserve_price = min_reserve_price;
+    position.lp_max_reserve_price = max_reserve_price;
+
+    if position.has_lp_range() {
+        let lp_range = market.force_get_lp_range_mut(min_reserve_price, max_reserve_price)?;
+        lp_range.lp_shares = lp_range.lp_shares.safe_add(lp_shares)?;
+    }
+
+    // ranged and unranged lps settle against different accumulators
+    update_lp_per_share_checkpoints(position, market)?;
+
+    let is_active = is_lp_position_active(market, position)?;
+    if was_active && !is_active {
+        remove_lp_shares_from_amm(market, lp_shares)?;
+    } else if !was_active && is_active {
+        add_lp_shares_to_amm(market, lp_shares)?;
+    }
+
+    crate::validation::perp_market::validate_perp_market(market)?;
+
+    Ok((position_delta, pnl, fee))
 }
 
 pub fn settle_lp(
@@ -115,7 +273,7 @@ pub fn settle_lp(
 ) -> DriftResult {
     if let Ok(position) = user.get_perp_position_mut(market.market_index) {
         if position.lp_shares > 0 {
-            let (position_delta, pnl) = settle_lp_position(position, market)?;
+            let (position_delta, pnl, fee) = settle_lp_position(position, market)?;
 
             crate::emit!(LPRecord {
                 ts: now,
@@ -125,6 +283,7 @@ pub fn settle_lp(
                 delta_base_asset_amount: position_delta.base_asset_amount,
                 delta_quote_asset_amount: position_delta.quote_asset_amount,
                 pnl,
+                fee,
                 n_shares: 0
             });
         }
@@ -151,9 +310,9 @@ pub fn burn_lp_shares(
     market: &mut PerpMarket,
     shares_to_burn: u64,
     oracle_price: i64,
-) -> DriftResult<(PositionDelta, i64)> {
+) -> DriftResult<(PositionDelta, i64, i64)> {
     // settle
-    let (position_delta, pnl) = settle_lp_position(position, market)?;
+    let (position_delta, pnl, fee) = settle_lp_position(position, market)?;
 
     // clean up
     let unsettled_remainder = market
@@ -161,7 +320,10 @@ pub fn burn_lp_shares(
         .base_asset_amount_with_unsettled_lp
         .safe_add(position.remainder_base_asset_amount.cast()?)?;
 
-    if shares_to_burn as u128 == market.amm.user_lp_shares && unsettled_remainder != 0 {
+    let is_active = is_lp_position_active(market, position)?;
+
+    if is_active && shares_to_burn as u128 == market.amm.user_lp_shares && unsettled_remainder != 0
+    {
         crate::validate!(
             unsettled_remainder.unsigned_abs() <= market.amm.order_step_size as u128,
             ErrorCode::UnableToBurnLPTokens,
@@ -201,29 +363,152 @@ pub fn burn_lp_shares(
     }
 
     // update last_ metrics
-    position.last_net_base_asset_amount_per_lp = market.amm.base_asset_amount_per_lp.cast()?;
-    position.last_net_quote_asset_amount_per_lp = market.amm.quote_asset_amount_per_lp.cast()?;
+    update_lp_per_share_checkpoints(position, market)?;
 
     // burn shares
     position.lp_shares = position.lp_shares.safe_sub(shares_to_burn)?;
 
-    market.amm.user_lp_shares = market.amm.user_lp_shares.safe_sub(shares_to_burn.cast()?)?;
+    // what's left is no longer lp inventory
+    if position.lp_shares == 0 {
+        position.lp_base_asset_amount = 0;
+    }
 
-    // update market state
-    let new_sqrt_k = market.amm.sqrt_k.safe_sub(shares_to_burn.cast()?)?;
-    let new_sqrt_k_u192 = U192::from(new_sqrt_k);
+    if position.has_lp_range() {
+        let lp_range = market
+            .get_lp_range_mut(position.lp_min_reserve_price, position.lp_max_reserve_price)?;
+        lp_range.lp_shares = lp_range.lp_shares.safe_sub(shares_to_burn.cast()?)?;
+    }
 
-    let update_k_result = get_update_k_result(market, new_sqrt_k_u192, false)?;
-    update_k(market, &update_k_result)?;
+    // shares of an inactive range already left sqrt_k
+    if is_active {
+        remove_lp_shares_from_amm(market, shares_to_burn.cast()?)?;
+    }
 
     crate::validation::perp_market::validate_perp_market(market)?;
     crate::validation::position::validate_perp_position_with_perp_market(position, market)?;
 
-    Ok((position_delta, pnl))
+    Ok((position_delta, pnl, fee))
+}
+
+/// Settles funding and mints lp shares for one market. Callers run the margin check,
+/// so several markets can be added before checking once.
+pub fn add_perp_lp_shares(
+    perp_market_map: &PerpMarketMap,
+    user: &mut User,
+    user_key: Pubkey,
+    n_shares: u64,
+    market_index: u16,
+    now: i64,
+) -> DriftResult {
+    let mut market = perp_market_map.get_ref_mut(&market_index)?;
+
+    validate!(
+        matches!(
+            market.status,
+            MarketStatus::Active
+                | MarketStatus::FundingPaused
+                | MarketStatus::FillPaused
+                | MarketStatus::WithdrawPaused
+        ),
+        ErrorCode::MarketStatusInvalidForNewLP,
+        "Market Status doesn't allow for new LP liquidity"
+    )?;
+
+    validate!(
+        n_shares >= market.amm.order_step_size,
+        ErrorCode::NewLPSizeTooSmall,
+        "minting {} shares is less than step size {}",
+        n_shares,
+        market.amm.order_step_size,
+    )?;
+
+    controller::funding::settle_funding_payment(user, &user_key, &mut market, now)?;
+
+    // standardize n shares to mint
+    let n_shares_to_mint = crate::math::orders::standardize_base_asset_amount(
+        n_shares.cast()?,
+        market.amm.order_step_size,
+    )?
+    .cast::<u64>()?;
+
+    mint_lp_shares(
+        user.force_get_perp_position_mut(market_index)?,
+        &mut market,
+        n_shares_to_mint,
+    )?;
+
+    user.last_add_perp_lp_shares_ts = now;
+
+    emit!(LPRecord {
+        ts: now,
+        action: LPAction::AddLiquidity,
+        user: user_key,
+        n_shares,
+        market_index,
+        ..LPRecord::default()
+    });
+
+    Ok(())
+}
+
+pub fn validate_lp_shares_batch(params: &[LPSharesParams]) -> DriftResult {
+    validate!(
+        !params.is_empty() && params.len() <= MAX_PERP_POSITIONS as usize,
+        ErrorCode::InvalidLPSharesBatch,
+        "batch has {} markets, must be between 1 and {}",
+        params.len(),
+        MAX_PERP_POSITIONS
+    )?;
+
+    for (i, param) in params.iter().enumerate() {
+        validate!(
+            !params[..i]
+                .iter()
+                .any(|other| other.market_index == param.market_index),
+            ErrorCode::InvalidLPSharesBatch,
+            "market {} is in the batch more than once",
+            param.market_index
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Mints lp shares in every market of the batch, then checks initial margin once against the
+/// combined position
+pub fn add_perp_lp_shares_batch(
+    perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
+    oracle_map: &mut OracleMap,
+    user: &mut User,
+    user_key: Pubkey,
+    params: &[LPSharesParams],
+    now: i64,
+) -> DriftResult {
+    validate_lp_shares_batch(params)?;
+
+    for param in params.iter() {
+        add_perp_lp_shares(
+            perp_market_map,
+            user,
+            user_key,
+            param.n_shares,
+            param.market_index,
+            now,
+        )?;
+    }
+
+    validate!(
+        meets_initial_margin_requirement(user, perp_market_map, spot_market_map, oracle_map)?,
+        ErrorCode::InsufficientCollateral,
+        "User does not meet initial margin requirement"
+    )?;
+
+    Ok(())
 }
 
 pub fn remove_perp_lp_shares(
-    perp_market_map: PerpMarketMap,
+    perp_market_map: &PerpMarketMap,
     oracle_map: &mut OracleMap,
     state: &Account<State>,
     user: &mut std::cell::RefMut<User>,
@@ -265,7 +550,7 @@ pub fn remove_perp_lp_shares(
     )?;
 
     let oracle_price = oracle_map.get_price_data(&market.amm.oracle)?.price;
-    let (position_delta, pnl) =
+    let (position_delta, pnl, fee) =
         burn_lp_shares(position, &mut market, shares_to_burn, oracle_price)?;
 
     emit!(LPRecord {
@@ -277,7 +562,127 @@ pub fn remove_perp_lp_shares(
         delta_base_asset_amount: position_delta.base_asset_amount,
         delta_quote_asset_amount: position_delta.quote_asset_amount,
         pnl,
+        fee,
     });
 
     Ok(())
 }
+
+/// Flattens the lp base of a position that opted into hedging. The reduce only order is limited
+/// to LP_HEDGE_MAX_ORACLE_SLIPPAGE from the oracle, so a maker supplied by the filler can't take
+/// the lp's inventory at a worse price. Returns the base asset amount hedged.
+pub fn hedge_lp_position(
+    market_index: u16,
+    state: &State,
+    user: &AccountLoader<User>,
+    user_stats: &AccountLoader<UserStats>,
+    perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
+    oracle_map: &mut OracleMap,
+    filler: &AccountLoader<User>,
+    filler_stats: &AccountLoader<UserStats>,
+    maker: Option<&AccountLoader<User>>,
+    maker_stats: Option<&AccountLoader<UserStats>>,
+    maker_order_id: Option<u32>,
+    referrer: Option<&AccountLoader<User>>,
+    referrer_stats: Option<&AccountLoader<UserStats>>,
+    clock: &Clock,
+) -> DriftResult<u64> {
+    let (params, base_asset_amount_before) = {
+        let user_key = user.key();
+        let user = &mut load_mut!(user)?;
+        let market = &mut perp_market_map.get_ref_mut(&market_index)?;
+
+        settle_funding_payment_then_lp(user, &user_key, market, clock.unix_timestamp)?;
+
+        let position = user.get_perp_position(market_index)?;
+        let base_asset_amount =
+            calculate_lp_hedge_base_asset_amount(position, market.amm.order_step_size)?;
+
+        validate!(
+            base_asset_amount > 0,
+            ErrorCode::LPHedgeNotNeeded,
+            "lp base asset amount {} within hedge threshold {}",
+            position.get_lp_base_asset_amount(),
+            position.lp_hedge_threshold
+        )?;
+
+        let direction = position.get_direction_to_close();
+        let limit_price = calculate_lp_hedge_limit_price(
+            oracle_map.get_price_data(&market.amm.oracle)?.price,
+            direction,
+            market.amm.order_tick_size,
+        )?;
+
+        let params = OrderParams {
+            order_type: OrderType::Limit,
+            market_type: MarketType::Perp,
+            direction,
+            base_asset_amount,
+            price: limit_price,
+            market_index,
+            reduce_only: true,
+            immediate_or_cancel: true,
+            ..OrderParams::default()
+        };
+
+        (params, position.base_asset_amount)
+    };
+
+    controller::orders::place_perp_order(
+        state,
+        user,
+        perp_market_map,
+        spot_market_map,
+        oracle_map,
+        clock,
+        params,
+    )?;
+
+    let order_id = load!(user)?.get_last_order_id();
+
+    controller::orders::fill_perp_order(
+        order_id,
+        state,
+        user,
+        user_stats,
+        spot_market_map,
+        perp_market_map,
+        oracle_map,
+        filler,
+        filler_stats,
+        maker,
+        maker_stats,
+        maker_order_id,
+        referrer,
+        referrer_stats,
+        clock,
+    )?;
+
+    let order_exists = load!(user)?
+        .orders
+        .iter()
+        .any(|order| order.order_id == order_id);
+
+    if order_exists {
+        controller::orders::cancel_order_by_order_id(
+            order_id,
+            user,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            clock,
+        )?;
+    }
+
+    let user = &mut load_mut!(user)?;
+    let position = user.get_perp_position_mut(market_index)?;
+    let base_asset_amount_hedged = position
+        .base_asset_amount
+        .safe_sub(base_asset_amount_before)?;
+    position.lp_base_asset_amount = position
+        .lp_base_asset_amount
+        .safe_add(base_asset_amount_hedged)?;
+
+    Ok(base_asset_amount_hedged.unsigned_abs())
+}
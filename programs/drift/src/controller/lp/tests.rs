@@ -1,5 +1,10 @@
 use crate::controller::lp::*;
-use crate::math::constants::{AMM_RESERVE_PRECISION, BASE_PRECISION_U64};
+use crate::controller::position::{update_lp_market_position, PositionDelta};
+use crate::math::constants::{
+    AMM_RESERVE_PRECISION, BASE_PRECISION_U64, MAX_LP_RANGES, PEG_PRECISION, PRICE_PRECISION_U64,
+    QUOTE_PRECISION,
+};
+use crate::math::lp::calculate_settle_lp_metrics;
 use crate::state::perp_market::AMM;
 use crate::state::user::PerpPosition;
 
@@ -143,3 +148,836 @@ fn test_partial_long_settle() {
     assert_eq!(position.last_net_base_asset_amount_per_lp, -10);
     assert_eq!(position.last_net_quote_asset_amount_per_lp, 10);
 }
+
+#[test]
+fn test_lp_range() {
+    let mut position = PerpPosition {
+        lp_min_reserve_price: 2 * PRICE_PRECISION_U64,
+        ..PerpPosition::default()
+    };
+
+    let amm = AMM {
+        order_step_size: 1,
+        ..AMM::default_test()
+    };
+    let mut market = PerpMarket {
+        amm,
+        ..PerpMarket::default_test()
+    };
+    let og_market = market;
+
+    // reserve price is $1, below the range so the shares stay out of sqrt_k
+    mint_lp_shares(&mut position, &mut market, BASE_PRECISION_U64).unwrap();
+    let lp_range = *market.get_lp_range(2 * PRICE_PRECISION_U64, 0).unwrap();
+    assert!(!lp_range.active);
+    assert_eq!(lp_range.lp_shares, BASE_PRECISION_U64 as u128);
+    assert_eq!(position.lp_shares, BASE_PRECISION_U64);
+    assert_eq!(market.amm.sqrt_k, og_market.amm.sqrt_k);
+    assert_eq!(market.amm.user_lp_shares, 0);
+
+    // trades while out of range aren't attributed to the lp
+    let delta = PositionDelta {
+        base_asset_amount: 10 * BASE_PRECISION_U64 as i64,
+        quote_asset_amount: -10 * QUOTE_PRECISION as i64,
+    };
+    update_lp_market_position(&mut market, &delta, 0).unwrap();
+    assert_eq!(
+        *market.get_lp_range(2 * PRICE_PRECISION_U64, 0).unwrap(),
+        lp_range
+    );
+    settle_lp_position(&mut position, &mut market).unwrap();
+    assert_eq!(position.base_asset_amount, 0);
+    assert_eq!(position.quote_asset_amount, 0);
+
+    // range now covers the reserve price, shares join sqrt_k
+    update_lp_range(
+        &mut position,
+        &mut market,
+        PRICE_PRECISION_U64 / 2,
+        2 * PRICE_PRECISION_U64,
+    )
+    .unwrap();
+    assert_eq!(
+        market.amm.sqrt_k,
+        og_market.amm.sqrt_k + BASE_PRECISION_U64 as u128
+    );
+    assert_eq!(market.amm.user_lp_shares, BASE_PRECISION_U64 as u128);
+    // the old range had no shares left and its slot was reused
+    assert!(market.get_lp_range(2 * PRICE_PRECISION_U64, 0).is_err());
+    let lp_range = market
+        .get_lp_range(PRICE_PRECISION_U64 / 2, 2 * PRICE_PRECISION_U64)
+        .unwrap();
+    assert!(lp_range.active);
+    assert_eq!(lp_range.lp_shares, BASE_PRECISION_U64 as u128);
+
+    let lp_range = market
+        .get_lp_range_mut(PRICE_PRECISION_U64 / 2, 2 * PRICE_PRECISION_U64)
+        .unwrap();
+    lp_range.base_asset_amount_per_lp = 10;
+    lp_range.quote_asset_amount_per_lp = -10;
+    market.amm.base_asset_amount_with_unsettled_lp = -10;
+    market.amm.base_asset_amount_short = -10;
+
+    settle_lp_position(&mut position, &mut market).unwrap();
+    assert_eq!(position.base_asset_amount, 10);
+    assert_eq!(position.quote_asset_amount, -10);
+
+    // invalid range
+    assert!(update_lp_range(
+        &mut position,
+        &mut market,
+        2 * PRICE_PRECISION_U64,
+        PRICE_PRECISION_U64
+    )
+    .is_err());
+
+    burn_lp_shares(&mut position, &mut market, BASE_PRECISION_U64, 0).unwrap();
+    assert_eq!(position.lp_shares, 0);
+    assert_eq!(market.amm.user_lp_shares, 0);
+    assert_eq!(market.amm.sqrt_k, og_market.amm.sqrt_k);
+    assert!(market
+        .get_lp_range(PRICE_PRECISION_U64 / 2, 2 * PRICE_PRECISION_U64)
+        .unwrap()
+        .is_available());
+}
+
+#[test]
+fn test_lp_ranges_leave_sqrt_k_independently() {
+    let amm = AMM {
+        order_step_size: 1,
+        ..AMM::default_test()
+    };
+    let mut market = PerpMarket {
+        amm,
+        ..PerpMarket::default_test()
+    };
+
+    let mut narrow_position = PerpPosition {
+        lp_max_reserve_price: 2 * PRICE_PRECISION_U64,
+        ..PerpPosition::default()
+    };
+    let mut wide_position = PerpPosition {
+        lp_max_reserve_price: 4 * PRICE_PRECISION_U64,
+        ..PerpPosition::default()
+    };
+    let mut position = PerpPosition::default();
+
+    // reserve price is $1, inside both ranges. the narrow lp is tiny
+    mint_lp_shares(&mut narrow_position, &mut market, BASE_PRECISION_U64 / 100).unwrap();
+    mint_lp_shares(&mut wide_position, &mut market, BASE_PRECISION_U64).unwrap();
+    mint_lp_shares(&mut position, &mut market, BASE_PRECISION_U64).unwrap();
+    let sqrt_k_before = market.amm.sqrt_k;
+    assert_eq!(
+        market.amm.user_lp_shares,
+        2 * BASE_PRECISION_U64 as u128 + BASE_PRECISION_U64 as u128 / 100
+    );
+
+    let delta = PositionDelta {
+        base_asset_amount: 10 * BASE_PRECISION_U64 as i64,
+        quote_asset_amount: -10 * QUOTE_PRECISION as i64,
+    };
+
+    // in range, every lp takes its slice
+    update_lp_market_position(&mut market, &delta, 0).unwrap();
+    let narrow_base_asset_amount_per_lp = market
+        .get_lp_range(0, 2 * PRICE_PRECISION_U64)
+        .unwrap()
+        .base_asset_amount_per_lp;
+    assert_ne!(narrow_base_asset_amount_per_lp, 0);
+    assert_eq!(
+        narrow_base_asset_amount_per_lp,
+        market.amm.base_asset_amount_per_lp
+    );
+    assert_eq!(
+        market
+            .get_lp_range(0, 4 * PRICE_PRECISION_U64)
+            .unwrap()
+            .base_asset_amount_per_lp,
+        market.amm.base_asset_amount_per_lp
+    );
+
+    // reserve price moves to $3, the narrow range leaves sqrt_k without its lp settling
+    market.amm.peg_multiplier = 3 * PEG_PRECISION;
+    update_lp_ranges(&mut market).unwrap();
+    assert!(
+        !market
+            .get_lp_range(0, 2 * PRICE_PRECISION_U64)
+            .unwrap()
+            .active
+    );
+    assert!(
+        market
+            .get_lp_range(0, 4 * PRICE_PRECISION_U64)
+            .unwrap()
+            .active
+    );
+    assert_eq!(
+        market.amm.sqrt_k,
+        sqrt_k_before - BASE_PRECISION_U64 as u128 / 100
+    );
+    assert_eq!(market.amm.user_lp_shares, 2 * BASE_PRECISION_U64 as u128);
+
+    // the wide range keeps taking its slice, the narrow one stops
+    let base_asset_amount_per_lp_before = market.amm.base_asset_amount_per_lp;
+    let wide_base_asset_amount_per_lp_before = market
+        .get_lp_range(0, 4 * PRICE_PRECISION_U64)
+        .unwrap()
+        .base_asset_amount_per_lp;
+    update_lp_market_position(&mut market, &delta, 0).unwrap();
+    assert_eq!(
+        market
+            .get_lp_range(0, 2 * PRICE_PRECISION_U64)
+            .unwrap()
+            .base_asset_amount_per_lp,
+        narrow_base_asset_amount_per_lp
+    );
+    assert_eq!(
+        market
+            .get_lp_range(0, 4 * PRICE_PRECISION_U64)
+            .unwrap()
+            .base_asset_amount_per_lp
+            - wide_base_asset_amount_per_lp_before,
+        market.amm.base_asset_amount_per_lp - base_asset_amount_per_lp_before
+    );
+
+    let narrow_lp_metrics = calculate_settle_lp_metrics(&market, &narrow_position).unwrap();
+    let wide_lp_metrics = calculate_settle_lp_metrics(&market, &wide_position).unwrap();
+    let lp_metrics = calculate_settle_lp_metrics(&market, &position).unwrap();
+    assert_eq!(
+        narrow_lp_metrics.base_asset_amount,
+        narrow_base_asset_amount_per_lp / 100
+    );
+    assert_eq!(
+        wide_lp_metrics.base_asset_amount,
+        lp_metrics.base_asset_amount
+    );
+
+    // back in range, the narrow range rejoins sqrt_k
+    market.amm.peg_multiplier = PEG_PRECISION;
+    update_lp_ranges(&mut market).unwrap();
+    assert!(
+        market
+            .get_lp_range(0, 2 * PRICE_PRECISION_U64)
+            .unwrap()
+            .active
+    );
+    assert_eq!(
+        market.amm.user_lp_shares,
+        2 * BASE_PRECISION_U64 as u128 + BASE_PRECISION_U64 as u128 / 100
+    );
+}
+
+#[test]
+fn test_max_lp_ranges() {
+    let amm = AMM {
+        order_step_size: 1,
+        ..AMM::default_test()
+    };
+    let mut market = PerpMarket {
+        amm,
+        ..PerpMarket::default_test()
+    };
+
+    for i in 0..MAX_LP_RANGES as u64 {
+        let mut position = PerpPosition {
+            lp_max_reserve_price: (2 + i) * PRICE_PRECISION_U64,
+            ..PerpPosition::default()
+        };
+        mint_lp_shares(&mut position, &mut market, BASE_PRECISION_U64).unwrap();
+    }
+
+    let mut position = PerpPosition {
+        lp_max_reserve_price: 100 * PRICE_PRECISION_U64,
+        ..PerpPosition::default()
+    };
+    assert!(mint_lp_shares(&mut position, &mut market, BASE_PRECISION_U64).is_err());
+
+    // existing ranges can still be joined
+    let mut position = PerpPosition {
+        lp_max_reserve_price: 2 * PRICE_PRECISION_U64,
+        ..PerpPosition::default()
+    };
+    mint_lp_shares(&mut position, &mut market, BASE_PRECISION_U64).unwrap();
+    assert_eq!(
+        market
+            .get_lp_range(0, 2 * PRICE_PRECISION_U64)
+            .unwrap()
+            .lp_shares,
+        2 * BASE_PRECISION_U64 as u128
+    );
+}
+
+#[test]
+fn test_lp_fee_settle() {
+    let mut position = PerpPosition {
+        ..PerpPosition::default()
+    };
+
+    let amm = AMM {
+        order_step_size: 1,
+        ..AMM::default_test()
+    };
+    let mut market = PerpMarket {
+        amm,
+        ..PerpMarket::default_test()
+    };
+
+    // fees earned before minting don't belong to the lp
+    market.fee_per_lp = QUOTE_PRECISION;
+    mint_lp_shares(&mut position, &mut market, BASE_PRECISION_U64).unwrap();
+    assert_eq!(position.last_fee_per_lp, QUOTE_PRECISION as u64);
+
+    market.fee_per_lp = 3 * QUOTE_PRECISION;
+    market.amm.base_asset_amount_per_lp = 10;
+    market.amm.quote_asset_amount_per_lp = -10;
+    market.amm.base_asset_amount_with_unsettled_lp = -10;
+    market.amm.base_asset_amount_short = -10;
+
+    let (position_delta, pnl, fee) = settle_lp_position(&mut position, &mut market).unwrap();
+    assert_eq!(position_delta.quote_asset_amount, -10);
+    assert_eq!(pnl, 0);
+    assert_eq!(fee, 2 * QUOTE_PRECISION as i64);
+    assert_eq!(
+        position.quote_asset_amount,
+        -10 + 2 * QUOTE_PRECISION as i64
+    );
+    assert_eq!(
+        position.quote_break_even_amount,
+        -10 + 2 * QUOTE_PRECISION as i64
+    );
+    assert_eq!(position.last_fee_per_lp, 3 * QUOTE_PRECISION as u64);
+
+    // nothing left to claim
+    let (_, _, fee) = settle_lp_position(&mut position, &mut market).unwrap();
+    assert_eq!(fee, 0);
+}
+
+#[test]
+fn test_hedge_lp_position() {
+    use std::str::FromStr;
+
+    use anchor_lang::prelude::{AccountLoader, Clock, Pubkey};
+    use anchor_lang::Owner;
+
+    use crate::controller::position::PositionDirection;
+    use crate::create_account_info;
+    use crate::create_anchor_account_info;
+    use crate::error::ErrorCode;
+    use crate::math::constants::{
+        BASE_PRECISION_I64, SPOT_BALANCE_PRECISION_U64, SPOT_CUMULATIVE_INTEREST_PRECISION,
+        SPOT_WEIGHT_PRECISION,
+    };
+    use crate::state::oracle::{HistoricalOracleData, OracleSource};
+    use crate::state::oracle_map::OracleMap;
+    use crate::state::perp_market::MarketStatus;
+    use crate::state::perp_market_map::PerpMarketMap;
+    use crate::state::spot_market::{SpotBalanceType, SpotMarket};
+    use crate::state::spot_market_map::SpotMarketMap;
+    use crate::state::state::State;
+    use crate::state::user::{
+        MarketType, Order, OrderStatus, OrderType, SpotPosition, User, UserStats,
+    };
+    use crate::test_utils::*;
+    use crate::test_utils::{get_orders, get_positions, get_pyth_price, get_spot_positions};
+
+    let clock = Clock {
+        slot: 6,
+        epoch_start_timestamp: 0,
+        epoch: 0,
+        leader_schedule_epoch: 0,
+        unix_timestamp: 0,
+    };
+
+    let mut oracle_price = get_pyth_price(100, 6);
+    let oracle_price_key =
+        Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+    let pyth_program = crate::ids::pyth_program::id();
+    create_account_info!(
+        oracle_price,
+        &oracle_price_key,
+        &pyth_program,
+        oracle_account_info
+    );
+    let mut oracle_map = OracleMap::load_one(&oracle_account_info, clock.slot, None).unwrap();
+
+    let mut market = PerpMarket {
+        amm: AMM {
+            base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+            quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+            terminal_quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+            sqrt_k: 100 * AMM_RESERVE_PRECISION,
+            peg_multiplier: 100 * PEG_PRECISION,
+            max_slippage_ratio: 100,
+            max_fill_reserve_fraction: 100,
+            order_step_size: 1000,
+            order_tick_size: 1,
+            oracle: oracle_price_key,
+            max_spread: 1000,
+            base_asset_amount_long: 10 * BASE_PRECISION_U64 as i128,
+            base_asset_amount_with_amm: 10 * BASE_PRECISION_U64 as i128,
+            user_lp_shares: BASE_PRECISION_U64 as u128,
+            max_base_asset_reserve: u128::MAX,
+            min_base_asset_reserve: 0,
+            historical_oracle_data: HistoricalOracleData {
+                last_oracle_price_twap: oracle_price.twap,
+                last_oracle_price_twap_5min: oracle_price.twap,
+                last_oracle_price: oracle_price.agg.price,
+                ..HistoricalOracleData::default()
+            },
+            ..AMM::default()
+        },
+        margin_ratio_initial: 1000,
+        margin_ratio_maintenance: 500,
+        number_of_users_with_base: 1,
+        number_of_users: 1,
+        status: MarketStatus::Active,
+        ..PerpMarket::default()
+    };
+    create_anchor_account_info!(market, PerpMarket, market_account_info);
+    let market_map = PerpMarketMap::load_one(&market_account_info, true).unwrap();
+
+    let mut spot_market = SpotMarket {
+        market_index: 0,
+        oracle_source: OracleSource::QuoteAsset,
+        cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+        decimals: 6,
+        initial_asset_weight: SPOT_WEIGHT_PRECISION,
+        maintenance_asset_weight: SPOT_WEIGHT_PRECISION,
+        ..SpotMarket::default()
+    };
+    create_anchor_account_info!(spot_market, SpotMarket, spot_market_account_info);
+    let spot_market_map = SpotMarketMap::load_one(&spot_market_account_info, true).unwrap();
+
+    let spot_positions = get_spot_positions(SpotPosition {
+        market_index: 0,
+        balance_type: SpotBalanceType::Deposit,
+        scaled_balance: 10_000 * SPOT_BALANCE_PRECISION_U64,
+        ..SpotPosition::default()
+    });
+
+    // 4 of the 10 base came from lp settlement, the rest is directional
+    let mut user = User {
+        perp_positions: get_positions(PerpPosition {
+            market_index: 0,
+            base_asset_amount: 10 * BASE_PRECISION_U64 as i64,
+            quote_asset_amount: -1000 * QUOTE_PRECISION as i64,
+            quote_entry_amount: -1000 * QUOTE_PRECISION as i64,
+            quote_break_even_amount: -1000 * QUOTE_PRECISION as i64,
+            lp_shares: BASE_PRECISION_U64,
+            lp_base_asset_amount: 4 * BASE_PRECISION_U64 as i64,
+            lp_hedge_threshold: BASE_PRECISION_U64,
+            ..PerpPosition::default()
+        }),
+        spot_positions,
+        ..User::default()
+    };
+    let user_key = Pubkey::new_unique();
+    create_anchor_account_info!(user, &user_key, User, user_account_info);
+    let user_account_loader: AccountLoader<User> =
+        AccountLoader::try_from(&user_account_info).unwrap();
+
+    create_anchor_account_info!(UserStats::default(), UserStats, user_stats_account_info);
+    let user_stats_account_loader: AccountLoader<UserStats> =
+        AccountLoader::try_from(&user_stats_account_info).unwrap();
+
+    let filler_key = Pubkey::from_str("My11111111111111111111111111111111111111111").unwrap();
+    create_anchor_account_info!(User::default(), &filler_key, User, filler_account_info);
+    let filler_account_loader: AccountLoader<User> =
+        AccountLoader::try_from(&filler_account_info).unwrap();
+
+    create_anchor_account_info!(UserStats::default(), UserStats, filler_stats_account_info);
+    let filler_stats_account_loader: AccountLoader<UserStats> =
+        AccountLoader::try_from(&filler_stats_account_info).unwrap();
+
+    let maker_order = Order {
+        market_index: 0,
+        order_id: 1,
+        status: OrderStatus::Open,
+        order_type: OrderType::Limit,
+        market_type: MarketType::Perp,
+        direction: PositionDirection::Long,
+        base_asset_amount: 4 * BASE_PRECISION_U64,
+        post_only: true,
+        slot: 0,
+        ..Order::default()
+    };
+
+    // bid 1% under the oracle, outside the hedge band
+    let mut far_maker = User {
+        orders: get_orders(Order {
+            price: 99 * PRICE_PRECISION_U64,
+            ..maker_order
+        }),
+        perp_positions: get_positions(PerpPosition {
+            market_index: 0,
+            open_orders: 1,
+            open_bids: 4 * BASE_PRECISION_I64,
+            ..PerpPosition::default()
+        }),
+        spot_positions,
+        ..User::default()
+    };
+    let far_maker_key = Pubkey::new_unique();
+    create_anchor_account_info!(far_maker, &far_maker_key, User, far_maker_account_info);
+    let far_maker_account_loader: AccountLoader<User> =
+        AccountLoader::try_from(&far_maker_account_info).unwrap();
+
+    // bid .2% under the oracle, inside the hedge band
+    let mut maker = User {
+        orders: get_orders(Order {
+            price: 998 * PRICE_PRECISION_U64 / 10,
+            ..maker_order
+        }),
+        perp_positions: get_positions(PerpPosition {
+            market_index: 0,
+            open_orders: 1,
+            open_bids: 4 * BASE_PRECISION_I64,
+            ..PerpPosition::default()
+        }),
+        spot_positions,
+        ..User::default()
+    };
+    let maker_key = Pubkey::new_unique();
+    create_anchor_account_info!(maker, &maker_key, User, maker_account_info);
+    let maker_account_loader: AccountLoader<User> =
+        AccountLoader::try_from(&maker_account_info).unwrap();
+
+    create_anchor_account_info!(UserStats::default(), UserStats, maker_stats_account_info);
+    let maker_stats_account_loader: AccountLoader<UserStats> =
+        AccountLoader::try_from(&maker_stats_account_info).unwrap();
+
+    // hedge orders can't reach the amm before the auction ends, only the maker can fill them
+    let state = State {
+        min_perp_auction_duration: 10,
+        default_market_order_time_in_force: 10,
+        ..State::default()
+    };
+
+    let base_asset_amount_hedged = hedge_lp_position(
+        0,
+        &state,
+        &user_account_loader,
+        &user_stats_account_loader,
+        &market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        &filler_account_loader,
+        &filler_stats_account_loader,
+        Some(&far_maker_account_loader),
+        Some(&maker_stats_account_loader),
+        Some(1),
+        None,
+        None,
+        &clock,
+    )
+    .unwrap();
+    assert_eq!(base_asset_amount_hedged, 0);
+
+    {
+        let user = user_account_loader.load().unwrap();
+        assert_eq!(
+            user.perp_positions[0].base_asset_amount,
+            10 * BASE_PRECISION_U64 as i64
+        );
+        assert_eq!(
+            user.perp_positions[0].lp_base_asset_amount,
+            4 * BASE_PRECISION_U64 as i64
+        );
+        assert_eq!(user.perp_positions[0].open_orders, 0);
+    }
+
+    let base_asset_amount_hedged = hedge_lp_position(
+        0,
+        &state,
+        &user_account_loader,
+        &user_stats_account_loader,
+        &market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        &filler_account_loader,
+        &filler_stats_account_loader,
+        Some(&maker_account_loader),
+        Some(&maker_stats_account_loader),
+        Some(1),
+        None,
+        None,
+        &clock,
+    )
+    .unwrap();
+    assert_eq!(base_asset_amount_hedged, 4 * BASE_PRECISION_U64);
+
+    {
+        // only the lp base is closed, the directional base is left alone
+        let user = user_account_loader.load().unwrap();
+        assert_eq!(
+            user.perp_positions[0].base_asset_amount,
+            6 * BASE_PRECISION_U64 as i64
+        );
+        assert_eq!(user.perp_positions[0].lp_base_asset_amount, 0);
+        assert_eq!(user.perp_positions[0].open_orders, 0);
+
+        let maker = maker_account_loader.load().unwrap();
+        assert_eq!(
+            maker.perp_positions[0].base_asset_amount,
+            4 * BASE_PRECISION_U64 as i64
+        );
+    }
+
+    let result = hedge_lp_position(
+        0,
+        &state,
+        &user_account_loader,
+        &user_stats_account_loader,
+        &market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        &filler_account_loader,
+        &filler_stats_account_loader,
+        None,
+        None,
+        None,
+        None,
+        None,
+        &clock,
+    );
+    assert_eq!(result, Err(ErrorCode::LPHedgeNotNeeded));
+}
+
+mod add_perp_lp_shares_batch {
+    use std::str::FromStr;
+
+    use anchor_lang::prelude::Pubkey;
+    use anchor_lang::Owner;
+
+    use crate::controller::lp::*;
+    use crate::create_account_info;
+    use crate::create_anchor_account_info;
+    use crate::error::ErrorCode;
+    use crate::instructions::LPSharesParams;
+    use crate::math::constants::{
+        AMM_RESERVE_PRECISION, BASE_PRECISION_U64, PEG_PRECISION, SPOT_BALANCE_PRECISION_U64,
+        SPOT_CUMULATIVE_INTEREST_PRECISION, SPOT_WEIGHT_PRECISION,
+    };
+    use crate::state::oracle::{HistoricalOracleData, OracleSource};
+    use crate::state::oracle_map::OracleMap;
+    use crate::state::perp_market::{MarketStatus, PerpMarket, AMM};
+    use crate::state::perp_market_map::PerpMarketMap;
+    use crate::state::spot_market::{SpotBalanceType, SpotMarket};
+    use crate::state::spot_market_map::SpotMarketMap;
+    use crate::state::user::{SpotPosition, User};
+    use crate::test_utils::*;
+
+    fn get_market(market_index: u16, oracle: Pubkey, oracle_price: i64) -> PerpMarket {
+        PerpMarket {
+            amm: AMM {
+                peg_multiplier: 100 * PEG_PRECISION,
+                order_step_size: 1000,
+                oracle,
+                historical_oracle_data: HistoricalOracleData {
+                    last_oracle_price: oracle_price,
+                    last_oracle_price_twap: oracle_price,
+                    last_oracle_price_twap_5min: oracle_price,
+                    ..HistoricalOracleData::default()
+                },
+                ..AMM::default_test()
+            },
+            market_index,
+            status: MarketStatus::Active,
+            ..PerpMarket::default_test()
+        }
+    }
+
+    fn get_user(deposit: u64) -> User {
+        User {
+            spot_positions: get_spot_positions(SpotPosition {
+                market_index: 0,
+                balance_type: SpotBalanceType::Deposit,
+                scaled_balance: deposit * SPOT_BALANCE_PRECISION_U64,
+                ..SpotPosition::default()
+            }),
+            ..User::default()
+        }
+    }
+
+    #[test]
+    fn single_margin_check_across_markets() {
+        let slot = 0;
+
+        let mut oracle_price = get_pyth_price(100, 6);
+        let oracle_price_key =
+            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let pyth_program = crate::ids::pyth_program::id();
+        create_account_info!(
+            oracle_price,
+            &oracle_price_key,
+            &pyth_program,
+            oracle_account_info
+        );
+        let mut oracle_map = OracleMap::load_one(&oracle_account_info, slot, None).unwrap();
+
+        let mut market = get_market(0, oracle_price_key, oracle_price.agg.price);
+        create_anchor_account_info!(market, PerpMarket, market_account_info);
+        let mut market_1 = get_market(1, oracle_price_key, oracle_price.agg.price);
+        create_anchor_account_info!(market_1, PerpMarket, market_1_account_info);
+        let perp_market_map =
+            PerpMarketMap::load_multiple(vec![&market_account_info, &market_1_account_info], true)
+                .unwrap();
+
+        let mut spot_market = SpotMarket {
+            market_index: 0,
+            oracle_source: OracleSource::QuoteAsset,
+            cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            decimals: 6,
+            initial_asset_weight: SPOT_WEIGHT_PRECISION,
+            maintenance_asset_weight: SPOT_WEIGHT_PRECISION,
+            ..SpotMarket::default()
+        };
+        create_anchor_account_info!(spot_market, SpotMarket, spot_market_account_info);
+        let spot_market_map = SpotMarketMap::load_one(&spot_market_account_info, true).unwrap();
+
+        // 100 shares leave each market with a worst case of ~41.4 base ($4142), $414 initial margin
+        let params = vec![
+            LPSharesParams {
+                market_index: 0,
+                n_shares: 100 * BASE_PRECISION_U64,
+            },
+            LPSharesParams {
+                market_index: 1,
+                n_shares: 100 * BASE_PRECISION_U64,
+            },
+        ];
+
+        let mut user = get_user(1000);
+        add_perp_lp_shares_batch(
+            &perp_market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            &mut user,
+            Pubkey::default(),
+            &params,
+            0,
+        )
+        .unwrap();
+
+        assert_eq!(user.perp_positions[0].market_index, 0);
+        assert_eq!(user.perp_positions[0].lp_shares, 100 * BASE_PRECISION_U64);
+        assert_eq!(user.perp_positions[1].market_index, 1);
+        assert_eq!(user.perp_positions[1].lp_shares, 100 * BASE_PRECISION_U64);
+
+        let market = perp_market_map.get_ref(&1).unwrap();
+        assert_eq!(market.amm.sqrt_k, 200 * AMM_RESERVE_PRECISION);
+        assert_eq!(market.amm.user_lp_shares, 100 * AMM_RESERVE_PRECISION);
+    }
+
+    #[test]
+    fn combined_position_fails_initial_margin() {
+        let slot = 0;
+
+        let mut oracle_price = get_pyth_price(100, 6);
+        let oracle_price_key =
+            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let pyth_program = crate::ids::pyth_program::id();
+        create_account_info!(
+            oracle_price,
+            &oracle_price_key,
+            &pyth_program,
+            oracle_account_info
+        );
+        let mut oracle_map = OracleMap::load_one(&oracle_account_info, slot, None).unwrap();
+
+        let mut market = get_market(0, oracle_price_key, oracle_price.agg.price);
+        create_anchor_account_info!(market, PerpMarket, market_account_info);
+        let mut market_1 = get_market(1, oracle_price_key, oracle_price.agg.price);
+        create_anchor_account_info!(market_1, PerpMarket, market_1_account_info);
+        let perp_market_map =
+            PerpMarketMap::load_multiple(vec![&market_account_info, &market_1_account_info], true)
+                .unwrap();
+
+        let mut spot_market = SpotMarket {
+            market_index: 0,
+            oracle_source: OracleSource::QuoteAsset,
+            cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            decimals: 6,
+            initial_asset_weight: SPOT_WEIGHT_PRECISION,
+            maintenance_asset_weight: SPOT_WEIGHT_PRECISION,
+            ..SpotMarket::default()
+        };
+        create_anchor_account_info!(spot_market, SpotMarket, spot_market_account_info);
+        let spot_market_map = SpotMarketMap::load_one(&spot_market_account_info, true).unwrap();
+
+        let param = LPSharesParams {
+            market_index: 0,
+            n_shares: 100 * BASE_PRECISION_U64,
+        };
+
+        // $600 covers either market on its own
+        let mut user = get_user(600);
+        add_perp_lp_shares_batch(
+            &perp_market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            &mut user,
+            Pubkey::default(),
+            &[param],
+            0,
+        )
+        .unwrap();
+
+        // but not both together
+        let mut user = get_user(600);
+        let result = add_perp_lp_shares_batch(
+            &perp_market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            &mut user,
+            Pubkey::default(),
+            &[
+                LPSharesParams {
+                    market_index: 1,
+                    ..param
+                },
+                param,
+            ],
+            0,
+        );
+        assert_eq!(result, Err(ErrorCode::InsufficientCollateral));
+    }
+
+    #[test]
+    fn invalid_params() {
+        let param = LPSharesParams {
+            market_index: 0,
+            n_shares: BASE_PRECISION_U64,
+        };
+
+        assert_eq!(
+            validate_lp_shares_batch(&[]),
+            Err(ErrorCode::InvalidLPSharesBatch)
+        );
+
+        assert_eq!(
+            validate_lp_shares_batch(&[
+                param,
+                LPSharesParams {
+                    market_index: 1,
+                    ..param
+                },
+                param
+            ]),
+            Err(ErrorCode::InvalidLPSharesBatch)
+        );
+
+        let too_many: Vec<LPSharesParams> = (0..=MAX_PERP_POSITIONS as u16)
+            .map(|market_index| LPSharesParams {
+                market_index,
+                ..param
+            })
+            .collect();
+        assert_eq!(
+            validate_lp_shares_batch(&too_many),
+            Err(ErrorCode::InvalidLPSharesBatch)
+        );
+
+        assert_eq!(validate_lp_shares_batch(&too_many[1..]), Ok(()));
+    }
+}
@@ -1,4 +1,5 @@
 pub mod amm;
+pub mod backstop_vault;
 pub mod funding;
 pub mod insurance;
 pub mod liquidation;
@@ -40,6 +40,7 @@ use crate::math::fees::{FillFees, SerumFillFees};
 use crate::math::fulfillment::{
     determine_perp_fulfillment_methods, determine_spot_fulfillment_methods,
 };
+use crate::math::insurance::validate_insurance_coverage_for_risk_increasing_order;
 use crate::math::liquidation::validate_user_not_being_liquidated;
 use crate::math::matching::{
     are_orders_same_market_but_different_sides, calculate_fill_for_matched_orders,
@@ -308,6 +309,14 @@ pub fn place_perp_order(
         return Err(ErrorCode::InvalidOrderNotRiskReducing);
     }
 
+    if is_order_risk_increasing(
+        &params.direction,
+        order_base_asset_amount,
+        user.perp_positions[position_index].base_asset_amount,
+    )? {
+        validate_insurance_coverage_for_risk_increasing_order(market, now)?;
+    }
+
     let (taker, taker_order, maker, maker_order) =
         get_taker_and_maker_for_order_record(&user_key, &new_order);
 
@@ -1413,9 +1413,10 @@ pub mod fulfill_order {
     use crate::create_anchor_account_info;
     use crate::math::constants::{
         AMM_RESERVE_PRECISION, BASE_PRECISION_I64, BASE_PRECISION_U64,
-        BID_ASK_SPREAD_PRECISION_I64, PEG_PRECISION, PRICE_PRECISION, PRICE_PRECISION_I64,
-        PRICE_PRECISION_U64, QUOTE_PRECISION_I64, QUOTE_PRECISION_U64, SPOT_BALANCE_PRECISION_U64,
-        SPOT_CUMULATIVE_INTEREST_PRECISION, SPOT_WEIGHT_PRECISION,
+        BID_ASK_SPREAD_PRECISION_I64, MAX_OPEN_ORDERS, MAX_PERP_POSITIONS, PEG_PRECISION,
+        PRICE_PRECISION, PRICE_PRECISION_I64, PRICE_PRECISION_U64, QUOTE_PRECISION_I64,
+        QUOTE_PRECISION_U64, SPOT_BALANCE_PRECISION_U64, SPOT_CUMULATIVE_INTEREST_PRECISION,
+        SPOT_WEIGHT_PRECISION,
     };
     use crate::state::oracle::{HistoricalOracleData, OracleSource};
     use crate::state::perp_market::{PerpMarket, AMM};
@@ -2507,7 +2508,7 @@ pub mod fulfill_order {
 
         let mut oracle_map = get_oracle_map();
 
-        let mut taker_orders = [Order::default(); 32];
+        let mut taker_orders = [Order::default(); MAX_OPEN_ORDERS as usize];
         taker_orders[0] = Order {
             market_index: 0,
             status: OrderStatus::Open,
@@ -2534,7 +2535,7 @@ pub mod fulfill_order {
         };
 
         // Taker has sol order and position at index 0, btc at index 1
-        let mut taker_positions = [PerpPosition::default(); 8];
+        let mut taker_positions = [PerpPosition::default(); MAX_PERP_POSITIONS as usize];
         taker_positions[0] = PerpPosition {
             market_index: 0,
             open_orders: 1,
@@ -2561,7 +2562,7 @@ pub mod fulfill_order {
         };
 
         // Maker has sol order and position at index 1, btc at index 1
-        let mut maker_orders = [Order::default(); 32];
+        let mut maker_orders = [Order::default(); MAX_OPEN_ORDERS as usize];
         maker_orders[0] = Order {
             market_index: 1,
             post_only: true,
@@ -2581,7 +2582,7 @@ pub mod fulfill_order {
             ..Order::default()
         };
 
-        let mut maker_positions = [PerpPosition::default(); 8];
+        let mut maker_positions = [PerpPosition::default(); MAX_PERP_POSITIONS as usize];
         maker_positions[0] = PerpPosition {
             market_index: 1,
             open_orders: 1,
@@ -3224,8 +3225,8 @@ pub mod fulfill_spot_order_with_match {
     use crate::controller::orders::fulfill_spot_order_with_match;
     use crate::controller::position::PositionDirection;
     use crate::math::constants::{
-        LAMPORTS_PER_SOL_I64, LAMPORTS_PER_SOL_U64, PRICE_PRECISION_U64, QUOTE_PRECISION_U64,
-        SPOT_BALANCE_PRECISION, SPOT_BALANCE_PRECISION_U64,
+        LAMPORTS_PER_SOL_I64, LAMPORTS_PER_SOL_U64, MAX_SPOT_POSITIONS, PRICE_PRECISION_U64,
+        QUOTE_PRECISION_U64, SPOT_BALANCE_PRECISION, SPOT_BALANCE_PRECISION_U64,
     };
     use crate::state::spot_market::{SpotBalanceType, SpotMarket};
     use crate::state::user::{MarketType, Order, OrderType, SpotPosition, User, UserStats};
@@ -3235,7 +3236,7 @@ pub mod fulfill_spot_order_with_match {
 
     #[test]
     fn long_taker_order_fulfilled_start_of_auction() {
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         taker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 101 * SPOT_BALANCE_PRECISION_U64,
@@ -3265,7 +3266,7 @@ pub mod fulfill_spot_order_with_match {
             ..User::default()
         };
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         maker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -3363,7 +3364,7 @@ pub mod fulfill_spot_order_with_match {
 
     #[test]
     fn long_taker_order_fulfilled_middle_of_auction() {
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         taker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 161 * SPOT_BALANCE_PRECISION_U64,
@@ -3393,7 +3394,7 @@ pub mod fulfill_spot_order_with_match {
             ..User::default()
         };
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         maker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -3491,7 +3492,7 @@ pub mod fulfill_spot_order_with_match {
 
     #[test]
     fn short_taker_order_fulfilled_start_of_auction() {
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         taker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -3517,7 +3518,7 @@ pub mod fulfill_spot_order_with_match {
             ..User::default()
         };
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         maker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 101 * SPOT_BALANCE_PRECISION_U64,
@@ -3619,7 +3620,7 @@ pub mod fulfill_spot_order_with_match {
 
     #[test]
     fn short_taker_order_fulfilled_middle_of_auction() {
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         taker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -3645,7 +3646,7 @@ pub mod fulfill_spot_order_with_match {
             ..User::default()
         };
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         maker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 101 * SPOT_BALANCE_PRECISION_U64,
@@ -3747,7 +3748,7 @@ pub mod fulfill_spot_order_with_match {
 
     #[test]
     fn long_taker_order_auction_price_does_not_satisfy_maker() {
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         taker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 101 * SPOT_BALANCE_PRECISION_U64,
@@ -3777,7 +3778,7 @@ pub mod fulfill_spot_order_with_match {
             ..User::default()
         };
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         maker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -3849,7 +3850,7 @@ pub mod fulfill_spot_order_with_match {
 
     #[test]
     fn short_taker_order_auction_price_does_not_satisfy_maker() {
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         taker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -3875,7 +3876,7 @@ pub mod fulfill_spot_order_with_match {
             ..User::default()
         };
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         maker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 101 * SPOT_BALANCE_PRECISION_U64,
@@ -3951,7 +3952,7 @@ pub mod fulfill_spot_order_with_match {
 
     #[test]
     fn maker_taker_same_direction() {
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         taker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -3977,7 +3978,7 @@ pub mod fulfill_spot_order_with_match {
             ..User::default()
         };
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         maker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 101 * SPOT_BALANCE_PRECISION_U64,
@@ -4053,7 +4054,7 @@ pub mod fulfill_spot_order_with_match {
 
     #[test]
     fn maker_taker_different_market_index() {
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         taker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 101 * SPOT_BALANCE_PRECISION_U64,
@@ -4083,7 +4084,7 @@ pub mod fulfill_spot_order_with_match {
             ..User::default()
         };
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         maker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -4155,7 +4156,7 @@ pub mod fulfill_spot_order_with_match {
 
     #[test]
     fn long_taker_order_bigger_than_maker() {
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         taker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 101 * SPOT_BALANCE_PRECISION_U64,
@@ -4185,7 +4186,7 @@ pub mod fulfill_spot_order_with_match {
             ..User::default()
         };
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         maker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -4295,7 +4296,7 @@ pub mod fulfill_spot_order_with_match {
 
     #[test]
     fn long_taker_order_smaller_than_maker() {
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         taker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 101 * SPOT_BALANCE_PRECISION_U64,
@@ -4325,7 +4326,7 @@ pub mod fulfill_spot_order_with_match {
             ..User::default()
         };
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         maker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -4435,7 +4436,7 @@ pub mod fulfill_spot_order_with_match {
 
     #[test]
     fn double_dutch_auction() {
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         taker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 101 * SPOT_BALANCE_PRECISION_U64,
@@ -4465,7 +4466,7 @@ pub mod fulfill_spot_order_with_match {
             ..User::default()
         };
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         maker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -4565,7 +4566,7 @@ pub mod fulfill_spot_order_with_match {
 
     #[test]
     fn taker_bid_crosses_maker_ask() {
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         taker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 101 * SPOT_BALANCE_PRECISION_U64,
@@ -4593,7 +4594,7 @@ pub mod fulfill_spot_order_with_match {
             ..User::default()
         };
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         maker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -4691,7 +4692,7 @@ pub mod fulfill_spot_order_with_match {
 
     #[test]
     fn taker_ask_crosses_maker_bid() {
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         taker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -4715,7 +4716,7 @@ pub mod fulfill_spot_order_with_match {
             ..User::default()
         };
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         maker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 101 * SPOT_BALANCE_PRECISION_U64,
@@ -4826,8 +4827,8 @@ pub mod fulfill_spot_order {
     use crate::create_account_info;
     use crate::create_anchor_account_info;
     use crate::math::constants::{
-        LAMPORTS_PER_SOL_I64, LAMPORTS_PER_SOL_U64, PRICE_PRECISION_U64, SPOT_BALANCE_PRECISION,
-        SPOT_BALANCE_PRECISION_U64,
+        LAMPORTS_PER_SOL_I64, LAMPORTS_PER_SOL_U64, MAX_OPEN_ORDERS, MAX_SPOT_POSITIONS,
+        PRICE_PRECISION_U64, SPOT_BALANCE_PRECISION, SPOT_BALANCE_PRECISION_U64,
     };
     use crate::state::perp_market_map::PerpMarketMap;
     use crate::state::spot_market::{SpotBalanceType, SpotMarket};
@@ -4882,7 +4883,7 @@ pub mod fulfill_spot_order {
     //     )
     //     .unwrap();
     //
-    //     let mut taker_spot_positions = [SpotPosition::default(); 8];
+    //     let mut taker_spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
     //     taker_spot_positions[0] = SpotPosition {
     //         market_index: 0,
     //         scaled_balance: SPOT_BALANCE_PRECISION_U64,
@@ -4923,7 +4924,7 @@ pub mod fulfill_spot_order {
     //     let taker_stats_account_loader: AccountLoader<UserStats> =
     //         AccountLoader::try_from(&taker_stats_account_info).unwrap();
     //
-    //     let mut maker_spot_positions = [SpotPosition::default(); 8];
+    //     let mut maker_spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
     //     maker_spot_positions[1] = SpotPosition {
     //         market_index: 1,
     //         balance_type: SpotBalanceType::Deposit,
@@ -5069,7 +5070,7 @@ pub mod fulfill_spot_order {
         )
         .unwrap();
 
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         taker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 101 * SPOT_BALANCE_PRECISION_U64,
@@ -5088,7 +5089,7 @@ pub mod fulfill_spot_order {
             open_bids: LAMPORTS_PER_SOL_I64,
             ..SpotPosition::default()
         };
-        let mut taker_orders = [Order::default(); 32];
+        let mut taker_orders = [Order::default(); MAX_OPEN_ORDERS as usize];
         taker_orders[0] = Order {
             order_id: 1,
             market_index: 1,
@@ -5133,7 +5134,7 @@ pub mod fulfill_spot_order {
         let taker_stats_account_loader: AccountLoader<UserStats> =
             AccountLoader::try_from(&taker_stats_account_info).unwrap();
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         maker_spot_positions[1] = SpotPosition {
             market_index: 2,
             balance_type: SpotBalanceType::Deposit,
@@ -5150,7 +5151,7 @@ pub mod fulfill_spot_order {
             open_asks: -LAMPORTS_PER_SOL_I64,
             ..SpotPosition::default()
         };
-        let mut maker_orders = [Order::default(); 32];
+        let mut maker_orders = [Order::default(); MAX_OPEN_ORDERS as usize];
         maker_orders[0] = Order {
             order_id: 2,
             market_index: 2,
@@ -5283,8 +5284,8 @@ pub mod fill_spot_order {
     use crate::create_account_info;
     use crate::create_anchor_account_info;
     use crate::math::constants::{
-        LAMPORTS_PER_SOL_I64, LAMPORTS_PER_SOL_U64, PRICE_PRECISION_U64, SPOT_BALANCE_PRECISION,
-        SPOT_BALANCE_PRECISION_U64,
+        LAMPORTS_PER_SOL_I64, LAMPORTS_PER_SOL_U64, MAX_SPOT_POSITIONS, PRICE_PRECISION_U64,
+        SPOT_BALANCE_PRECISION, SPOT_BALANCE_PRECISION_U64,
     };
     use crate::state::perp_market_map::PerpMarketMap;
     use crate::state::spot_market::{SpotBalanceType, SpotMarket};
@@ -5336,7 +5337,7 @@ pub mod fill_spot_order {
         )
         .unwrap();
 
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         taker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 101 * SPOT_BALANCE_PRECISION_U64,
@@ -5377,7 +5378,7 @@ pub mod fill_spot_order {
         let taker_stats_account_loader: AccountLoader<UserStats> =
             AccountLoader::try_from(&taker_stats_account_info).unwrap();
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         maker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -5492,7 +5493,7 @@ pub mod fill_spot_order {
         )
         .unwrap();
 
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         taker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 101 * SPOT_BALANCE_PRECISION_U64,
@@ -5534,7 +5535,7 @@ pub mod fill_spot_order {
         let taker_stats_account_loader: AccountLoader<UserStats> =
             AccountLoader::try_from(&taker_stats_account_info).unwrap();
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         maker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -38,3 +38,47 @@ pub fn seed_and_create_pda<'a>(
 
     Ok(())
 }
+
+// grows an account created before fields were appended to its layout, appended fields start zeroed
+pub fn resize_pda<'a>(
+    funder: &AccountInfo<'a>,
+    rent: &Rent,
+    space: usize,
+    discriminator: &[u8; 8],
+    system_program: &AccountInfo<'a>,
+    pda_account: &AccountInfo<'a>,
+    error_code: ErrorCode,
+) -> anchor_lang::Result<()> {
+    validate!(
+        pda_account.data_len() < space,
+        error_code,
+        "account size {} is already at least {}",
+        pda_account.data_len(),
+        space
+    )?;
+
+    validate!(
+        pda_account.data_len() >= 8 && pda_account.try_borrow_data()?[..8] == discriminator[..],
+        error_code,
+        "account discriminator does not match"
+    )?;
+
+    let lamports_required = rent
+        .minimum_balance(space)
+        .saturating_sub(pda_account.lamports());
+
+    if lamports_required > 0 {
+        solana_program::program::invoke(
+            &solana_program::system_instruction::transfer(
+                funder.key,
+                pda_account.key,
+                lamports_required,
+            ),
+            &[funder.clone(), pda_account.clone(), system_program.clone()],
+        )?;
+    }
+
+    pda_account.realloc(space, true)?;
+
+    Ok(())
+}
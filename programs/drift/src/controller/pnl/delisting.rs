@@ -2419,6 +2419,7 @@ pub mod delisting_test {
                 clock.slot,
                 clock.unix_timestamp,
                 &state,
+                None,
             )
             .unwrap();
 
@@ -2496,6 +2497,7 @@ pub mod delisting_test {
                 clock.unix_timestamp,
                 clock.slot,
                 10,
+                None,
             )
             .unwrap();
 
@@ -2582,6 +2584,7 @@ pub mod delisting_test {
                 clock.unix_timestamp,
                 clock.slot,
                 10,
+                None,
             )
             .unwrap();
 
@@ -2707,6 +2710,7 @@ pub mod delisting_test {
                 &mut oracle_map,
                 clock.unix_timestamp,
                 0,
+                0,
             )
             .unwrap();
 
@@ -337,10 +337,13 @@ pub fn update_position_and_market(
     // Update user position
     if let PositionUpdateType::Close = update_type {
         position.last_cumulative_funding_rate = 0;
+        position.cumulative_funding = 0;
     } else if matches!(
         update_type,
         PositionUpdateType::Open | PositionUpdateType::Flip
     ) {
+        position.cumulative_funding = 0;
+
         if new_base_asset_amount > 0 {
             position.last_cumulative_funding_rate =
                 market.amm.cumulative_funding_rate_long.cast()?;
@@ -375,6 +378,7 @@ pub fn update_lp_market_position(
     fee_to_market: i128,
 ) -> DriftResult<(i128, i128, i128)> {
     let total_lp_shares = market.amm.sqrt_k;
+    // shares of inactive lp ranges already left sqrt_k and user_lp_shares
     let user_lp_shares = market.amm.user_lp_shares;
 
     if user_lp_shares == 0 {
@@ -427,11 +431,25 @@ pub fn update_lp_market_position(
         0
     };
 
-    // update per lp position
-    market.amm.quote_asset_amount_per_lp = market
-        .amm
-        .quote_asset_amount_per_lp
-        .safe_add(per_lp_fee.cast()?)?;
+    // tracked apart from inventory so lps can attribute fees, see settle_lp_position
+    market.fee_per_lp = market.fee_per_lp.safe_add(per_lp_fee.cast()?)?;
+
+    // active lp ranges are part of sqrt_k, their shares take the same slice
+    for lp_range in market
+        .lp_ranges
+        .iter_mut()
+        .filter(|lp_range| lp_range.active && !lp_range.is_available())
+    {
+        lp_range.base_asset_amount_per_lp = lp_range
+            .base_asset_amount_per_lp
+            .safe_add(-per_lp_delta_base)?;
+
+        lp_range.quote_asset_amount_per_lp = lp_range
+            .quote_asset_amount_per_lp
+            .safe_add(-per_lp_delta_quote)?;
+
+        lp_range.fee_per_lp = lp_range.fee_per_lp.safe_add(per_lp_fee.cast()?)?;
+    }
 
     market.amm.base_asset_amount_with_amm = market
         .amm
@@ -491,6 +509,107 @@ pub fn update_position_with_base_asset_amount(
     Ok((quote_asset_amount, quote_asset_amount_surplus, pnl))
 }
 
+pub fn transfer_perp_position(
+    from_position: &mut PerpPosition,
+    to_position: &mut PerpPosition,
+    market: &mut PerpMarket,
+    base_asset_amount: u64,
+) -> DriftResult<(PositionDelta, PositionDelta)> {
+    validate!(
+        from_position.is_open_position(),
+        ErrorCode::InvalidTransferPerpPosition,
+        "from position has no base asset amount to transfer"
+    )?;
+
+    validate!(
+        base_asset_amount > 0
+            && base_asset_amount <= from_position.base_asset_amount.unsigned_abs(),
+        ErrorCode::InvalidTransferPerpPosition,
+        "invalid base asset amount to transfer {} (position base asset amount {})",
+        base_asset_amount,
+        from_position.base_asset_amount
+    )?;
+
+    validate!(
+        is_multiple_of_step_size(base_asset_amount, market.amm.order_step_size)?,
+        ErrorCode::InvalidTransferPerpPosition,
+        "base asset amount {} not a multiple of step size {}",
+        base_asset_amount,
+        market.amm.order_step_size
+    )?;
+
+    // the transferred base carries its share of the entry and break even amounts,
+    // so neither side realizes pnl and the market's net quote is unchanged
+    let quote_entry_amount = from_position
+        .quote_entry_amount
+        .cast::<i128>()?
+        .safe_mul(base_asset_amount.cast()?)?
+        .safe_div(from_position.base_asset_amount.unsigned_abs().cast()?)?
+        .cast::<i64>()?;
+
+    let quote_break_even_amount = from_position
+        .quote_break_even_amount
+        .cast::<i128>()?
+        .safe_mul(base_asset_amount.cast()?)?
+        .safe_div(from_position.base_asset_amount.unsigned_abs().cast()?)?
+        .cast::<i64>()?;
+
+    let base_asset_amount = if from_position.base_asset_amount > 0 {
+        base_asset_amount.cast::<i64>()?
+    } else {
+        base_asset_amount.cast::<i64>()?.safe_mul(-1)?
+    };
+
+    let from_position_delta = PositionDelta {
+        base_asset_amount: -base_asset_amount,
+        quote_asset_amount: -quote_entry_amount,
+    };
+
+    let to_position_delta = PositionDelta {
+        base_asset_amount,
+        quote_asset_amount: quote_entry_amount,
+    };
+
+    update_position_and_market(from_position, market, &from_position_delta)?;
+
+    let to_update_type = get_position_update_type(to_position, &to_position_delta);
+    update_position_and_market(to_position, market, &to_position_delta)?;
+
+    // the part of the transferred base that opens or extends the to position keeps its break
+    // even premium, base that closes the to position's opposite side realizes against its entry
+    let opened_base_asset_amount = match to_update_type {
+        PositionUpdateType::Open | PositionUpdateType::Increase => base_asset_amount.unsigned_abs(),
+        PositionUpdateType::Flip => to_position.base_asset_amount.unsigned_abs(),
+        PositionUpdateType::Reduce | PositionUpdateType::Close => 0,
+    };
+
+    if opened_base_asset_amount > 0 {
+        let break_even_adjustment = quote_break_even_amount
+            .safe_sub(quote_entry_amount)?
+            .cast::<i128>()?
+            .safe_mul(opened_base_asset_amount.cast()?)?
+            .safe_div(base_asset_amount.unsigned_abs().cast()?)?
+            .cast::<i64>()?;
+
+        to_position.quote_break_even_amount = to_position
+            .quote_break_even_amount
+            .safe_add(break_even_adjustment)?;
+        if to_position.base_asset_amount > 0 {
+            market.amm.quote_break_even_amount_long = market
+                .amm
+                .quote_break_even_amount_long
+                .safe_add(break_even_adjustment.cast()?)?;
+        } else {
+            market.amm.quote_break_even_amount_short = market
+                .amm
+                .quote_break_even_amount_short
+                .safe_add(break_even_adjustment.cast()?)?;
+        }
+    }
+
+    Ok((from_position_delta, to_position_delta))
+}
+
 fn calculate_quote_asset_amount_surplus(
     position_direction: PositionDirection,
     quote_asset_swapped: u64,
@@ -1,8 +1,9 @@
 use crate::controller::position::{
-    update_lp_market_position, update_position_and_market, PositionDelta,
+    transfer_perp_position, update_lp_market_position, update_position_and_market, PositionDelta,
 };
 use crate::math::constants::{
-    AMM_RESERVE_PRECISION, AMM_RESERVE_PRECISION_I128, BASE_PRECISION_I64,
+    AMM_RESERVE_PRECISION, AMM_RESERVE_PRECISION_I128, BASE_PRECISION_I128, BASE_PRECISION_I64,
+    BASE_PRECISION_U64, PRICE_PRECISION_I64, QUOTE_PRECISION_I128, QUOTE_PRECISION_I64,
 };
 use crate::state::perp_market::{PerpMarket, AMM};
 use crate::state::user::PerpPosition;
@@ -99,6 +100,35 @@ fn half_half_amm_lp_split() {
     );
 }
 
+#[test]
+fn lp_fee_tracked_separately_from_inventory() {
+    let delta = PositionDelta {
+        base_asset_amount: 10 * BASE_PRECISION_I64,
+        quote_asset_amount: -10 * BASE_PRECISION_I64,
+    };
+
+    let amm = AMM {
+        user_lp_shares: 100 * AMM_RESERVE_PRECISION,
+        sqrt_k: 200 * AMM_RESERVE_PRECISION,
+        base_asset_amount_with_amm: 10 * AMM_RESERVE_PRECISION_I128,
+        ..AMM::default_test()
+    };
+    let mut market = PerpMarket {
+        amm,
+        ..PerpMarket::default_test()
+    };
+
+    update_lp_market_position(&mut market, &delta, 100 * QUOTE_PRECISION_I128).unwrap();
+
+    // inventory only reflects the fill
+    assert_eq!(
+        market.amm.quote_asset_amount_per_lp as i64,
+        10 * BASE_PRECISION_I64 / 200
+    );
+    // 80% of the fee, half of which belongs to the lps
+    assert_eq!(market.fee_per_lp, 400_000);
+}
+
 #[test]
 fn increase_long_from_no_position() {
     let mut existing_position = PerpPosition::default();
@@ -381,6 +411,7 @@ fn flip_long_to_short_profitable() {
         quote_entry_amount: -10,
         quote_break_even_amount: -12,
         last_cumulative_funding_rate: 1,
+        cumulative_funding: -3,
         ..PerpPosition::default()
     };
     let position_delta = PositionDelta {
@@ -412,6 +443,7 @@ fn flip_long_to_short_profitable() {
     assert_eq!(existing_position.quote_break_even_amount, 2);
     assert_eq!(pnl, 10);
     assert_eq!(existing_position.last_cumulative_funding_rate, 2);
+    assert_eq!(existing_position.cumulative_funding, 0);
 
     assert_eq!(market.number_of_users_with_base, 1);
     assert_eq!(market.amm.base_asset_amount_long, 0);
@@ -682,6 +714,7 @@ fn close_long_profitable() {
         quote_entry_amount: -10,
         quote_break_even_amount: -12,
         last_cumulative_funding_rate: 1,
+        cumulative_funding: -3,
         ..PerpPosition::default()
     };
     let position_delta = PositionDelta {
@@ -711,6 +744,7 @@ fn close_long_profitable() {
     assert_eq!(existing_position.quote_break_even_amount, 0);
     assert_eq!(pnl, 5);
     assert_eq!(existing_position.last_cumulative_funding_rate, 0);
+    assert_eq!(existing_position.cumulative_funding, 0);
 
     assert_eq!(market.number_of_users_with_base, 1);
     assert_eq!(market.amm.base_asset_amount_long, 1);
@@ -964,3 +998,314 @@ fn close_short_with_quote_break_even_amount_more_than_quote_asset_amount() {
     assert_eq!(market.amm.quote_break_even_amount_long, 0);
     assert_eq!(market.amm.quote_break_even_amount_short, 0);
 }
+
+#[test]
+fn transfer_part_of_long_to_empty_position() {
+    let mut from_position = PerpPosition {
+        base_asset_amount: 10 * BASE_PRECISION_I64,
+        quote_asset_amount: -100 * QUOTE_PRECISION_I64,
+        quote_break_even_amount: -100 * QUOTE_PRECISION_I64,
+        quote_entry_amount: -100 * QUOTE_PRECISION_I64,
+        ..PerpPosition::default()
+    };
+    let mut to_position = PerpPosition::default();
+    let mut market = PerpMarket {
+        amm: AMM {
+            base_asset_amount_long: 10 * BASE_PRECISION_I128,
+            quote_asset_amount: -100 * QUOTE_PRECISION_I128,
+            quote_break_even_amount_long: -100 * QUOTE_PRECISION_I128,
+            quote_entry_amount_long: -100 * QUOTE_PRECISION_I128,
+            ..AMM::default_test()
+        },
+        number_of_users_with_base: 1,
+        number_of_users: 1,
+        ..PerpMarket::default_test()
+    };
+
+    let (from_position_delta, to_position_delta) = transfer_perp_position(
+        &mut from_position,
+        &mut to_position,
+        &mut market,
+        4 * BASE_PRECISION_U64,
+    )
+    .unwrap();
+
+    assert_eq!(
+        from_position_delta.base_asset_amount,
+        -4 * BASE_PRECISION_I64
+    );
+    assert_eq!(
+        from_position_delta.quote_asset_amount,
+        40 * QUOTE_PRECISION_I64
+    );
+    assert_eq!(to_position_delta.base_asset_amount, 4 * BASE_PRECISION_I64);
+    assert_eq!(
+        to_position_delta.quote_asset_amount,
+        -40 * QUOTE_PRECISION_I64
+    );
+
+    assert_eq!(from_position.base_asset_amount, 6 * BASE_PRECISION_I64);
+    assert_eq!(from_position.quote_asset_amount, -60 * QUOTE_PRECISION_I64);
+    assert_eq!(from_position.quote_entry_amount, -60 * QUOTE_PRECISION_I64);
+    assert_eq!(
+        from_position.quote_break_even_amount,
+        -60 * QUOTE_PRECISION_I64
+    );
+
+    assert_eq!(to_position.base_asset_amount, 4 * BASE_PRECISION_I64);
+    assert_eq!(to_position.quote_asset_amount, -40 * QUOTE_PRECISION_I64);
+    assert_eq!(to_position.quote_entry_amount, -40 * QUOTE_PRECISION_I64);
+    assert_eq!(
+        to_position.quote_break_even_amount,
+        -40 * QUOTE_PRECISION_I64
+    );
+
+    assert_eq!(market.number_of_users_with_base, 2);
+    assert_eq!(market.number_of_users, 2);
+    assert_eq!(market.amm.base_asset_amount_long, 10 * BASE_PRECISION_I128);
+    assert_eq!(market.amm.base_asset_amount_short, 0);
+    assert_eq!(market.amm.quote_asset_amount, -100 * QUOTE_PRECISION_I128);
+    assert_eq!(
+        market.amm.quote_entry_amount_long,
+        -100 * QUOTE_PRECISION_I128
+    );
+    assert_eq!(
+        market.amm.quote_break_even_amount_long,
+        -100 * QUOTE_PRECISION_I128
+    );
+}
+
+#[test]
+fn transfer_entire_short_into_existing_long() {
+    let mut from_position = PerpPosition {
+        base_asset_amount: -2 * BASE_PRECISION_I64,
+        quote_asset_amount: 20 * QUOTE_PRECISION_I64,
+        quote_break_even_amount: 20 * QUOTE_PRECISION_I64,
+        quote_entry_amount: 20 * QUOTE_PRECISION_I64,
+        ..PerpPosition::default()
+    };
+    let mut to_position = PerpPosition {
+        base_asset_amount: 2 * BASE_PRECISION_I64,
+        quote_asset_amount: -20 * QUOTE_PRECISION_I64,
+        quote_break_even_amount: -20 * QUOTE_PRECISION_I64,
+        quote_entry_amount: -20 * QUOTE_PRECISION_I64,
+        ..PerpPosition::default()
+    };
+    let mut market = PerpMarket {
+        amm: AMM {
+            base_asset_amount_long: 2 * BASE_PRECISION_I128,
+            base_asset_amount_short: -2 * BASE_PRECISION_I128,
+            quote_asset_amount: 0,
+            quote_break_even_amount_long: -20 * QUOTE_PRECISION_I128,
+            quote_entry_amount_long: -20 * QUOTE_PRECISION_I128,
+            quote_break_even_amount_short: 20 * QUOTE_PRECISION_I128,
+            quote_entry_amount_short: 20 * QUOTE_PRECISION_I128,
+            ..AMM::default_test()
+        },
+        number_of_users_with_base: 2,
+        number_of_users: 2,
+        ..PerpMarket::default_test()
+    };
+
+    transfer_perp_position(
+        &mut from_position,
+        &mut to_position,
+        &mut market,
+        2 * BASE_PRECISION_U64,
+    )
+    .unwrap();
+
+    assert_eq!(from_position.base_asset_amount, 0);
+    assert_eq!(from_position.quote_asset_amount, 0);
+    assert_eq!(to_position.base_asset_amount, 0);
+    assert_eq!(to_position.quote_asset_amount, 0);
+
+    assert_eq!(market.number_of_users_with_base, 0);
+    assert_eq!(market.number_of_users, 0);
+    assert_eq!(market.amm.base_asset_amount_long, 0);
+    assert_eq!(market.amm.base_asset_amount_short, 0);
+    assert_eq!(market.amm.quote_asset_amount, 0);
+    assert_eq!(market.amm.quote_entry_amount_long, 0);
+    assert_eq!(market.amm.quote_entry_amount_short, 0);
+}
+
+#[test]
+fn transfer_part_of_short_into_existing_short_moves_break_even() {
+    let mut from_position = PerpPosition {
+        base_asset_amount: -4 * BASE_PRECISION_I64,
+        quote_asset_amount: 38 * QUOTE_PRECISION_I64,
+        quote_break_even_amount: 36 * QUOTE_PRECISION_I64,
+        quote_entry_amount: 40 * QUOTE_PRECISION_I64,
+        ..PerpPosition::default()
+    };
+    let mut to_position = PerpPosition {
+        base_asset_amount: -BASE_PRECISION_I64,
+        quote_asset_amount: 12 * QUOTE_PRECISION_I64,
+        quote_break_even_amount: 12 * QUOTE_PRECISION_I64,
+        quote_entry_amount: 12 * QUOTE_PRECISION_I64,
+        ..PerpPosition::default()
+    };
+    let mut market = PerpMarket {
+        amm: AMM {
+            base_asset_amount_short: -5 * BASE_PRECISION_I128,
+            quote_asset_amount: 50 * QUOTE_PRECISION_I128,
+            quote_break_even_amount_short: 48 * QUOTE_PRECISION_I128,
+            quote_entry_amount_short: 52 * QUOTE_PRECISION_I128,
+            ..AMM::default_test()
+        },
+        number_of_users_with_base: 2,
+        number_of_users: 2,
+        ..PerpMarket::default_test()
+    };
+
+    let (from_position_delta, to_position_delta) = transfer_perp_position(
+        &mut from_position,
+        &mut to_position,
+        &mut market,
+        2 * BASE_PRECISION_U64,
+    )
+    .unwrap();
+
+    assert_eq!(
+        from_position_delta.base_asset_amount,
+        2 * BASE_PRECISION_I64
+    );
+    assert_eq!(
+        from_position_delta.quote_asset_amount,
+        -20 * QUOTE_PRECISION_I64
+    );
+    assert_eq!(to_position_delta.base_asset_amount, -2 * BASE_PRECISION_I64);
+    assert_eq!(
+        to_position_delta.quote_asset_amount,
+        20 * QUOTE_PRECISION_I64
+    );
+
+    // no pnl realized: the remaining position keeps half of its cost basis
+    assert_eq!(from_position.base_asset_amount, -2 * BASE_PRECISION_I64);
+    assert_eq!(from_position.quote_asset_amount, 18 * QUOTE_PRECISION_I64);
+    assert_eq!(from_position.quote_entry_amount, 20 * QUOTE_PRECISION_I64);
+    assert_eq!(
+        from_position.quote_break_even_amount,
+        18 * QUOTE_PRECISION_I64
+    );
+
+    assert_eq!(to_position.base_asset_amount, -3 * BASE_PRECISION_I64);
+    assert_eq!(to_position.quote_asset_amount, 32 * QUOTE_PRECISION_I64);
+    assert_eq!(to_position.quote_entry_amount, 32 * QUOTE_PRECISION_I64);
+    assert_eq!(
+        to_position.quote_break_even_amount,
+        30 * QUOTE_PRECISION_I64
+    );
+
+    assert_eq!(market.number_of_users_with_base, 2);
+    assert_eq!(market.number_of_users, 2);
+    assert_eq!(market.amm.base_asset_amount_short, -5 * BASE_PRECISION_I128);
+    assert_eq!(market.amm.quote_asset_amount, 50 * QUOTE_PRECISION_I128);
+    assert_eq!(
+        market.amm.quote_entry_amount_short,
+        52 * QUOTE_PRECISION_I128
+    );
+    assert_eq!(
+        market.amm.quote_break_even_amount_short,
+        48 * QUOTE_PRECISION_I128
+    );
+}
+
+#[test]
+fn transfer_more_than_position_fails() {
+    let mut from_position = PerpPosition {
+        base_asset_amount: BASE_PRECISION_I64,
+        quote_asset_amount: -10 * QUOTE_PRECISION_I64,
+        quote_break_even_amount: -10 * QUOTE_PRECISION_I64,
+        quote_entry_amount: -10 * QUOTE_PRECISION_I64,
+        ..PerpPosition::default()
+    };
+    let mut to_position = PerpPosition::default();
+    let mut market = PerpMarket {
+        amm: AMM {
+            base_asset_amount_long: BASE_PRECISION_I128,
+            quote_asset_amount: -10 * QUOTE_PRECISION_I128,
+            ..AMM::default_test()
+        },
+        number_of_users_with_base: 1,
+        number_of_users: 1,
+        ..PerpMarket::default_test()
+    };
+
+    let result = transfer_perp_position(
+        &mut from_position,
+        &mut to_position,
+        &mut market,
+        2 * BASE_PRECISION_U64,
+    );
+
+    assert!(result.is_err());
+}
+
+#[test]
+fn transfer_short_flips_existing_long_keeps_break_even_on_opened_base() {
+    let mut from_position = PerpPosition {
+        base_asset_amount: -4 * BASE_PRECISION_I64,
+        quote_asset_amount: 38 * QUOTE_PRECISION_I64,
+        quote_break_even_amount: 36 * QUOTE_PRECISION_I64,
+        quote_entry_amount: 40 * QUOTE_PRECISION_I64,
+        ..PerpPosition::default()
+    };
+    let mut to_position = PerpPosition {
+        base_asset_amount: BASE_PRECISION_I64,
+        quote_asset_amount: -12 * QUOTE_PRECISION_I64,
+        quote_break_even_amount: -12 * QUOTE_PRECISION_I64,
+        quote_entry_amount: -12 * QUOTE_PRECISION_I64,
+        ..PerpPosition::default()
+    };
+    let mut market = PerpMarket {
+        amm: AMM {
+            base_asset_amount_long: BASE_PRECISION_I128,
+            base_asset_amount_short: -4 * BASE_PRECISION_I128,
+            quote_asset_amount: 26 * QUOTE_PRECISION_I128,
+            quote_entry_amount_long: -12 * QUOTE_PRECISION_I128,
+            quote_break_even_amount_long: -12 * QUOTE_PRECISION_I128,
+            quote_entry_amount_short: 40 * QUOTE_PRECISION_I128,
+            quote_break_even_amount_short: 36 * QUOTE_PRECISION_I128,
+            ..AMM::default_test()
+        },
+        number_of_users_with_base: 2,
+        number_of_users: 2,
+        ..PerpMarket::default_test()
+    };
+
+    transfer_perp_position(
+        &mut from_position,
+        &mut to_position,
+        &mut market,
+        4 * BASE_PRECISION_U64,
+    )
+    .unwrap();
+
+    assert_eq!(from_position.base_asset_amount, 0);
+    assert_eq!(from_position.quote_entry_amount, 0);
+    assert_eq!(from_position.quote_break_even_amount, 0);
+
+    // 1 of the 4 base closes the long, the other 3 open a short that keeps 3/4 of the premium
+    assert_eq!(to_position.base_asset_amount, -3 * BASE_PRECISION_I64);
+    assert_eq!(to_position.quote_asset_amount, 28 * QUOTE_PRECISION_I64);
+    assert_eq!(to_position.quote_entry_amount, 30 * QUOTE_PRECISION_I64);
+    assert_eq!(
+        to_position.quote_break_even_amount,
+        27 * QUOTE_PRECISION_I64
+    );
+
+    assert_eq!(market.number_of_users_with_base, 1);
+    assert_eq!(market.amm.base_asset_amount_long, 0);
+    assert_eq!(market.amm.base_asset_amount_short, -3 * BASE_PRECISION_I128);
+    assert_eq!(market.amm.quote_entry_amount_long, 0);
+    assert_eq!(market.amm.quote_break_even_amount_long, 0);
+    assert_eq!(
+        market.amm.quote_entry_amount_short,
+        30 * QUOTE_PRECISION_I128
+    );
+    assert_eq!(
+        market.amm.quote_break_even_amount_short,
+        27 * QUOTE_PRECISION_I128
+    );
+}
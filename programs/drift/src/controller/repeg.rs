@@ -5,6 +5,8 @@ use anchor_lang::prelude::*;
 use solana_program::msg;
 
 use crate::controller::amm::update_spreads;
+use crate::controller::lp::update_lp_ranges;
+use crate::controller::position::update_quote_asset_amount;
 use crate::controller::spot_balance::update_spot_balances;
 use crate::error::ErrorCode;
 use crate::error::*;
@@ -12,7 +14,10 @@ use crate::load_mut;
 use crate::math::amm;
 use crate::math::bn;
 use crate::math::casting::Cast;
-use crate::math::constants::{K_BPS_UPDATE_SCALE, QUOTE_PRECISION, QUOTE_SPOT_MARKET_INDEX};
+use crate::math::constants::{
+    KEEPER_REPEG_MIN_SLOTS, KEEPER_REPEG_TERMINAL_DIVERGENCE, K_BPS_UPDATE_SCALE, QUOTE_PRECISION,
+    QUOTE_SPOT_MARKET_INDEX,
+};
 use crate::math::cp_curve;
 use crate::math::cp_curve::get_update_k_result;
 use crate::math::oracle;
@@ -23,11 +28,12 @@ use crate::math::spot_balance::get_token_amount;
 
 use crate::state::oracle::OraclePriceData;
 use crate::state::oracle_map::OracleMap;
-use crate::state::perp_market::{MarketStatus, PerpMarket};
+use crate::state::perp_market::{AmmMode, MarketStatus, PerpMarket};
 use crate::state::perp_market_map::PerpMarketMap;
 use crate::state::spot_market::SpotBalanceType;
 use crate::state::spot_market_map::SpotMarketMap;
 use crate::state::state::{OracleGuardRails, State};
+use crate::state::user::User;
 use crate::validate;
 
 #[cfg(test)]
@@ -42,6 +48,13 @@ pub fn repeg(
 ) -> DriftResult<i128> {
     // for adhoc admin only repeg
 
+    validate!(
+        market.amm_mode != AmmMode::OraclePegged,
+        ErrorCode::AmmOraclePegged,
+        "perp market {} amm is pegged to the oracle",
+        market.market_index
+    )?;
+
     if new_peg_candidate == market.amm.peg_multiplier {
         return Err(ErrorCode::InvalidRepegRedundant);
     }
@@ -158,23 +171,13 @@ pub fn _update_amm(
         let curve_update_intensity =
             min(market.amm.curve_update_intensity, 100_u8).cast::<i128>()?;
 
-        if curve_update_intensity > 0 {
-            let (optimal_peg, fee_budget, check_lower_bound) =
-                repeg::calculate_optimal_peg_and_budget(market, oracle_price_data)?;
-
-            let (repegged_market, repegged_cost) =
-                repeg::adjust_amm(market, optimal_peg, fee_budget, true)?;
-
-            let cost_applied = apply_cost_to_market(market, repegged_cost, check_lower_bound)?;
-
-            if cost_applied {
-                market.amm.base_asset_reserve = repegged_market.amm.base_asset_reserve;
-                market.amm.quote_asset_reserve = repegged_market.amm.quote_asset_reserve;
-                market.amm.sqrt_k = repegged_market.amm.sqrt_k;
-
-                market.amm.terminal_quote_asset_reserve =
-                    repegged_market.amm.terminal_quote_asset_reserve;
-                market.amm.peg_multiplier = repegged_market.amm.peg_multiplier;
+        if market.amm_mode == AmmMode::OraclePegged {
+            // peg follows the oracle for free, the amm carries the inventory pnl instead of
+            // paying repeg cost out of total_fee_minus_distributions
+            market.amm.peg_multiplier =
+                repeg::calculate_oracle_pegged_peg(&market.amm, oracle_price_data)?;
+        } else if curve_update_intensity > 0 {
+            if let Some(repegged_cost) = repeg_to_optimal_peg(market, oracle_price_data)? {
                 amm_update_cost = repegged_cost;
             }
         }
@@ -201,11 +204,131 @@ pub fn _update_amm(
         market.amm.last_oracle_valid = false;
     }
 
-    update_spreads(&mut market.amm, reserve_price_after)?;
+    // lp ranges the reserve price crossed since the last update join or leave sqrt_k
+    update_lp_ranges(market)?;
+
+    update_spreads(market, reserve_price_after)?;
 
     Ok(amm_update_cost)
 }
 
+// returns the cost if the fee pool could pay for the budgeted repeg
+fn repeg_to_optimal_peg(
+    market: &mut PerpMarket,
+    oracle_price_data: &OraclePriceData,
+) -> DriftResult<Option<i128>> {
+    let (optimal_peg, fee_budget, check_lower_bound) =
+        repeg::calculate_optimal_peg_and_budget(market, oracle_price_data)?;
+
+    let (repegged_market, repegged_cost) =
+        repeg::adjust_amm(market, optimal_peg, fee_budget, true)?;
+
+    let cost_applied = apply_cost_to_market(market, repegged_cost, check_lower_bound)?;
+
+    if !cost_applied {
+        return Ok(None);
+    }
+
+    market.amm.base_asset_reserve = repegged_market.amm.base_asset_reserve;
+    market.amm.quote_asset_reserve = repegged_market.amm.quote_asset_reserve;
+    market.amm.sqrt_k = repegged_market.amm.sqrt_k;
+
+    market.amm.terminal_quote_asset_reserve = repegged_market.amm.terminal_quote_asset_reserve;
+    market.amm.peg_multiplier = repegged_market.amm.peg_multiplier;
+
+    Ok(Some(repegged_cost))
+}
+
+/// Permissionless budgeted repeg. Moves the peg toward the oracle using the same
+/// budget as the formulaic update in `_update_amm`, but only once the terminal price
+/// has drifted more than KEEPER_REPEG_TERMINAL_DIVERGENCE from the oracle and at most
+/// once every KEEPER_REPEG_MIN_SLOTS per market. The keeper is paid `keeper_reward` out
+/// of the fee pool if the peg moved and the lower bound allows it.
+///
+/// Returns (repeg cost, keeper reward paid)
+pub fn keeper_repeg(
+    market: &mut PerpMarket,
+    keeper: &mut User,
+    oracle_price_data: &OraclePriceData,
+    state: &State,
+    keeper_reward: u64,
+    clock_slot: u64,
+) -> DriftResult<(i128, u64)> {
+    validate!(
+        market.amm_mode != AmmMode::OraclePegged,
+        ErrorCode::AmmOraclePegged,
+        "perp market {} amm is pegged to the oracle",
+        market.market_index
+    )?;
+
+    validate!(
+        market.last_keeper_repeg_slot == 0
+            || clock_slot.saturating_sub(market.last_keeper_repeg_slot) >= KEEPER_REPEG_MIN_SLOTS,
+        ErrorCode::KeeperRepegTooFrequent,
+        "perp market {} last repegged at slot {}, current slot {}",
+        market.market_index,
+        market.last_keeper_repeg_slot,
+        clock_slot
+    )?;
+
+    let oracle_validity = oracle_validity(
+        market.amm.historical_oracle_data.last_oracle_price_twap,
+        oracle_price_data,
+        &state.oracle_guard_rails.validity,
+    )?;
+
+    validate!(
+        is_oracle_valid_for_action(oracle_validity, Some(DriftAction::UpdateAMMCurve))?,
+        ErrorCode::InvalidOracle,
+        "oracle invalid for repeg ({:?})",
+        oracle_validity
+    )?;
+
+    let (terminal_price, _, _) = amm::calculate_terminal_price_and_reserves(&market.amm)?;
+    let terminal_oracle_divergence =
+        repeg::calculate_terminal_oracle_divergence(terminal_price, oracle_price_data.price)?;
+
+    validate!(
+        terminal_oracle_divergence > KEEPER_REPEG_TERMINAL_DIVERGENCE,
+        ErrorCode::InvalidRepegRedundant,
+        "terminal price {} within {} of oracle {}",
+        terminal_price,
+        KEEPER_REPEG_TERMINAL_DIVERGENCE,
+        oracle_price_data.price
+    )?;
+
+    let peg_multiplier_before = market.amm.peg_multiplier;
+
+    let adjustment_cost = repeg_to_optimal_peg(market, oracle_price_data)?
+        .ok_or(ErrorCode::InvalidRepegProfitability)?;
+
+    market.last_keeper_repeg_slot = clock_slot;
+
+    // only pay for a repeg that actually moved the peg
+    let peg_changed = market.amm.peg_multiplier != peg_multiplier_before;
+
+    let keeper_reward = match keeper.force_get_perp_position_mut(market.market_index) {
+        Ok(keeper_position) => {
+            if peg_changed
+                && keeper_reward > 0
+                && apply_cost_to_market(market, keeper_reward.cast()?, true)?
+            {
+                update_quote_asset_amount(keeper_position, market, keeper_reward.cast()?)?;
+                keeper_reward
+            } else {
+                0
+            }
+        }
+        // Dont throw error if keeper doesnt have position available
+        Err(_) => 0,
+    };
+
+    let reserve_price_after = market.amm.reserve_price()?;
+    update_spreads(market, reserve_price_after)?;
+
+    Ok((adjustment_cost, keeper_reward))
+}
+
 pub fn update_amm_and_check_validity(
     market: &mut PerpMarket,
     oracle_price_data: &OraclePriceData,
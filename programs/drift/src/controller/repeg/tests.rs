@@ -1,14 +1,14 @@
 use crate::controller::repeg::*;
 use crate::math::constants::{
-    AMM_RESERVE_PRECISION, PEG_PRECISION, PRICE_PRECISION, PRICE_PRECISION_I64,
-    PRICE_PRECISION_U64, QUOTE_PRECISION,
+    AMM_RESERVE_PRECISION, KEEPER_REPEG_MIN_SLOTS, PEG_PRECISION, PRICE_PRECISION,
+    PRICE_PRECISION_I64, PRICE_PRECISION_U64, QUOTE_PRECISION,
 };
 use crate::math::oracle::OracleValidity;
 use crate::math::repeg::{
     calculate_fee_pool, calculate_peg_from_target_price, calculate_repeg_cost,
 };
 use crate::state::oracle::HistoricalOracleData;
-use crate::state::perp_market::{ContractTier, AMM};
+use crate::state::perp_market::{AmmMode, ContractTier, AMM};
 use crate::state::state::{PriceDivergenceGuardRails, ValidityGuardRails};
 
 #[test]
@@ -465,3 +465,220 @@ pub fn update_amm_larg_conf_w_neg_tfmd_test() {
     assert_eq!(mrk, 18838349499);
     assert_eq!(ask, 18838349499);
 }
+
+#[test]
+pub fn update_amm_oracle_pegged_test() {
+    let mut market = PerpMarket {
+        amm: AMM {
+            base_asset_reserve: 65 * AMM_RESERVE_PRECISION,
+            quote_asset_reserve: 63015384615,
+            terminal_quote_asset_reserve: 64 * AMM_RESERVE_PRECISION,
+            sqrt_k: 64 * AMM_RESERVE_PRECISION,
+            peg_multiplier: 19_400 * PEG_PRECISION,
+            base_asset_amount_with_amm: -(AMM_RESERVE_PRECISION as i128),
+            mark_std: PRICE_PRECISION as u64,
+            last_mark_price_twap_ts: 0,
+            historical_oracle_data: HistoricalOracleData {
+                last_oracle_price_twap: 19_400 * PRICE_PRECISION_I64,
+                ..HistoricalOracleData::default()
+            },
+            base_spread: 250,
+            curve_update_intensity: 100,
+            max_spread: 55500,
+            ..AMM::default()
+        },
+        amm_mode: AmmMode::OraclePegged,
+        status: MarketStatus::Initialized,
+        contract_tier: ContractTier::B,
+        margin_ratio_initial: 555,
+        ..PerpMarket::default()
+    };
+
+    let state = State {
+        oracle_guard_rails: OracleGuardRails {
+            price_divergence: PriceDivergenceGuardRails {
+                mark_oracle_divergence_numerator: 1,
+                mark_oracle_divergence_denominator: 10,
+            },
+            validity: ValidityGuardRails {
+                slots_before_stale_for_amm: 10,     // 5s
+                slots_before_stale_for_margin: 120, // 60s
+                confidence_interval_max_size: 1000,
+                too_volatile_ratio: 5,
+            },
+            use_for_liquidations: true,
+        },
+        ..State::default()
+    };
+
+    let now = 10000;
+    let slot = 81680085;
+    let oracle_price_data = OraclePriceData {
+        price: (12_400 * PRICE_PRECISION) as i64,
+        confidence: 10 * PRICE_PRECISION_U64,
+        delay: 2,
+        has_sufficient_number_of_data_points: true,
+    };
+
+    let reserve_price_before = market.amm.reserve_price().unwrap();
+    assert_eq!(reserve_price_before, 18807668638);
+
+    let cost_of_update = _update_amm(&mut market, &oracle_price_data, &state, now, slot).unwrap();
+
+    // pulled to the closer edge of the confidence band without charging the fee pool
+    assert_eq!(cost_of_update, 0);
+    assert_eq!(market.amm.total_fee_minus_distributions, 0);
+    assert_eq!(market.amm.base_asset_reserve, 65 * AMM_RESERVE_PRECISION);
+    assert_eq!(market.amm.quote_asset_reserve, 63015384615);
+
+    let reserve_price_after = market.amm.reserve_price().unwrap();
+    assert_eq!(market.amm.peg_multiplier, 12800842285);
+    assert_eq!(reserve_price_after, 12409999999); // oracle + conf
+
+    // already inside the band, peg is left alone
+    let peg_before = market.amm.peg_multiplier;
+    let oracle_price_data = OraclePriceData {
+        price: (12_405 * PRICE_PRECISION) as i64,
+        ..oracle_price_data
+    };
+    let cost_of_update = _update_amm(&mut market, &oracle_price_data, &state, now, slot).unwrap();
+    assert_eq!(cost_of_update, 0);
+    assert_eq!(market.amm.peg_multiplier, peg_before);
+}
+
+#[test]
+pub fn keeper_repeg_test() {
+    let mut market = PerpMarket {
+        amm: AMM {
+            base_asset_reserve: 65 * AMM_RESERVE_PRECISION,
+            quote_asset_reserve: 63015384615,
+            terminal_quote_asset_reserve: 64 * AMM_RESERVE_PRECISION,
+            sqrt_k: 64 * AMM_RESERVE_PRECISION,
+            peg_multiplier: 19_400 * PEG_PRECISION,
+            base_asset_amount_with_amm: -(AMM_RESERVE_PRECISION as i128),
+            mark_std: PRICE_PRECISION as u64,
+            last_mark_price_twap_ts: 0,
+            historical_oracle_data: HistoricalOracleData {
+                last_oracle_price_twap: 19_400 * PRICE_PRECISION_I64,
+                ..HistoricalOracleData::default()
+            },
+            total_fee_minus_distributions: 100_000 * QUOTE_PRECISION as i128,
+            base_spread: 250,
+            curve_update_intensity: 100,
+            max_spread: 55500,
+            ..AMM::default()
+        },
+        status: MarketStatus::Active,
+        contract_tier: ContractTier::B,
+        margin_ratio_initial: 555,
+        ..PerpMarket::default()
+    };
+
+    let state = State {
+        oracle_guard_rails: OracleGuardRails {
+            price_divergence: PriceDivergenceGuardRails {
+                mark_oracle_divergence_numerator: 1,
+                mark_oracle_divergence_denominator: 10,
+            },
+            validity: ValidityGuardRails {
+                slots_before_stale_for_amm: 10,     // 5s
+                slots_before_stale_for_margin: 120, // 60s
+                confidence_interval_max_size: 1000,
+                too_volatile_ratio: 5,
+            },
+            use_for_liquidations: true,
+        },
+        ..State::default()
+    };
+
+    let mut keeper = User::default();
+    let keeper_reward = 10_000;
+    let slot = 1_000;
+
+    let (terminal_price_before, _, _) =
+        amm::calculate_terminal_price_and_reserves(&market.amm).unwrap();
+
+    // terminal price already at the oracle, nothing to do
+    let oracle_price_data = OraclePriceData {
+        price: terminal_price_before as i64,
+        confidence: 0,
+        delay: 2,
+        has_sufficient_number_of_data_points: true,
+    };
+    let result = keeper_repeg(
+        &mut market,
+        &mut keeper,
+        &oracle_price_data,
+        &state,
+        keeper_reward,
+        slot,
+    );
+    assert_eq!(result, Err(ErrorCode::InvalidRepegRedundant));
+
+    let oracle_price_data = OraclePriceData {
+        price: (12_400 * PRICE_PRECISION) as i64,
+        ..oracle_price_data
+    };
+
+    // oracle pegged markets never pay for a repeg
+    market.amm_mode = AmmMode::OraclePegged;
+    let result = keeper_repeg(
+        &mut market,
+        &mut keeper,
+        &oracle_price_data,
+        &state,
+        keeper_reward,
+        slot,
+    );
+    assert_eq!(result, Err(ErrorCode::AmmOraclePegged));
+    market.amm_mode = AmmMode::Curve;
+
+    let total_fee_minus_distributions_before = market.amm.total_fee_minus_distributions;
+    let (adjustment_cost, keeper_reward_paid) = keeper_repeg(
+        &mut market,
+        &mut keeper,
+        &oracle_price_data,
+        &state,
+        keeper_reward,
+        slot,
+    )
+    .unwrap();
+
+    assert!(adjustment_cost > 0);
+    assert_eq!(keeper_reward_paid, keeper_reward);
+    assert_eq!(
+        keeper.perp_positions[0].quote_asset_amount,
+        keeper_reward as i64
+    );
+    assert_eq!(
+        market.amm.total_fee_minus_distributions,
+        total_fee_minus_distributions_before - adjustment_cost - keeper_reward as i128
+    );
+
+    assert_eq!(market.last_keeper_repeg_slot, slot);
+
+    let (terminal_price_after, _, _) =
+        amm::calculate_terminal_price_and_reserves(&market.amm).unwrap();
+    let oracle_price = oracle_price_data.price as u64;
+    assert!(terminal_price_after < terminal_price_before);
+    assert!(
+        terminal_price_after.max(oracle_price) - terminal_price_after.min(oracle_price)
+            < terminal_price_before - oracle_price
+    );
+
+    // market can't be repegged again until KEEPER_REPEG_MIN_SLOTS have passed
+    let oracle_price_data = OraclePriceData {
+        price: (9_400 * PRICE_PRECISION) as i64,
+        ..oracle_price_data
+    };
+    let result = keeper_repeg(
+        &mut market,
+        &mut keeper,
+        &oracle_price_data,
+        &state,
+        keeper_reward,
+        slot + KEEPER_REPEG_MIN_SLOTS - 1,
+    );
+    assert_eq!(result, Err(ErrorCode::KeeperRepegTooFrequent));
+    assert_eq!(market.last_keeper_repeg_slot, slot);
+}
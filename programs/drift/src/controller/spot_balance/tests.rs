@@ -10,10 +10,10 @@ use crate::create_account_info;
 use crate::create_anchor_account_info;
 use crate::math::constants::{
     AMM_RESERVE_PRECISION, BASE_PRECISION_I128, BASE_PRECISION_I64, LIQUIDATION_FEE_PRECISION,
-    PEG_PRECISION, QUOTE_PRECISION, QUOTE_PRECISION_I128, QUOTE_PRECISION_I64, QUOTE_PRECISION_U64,
-    SPOT_BALANCE_PRECISION, SPOT_BALANCE_PRECISION_U64, SPOT_CUMULATIVE_INTEREST_PRECISION,
-    SPOT_RATE_PRECISION_U32, SPOT_UTILIZATION_PRECISION, SPOT_UTILIZATION_PRECISION_U32,
-    SPOT_WEIGHT_PRECISION,
+    MAX_OPEN_ORDERS, MAX_PERP_POSITIONS, MAX_SPOT_POSITIONS, PEG_PRECISION, QUOTE_PRECISION,
+    QUOTE_PRECISION_I128, QUOTE_PRECISION_I64, QUOTE_PRECISION_U64, SPOT_BALANCE_PRECISION,
+    SPOT_BALANCE_PRECISION_U64, SPOT_CUMULATIVE_INTEREST_PRECISION, SPOT_RATE_PRECISION_U32,
+    SPOT_UTILIZATION_PRECISION, SPOT_UTILIZATION_PRECISION_U32, SPOT_WEIGHT_PRECISION,
 };
 use crate::math::margin::{
     calculate_margin_requirement_and_total_collateral, MarginRequirementType,
@@ -117,7 +117,7 @@ fn test_daily_withdraw_limits() {
         Vec::from([&spot_market_account_info, &sol_spot_market_account_info]);
     let _spot_market_map = SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-    let mut spot_positions = [SpotPosition::default(); 8];
+    let mut spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
     spot_positions[0] = SpotPosition {
         market_index: 0,
         balance_type: SpotBalanceType::Deposit,
@@ -125,8 +125,8 @@ fn test_daily_withdraw_limits() {
         ..SpotPosition::default()
     };
     let mut user = User {
-        orders: [Order::default(); 32],
-        perp_positions: [PerpPosition::default(); 8],
+        orders: [Order::default(); MAX_OPEN_ORDERS as usize],
+        perp_positions: [PerpPosition::default(); MAX_PERP_POSITIONS as usize],
         spot_positions,
         ..User::default()
     };
@@ -421,7 +421,7 @@ fn test_check_withdraw_limits() {
         Vec::from([&spot_market_account_info, &sol_spot_market_account_info]);
     let _spot_market_map = SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-    let mut spot_positions = [SpotPosition::default(); 8];
+    let mut spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
     spot_positions[0] = SpotPosition {
         market_index: 0,
         balance_type: SpotBalanceType::Deposit,
@@ -435,8 +435,8 @@ fn test_check_withdraw_limits() {
         ..SpotPosition::default()
     };
     // let mut user = User {
-    //     orders: [Order::default(); 32],
-    //     perp_positions: [PerpPosition::default(); 8],
+    //     orders: [Order::default(); MAX_OPEN_ORDERS as usize],
+    //     perp_positions: [PerpPosition::default(); MAX_PERP_POSITIONS as usize],
     //     spot_positions,
     //     ..User::default()
     // };
@@ -553,7 +553,7 @@ fn check_fee_collection() {
         Vec::from([&spot_market_account_info, &sol_spot_market_account_info]);
     let _spot_market_map = SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-    let mut spot_positions = [SpotPosition::default(); 8];
+    let mut spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
     spot_positions[0] = SpotPosition {
         market_index: 1,
         balance_type: SpotBalanceType::Deposit,
@@ -561,8 +561,8 @@ fn check_fee_collection() {
         ..SpotPosition::default()
     };
     let mut user = User {
-        orders: [Order::default(); 32],
-        perp_positions: [PerpPosition::default(); 8],
+        orders: [Order::default(); MAX_OPEN_ORDERS as usize],
+        perp_positions: [PerpPosition::default(); MAX_PERP_POSITIONS as usize],
         spot_positions,
         ..User::default()
     };
@@ -885,7 +885,7 @@ fn check_fee_collection_larger_nums() {
         Vec::from([&spot_market_account_info, &sol_spot_market_account_info]);
     let _spot_market_map = SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-    let mut spot_positions = [SpotPosition::default(); 8];
+    let mut spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
     spot_positions[0] = SpotPosition {
         market_index: 1,
         balance_type: SpotBalanceType::Deposit,
@@ -893,8 +893,8 @@ fn check_fee_collection_larger_nums() {
         ..SpotPosition::default()
     };
     let mut user = User {
-        orders: [Order::default(); 32],
-        perp_positions: [PerpPosition::default(); 8],
+        orders: [Order::default(); MAX_OPEN_ORDERS as usize],
+        perp_positions: [PerpPosition::default(); MAX_PERP_POSITIONS as usize],
         spot_positions,
         ..User::default()
     };
@@ -1174,7 +1174,7 @@ fn attempt_borrow_with_massive_upnl() {
     let spot_market_map = SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
     // user has 100 sol
-    let mut spot_positions = [SpotPosition::default(); 8];
+    let mut spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
     spot_positions[0] = SpotPosition {
         market_index: 1,
         balance_type: SpotBalanceType::Deposit,
@@ -1183,7 +1183,7 @@ fn attempt_borrow_with_massive_upnl() {
     };
 
     let user = User {
-        orders: [Order::default(); 32],
+        orders: [Order::default(); MAX_OPEN_ORDERS as usize],
         perp_positions: get_positions(PerpPosition {
             market_index: 0,
             base_asset_amount: 1000 * BASE_PRECISION_I64,
@@ -3,7 +3,8 @@ mod update_spot_position_balance {
         transfer_spot_position_deposit, update_spot_balances_and_cumulative_deposits,
     };
     use crate::math::constants::{
-        LAMPORTS_PER_SOL_I64, SPOT_BALANCE_PRECISION, SPOT_BALANCE_PRECISION_U64,
+        LAMPORTS_PER_SOL_I64, MAX_SPOT_POSITIONS, SPOT_BALANCE_PRECISION,
+        SPOT_BALANCE_PRECISION_U64,
     };
     use crate::state::spot_market::{SpotBalanceType, SpotMarket};
     use crate::state::user::{SpotPosition, User};
@@ -90,7 +91,7 @@ mod update_spot_position_balance {
     fn transfer_fail() {
         let mut user = User::default();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -1,40 +1,336 @@
+use crate::controller::pda::seed_and_create_pda;
+use crate::error::{DriftResult, ErrorCode};
+use crate::ids::token_2022_program;
+use crate::math::token_2022::{
+    calculate_pre_fee_amount, get_mint_decimals, get_token_account_length, ACCOUNT_TYPE_ACCOUNT,
+    BASE_ACCOUNT_LENGTH,
+};
 use crate::signer::get_signer_seeds;
+use crate::validate;
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::program::{invoke, invoke_signed};
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_lang::system_program;
+use anchor_spl::token::spl_token::state::{Account as SplTokenAccount, AccountState};
+use anchor_spl::token::{
+    self, Burn, CloseAccount, MintTo, SyncNative, Token, TokenAccount, Transfer,
+};
+
+const TRANSFER_CHECKED_INSTRUCTION: u8 = 12;
+const INITIALIZE_ACCOUNT_3_INSTRUCTION: u8 = 18;
+
+pub fn is_token_2022_program(token_program: &AccountInfo) -> bool {
+    token_program.key == &token_2022_program::id()
+}
+
+// reads the spl token base layout shared by token and token-2022 accounts
+pub fn load_token_account(
+    token_account: &AccountInfo,
+    token_program: &AccountInfo,
+) -> DriftResult<SplTokenAccount> {
+    validate!(
+        token_account.owner == token_program.key,
+        ErrorCode::InvalidTokenAccount,
+        "token account {} not owned by token program",
+        token_account.key
+    )?;
+
+    unpack_token_account(token_account)
+}
+
+// for program vaults read by instructions that don't take the token program
+pub fn load_vault_token_account(token_account: &AccountInfo) -> DriftResult<SplTokenAccount> {
+    validate!(
+        token_account.owner == &token::ID || token_account.owner == &token_2022_program::id(),
+        ErrorCode::InvalidTokenAccount,
+        "token account {} not owned by a token program",
+        token_account.key
+    )?;
+
+    unpack_token_account(token_account)
+}
+
+fn unpack_token_account(token_account: &AccountInfo) -> DriftResult<SplTokenAccount> {
+    let data = token_account
+        .try_borrow_data()
+        .or(Err(ErrorCode::InvalidTokenAccount))?;
+
+    validate!(
+        data.len() == BASE_ACCOUNT_LENGTH
+            || (token_account.owner == &token_2022_program::id()
+                && data.len() > BASE_ACCOUNT_LENGTH
+                && data[BASE_ACCOUNT_LENGTH] == ACCOUNT_TYPE_ACCOUNT),
+        ErrorCode::InvalidTokenAccount,
+        "invalid token account data for {}",
+        token_account.key
+    )?;
+
+    let account = SplTokenAccount::unpack_from_slice(&data[..BASE_ACCOUNT_LENGTH])
+        .or(Err(ErrorCode::InvalidTokenAccount))?;
+
+    validate!(
+        account.state != AccountState::Uninitialized,
+        ErrorCode::InvalidTokenAccount,
+        "token account {} not initialized",
+        token_account.key
+    )?;
+
+    Ok(account)
+}
+
+pub fn validate_user_token_account(
+    user_token_account: &SplTokenAccount,
+    mint: &Pubkey,
+    authority: &Pubkey,
+) -> DriftResult {
+    validate!(
+        user_token_account.mint == *mint && user_token_account.owner == *authority,
+        ErrorCode::InvalidTokenAccount,
+        "user token account must be owned by authority and match vault mint {}",
+        mint
+    )
+}
 
 pub fn send_from_program_vault<'info>(
-    token_program: &Program<'info, Token>,
-    from: &Account<'info, TokenAccount>,
-    to: &Account<'info, TokenAccount>,
+    token_program: &AccountInfo<'info>,
+    from: &AccountInfo<'info>,
+    to: &AccountInfo<'info>,
     authority: &AccountInfo<'info>,
     nonce: u8,
     amount: u64,
+    mint: &Option<AccountInfo<'info>>,
 ) -> Result<()> {
     let signature_seeds = get_signer_seeds(&nonce);
     let signers = &[&signature_seeds[..]];
+
+    if is_token_2022_program(token_program) {
+        return transfer_checked(token_program, from, to, authority, amount, mint, signers);
+    }
+
     let cpi_accounts = Transfer {
-        from: from.to_account_info().clone(),
-        to: to.to_account_info().clone(),
-        authority: authority.to_account_info().clone(),
+        from: from.clone(),
+        to: to.clone(),
+        authority: authority.clone(),
     };
-    let cpi_program = token_program.to_account_info();
+    let cpi_program = token_program.clone();
     let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, signers);
     token::transfer(cpi_context, amount)
 }
 
 pub fn receive<'info>(
-    token_program: &Program<'info, Token>,
-    from: &Account<'info, TokenAccount>,
-    to: &Account<'info, TokenAccount>,
+    token_program: &AccountInfo<'info>,
+    from: &AccountInfo<'info>,
+    to: &AccountInfo<'info>,
     authority: &AccountInfo<'info>,
     amount: u64,
+    mint: &Option<AccountInfo<'info>>,
 ) -> Result<()> {
+    if is_token_2022_program(token_program) {
+        return transfer_checked(token_program, from, to, authority, amount, mint, &[]);
+    }
+
     let cpi_accounts = Transfer {
-        from: from.to_account_info().clone(),
+        from: from.clone(),
+        to: to.clone(),
+        authority: authority.clone(),
+    };
+    let cpi_program = token_program.clone();
+    let cpi_context = CpiContext::new(cpi_program, cpi_accounts);
+    token::transfer(cpi_context, amount)
+}
+
+// token-2022 transfer fees are withheld from the receiving account, so transfers between program
+// vaults send the pre fee amount for the receiving vault to be credited in full
+pub fn send_between_program_vaults<'info>(
+    token_program: &AccountInfo<'info>,
+    from: &AccountInfo<'info>,
+    to: &AccountInfo<'info>,
+    authority: &AccountInfo<'info>,
+    nonce: u8,
+    amount: u64,
+    mint: &Option<AccountInfo<'info>>,
+) -> Result<u64> {
+    let amount = match mint {
+        Some(mint) if is_token_2022_program(token_program) => {
+            calculate_pre_fee_amount(&mint.try_borrow_data()?, Clock::get()?.epoch, amount)?
+        }
+        _ => amount,
+    };
+
+    send_from_program_vault(token_program, from, to, authority, nonce, amount, mint)?;
+
+    Ok(amount)
+}
+
+// token-2022 requires the mint for transfers so that extensions (e.g. transfer fees) are applied
+fn transfer_checked<'info>(
+    token_program: &AccountInfo<'info>,
+    from: &AccountInfo<'info>,
+    to: &AccountInfo<'info>,
+    authority: &AccountInfo<'info>,
+    amount: u64,
+    mint: &Option<AccountInfo<'info>>,
+    signers: &[&[&[u8]]],
+) -> Result<()> {
+    let mint = mint.as_ref().ok_or_else(|| {
+        msg!("token-2022 transfer requires the mint account");
+        ErrorCode::InvalidTokenMint
+    })?;
+    let decimals = get_mint_decimals(&mint.try_borrow_data()?)?;
+
+    let mut data = Vec::with_capacity(10);
+    data.push(TRANSFER_CHECKED_INSTRUCTION);
+    data.extend_from_slice(&amount.to_le_bytes());
+    data.push(decimals);
+
+    let instruction = Instruction {
+        program_id: *token_program.key,
+        accounts: vec![
+            AccountMeta::new(*from.key, false),
+            AccountMeta::new_readonly(*mint.key, false),
+            AccountMeta::new(*to.key, false),
+            AccountMeta::new_readonly(*authority.key, true),
+        ],
+        data,
+    };
+
+    invoke_signed(
+        &instruction,
+        &[
+            from.clone(),
+            mint.clone(),
+            to.clone(),
+            authority.clone(),
+            token_program.clone(),
+        ],
+        signers,
+    )
+    .map_err(Into::into)
+}
+
+// creates a token account at a program address, sized for the mint's required account extensions
+#[allow(clippy::too_many_arguments)]
+pub fn initialize_program_token_account<'info>(
+    program_id: &Pubkey,
+    token_program: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    account: &AccountInfo<'info>,
+    seeds: &[&[u8]],
+    mint: &AccountInfo<'info>,
+    authority: &Pubkey,
+) -> Result<()> {
+    let space = if is_token_2022_program(token_program) {
+        get_token_account_length(&mint.try_borrow_data()?)?
+    } else {
+        BASE_ACCOUNT_LENGTH
+    };
+
+    seed_and_create_pda(
+        program_id,
+        payer,
+        &Rent::get()?,
+        space,
+        token_program.key,
+        system_program,
+        account,
+        seeds,
+    )?;
+
+    let mut data = Vec::with_capacity(33);
+    data.push(INITIALIZE_ACCOUNT_3_INSTRUCTION);
+    data.extend_from_slice(authority.as_ref());
+
+    let instruction = Instruction {
+        program_id: *token_program.key,
+        accounts: vec![
+            AccountMeta::new(*account.key, false),
+            AccountMeta::new_readonly(*mint.key, false),
+        ],
+        data,
+    };
+
+    invoke(
+        &instruction,
+        &[account.clone(), mint.clone(), token_program.clone()],
+    )
+    .map_err(Into::into)
+}
+
+pub fn wrap_native_sol<'info>(
+    token_program: &Program<'info, Token>,
+    system_program: &Program<'info, System>,
+    from: &AccountInfo<'info>,
+    to: &Account<'info, TokenAccount>,
+    amount: u64,
+) -> Result<()> {
+    let cpi_accounts = system_program::Transfer {
+        from: from.clone(),
         to: to.to_account_info().clone(),
-        authority: authority.to_account_info().clone(),
+    };
+    let cpi_context = CpiContext::new(system_program.to_account_info(), cpi_accounts);
+    system_program::transfer(cpi_context, amount)?;
+
+    let cpi_accounts = SyncNative {
+        account: to.to_account_info().clone(),
+    };
+    let cpi_context = CpiContext::new(token_program.to_account_info(), cpi_accounts);
+    token::sync_native(cpi_context)
+}
+
+pub fn close_program_token_account<'info>(
+    token_program: &Program<'info, Token>,
+    account: &Account<'info, TokenAccount>,
+    destination: &AccountInfo<'info>,
+    authority: &AccountInfo<'info>,
+    nonce: u8,
+) -> Result<()> {
+    let signature_seeds = get_signer_seeds(&nonce);
+    let signers = &[&signature_seeds[..]];
+    let cpi_accounts = CloseAccount {
+        account: account.to_account_info().clone(),
+        destination: destination.clone(),
+        authority: authority.clone(),
     };
     let cpi_program = token_program.to_account_info();
+    let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, signers);
+    token::close_account(cpi_context)
+}
+
+pub fn mint_from_program_mint<'info>(
+    token_program: &AccountInfo<'info>,
+    mint: &AccountInfo<'info>,
+    to: &AccountInfo<'info>,
+    authority: &AccountInfo<'info>,
+    nonce: u8,
+    amount: u64,
+) -> Result<()> {
+    let signature_seeds = get_signer_seeds(&nonce);
+    let signers = &[&signature_seeds[..]];
+    let cpi_accounts = MintTo {
+        mint: mint.clone(),
+        to: to.clone(),
+        authority: authority.clone(),
+    };
+    let cpi_program = token_program.clone();
+    let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, signers);
+    token::mint_to(cpi_context, amount)
+}
+
+pub fn burn<'info>(
+    token_program: &AccountInfo<'info>,
+    mint: &AccountInfo<'info>,
+    from: &AccountInfo<'info>,
+    authority: &AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    let cpi_accounts = Burn {
+        mint: mint.clone(),
+        from: from.clone(),
+        authority: authority.clone(),
+    };
+    let cpi_program = token_program.clone();
     let cpi_context = CpiContext::new(cpi_program, cpi_accounts);
-    token::transfer(cpi_context, amount)
+    token::burn(cpi_context, amount)
 }
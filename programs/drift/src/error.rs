@@ -435,6 +435,80 @@ pub enum ErrorCode {
     MarketBeingInitialized,
     #[msg("Invalid Sub Account Id")]
     InvalidUserSubAccountId,
+    #[msg("InvalidTransferPerpPosition")]
+    InvalidTransferPerpPosition,
+    #[msg("InvalidUserAccountSize")]
+    InvalidUserAccountSize,
+    #[msg("InvalidTokenMint")]
+    InvalidTokenMint,
+    #[msg("UnsupportedMintExtension")]
+    UnsupportedMintExtension,
+    #[msg("InvalidTokenAccount")]
+    InvalidTokenAccount,
+    #[msg("BackstopLiquidationNotAvailable")]
+    BackstopLiquidationNotAvailable,
+    #[msg("InsufficientBackstopShares")]
+    InsufficientBackstopShares,
+    #[msg("InvalidBackstopVault")]
+    InvalidBackstopVault,
+    #[msg("InvalidInsuranceFundStakeMarket")]
+    InvalidInsuranceFundStakeMarket,
+    #[msg("InvalidPerpMarketInsuranceFundVault")]
+    InvalidPerpMarketInsuranceFundVault,
+    #[msg("InvalidInsuranceFundRewardVault")]
+    InvalidInsuranceFundRewardVault,
+    #[msg("InvalidInsuranceFundSharesMint")]
+    InvalidInsuranceFundSharesMint,
+    #[msg("InsufficientInsuranceCoverage")]
+    InsufficientInsuranceCoverage,
+    #[msg("InstantIFUnstakeDisabled")]
+    InstantIFUnstakeDisabled,
+    #[msg("InvalidUserLiquidationHistory")]
+    InvalidUserLiquidationHistory,
+    #[msg("AmmOraclePegged")]
+    AmmOraclePegged,
+    #[msg("InvalidLPRange")]
+    InvalidLPRange,
+    #[msg("LPHedgeNotNeeded")]
+    LPHedgeNotNeeded,
+    #[msg("UserMustBeWritable")]
+    UserMustBeWritable,
+    #[msg("CouldNotDeserializeUser")]
+    CouldNotDeserializeUser,
+    #[msg("InvalidMarketAccountSize")]
+    InvalidMarketAccountSize,
+    #[msg("InvalidLiquidatorFeeRampSlots")]
+    InvalidLiquidatorFeeRampSlots,
+    #[msg("InvalidBackstopUnstake")]
+    InvalidBackstopUnstake,
+    #[msg("BackstopHasNoPositionToUnwind")]
+    BackstopHasNoPositionToUnwind,
+    #[msg("InvalidInsuranceFundRewardsClaim")]
+    InvalidInsuranceFundRewardsClaim,
+    #[msg("InvalidInsuranceFundStakeAccountSize")]
+    InvalidInsuranceFundStakeAccountSize,
+    #[msg("InvalidInsuranceFundSharesTokenization")]
+    InvalidInsuranceFundSharesTokenization,
+    #[msg("StaleInsuranceCoverage")]
+    StaleInsuranceCoverage,
+    #[msg("InvalidInstantUnstakeHaircut")]
+    InvalidInstantUnstakeHaircut,
+    #[msg("KeeperRepegTooFrequent")]
+    KeeperRepegTooFrequent,
+    #[msg("InvalidLPHedgeThreshold")]
+    InvalidLPHedgeThreshold,
+    #[msg("InvalidLPSharesBatch")]
+    InvalidLPSharesBatch,
+    #[msg("InvalidFundingMaxPriceSpread")]
+    InvalidFundingMaxPriceSpread,
+    #[msg("InvalidFundingInterestRate")]
+    InvalidFundingInterestRate,
+    #[msg("TooManyUserAccounts")]
+    TooManyUserAccounts,
+    #[msg("LPRangeNotFound")]
+    LPRangeNotFound,
+    #[msg("MaxNumberOfLPRanges")]
+    MaxNumberOfLPRanges,
 }
 
 #[macro_export]
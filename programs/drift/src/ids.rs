@@ -31,3 +31,8 @@ pub mod msrm_mint {
     use solana_program::declare_id;
     declare_id!("MSRMcoVyrFxnSgo5uXwone5SKcGhT1KEJMFEkMEWf9L");
 }
+
+pub mod token_2022_program {
+    use solana_program::declare_id;
+    declare_id!("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb");
+}
@@ -1,7 +1,7 @@
 use std::convert::identity;
 use std::mem::size_of;
 
-use anchor_lang::prelude::*;
+use anchor_lang::{prelude::*, Discriminator};
 use anchor_spl::token::{Mint, Token, TokenAccount};
 use bytemuck::cast_slice;
 use serum_dex::state::ToAlignedBytes;
@@ -12,14 +12,19 @@ use crate::error::ErrorCode;
 use crate::get_then_update_id;
 use crate::instructions::constraints::*;
 use crate::instructions::keeper::SpotFulfillmentType;
+use crate::instructions::optional_accounts::{
+    get_perp_market_insurance_fund_vault, get_token_mint_for_vault,
+};
 use crate::load;
 use crate::load_mut;
 use crate::math::casting::Cast;
 use crate::math::constants::{
     DEFAULT_BASE_ASSET_AMOUNT_STEP_SIZE, DEFAULT_LIQUIDATION_MARGIN_BUFFER_RATIO,
     DEFAULT_QUOTE_ASSET_AMOUNT_TICK_SIZE, IF_FACTOR_PRECISION, INSURANCE_A_MAX, INSURANCE_B_MAX,
-    INSURANCE_C_MAX, INSURANCE_SPECULATIVE_MAX, LIQUIDATION_FEE_PRECISION,
-    MAX_CONCENTRATION_COEFFICIENT, MAX_UPDATE_K_PRICE_CHANGE, QUOTE_SPOT_MARKET_INDEX,
+    INSURANCE_C_MAX, INSURANCE_SPECULATIVE_MAX, LIQUIDATION_FEE_PRECISION, MAX_BORROW_RATE_KINKS,
+    MAX_CONCENTRATION_COEFFICIENT, MAX_FUNDING_INTEREST_RATE, MAX_FUNDING_PRICE_SPREAD,
+    MAX_LIQUIDATOR_FEE_RAMP_SLOTS, MAX_LP_RANGES, MAX_UPDATE_K_PRICE_CHANGE,
+    MAX_VOLATILITY_SPREAD_SCALE, PERCENTAGE_PRECISION, QUOTE_SPOT_MARKET_INDEX,
     SPOT_CUMULATIVE_INTEREST_PRECISION, SPOT_IMF_PRECISION, SPOT_WEIGHT_PRECISION,
     TWENTY_FOUR_HOUR,
 };
@@ -29,22 +34,28 @@ use crate::math::orders::is_multiple_of_step_size;
 use crate::math::repeg::get_total_fee_lower_bound;
 use crate::math::safe_math::SafeMath;
 use crate::math::spot_balance::get_token_amount;
+use crate::math::token_2022::{
+    calculate_transfer_fee, get_mint_decimals, validate_mint_extensions,
+};
 use crate::math::{amm, bn, oracle};
 use crate::math_error;
-use crate::state::events::CurveRecord;
+use crate::state::backstop_vault::BackstopVault;
+use crate::state::events::{CurveRecord, CurveUpdateReason};
 use crate::state::oracle::{
     get_oracle_price, get_pyth_price, get_switchboard_price, HistoricalIndexData,
     HistoricalOracleData, OraclePriceData, OracleSource,
 };
 use crate::state::perp_market::{
-    ContractTier, ContractType, InsuranceClaim, MarketStatus, PerpMarket, PoolBalance, AMM,
+    AmmMode, ContractTier, ContractType, FundingPremiumSampling, InsuranceClaim, LPRange,
+    MarketStatus, PerpMarket, PoolBalance, SpreadModel, AMM,
 };
 use crate::state::serum::{load_open_orders, load_serum_market};
 use crate::state::spot_market::{
-    AssetTier, InsuranceFund, SerumV3FulfillmentConfig, SpotBalanceType, SpotFulfillmentStatus,
-    SpotMarket,
+    AssetTier, BorrowRateKink, InsuranceFund, InsuranceFundExtension, SerumV3FulfillmentConfig,
+    SpotBalanceType, SpotFulfillmentStatus, SpotMarket,
 };
 use crate::state::state::{ExchangeStatus, FeeStructure, OracleGuardRails, State};
+use crate::state::user::{User, UserStats};
 use crate::validate;
 use crate::validation::fee_structure::validate_fee_structure;
 use crate::validation::margin::{validate_margin, validate_margin_weights};
@@ -99,21 +110,63 @@ pub fn handle_initialize_spot_market(
 ) -> Result<()> {
     let state = &mut ctx.accounts.state;
     let spot_market_pubkey = ctx.accounts.spot_market.key();
+    let token_program = &ctx.accounts.token_program;
+    let spot_market_mint = &ctx.accounts.spot_market_mint;
 
-    // protocol must be authority of collateral vault
-    if ctx.accounts.spot_market_vault.owner != state.signer {
-        return Err(ErrorCode::InvalidSpotMarketAuthority.into());
-    }
+    validate!(
+        spot_market_mint.owner == token_program.key,
+        ErrorCode::InvalidTokenMint,
+        "mint {} not owned by token program {}",
+        spot_market_mint.key,
+        token_program.key
+    )?;
 
-    // protocol must be authority of collateral vault
-    if ctx.accounts.insurance_fund_vault.owner != state.signer {
-        return Err(ErrorCode::InvalidInsuranceFundAuthority.into());
-    }
+    let mint_decimals = {
+        let mint_data = spot_market_mint.try_borrow_data()?;
+        validate_mint_extensions(&mint_data)?;
+        get_mint_decimals(&mint_data)?
+    };
 
-    validate_borrow_rate(optimal_utilization, optimal_borrow_rate, max_borrow_rate)?;
+    validate_borrow_rate(
+        optimal_utilization,
+        optimal_borrow_rate,
+        max_borrow_rate,
+        0,
+        &[],
+    )?;
 
     let spot_market_index = get_then_update_id!(state, number_of_spot_markets);
 
+    // protocol is the authority of the collateral and insurance fund vaults
+    let spot_market_index_bytes = spot_market_index.to_le_bytes();
+    controller::token::initialize_program_token_account(
+        ctx.program_id,
+        token_program,
+        &ctx.accounts.system_program.to_account_info(),
+        &ctx.accounts.admin.to_account_info(),
+        &ctx.accounts.spot_market_vault,
+        &[
+            b"spot_market_vault".as_ref(),
+            spot_market_index_bytes.as_ref(),
+        ],
+        spot_market_mint,
+        &state.signer,
+    )?;
+
+    controller::token::initialize_program_token_account(
+        ctx.program_id,
+        token_program,
+        &ctx.accounts.system_program.to_account_info(),
+        &ctx.accounts.admin.to_account_info(),
+        &ctx.accounts.insurance_fund_vault,
+        &[
+            b"insurance_fund_vault".as_ref(),
+            spot_market_index_bytes.as_ref(),
+        ],
+        spot_market_mint,
+        &state.signer,
+    )?;
+
     if oracle_source == OracleSource::QuoteAsset {
         // catches inconsistent parameters
         validate!(
@@ -150,7 +203,7 @@ pub fn handle_initialize_spot_market(
             )?;
 
             validate!(
-                ctx.accounts.spot_market_mint.decimals == 6,
+                mint_decimals == 6,
                 ErrorCode::InvalidSpotMarketInitialization,
                 "For quote asset spot market, mint decimals must be 6"
             )?;
@@ -161,7 +214,7 @@ pub fn handle_initialize_spot_market(
             )
         } else {
             validate!(
-                ctx.accounts.spot_market_mint.decimals >= 6,
+                mint_decimals >= 6,
                 ErrorCode::InvalidSpotMarketInitialization,
                 "Mint decimals must be greater than or equal to 6"
             )?;
@@ -195,7 +248,7 @@ pub fn handle_initialize_spot_market(
         .cast()
         .or(Err(ErrorCode::UnableToCastUnixTime))?;
 
-    let decimals = ctx.accounts.spot_market_mint.decimals.cast::<u32>()?;
+    let decimals = mint_decimals.cast::<u32>()?;
     let order_step_size = 10_u64.pow(2 + decimals - 6); // 10 for usdc/btc, 10000 for sol
 
     **spot_market = SpotMarket {
@@ -218,7 +271,7 @@ pub fn handle_initialize_spot_market(
         historical_oracle_data: historical_oracle_data_default,
         historical_index_data: historical_index_data_default,
         mint: ctx.accounts.spot_market_mint.key(),
-        vault: *ctx.accounts.spot_market_vault.to_account_info().key,
+        vault: ctx.accounts.spot_market_vault.key(),
         revenue_pool: PoolBalance {
             scaled_balance: 0,
             market_index: spot_market_index,
@@ -228,6 +281,8 @@ pub fn handle_initialize_spot_market(
         optimal_utilization,
         optimal_borrow_rate,
         max_borrow_rate,
+        min_borrow_rate: 0,
+        borrow_rate_kinks: [BorrowRateKink::default(); MAX_BORROW_RATE_KINKS],
         deposit_balance: 0,
         borrow_balance: 0,
         max_token_deposits: 0,
@@ -245,6 +300,7 @@ pub fn handle_initialize_spot_market(
         imf_factor,
         liquidator_fee,
         if_liquidation_fee: LIQUIDATION_FEE_PRECISION / 100, // 1%
+        liquidator_fee_ramp_slots: 0,
         withdraw_guard_threshold: 0,
         order_step_size,
         order_tick_size: DEFAULT_QUOTE_ASSET_AMOUNT_TICK_SIZE,
@@ -257,14 +313,39 @@ pub fn handle_initialize_spot_market(
         orders_enabled: spot_market_index != 0,
         padding: [0; 6],
         insurance_fund: InsuranceFund {
-            vault: *ctx.accounts.insurance_fund_vault.to_account_info().key,
+            vault: ctx.accounts.insurance_fund_vault.key(),
             ..InsuranceFund::default()
         },
+        insurance_fund_extension: InsuranceFundExtension::default(),
     };
 
     Ok(())
 }
 
+pub fn handle_resize_spot_market(ctx: Context<ResizeSpotMarket>, _market_index: u16) -> Result<()> {
+    controller::pda::resize_pda(
+        &ctx.accounts.admin.to_account_info(),
+        &Rent::get()?,
+        std::mem::size_of::<SpotMarket>() + 8,
+        &SpotMarket::discriminator(),
+        &ctx.accounts.system_program.to_account_info(),
+        &ctx.accounts.spot_market,
+        ErrorCode::InvalidMarketAccountSize,
+    )
+}
+
+pub fn handle_resize_perp_market(ctx: Context<ResizePerpMarket>, _market_index: u16) -> Result<()> {
+    controller::pda::resize_pda(
+        &ctx.accounts.admin.to_account_info(),
+        &Rent::get()?,
+        std::mem::size_of::<PerpMarket>() + 8,
+        &PerpMarket::discriminator(),
+        &ctx.accounts.system_program.to_account_info(),
+        &ctx.accounts.perp_market,
+        ErrorCode::InvalidMarketAccountSize,
+    )
+}
+
 pub fn handle_initialize_serum_fulfillment_config(
     ctx: Context<InitializeSerumFulfillmentConfig>,
     market_index: u16,
@@ -528,13 +609,33 @@ pub fn handle_initialize_perp_market(
         next_curve_record_id: 1,
         pnl_pool: PoolBalance::default(),
         insurance_claim: InsuranceClaim::default(),
+        insurance_fund: InsuranceFund::default(),
+        funding_premium_sampling: FundingPremiumSampling::TimeWeighted,
         unrealized_pnl_initial_asset_weight: SPOT_WEIGHT_PRECISION.cast()?, // 100%
         unrealized_pnl_maintenance_asset_weight: SPOT_WEIGHT_PRECISION.cast()?, // 100%
         unrealized_pnl_imf_factor: 0,
         unrealized_pnl_max_imbalance: 0,
         liquidator_fee,
         if_liquidation_fee: LIQUIDATION_FEE_PRECISION / 100, // 1%
-        padding: [0; 3],
+        liquidator_fee_ramp_slots: 0,
+        padding: [0; 2],
+        padding1: [0; 4],
+        insurance_fund_settled_fee_withdrawn: 0,
+        insurance_fund_extension: InsuranceFundExtension::default(),
+        insurance_coverage_ratio: 0,
+        min_insurance_coverage_ratio: 0,
+        last_insurance_coverage_update_ts: 0,
+        last_keeper_repeg_slot: 0,
+        padding2: [0; 8],
+        lp_ranges: [LPRange::default(); MAX_LP_RANGES as usize],
+        funding_max_price_spread: 0,
+        funding_interest_rate: 0,
+        volatility_spread_scale: 0,
+        intensity_spread_scale: 0,
+        spread_model: SpreadModel::Legacy,
+        amm_mode: AmmMode::Curve,
+        padding3: [0; 6],
+        fee_per_lp: 0,
         amm: AMM {
             oracle: *ctx.accounts.oracle.key,
             oracle_source,
@@ -700,12 +801,7 @@ pub fn handle_move_amm_price(
     sqrt_k: u128,
 ) -> Result<()> {
     let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
-    controller::amm::move_price(
-        &mut perp_market.amm,
-        base_asset_reserve,
-        quote_asset_reserve,
-        sqrt_k,
-    )?;
+    controller::amm::move_price(perp_market, base_asset_reserve, quote_asset_reserve, sqrt_k)?;
     validate_perp_market(perp_market)?;
 
     Ok(())
@@ -861,16 +957,39 @@ pub fn handle_deposit_into_perp_market_fee_pool(
     amount: u64,
 ) -> Result<()> {
     let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
+    let token_program = &ctx.accounts.token_program;
+
+    let spot_market_vault_mint =
+        controller::token::load_token_account(&ctx.accounts.spot_market_vault, token_program)?.mint;
+    controller::token::validate_user_token_account(
+        &controller::token::load_token_account(&ctx.accounts.source_vault, token_program)?,
+        &spot_market_vault_mint,
+        ctx.accounts.admin.key,
+    )?;
+    let mint = get_token_mint_for_vault(
+        &mut ctx.remaining_accounts.iter().peekable(),
+        &spot_market_vault_mint,
+    )?;
+
+    // token-2022 transfer fees are withheld from what the vault receives, so only the net is credited
+    let amount_received = match &mint {
+        Some(mint) => amount.safe_sub(calculate_transfer_fee(
+            &mint.try_borrow_data()?,
+            Clock::get()?.epoch,
+            amount,
+        )?)?,
+        None => amount,
+    };
 
     perp_market.amm.total_fee_minus_distributions = perp_market
         .amm
         .total_fee_minus_distributions
-        .safe_add(amount.cast()?)?;
+        .safe_add(amount_received.cast()?)?;
 
     let quote_spot_market = &mut load_mut!(ctx.accounts.quote_spot_market)?;
 
     controller::spot_balance::update_spot_balances(
-        amount.cast::<u128>()?,
+        amount_received.cast::<u128>()?,
         &SpotBalanceType::Deposit,
         quote_spot_market,
         &mut perp_market.amm.fee_pool,
@@ -878,11 +997,12 @@ pub fn handle_deposit_into_perp_market_fee_pool(
     )?;
 
     controller::token::receive(
-        &ctx.accounts.token_program,
+        token_program,
         &ctx.accounts.source_vault,
         &ctx.accounts.spot_market_vault,
         &ctx.accounts.admin.to_account_info(),
         amount,
+        &mint,
     )?;
 
     Ok(())
@@ -945,6 +1065,7 @@ pub fn handle_repeg_amm_curve(ctx: Context<RepegCurve>, new_peg_candidate: u128)
         adjustment_cost,
         oracle_price,
         fill_record: 0,
+        reason: CurveUpdateReason::AdminRepeg,
     });
 
     Ok(())
@@ -1160,6 +1281,7 @@ pub fn handle_update_k(ctx: Context<AdminUpdateK>, sqrt_k: u128) -> Result<()> {
         total_fee_minus_distributions,
         oracle_price,
         fill_record: 0,
+        reason: CurveUpdateReason::AdminUpdateK,
     });
 
     Ok(())
@@ -1290,6 +1412,43 @@ pub fn handle_update_perp_market_max_imbalances(
     Ok(())
 }
 
+#[access_control(
+    market_valid(&ctx.accounts.perp_market)
+)]
+pub fn handle_update_perp_market_min_insurance_coverage_ratio(
+    ctx: Context<AdminUpdatePerpMarketInsuranceCoverage>,
+    min_insurance_coverage_ratio: u32,
+) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
+
+    msg!(
+        "perp_market.min_insurance_coverage_ratio: {:?} -> {:?}",
+        perp_market.min_insurance_coverage_ratio,
+        min_insurance_coverage_ratio
+    );
+
+    perp_market.min_insurance_coverage_ratio = min_insurance_coverage_ratio;
+
+    // refresh the ratio so orders aren't gated on one that was never computed
+    let perp_market_insurance_fund_vault = get_perp_market_insurance_fund_vault(
+        &mut ctx.remaining_accounts.iter().peekable(),
+        perp_market,
+    )?;
+
+    controller::insurance::update_perp_market_insurance_coverage(
+        perp_market,
+        controller::token::load_vault_token_account(&ctx.accounts.insurance_fund_vault)?.amount,
+        match &perp_market_insurance_fund_vault {
+            Some(vault) => controller::token::load_vault_token_account(vault)?.amount,
+            None => 0,
+        },
+        now,
+    )?;
+
+    Ok(())
+}
+
 pub fn handle_update_perp_market_name(
     ctx: Context<AdminUpdatePerpMarket>,
     name: [u8; 32],
@@ -1341,6 +1500,27 @@ pub fn handle_update_perp_liquidation_fee(
     Ok(())
 }
 
+pub fn handle_update_perp_market_liquidator_fee_ramp_slots(
+    ctx: Context<AdminUpdatePerpMarket>,
+    liquidator_fee_ramp_slots: u32,
+) -> Result<()> {
+    validate!(
+        liquidator_fee_ramp_slots <= MAX_LIQUIDATOR_FEE_RAMP_SLOTS,
+        ErrorCode::InvalidLiquidatorFeeRampSlots,
+        "liquidator_fee_ramp_slots must be <= {}",
+        MAX_LIQUIDATOR_FEE_RAMP_SLOTS
+    )?;
+
+    let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
+    msg!(
+        "perp_market.liquidator_fee_ramp_slots: {:?} -> {:?}",
+        perp_market.liquidator_fee_ramp_slots,
+        liquidator_fee_ramp_slots
+    );
+    perp_market.liquidator_fee_ramp_slots = liquidator_fee_ramp_slots;
+    Ok(())
+}
+
 pub fn handle_update_insurance_fund_unstaking_period(
     ctx: Context<AdminUpdateSpotMarket>,
     insurance_fund_unstaking_period: i64,
@@ -1350,6 +1530,285 @@ pub fn handle_update_insurance_fund_unstaking_period(
     Ok(())
 }
 
+pub fn handle_update_insurance_fund_instant_unstake_haircut(
+    ctx: Context<AdminUpdateSpotMarket>,
+    instant_unstake_haircut: u32,
+) -> Result<()> {
+    let spot_market = &mut load_mut!(ctx.accounts.spot_market)?;
+
+    validate!(
+        instant_unstake_haircut.cast::<u128>()? <= PERCENTAGE_PRECISION,
+        ErrorCode::InvalidInstantUnstakeHaircut,
+        "instant_unstake_haircut must be <= 100%"
+    )?;
+
+    spot_market.insurance_fund_extension.instant_unstake_haircut = instant_unstake_haircut;
+    Ok(())
+}
+
+pub fn handle_initialize_perp_market_insurance_fund(
+    ctx: Context<InitializePerpMarketInsuranceFund>,
+    market_index: u16,
+    insurance_fund_unstaking_period: i64,
+) -> Result<()> {
+    let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
+    let quote_spot_market = load!(ctx.accounts.quote_spot_market)?;
+
+    validate!(
+        !perp_market.has_insurance_fund(),
+        ErrorCode::InvalidPerpMarketInsuranceFundVault,
+        "perp market {} already has an insurance fund",
+        market_index
+    )?;
+
+    validate!(
+        ctx.accounts.quote_asset_mint.key == &quote_spot_market.mint,
+        ErrorCode::InvalidTokenMint,
+        "mint {} does not match quote spot market mint {}",
+        ctx.accounts.quote_asset_mint.key,
+        quote_spot_market.mint
+    )?;
+
+    validate!(
+        ctx.accounts.quote_asset_mint.owner == ctx.accounts.token_program.key,
+        ErrorCode::InvalidTokenMint,
+        "mint {} not owned by token program {}",
+        ctx.accounts.quote_asset_mint.key,
+        ctx.accounts.token_program.key
+    )?;
+
+    // protocol is the authority of the perp market's insurance fund vault
+    let market_index_bytes = market_index.to_le_bytes();
+    controller::token::initialize_program_token_account(
+        ctx.program_id,
+        &ctx.accounts.token_program,
+        &ctx.accounts.system_program.to_account_info(),
+        &ctx.accounts.admin.to_account_info(),
+        &ctx.accounts.perp_market_insurance_fund_vault,
+        &[
+            b"perp_market_insurance_fund_vault".as_ref(),
+            market_index_bytes.as_ref(),
+        ],
+        &ctx.accounts.quote_asset_mint,
+        &ctx.accounts.state.signer,
+    )?;
+
+    perp_market.insurance_fund = InsuranceFund {
+        vault: ctx.accounts.perp_market_insurance_fund_vault.key(),
+        unstaking_period: insurance_fund_unstaking_period,
+        ..InsuranceFund::default()
+    };
+    // fees swept before the insurance fund existed stay with the quote spot market's insurance fund
+    perp_market.insurance_fund_settled_fee_withdrawn = perp_market.amm.total_fee_withdrawn;
+
+    Ok(())
+}
+
+pub fn handle_update_perp_market_insurance_fund_unstaking_period(
+    ctx: Context<AdminUpdatePerpMarket>,
+    insurance_fund_unstaking_period: i64,
+) -> Result<()> {
+    let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
+    validate!(
+        perp_market.has_insurance_fund(),
+        ErrorCode::InvalidPerpMarketInsuranceFundVault,
+        "perp market {} has no insurance fund",
+        perp_market.market_index
+    )?;
+
+    perp_market.insurance_fund.unstaking_period = insurance_fund_unstaking_period;
+    Ok(())
+}
+
+pub fn handle_update_perp_market_if_factor(
+    ctx: Context<AdminUpdatePerpMarket>,
+    user_if_factor: u32,
+    total_if_factor: u32,
+) -> Result<()> {
+    let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
+    validate!(
+        perp_market.has_insurance_fund(),
+        ErrorCode::InvalidPerpMarketInsuranceFundVault,
+        "perp market {} has no insurance fund",
+        perp_market.market_index
+    )?;
+
+    validate!(
+        user_if_factor <= total_if_factor,
+        ErrorCode::DefaultError,
+        "user_if_factor must be <= total_if_factor"
+    )?;
+
+    validate!(
+        total_if_factor <= IF_FACTOR_PRECISION.cast()?,
+        ErrorCode::DefaultError,
+        "total_if_factor must be <= 100%"
+    )?;
+
+    msg!(
+        "perp_market.insurance_fund.user_factor: {:?} -> {:?}",
+        perp_market.insurance_fund.user_factor,
+        user_if_factor
+    );
+    msg!(
+        "perp_market.insurance_fund.total_factor: {:?} -> {:?}",
+        perp_market.insurance_fund.total_factor,
+        total_if_factor
+    );
+
+    perp_market.insurance_fund.user_factor = user_if_factor;
+    perp_market.insurance_fund.total_factor = total_if_factor;
+
+    Ok(())
+}
+
+pub fn handle_update_perp_market_revenue_settle_period(
+    ctx: Context<AdminUpdatePerpMarket>,
+    revenue_settle_period: i64,
+) -> Result<()> {
+    let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
+    validate!(
+        perp_market.has_insurance_fund(),
+        ErrorCode::InvalidPerpMarketInsuranceFundVault,
+        "perp market {} has no insurance fund",
+        perp_market.market_index
+    )?;
+
+    validate!(revenue_settle_period > 0, ErrorCode::DefaultError)?;
+    msg!(
+        "perp_market.insurance_fund.revenue_settle_period: {:?} -> {:?}",
+        perp_market.insurance_fund.revenue_settle_period,
+        revenue_settle_period
+    );
+    perp_market.insurance_fund.revenue_settle_period = revenue_settle_period;
+    Ok(())
+}
+
+pub fn handle_update_perp_market_insurance_fund_instant_unstake_haircut(
+    ctx: Context<AdminUpdatePerpMarket>,
+    instant_unstake_haircut: u32,
+) -> Result<()> {
+    let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
+    validate!(
+        perp_market.has_insurance_fund(),
+        ErrorCode::InvalidPerpMarketInsuranceFundVault,
+        "perp market {} has no insurance fund",
+        perp_market.market_index
+    )?;
+
+    validate!(
+        instant_unstake_haircut.cast::<u128>()? <= PERCENTAGE_PRECISION,
+        ErrorCode::InvalidInstantUnstakeHaircut,
+        "instant_unstake_haircut must be <= 100%"
+    )?;
+
+    perp_market.insurance_fund_extension.instant_unstake_haircut = instant_unstake_haircut;
+    Ok(())
+}
+
+pub fn handle_initialize_insurance_fund_reward_vault(
+    ctx: Context<InitializeInsuranceFundRewardVault>,
+    market_index: u16,
+) -> Result<()> {
+    let spot_market = &mut load_mut!(ctx.accounts.spot_market)?;
+    let token_program = &ctx.accounts.token_program;
+    let reward_mint = &ctx.accounts.reward_mint;
+
+    validate!(
+        spot_market.insurance_fund_extension.reward_vault == Pubkey::default(),
+        ErrorCode::InvalidInsuranceFundRewardVault,
+        "spot market {} already has an insurance fund reward vault",
+        market_index
+    )?;
+
+    validate!(
+        reward_mint.owner == token_program.key,
+        ErrorCode::InvalidTokenMint,
+        "mint {} not owned by token program {}",
+        reward_mint.key,
+        token_program.key
+    )?;
+
+    // rewards are paid out through the same token-2022 paths as deposits, so the same extensions are allowed
+    validate_mint_extensions(&reward_mint.try_borrow_data()?)?;
+
+    // protocol is the authority of the reward vault
+    let market_index_bytes = market_index.to_le_bytes();
+    controller::token::initialize_program_token_account(
+        ctx.program_id,
+        token_program,
+        &ctx.accounts.system_program.to_account_info(),
+        &ctx.accounts.admin.to_account_info(),
+        &ctx.accounts.insurance_fund_reward_vault,
+        &[
+            b"insurance_fund_reward_vault".as_ref(),
+            market_index_bytes.as_ref(),
+        ],
+        reward_mint,
+        &ctx.accounts.state.signer,
+    )?;
+
+    spot_market.insurance_fund_extension.reward_vault =
+        ctx.accounts.insurance_fund_reward_vault.key();
+    spot_market.insurance_fund_extension.last_reward_update_ts = Clock::get()?.unix_timestamp;
+
+    Ok(())
+}
+
+pub fn handle_update_insurance_fund_reward_emission(
+    ctx: Context<AdminUpdateSpotMarket>,
+    reward_emission_rate: u64,
+    reward_emission_end_ts: i64,
+) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let spot_market = &mut *load_mut!(ctx.accounts.spot_market)?;
+
+    validate!(
+        spot_market.insurance_fund_extension.reward_vault != Pubkey::default(),
+        ErrorCode::InvalidInsuranceFundRewardVault,
+        "spot market {} has no insurance fund reward vault",
+        spot_market.market_index
+    )?;
+
+    // accrue rewards at the old emission rate before switching
+    controller::insurance::update_insurance_fund_rewards(spot_market, now)?;
+
+    spot_market.insurance_fund_extension.reward_emission_rate = reward_emission_rate;
+    spot_market.insurance_fund_extension.reward_emission_end_ts = reward_emission_end_ts;
+
+    Ok(())
+}
+
+pub fn handle_initialize_insurance_fund_shares_mint(
+    ctx: Context<InitializeInsuranceFundSharesMint>,
+    market_index: u16,
+    decimals: u8,
+) -> Result<()> {
+    let spot_market = &mut load_mut!(ctx.accounts.spot_market)?;
+
+    validate!(
+        spot_market.insurance_fund_extension.shares_mint == Pubkey::default(),
+        ErrorCode::InvalidInsuranceFundSharesMint,
+        "spot market {} already has an insurance fund shares mint",
+        market_index
+    )?;
+
+    // shares start 1:1 with the insurance fund vault amount
+    validate!(
+        decimals.cast::<u32>()? == spot_market.decimals,
+        ErrorCode::InvalidInsuranceFundSharesMint,
+        "shares mint decimals {} must match spot market decimals {}",
+        decimals,
+        spot_market.decimals
+    )?;
+
+    spot_market.insurance_fund_extension.shares_mint =
+        ctx.accounts.insurance_fund_shares_mint.key();
+    spot_market.insurance_fund_extension.shares_mint_base = spot_market.insurance_fund.shares_base;
+
+    Ok(())
+}
+
 pub fn handle_update_spot_market_liquidation_fee(
     ctx: Context<AdminUpdateSpotMarket>,
     liquidator_fee: u32,
@@ -1373,6 +1832,27 @@ pub fn handle_update_spot_market_liquidation_fee(
     Ok(())
 }
 
+pub fn handle_update_spot_market_liquidator_fee_ramp_slots(
+    ctx: Context<AdminUpdateSpotMarket>,
+    liquidator_fee_ramp_slots: u32,
+) -> Result<()> {
+    validate!(
+        liquidator_fee_ramp_slots <= MAX_LIQUIDATOR_FEE_RAMP_SLOTS,
+        ErrorCode::InvalidLiquidatorFeeRampSlots,
+        "liquidator_fee_ramp_slots must be <= {}",
+        MAX_LIQUIDATOR_FEE_RAMP_SLOTS
+    )?;
+
+    let spot_market = &mut load_mut!(ctx.accounts.spot_market)?;
+    msg!(
+        "spot_market.liquidator_fee_ramp_slots: {:?} -> {:?}",
+        spot_market.liquidator_fee_ramp_slots,
+        liquidator_fee_ramp_slots
+    );
+    spot_market.liquidator_fee_ramp_slots = liquidator_fee_ramp_slots;
+    Ok(())
+}
+
 pub fn handle_update_withdraw_guard_threshold(
     ctx: Context<AdminUpdateSpotMarket>,
     withdraw_guard_threshold: u64,
@@ -1505,12 +1985,27 @@ pub fn handle_update_spot_market_borrow_rate(
     optimal_utilization: u32,
     optimal_borrow_rate: u32,
     max_borrow_rate: u32,
+    min_borrow_rate: u32,
+    borrow_rate_kinks: Vec<BorrowRateKink>,
 ) -> Result<()> {
     let spot_market = &mut load_mut!(ctx.accounts.spot_market)?;
-    validate_borrow_rate(optimal_utilization, optimal_borrow_rate, max_borrow_rate)?;
+    validate_borrow_rate(
+        optimal_utilization,
+        optimal_borrow_rate,
+        max_borrow_rate,
+        min_borrow_rate,
+        &borrow_rate_kinks,
+    )?;
     spot_market.optimal_utilization = optimal_utilization;
     spot_market.optimal_borrow_rate = optimal_borrow_rate;
     spot_market.max_borrow_rate = max_borrow_rate;
+    spot_market.min_borrow_rate = min_borrow_rate;
+
+    spot_market.borrow_rate_kinks = [BorrowRateKink::default(); MAX_BORROW_RATE_KINKS];
+    for (i, kink) in borrow_rate_kinks.into_iter().enumerate() {
+        spot_market.borrow_rate_kinks[i] = kink;
+    }
+
     Ok(())
 }
 
@@ -1630,7 +2125,7 @@ pub fn handle_update_perp_market_concentration_coef(
 
     let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
     let prev_concentration_coef = perp_market.amm.concentration_coef;
-    controller::amm::update_concentration_coef(&mut perp_market.amm, concentration_scale)?;
+    controller::amm::update_concentration_coef(perp_market, concentration_scale)?;
     let new_concentration_coef = perp_market.amm.concentration_coef;
 
     msg!(
@@ -1784,6 +2279,96 @@ pub fn handle_update_perp_market_max_spread(
     Ok(())
 }
 
+#[access_control(
+    market_valid(&ctx.accounts.perp_market)
+)]
+pub fn handle_update_perp_market_spread_model(
+    ctx: Context<AdminUpdatePerpMarket>,
+    spread_model: SpreadModel,
+    volatility_spread_scale: u32,
+    intensity_spread_scale: u32,
+) -> Result<()> {
+    let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
+    validate!(
+        volatility_spread_scale.cast::<u64>()? <= MAX_VOLATILITY_SPREAD_SCALE,
+        ErrorCode::DefaultError,
+        "invalid volatility_spread_scale > {}",
+        MAX_VOLATILITY_SPREAD_SCALE
+    )?;
+
+    validate!(
+        intensity_spread_scale <= perp_market.amm.max_spread,
+        ErrorCode::DefaultError,
+        "invalid intensity_spread_scale > max_spread",
+    )?;
+
+    perp_market.spread_model = spread_model;
+    perp_market.volatility_spread_scale = volatility_spread_scale;
+    perp_market.intensity_spread_scale = intensity_spread_scale;
+
+    Ok(())
+}
+
+#[access_control(
+    market_valid(&ctx.accounts.perp_market)
+)]
+pub fn handle_update_perp_market_funding_params(
+    ctx: Context<AdminUpdatePerpMarket>,
+    funding_max_price_spread: u32,
+    funding_interest_rate: i32,
+    funding_premium_sampling: FundingPremiumSampling,
+) -> Result<()> {
+    let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
+    validate!(
+        funding_max_price_spread <= MAX_FUNDING_PRICE_SPREAD,
+        ErrorCode::InvalidFundingMaxPriceSpread,
+        "invalid funding_max_price_spread > {}",
+        MAX_FUNDING_PRICE_SPREAD
+    )?;
+
+    validate!(
+        funding_interest_rate.unsigned_abs() <= MAX_FUNDING_INTEREST_RATE,
+        ErrorCode::InvalidFundingInterestRate,
+        "invalid funding_interest_rate magnitude > {}",
+        MAX_FUNDING_INTEREST_RATE
+    )?;
+
+    msg!(
+        "perp market {} funding max price spread {} -> {}, interest rate {} -> {}, premium sampling {:?} -> {:?}",
+        perp_market.market_index,
+        perp_market.funding_max_price_spread,
+        funding_max_price_spread,
+        perp_market.funding_interest_rate,
+        funding_interest_rate,
+        perp_market.funding_premium_sampling,
+        funding_premium_sampling
+    );
+
+    perp_market.funding_max_price_spread = funding_max_price_spread;
+    perp_market.funding_interest_rate = funding_interest_rate;
+    perp_market.funding_premium_sampling = funding_premium_sampling;
+
+    Ok(())
+}
+
+#[access_control(
+    market_valid(&ctx.accounts.perp_market)
+)]
+pub fn handle_update_perp_market_amm_mode(
+    ctx: Context<AdminUpdatePerpMarket>,
+    amm_mode: AmmMode,
+) -> Result<()> {
+    let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
+    msg!(
+        "perp market {} amm mode {:?} -> {:?}",
+        perp_market.market_index,
+        perp_market.amm_mode,
+        amm_mode
+    );
+    perp_market.amm_mode = amm_mode;
+    Ok(())
+}
+
 #[access_control(
     market_valid(&ctx.accounts.perp_market)
 )]
@@ -1949,14 +2534,27 @@ pub fn handle_admin_remove_insurance_fund_stake(
         "market_index doesnt match spot_market"
     )?;
 
+    let token_program = &ctx.accounts.token_program;
+    let insurance_fund_vault =
+        controller::token::load_token_account(&ctx.accounts.insurance_fund_vault, token_program)?;
+    controller::token::validate_user_token_account(
+        &controller::token::load_token_account(&ctx.accounts.admin_token_account, token_program)?,
+        &insurance_fund_vault.mint,
+        ctx.accounts.admin.key,
+    )?;
+    let mint = get_token_mint_for_vault(
+        &mut ctx.remaining_accounts.iter().peekable(),
+        &insurance_fund_vault.mint,
+    )?;
+
     let n_shares = math::insurance::vault_amount_to_if_shares(
         amount,
         spot_market.insurance_fund.total_shares,
-        ctx.accounts.insurance_fund_vault.amount,
+        insurance_fund_vault.amount,
     )?;
 
     let withdrawn_amount = controller::insurance::admin_remove_insurance_fund_stake(
-        ctx.accounts.insurance_fund_vault.amount,
+        insurance_fund_vault.amount,
         n_shares,
         spot_market,
         now,
@@ -1964,16 +2562,19 @@ pub fn handle_admin_remove_insurance_fund_stake(
     )?;
 
     controller::token::send_from_program_vault(
-        &ctx.accounts.token_program,
+        token_program,
         &ctx.accounts.insurance_fund_vault,
         &ctx.accounts.admin_token_account,
         &ctx.accounts.drift_signer,
         state.signer_nonce,
         withdrawn_amount,
+        &mint,
     )?;
 
     validate!(
-        ctx.accounts.insurance_fund_vault.amount > 0,
+        controller::token::load_token_account(&ctx.accounts.insurance_fund_vault, token_program)?
+            .amount
+            > 0,
         ErrorCode::DefaultError,
         "insurance_fund_vault.amount must remain > 0"
     )?;
@@ -1981,6 +2582,76 @@ pub fn handle_admin_remove_insurance_fund_stake(
     Ok(())
 }
 
+pub fn handle_initialize_backstop_vault(
+    ctx: Context<InitializeBackstopVault>,
+    liquidation_delay_slots: u64,
+    unstaking_period: i64,
+) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let backstop_vault_key = ctx.accounts.backstop_vault.key();
+
+    let mut backstop_vault = ctx
+        .accounts
+        .backstop_vault
+        .load_init()
+        .or(Err(ErrorCode::UnableToLoadAccountLoader))?;
+
+    *backstop_vault = BackstopVault {
+        pubkey: backstop_vault_key,
+        user: ctx.accounts.backstop_user.key(),
+        user_stats: ctx.accounts.backstop_user_stats.key(),
+        total_shares: 0,
+        liquidation_delay_slots,
+        unstaking_period,
+    };
+
+    // the backstop user is owned by the vault pda, so it can only be touched by backstop instructions
+    let mut backstop_user = ctx
+        .accounts
+        .backstop_user
+        .load_init()
+        .or(Err(ErrorCode::UnableToLoadAccountLoader))?;
+    backstop_user.authority = backstop_vault_key;
+    backstop_user.sub_account_id = 0;
+    backstop_user.next_order_id = 1;
+    backstop_user.next_liquidation_id = 1;
+
+    let mut backstop_user_stats = ctx
+        .accounts
+        .backstop_user_stats
+        .load_init()
+        .or(Err(ErrorCode::UnableToLoadAccountLoader))?;
+    *backstop_user_stats = UserStats {
+        authority: backstop_vault_key,
+        number_of_sub_accounts: 1,
+        number_of_sub_accounts_created: 1,
+        last_taker_volume_30d_ts: now,
+        last_maker_volume_30d_ts: now,
+        last_filler_volume_30d_ts: now,
+        ..UserStats::default()
+    };
+
+    Ok(())
+}
+
+pub fn handle_update_backstop_vault_liquidation_delay(
+    ctx: Context<AdminUpdateBackstopVault>,
+    liquidation_delay_slots: u64,
+) -> Result<()> {
+    let backstop_vault = &mut load_mut!(ctx.accounts.backstop_vault)?;
+    backstop_vault.liquidation_delay_slots = liquidation_delay_slots;
+    Ok(())
+}
+
+pub fn handle_update_backstop_vault_unstaking_period(
+    ctx: Context<AdminUpdateBackstopVault>,
+    unstaking_period: i64,
+) -> Result<()> {
+    let backstop_vault = &mut load_mut!(ctx.accounts.backstop_vault)?;
+    backstop_vault.unstaking_period = unstaking_period;
+    Ok(())
+}
+
 #[derive(Accounts)]
 pub struct Initialize<'info> {
     #[account(mut)]
@@ -2011,25 +2682,22 @@ pub struct InitializeSpotMarket<'info> {
         payer = admin
     )]
     pub spot_market: AccountLoader<'info, SpotMarket>,
-    pub spot_market_mint: Box<Account<'info, Mint>>,
+    /// CHECK: spl token or token-2022 mint, checked in `initialize_spot_market`
+    pub spot_market_mint: AccountInfo<'info>,
     #[account(
-        init,
+        mut,
         seeds = [b"spot_market_vault".as_ref(), state.number_of_spot_markets.to_le_bytes().as_ref()],
         bump,
-        payer = admin,
-        token::mint = spot_market_mint,
-        token::authority = drift_signer
     )]
-    pub spot_market_vault: Box<Account<'info, TokenAccount>>,
+    /// CHECK: created in `initialize_spot_market`
+    pub spot_market_vault: AccountInfo<'info>,
     #[account(
-        init,
+        mut,
         seeds = [b"insurance_fund_vault".as_ref(), state.number_of_spot_markets.to_le_bytes().as_ref()],
         bump,
-        payer = admin,
-        token::mint = spot_market_mint,
-        token::authority = drift_signer
     )]
-    pub insurance_fund_vault: Box<Account<'info, TokenAccount>>,
+    /// CHECK: created in `initialize_spot_market`
+    pub insurance_fund_vault: AccountInfo<'info>,
     #[account(
         constraint = state.signer.eq(&drift_signer.key())
     )]
@@ -2046,7 +2714,51 @@ pub struct InitializeSpotMarket<'info> {
     pub admin: Signer<'info>,
     pub rent: Sysvar<'info, Rent>,
     pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
+    #[account(
+        constraint = is_token_program(&token_program)?
+    )]
+    /// CHECK: spl token or token-2022 program
+    pub token_program: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(market_index: u16)]
+pub struct ResizeSpotMarket<'info> {
+    #[account(
+        mut,
+        seeds = [b"spot_market", market_index.to_le_bytes().as_ref()],
+        bump,
+        owner = crate::ID
+    )]
+    /// CHECK: markets created before fields were appended are too small to load, checked in handler
+    pub spot_market: AccountInfo<'info>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        has_one = admin
+    )]
+    pub state: Box<Account<'info, State>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(market_index: u16)]
+pub struct ResizePerpMarket<'info> {
+    #[account(
+        mut,
+        seeds = [b"perp_market", market_index.to_le_bytes().as_ref()],
+        bump,
+        owner = crate::ID
+    )]
+    /// CHECK: markets created before fields were appended are too small to load, checked in handler
+    pub perp_market: AccountInfo<'info>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        has_one = admin
+    )]
+    pub state: Box<Account<'info, State>>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
@@ -2143,6 +2855,138 @@ pub struct AdminUpdatePerpMarket<'info> {
     pub perp_market: AccountLoader<'info, PerpMarket>,
 }
 
+#[derive(Accounts)]
+pub struct AdminUpdatePerpMarketInsuranceCoverage<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        has_one = admin
+    )]
+    pub state: Box<Account<'info, State>>,
+    #[account(mut)]
+    pub perp_market: AccountLoader<'info, PerpMarket>,
+    #[account(
+        seeds = [b"insurance_fund_vault".as_ref(), 0_u16.to_le_bytes().as_ref()],
+        bump,
+    )]
+    /// CHECK: spl token or token-2022 account, checked in handler
+    pub insurance_fund_vault: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(market_index: u16)]
+pub struct InitializePerpMarketInsuranceFund<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        has_one = admin
+    )]
+    pub state: Box<Account<'info, State>>,
+    #[account(
+        mut,
+        seeds = [b"perp_market", market_index.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub perp_market: AccountLoader<'info, PerpMarket>,
+    #[account(
+        seeds = [b"spot_market", 0_u16.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub quote_spot_market: AccountLoader<'info, SpotMarket>,
+    /// CHECK: checked against quote spot market mint in `initialize_perp_market_insurance_fund`
+    pub quote_asset_mint: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"perp_market_insurance_fund_vault".as_ref(), market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    /// CHECK: created in `initialize_perp_market_insurance_fund`
+    pub perp_market_insurance_fund_vault: AccountInfo<'info>,
+    #[account(
+        constraint = state.signer.eq(&drift_signer.key())
+    )]
+    /// CHECK: program signer
+    pub drift_signer: AccountInfo<'info>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+    #[account(
+        constraint = is_token_program(&token_program)?
+    )]
+    /// CHECK: spl token or token-2022 program
+    pub token_program: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(market_index: u16)]
+pub struct InitializeInsuranceFundRewardVault<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        has_one = admin
+    )]
+    pub state: Box<Account<'info, State>>,
+    #[account(
+        mut,
+        seeds = [b"spot_market", market_index.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub spot_market: AccountLoader<'info, SpotMarket>,
+    /// CHECK: spl token or token-2022 mint, checked in `initialize_insurance_fund_reward_vault`
+    pub reward_mint: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"insurance_fund_reward_vault".as_ref(), market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    /// CHECK: created in `initialize_insurance_fund_reward_vault`
+    pub insurance_fund_reward_vault: AccountInfo<'info>,
+    #[account(
+        constraint = state.signer.eq(&drift_signer.key())
+    )]
+    /// CHECK: program signer
+    pub drift_signer: AccountInfo<'info>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+    #[account(
+        constraint = is_token_program(&token_program)?
+    )]
+    /// CHECK: spl token or token-2022 program
+    pub token_program: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(market_index: u16, decimals: u8)]
+pub struct InitializeInsuranceFundSharesMint<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        has_one = admin
+    )]
+    pub state: Box<Account<'info, State>>,
+    #[account(
+        mut,
+        seeds = [b"spot_market", market_index.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub spot_market: AccountLoader<'info, SpotMarket>,
+    #[account(
+        init,
+        seeds = [b"insurance_fund_shares_mint".as_ref(), market_index.to_le_bytes().as_ref()],
+        bump,
+        payer = admin,
+        mint::decimals = decimals,
+        mint::authority = drift_signer
+    )]
+    pub insurance_fund_shares_mint: Box<Account<'info, Mint>>,
+    #[account(
+        constraint = state.signer.eq(&drift_signer.key())
+    )]
+    /// CHECK: program signer
+    pub drift_signer: AccountInfo<'info>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
 #[derive(Accounts)]
 pub struct SettleExpiredMarketPoolsToRevenuePool<'info> {
     #[account(
@@ -2170,11 +3014,9 @@ pub struct DepositIntoMarketFeePool<'info> {
     #[account(mut)]
     pub perp_market: AccountLoader<'info, PerpMarket>,
     pub admin: Signer<'info>,
-    #[account(
-        mut,
-        token::authority = admin
-    )]
-    pub source_vault: Box<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    /// CHECK: spl token or token-2022 account, checked in handler
+    pub source_vault: AccountInfo<'info>,
     #[account(
         constraint = state.signer.eq(&drift_signer.key())
     )]
@@ -2191,8 +3033,13 @@ pub struct DepositIntoMarketFeePool<'info> {
         seeds = [b"spot_market_vault".as_ref(), 0_u16.to_le_bytes().as_ref()],
         bump,
     )]
-    pub spot_market_vault: Box<Account<'info, TokenAccount>>,
-    pub token_program: Program<'info, Token>,
+    /// CHECK: spl token or token-2022 account, checked in handler
+    pub spot_market_vault: AccountInfo<'info>,
+    #[account(
+        constraint = is_token_program(&token_program)?
+    )]
+    /// CHECK: spl token or token-2022 program
+    pub token_program: AccountInfo<'info>,
 }
 
 #[derive(Accounts)]
@@ -2273,17 +3120,70 @@ pub struct AdminRemoveInsuranceFundStake<'info> {
         seeds = [b"insurance_fund_vault".as_ref(), market_index.to_le_bytes().as_ref()],
         bump,
     )]
-    pub insurance_fund_vault: Box<Account<'info, TokenAccount>>,
+    /// CHECK: spl token or token-2022 account, checked in handler
+    pub insurance_fund_vault: AccountInfo<'info>,
     #[account(
         constraint = state.signer.eq(&drift_signer.key())
     )]
     /// CHECK: forced drift_signer
     pub drift_signer: AccountInfo<'info>,
+    #[account(mut)]
+    /// CHECK: spl token or token-2022 account, checked in handler
+    pub admin_token_account: AccountInfo<'info>,
+    #[account(
+        constraint = is_token_program(&token_program)?
+    )]
+    /// CHECK: spl token or token-2022 program
+    pub token_program: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeBackstopVault<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        has_one = admin
+    )]
+    pub state: Box<Account<'info, State>>,
+    #[account(
+        init,
+        seeds = [b"backstop_vault".as_ref()],
+        space = std::mem::size_of::<BackstopVault>() + 8,
+        bump,
+        payer = admin
+    )]
+    pub backstop_vault: AccountLoader<'info, BackstopVault>,
+    #[account(
+        init,
+        seeds = [b"user", backstop_vault.key().as_ref(), 0_u16.to_le_bytes().as_ref()],
+        space = std::mem::size_of::<User>() + 8,
+        bump,
+        payer = admin
+    )]
+    pub backstop_user: AccountLoader<'info, User>,
+    #[account(
+        init,
+        seeds = [b"user_stats", backstop_vault.key().as_ref()],
+        space = std::mem::size_of::<UserStats>() + 8,
+        bump,
+        payer = admin
+    )]
+    pub backstop_user_stats: AccountLoader<'info, UserStats>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AdminUpdateBackstopVault<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        has_one = admin
+    )]
+    pub state: Box<Account<'info, State>>,
     #[account(
         mut,
-        token::mint = insurance_fund_vault.mint,
-        token::authority = admin
+        seeds = [b"backstop_vault".as_ref()],
+        bump
     )]
-    pub admin_token_account: Box<Account<'info, TokenAccount>>,
-    pub token_program: Program<'info, Token>,
+    pub backstop_vault: AccountLoader<'info, BackstopVault>,
 }
@@ -0,0 +1,420 @@
+use anchor_lang::prelude::*;
+
+use crate::controller::token::{load_token_account, validate_user_token_account};
+use crate::error::{DriftResult, ErrorCode};
+use crate::instructions::constraints::*;
+use crate::instructions::optional_accounts::{get_token_mint_for_vault, load_maps, AccountMaps};
+use crate::math::constants::QUOTE_SPOT_MARKET_INDEX;
+use crate::math::margin::meets_initial_margin_requirement;
+use crate::math::safe_math::SafeMath;
+use crate::math::token_2022::calculate_transfer_fee;
+use crate::state::backstop_vault::{BackstopVault, BackstopVaultStake};
+use crate::state::oracle_map::OracleMap;
+use crate::state::perp_market_map::{MarketSet, PerpMarketMap};
+use crate::state::spot_market_map::{get_writable_spot_market_set, SpotMarketMap};
+use crate::state::state::State;
+use crate::state::user::User;
+use crate::validate;
+use crate::{controller, math};
+use crate::{load, load_mut};
+
+pub fn handle_initialize_backstop_vault_stake(
+    ctx: Context<InitializeBackstopVaultStake>,
+) -> Result<()> {
+    let mut backstop_vault_stake = ctx
+        .accounts
+        .backstop_vault_stake
+        .load_init()
+        .or(Err(ErrorCode::UnableToLoadAccountLoader))?;
+
+    let clock = Clock::get()?;
+    let now = clock.unix_timestamp;
+
+    *backstop_vault_stake = BackstopVaultStake::new(*ctx.accounts.authority.key, now);
+
+    Ok(())
+}
+
+#[access_control(
+    deposit_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_add_backstop_vault_stake(
+    ctx: Context<AddBackstopVaultStake>,
+    amount: u64,
+) -> Result<()> {
+    if amount == 0 {
+        return Err(ErrorCode::InsufficientDeposit.into());
+    }
+
+    let clock = Clock::get()?;
+    let now = clock.unix_timestamp;
+    let state = &ctx.accounts.state;
+    let backstop_vault = &mut load_mut!(ctx.accounts.backstop_vault)?;
+    let backstop_vault_stake = &mut load_mut!(ctx.accounts.backstop_vault_stake)?;
+    let backstop_user = &mut load_mut!(ctx.accounts.backstop_user)?;
+
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
+    let AccountMaps {
+        perp_market_map,
+        spot_market_map,
+        mut oracle_map,
+    } = load_maps(
+        remaining_accounts_iter,
+        &MarketSet::new(),
+        &get_writable_spot_market_set(QUOTE_SPOT_MARKET_INDEX),
+        clock.slot,
+        Some(state.oracle_guard_rails),
+    )?;
+
+    let token_program = &ctx.accounts.token_program;
+    let spot_market_vault_mint =
+        load_token_account(&ctx.accounts.spot_market_vault, token_program)?.mint;
+    validate_user_token_account(
+        &load_token_account(&ctx.accounts.user_token_account, token_program)?,
+        &spot_market_vault_mint,
+        ctx.accounts.authority.key,
+    )?;
+    let mint = get_token_mint_for_vault(remaining_accounts_iter, &spot_market_vault_mint)?;
+
+    let vault_value = update_interest_and_get_backstop_vault_value(
+        backstop_user,
+        &perp_market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        now,
+    )?;
+
+    // token-2022 transfer fees are withheld from what the vault receives, so only the net is staked
+    let transfer_fee = match &mint {
+        Some(mint) => calculate_transfer_fee(&mint.try_borrow_data()?, clock.epoch, amount)?,
+        None => 0,
+    };
+
+    controller::backstop_vault::add_backstop_vault_stake(
+        amount.safe_sub(transfer_fee)?,
+        vault_value,
+        backstop_vault,
+        backstop_vault_stake,
+        backstop_user,
+        &mut spot_market_map.get_ref_mut(&QUOTE_SPOT_MARKET_INDEX)?,
+        now,
+    )?;
+
+    controller::token::receive(
+        token_program,
+        &ctx.accounts.user_token_account,
+        &ctx.accounts.spot_market_vault,
+        &ctx.accounts.authority.to_account_info(),
+        amount,
+        &mint,
+    )?;
+
+    Ok(())
+}
+
+pub fn handle_request_remove_backstop_vault_stake(
+    ctx: Context<RequestRemoveBackstopVaultStake>,
+    shares: u128,
+) -> Result<()> {
+    let clock = Clock::get()?;
+    let now = clock.unix_timestamp;
+    let state = &ctx.accounts.state;
+    let backstop_vault = &load!(ctx.accounts.backstop_vault)?;
+    let backstop_vault_stake = &mut load_mut!(ctx.accounts.backstop_vault_stake)?;
+    let backstop_user = &load!(ctx.accounts.backstop_user)?;
+
+    let AccountMaps {
+        perp_market_map,
+        spot_market_map,
+        mut oracle_map,
+    } = load_maps(
+        &mut ctx.remaining_accounts.iter().peekable(),
+        &MarketSet::new(),
+        &get_writable_spot_market_set(QUOTE_SPOT_MARKET_INDEX),
+        clock.slot,
+        Some(state.oracle_guard_rails),
+    )?;
+
+    let vault_value = update_interest_and_get_backstop_vault_value(
+        backstop_user,
+        &perp_market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        now,
+    )?;
+
+    controller::backstop_vault::request_remove_backstop_vault_stake(
+        shares,
+        vault_value,
+        backstop_vault,
+        backstop_vault_stake,
+        now,
+    )?;
+
+    Ok(())
+}
+
+pub fn handle_cancel_request_remove_backstop_vault_stake(
+    ctx: Context<RequestRemoveBackstopVaultStake>,
+) -> Result<()> {
+    let clock = Clock::get()?;
+    let now = clock.unix_timestamp;
+    let state = &ctx.accounts.state;
+    let backstop_vault = &mut load_mut!(ctx.accounts.backstop_vault)?;
+    let backstop_vault_stake = &mut load_mut!(ctx.accounts.backstop_vault_stake)?;
+    let backstop_user = &load!(ctx.accounts.backstop_user)?;
+
+    let AccountMaps {
+        perp_market_map,
+        spot_market_map,
+        mut oracle_map,
+    } = load_maps(
+        &mut ctx.remaining_accounts.iter().peekable(),
+        &MarketSet::new(),
+        &get_writable_spot_market_set(QUOTE_SPOT_MARKET_INDEX),
+        clock.slot,
+        Some(state.oracle_guard_rails),
+    )?;
+
+    let vault_value = update_interest_and_get_backstop_vault_value(
+        backstop_user,
+        &perp_market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        now,
+    )?;
+
+    controller::backstop_vault::cancel_request_remove_backstop_vault_stake(
+        vault_value,
+        backstop_vault,
+        backstop_vault_stake,
+        now,
+    )?;
+
+    Ok(())
+}
+
+#[access_control(
+    withdraw_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_remove_backstop_vault_stake(ctx: Context<RemoveBackstopVaultStake>) -> Result<()> {
+    let clock = Clock::get()?;
+    let now = clock.unix_timestamp;
+    let state = &ctx.accounts.state;
+    let backstop_vault = &mut load_mut!(ctx.accounts.backstop_vault)?;
+    let backstop_vault_stake = &mut load_mut!(ctx.accounts.backstop_vault_stake)?;
+    let backstop_user = &mut load_mut!(ctx.accounts.backstop_user)?;
+
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
+    let AccountMaps {
+        perp_market_map,
+        spot_market_map,
+        mut oracle_map,
+    } = load_maps(
+        remaining_accounts_iter,
+        &MarketSet::new(),
+        &get_writable_spot_market_set(QUOTE_SPOT_MARKET_INDEX),
+        clock.slot,
+        Some(state.oracle_guard_rails),
+    )?;
+
+    let token_program = &ctx.accounts.token_program;
+    let spot_market_vault_mint =
+        load_token_account(&ctx.accounts.spot_market_vault, token_program)?.mint;
+    validate_user_token_account(
+        &load_token_account(&ctx.accounts.user_token_account, token_program)?,
+        &spot_market_vault_mint,
+        ctx.accounts.authority.key,
+    )?;
+    let mint = get_token_mint_for_vault(remaining_accounts_iter, &spot_market_vault_mint)?;
+
+    let vault_value = update_interest_and_get_backstop_vault_value(
+        backstop_user,
+        &perp_market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        now,
+    )?;
+
+    let amount = controller::backstop_vault::remove_backstop_vault_stake(
+        vault_value,
+        backstop_vault,
+        backstop_vault_stake,
+        backstop_user,
+        &mut spot_market_map.get_ref_mut(&QUOTE_SPOT_MARKET_INDEX)?,
+        now,
+    )?;
+
+    // stakers cant pull collateral that backs positions the vault has absorbed
+    validate!(
+        meets_initial_margin_requirement(
+            backstop_user,
+            &perp_market_map,
+            &spot_market_map,
+            &mut oracle_map
+        )?,
+        ErrorCode::InsufficientCollateral,
+        "backstop vault doesnt have enough free collateral to unstake"
+    )?;
+
+    controller::token::send_from_program_vault(
+        token_program,
+        &ctx.accounts.spot_market_vault,
+        &ctx.accounts.user_token_account,
+        &ctx.accounts.drift_signer,
+        state.signer_nonce,
+        amount,
+        &mint,
+    )?;
+
+    math::spot_withdraw::validate_spot_market_vault_amount(
+        &spot_market_map.get_ref(&QUOTE_SPOT_MARKET_INDEX)?,
+        load_token_account(&ctx.accounts.spot_market_vault, token_program)?.amount,
+    )?;
+
+    Ok(())
+}
+
+// accrues interest on the quote market first so the vault is valued at up to date deposit balances
+fn update_interest_and_get_backstop_vault_value(
+    backstop_user: &User,
+    perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
+    oracle_map: &mut OracleMap,
+    now: i64,
+) -> DriftResult<u64> {
+    {
+        let spot_market = &mut spot_market_map.get_ref_mut(&QUOTE_SPOT_MARKET_INDEX)?;
+        let oracle_price_data = oracle_map.get_price_data(&spot_market.oracle)?;
+        controller::spot_balance::update_spot_market_cumulative_interest(
+            spot_market,
+            Some(oracle_price_data),
+            now,
+        )?;
+    }
+
+    controller::backstop_vault::calculate_backstop_vault_value(
+        backstop_user,
+        perp_market_map,
+        spot_market_map,
+        oracle_map,
+    )
+}
+
+#[derive(Accounts)]
+pub struct InitializeBackstopVaultStake<'info> {
+    #[account(
+        init,
+        seeds = [b"backstop_vault_stake", authority.key.as_ref()],
+        space = std::mem::size_of::<BackstopVaultStake>() + 8,
+        bump,
+        payer = payer
+    )]
+    pub backstop_vault_stake: AccountLoader<'info, BackstopVaultStake>,
+    pub state: Box<Account<'info, State>>,
+    pub authority: Signer<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AddBackstopVaultStake<'info> {
+    pub state: Box<Account<'info, State>>,
+    #[account(
+        mut,
+        seeds = [b"backstop_vault".as_ref()],
+        bump
+    )]
+    pub backstop_vault: AccountLoader<'info, BackstopVault>,
+    #[account(
+        mut,
+        constraint = is_backstop_user(&backstop_vault, &backstop_user)?
+    )]
+    pub backstop_user: AccountLoader<'info, User>,
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub backstop_vault_stake: AccountLoader<'info, BackstopVaultStake>,
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"spot_market_vault".as_ref(), 0_u16.to_le_bytes().as_ref()],
+        bump,
+    )]
+    /// CHECK: spl token or token-2022 account, checked in handler
+    pub spot_market_vault: AccountInfo<'info>,
+    #[account(mut)]
+    /// CHECK: spl token or token-2022 account, checked in handler
+    pub user_token_account: AccountInfo<'info>,
+    #[account(
+        constraint = is_token_program(&token_program)?
+    )]
+    /// CHECK: spl token or token-2022 program
+    pub token_program: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RequestRemoveBackstopVaultStake<'info> {
+    pub state: Box<Account<'info, State>>,
+    #[account(
+        mut,
+        seeds = [b"backstop_vault".as_ref()],
+        bump
+    )]
+    pub backstop_vault: AccountLoader<'info, BackstopVault>,
+    #[account(
+        constraint = is_backstop_user(&backstop_vault, &backstop_user)?
+    )]
+    pub backstop_user: AccountLoader<'info, User>,
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub backstop_vault_stake: AccountLoader<'info, BackstopVaultStake>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RemoveBackstopVaultStake<'info> {
+    pub state: Box<Account<'info, State>>,
+    #[account(
+        mut,
+        seeds = [b"backstop_vault".as_ref()],
+        bump
+    )]
+    pub backstop_vault: AccountLoader<'info, BackstopVault>,
+    #[account(
+        mut,
+        constraint = is_backstop_user(&backstop_vault, &backstop_user)?
+    )]
+    pub backstop_user: AccountLoader<'info, User>,
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub backstop_vault_stake: AccountLoader<'info, BackstopVaultStake>,
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"spot_market_vault".as_ref(), 0_u16.to_le_bytes().as_ref()],
+        bump,
+    )]
+    /// CHECK: spl token or token-2022 account, checked in handler
+    pub spot_market_vault: AccountInfo<'info>,
+    #[account(
+        constraint = state.signer.eq(&drift_signer.key())
+    )]
+    /// CHECK: forced drift_signer
+    pub drift_signer: AccountInfo<'info>,
+    #[account(mut)]
+    /// CHECK: spl token or token-2022 account, checked in handler
+    pub user_token_account: AccountInfo<'info>,
+    #[account(
+        constraint = is_token_program(&token_program)?
+    )]
+    /// CHECK: spl token or token-2022 program
+    pub token_program: AccountInfo<'info>,
+}
@@ -1,9 +1,12 @@
 use anchor_lang::accounts::account::Account;
 use anchor_lang::accounts::account_loader::AccountLoader;
 use anchor_lang::accounts::signer::Signer;
-use anchor_lang::prelude::{AccountInfo, Pubkey};
+use anchor_lang::prelude::{AccountInfo, Key, Pubkey};
 
 use crate::error::ErrorCode;
+use crate::ids::token_2022_program;
+use crate::state::backstop_vault::BackstopVault;
+use crate::state::liquidation_history::UserLiquidationHistory;
 use crate::state::perp_market::{MarketStatus, PerpMarket};
 use crate::state::spot_market::SpotMarket;
 use crate::state::state::{ExchangeStatus, State};
@@ -27,6 +30,27 @@ pub fn is_stats_for_user(
     Ok(user_stats.authority.eq(&user.authority))
 }
 
+pub fn is_liquidation_history_for_user(
+    user: &AccountLoader<User>,
+    user_liquidation_history: &AccountLoader<UserLiquidationHistory>,
+) -> anchor_lang::Result<bool> {
+    let user = user.load()?;
+    let user_liquidation_history = user_liquidation_history.load()?;
+    Ok(user_liquidation_history.authority.eq(&user.authority))
+}
+
+pub fn is_backstop_user(
+    backstop_vault: &AccountLoader<BackstopVault>,
+    user: &AccountLoader<User>,
+) -> anchor_lang::Result<bool> {
+    Ok(backstop_vault.load()?.user.eq(&user.key()))
+}
+
+pub fn is_token_program(token_program: &AccountInfo) -> anchor_lang::Result<bool> {
+    Ok(token_program.key == &anchor_spl::token::ID
+        || token_program.key == &token_2022_program::id())
+}
+
 pub fn market_valid(market: &AccountLoader<PerpMarket>) -> anchor_lang::Result<()> {
     if market.load()?.status == MarketStatus::Delisted {
         return Err(ErrorCode::MarketDelisted.into());
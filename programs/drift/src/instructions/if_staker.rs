@@ -1,13 +1,26 @@
-use anchor_lang::prelude::*;
-use anchor_spl::token::{Token, TokenAccount};
+use std::iter::Peekable;
+use std::slice::Iter;
 
+use anchor_lang::{prelude::*, Discriminator};
+use anchor_spl::token;
+
+use crate::controller::token::{
+    load_token_account, load_vault_token_account, validate_user_token_account,
+};
 use crate::error::ErrorCode;
 use crate::instructions::constraints::*;
+use crate::instructions::optional_accounts::{
+    get_insurance_fund_shares_accounts, get_token_mint_for_vault,
+};
+use crate::load;
 use crate::load_mut;
+use crate::math::safe_math::SafeMath;
+use crate::math::token_2022::calculate_transfer_fee;
 use crate::state::insurance_fund_stake::InsuranceFundStake;
-use crate::state::spot_market::SpotMarket;
+use crate::state::perp_market::PerpMarket;
+use crate::state::spot_market::{InsuranceFundMarket, SpotMarket};
 use crate::state::state::State;
-use crate::state::user::UserStats;
+use crate::state::user::{MarketType, UserStats};
 use crate::validate;
 use crate::{controller, math};
 
@@ -29,6 +42,19 @@ pub fn handle_initialize_insurance_fund_stake(
     Ok(())
 }
 
+pub fn handle_resize_insurance_fund_stake(ctx: Context<ResizeInsuranceFundStake>) -> Result<()> {
+    // stakes created before the reward fields were appended can't be loaded until resized
+    controller::pda::resize_pda(
+        &ctx.accounts.payer.to_account_info(),
+        &Rent::get()?,
+        std::mem::size_of::<InsuranceFundStake>() + 8,
+        &InsuranceFundStake::discriminator(),
+        &ctx.accounts.system_program.to_account_info(),
+        &ctx.accounts.insurance_fund_stake,
+        ErrorCode::InvalidInsuranceFundStakeAccountSize,
+    )
+}
+
 pub fn handle_add_insurance_fund_stake(
     ctx: Context<AddInsuranceFundStake>,
     market_index: u16,
@@ -42,7 +68,7 @@ pub fn handle_add_insurance_fund_stake(
     let now = clock.unix_timestamp;
     let insurance_fund_stake = &mut load_mut!(ctx.accounts.insurance_fund_stake)?;
     let user_stats = &mut load_mut!(ctx.accounts.user_stats)?;
-    let spot_market = &mut load_mut!(ctx.accounts.spot_market)?;
+    let spot_market = &mut *load_mut!(ctx.accounts.spot_market)?;
     let state = &ctx.accounts.state;
 
     validate!(
@@ -51,11 +77,18 @@ pub fn handle_add_insurance_fund_stake(
         "insurance_fund_stake does not match market_index"
     )?;
 
-    validate!(
-        insurance_fund_stake.last_withdraw_request_shares == 0
-            && insurance_fund_stake.last_withdraw_request_value == 0,
-        ErrorCode::IFWithdrawRequestInProgress,
-        "withdraw request in progress"
+    let token_program = &ctx.accounts.token_program;
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
+    let (_, mint) = load_insurance_fund_vault(
+        &ctx.accounts.insurance_fund_vault,
+        &ctx.accounts.user_token_account,
+        token_program,
+        ctx.accounts.authority.key,
+        remaining_accounts_iter,
+    )?;
+    let insurance_fund_shares_accounts = get_insurance_fund_shares_accounts(
+        remaining_accounts_iter,
+        &spot_market.insurance_fund_extension.shares_mint,
     )?;
 
     {
@@ -64,55 +97,658 @@ pub fn handle_add_insurance_fund_stake(
             &ctx.accounts.insurance_fund_vault,
             spot_market,
             now,
-            &ctx.accounts.token_program,
+            token_program,
+            &ctx.accounts.drift_signer,
+            state,
+            &mint,
+        )?;
+
+        // reload the spot market vault balance so it's up-to-date
+        math::spot_withdraw::validate_spot_market_vault_amount(
+            spot_market,
+            load_token_account(&ctx.accounts.spot_market_vault, token_program)?.amount,
+        )?;
+    }
+
+    add_insurance_fund_stake(
+        amount,
+        &ctx.accounts.insurance_fund_vault,
+        &ctx.accounts.user_token_account,
+        &ctx.accounts.authority,
+        token_program,
+        &mint,
+        insurance_fund_stake,
+        user_stats,
+        spot_market,
+        &clock,
+    )?;
+
+    // the staked shares are minted as shares tokens when the shares mint accounts are passed
+    if let Some((insurance_fund_shares_mint, user_shares_token_account)) =
+        insurance_fund_shares_accounts
+    {
+        mint_insurance_fund_shares(
+            n_shares,
+            &ctx.accounts.insurance_fund_vault,
+            token_program,
+            &insurance_fund_shares_mint,
+            &user_shares_token_account,
+            ctx.accounts.authority.key,
             &ctx.accounts.drift_signer,
             state,
+            insurance_fund_stake,
+            user_stats,
+            spot_market,
+            now,
         )?;
+    }
+
+    Ok(())
+}
+
+pub fn handle_request_remove_insurance_fund_stake(
+    ctx: Context<RequestRemoveInsuranceFundStake>,
+    market_index: u16,
+    amount: u64,
+) -> Result<()> {
+    let clock = Clock::get()?;
+    let insurance_fund_stake = &mut load_mut!(ctx.accounts.insurance_fund_stake)?;
+    let user_stats = &mut load_mut!(ctx.accounts.user_stats)?;
+    let spot_market = &mut *load_mut!(ctx.accounts.spot_market)?;
+
+    validate!(
+        insurance_fund_stake.market_index == market_index,
+        ErrorCode::IncorrectSpotMarketAccountPassed,
+        "insurance_fund_stake does not match market_index"
+    )?;
+
+    let insurance_fund_shares_accounts =
+        get_insurance_fund_shares_burn_accounts(ctx.remaining_accounts, spot_market)?;
+
+    request_remove_insurance_fund_stake(
+        amount,
+        load_vault_token_account(&ctx.accounts.insurance_fund_vault)?.amount,
+        &ctx.accounts.authority,
+        insurance_fund_shares_accounts,
+        insurance_fund_stake,
+        user_stats,
+        spot_market,
+        clock.unix_timestamp,
+    )
+}
+
+pub fn handle_cancel_request_remove_insurance_fund_stake(
+    ctx: Context<RequestRemoveInsuranceFundStake>,
+    market_index: u16,
+) -> Result<()> {
+    let clock = Clock::get()?;
+    let insurance_fund_stake = &mut load_mut!(ctx.accounts.insurance_fund_stake)?;
+    let user_stats = &mut load_mut!(ctx.accounts.user_stats)?;
+    let spot_market = &mut *load_mut!(ctx.accounts.spot_market)?;
+
+    validate!(
+        insurance_fund_stake.market_index == market_index,
+        ErrorCode::IncorrectSpotMarketAccountPassed,
+        "insurance_fund_stake does not match market_index"
+    )?;
+
+    cancel_request_remove_insurance_fund_stake(
+        load_vault_token_account(&ctx.accounts.insurance_fund_vault)?.amount,
+        insurance_fund_stake,
+        user_stats,
+        spot_market,
+        clock.unix_timestamp,
+    )
+}
+
+#[access_control(
+    withdraw_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_remove_insurance_fund_stake(
+    ctx: Context<RemoveInsuranceFundStake>,
+    market_index: u16,
+) -> Result<()> {
+    let clock = Clock::get()?;
+    let now = clock.unix_timestamp;
+    let insurance_fund_stake = &mut load_mut!(ctx.accounts.insurance_fund_stake)?;
+    let user_stats = &mut load_mut!(ctx.accounts.user_stats)?;
+    let spot_market = &mut *load_mut!(ctx.accounts.spot_market)?;
+    let state = &ctx.accounts.state;
+
+    validate!(
+        insurance_fund_stake.market_index == market_index,
+        ErrorCode::IncorrectSpotMarketAccountPassed,
+        "insurance_fund_stake does not match market_index"
+    )?;
+
+    let (insurance_fund_vault_amount, mint) = load_insurance_fund_vault(
+        &ctx.accounts.insurance_fund_vault,
+        &ctx.accounts.user_token_account,
+        &ctx.accounts.token_program,
+        ctx.accounts.authority.key,
+        &mut ctx.remaining_accounts.iter().peekable(),
+    )?;
+
+    let amount = controller::insurance::remove_insurance_fund_stake(
+        insurance_fund_vault_amount,
+        insurance_fund_stake,
+        user_stats,
+        spot_market,
+        now,
+    )?;
+
+    send_from_insurance_fund_vault(
+        &ctx.accounts.insurance_fund_vault,
+        &ctx.accounts.user_token_account,
+        &ctx.accounts.drift_signer,
+        &ctx.accounts.token_program,
+        state,
+        amount,
+        &mint,
+    )?;
+
+    // validate relevant spot market balances before unstake
+    math::spot_withdraw::validate_spot_balances(spot_market)?;
+
+    Ok(())
+}
+
+#[access_control(
+    withdraw_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_instant_remove_insurance_fund_stake(
+    ctx: Context<RemoveInsuranceFundStake>,
+    market_index: u16,
+    n_shares: u128,
+) -> Result<()> {
+    let clock = Clock::get()?;
+    let now = clock.unix_timestamp;
+    let insurance_fund_stake = &mut load_mut!(ctx.accounts.insurance_fund_stake)?;
+    let user_stats = &mut load_mut!(ctx.accounts.user_stats)?;
+    let spot_market = &mut *load_mut!(ctx.accounts.spot_market)?;
+    let state = &ctx.accounts.state;
+
+    validate!(
+        insurance_fund_stake.market_index == market_index,
+        ErrorCode::IncorrectSpotMarketAccountPassed,
+        "insurance_fund_stake does not match market_index"
+    )?;
+
+    let (insurance_fund_vault_amount, mint) = load_insurance_fund_vault(
+        &ctx.accounts.insurance_fund_vault,
+        &ctx.accounts.user_token_account,
+        &ctx.accounts.token_program,
+        ctx.accounts.authority.key,
+        &mut ctx.remaining_accounts.iter().peekable(),
+    )?;
+
+    let amount = controller::insurance::instant_remove_insurance_fund_stake(
+        n_shares,
+        insurance_fund_vault_amount,
+        insurance_fund_stake,
+        user_stats,
+        spot_market,
+        now,
+    )?;
+
+    send_from_insurance_fund_vault(
+        &ctx.accounts.insurance_fund_vault,
+        &ctx.accounts.user_token_account,
+        &ctx.accounts.drift_signer,
+        &ctx.accounts.token_program,
+        state,
+        amount,
+        &mint,
+    )?;
+
+    // validate relevant spot market balances before unstake
+    math::spot_withdraw::validate_spot_balances(spot_market)?;
+
+    Ok(())
+}
+
+pub fn handle_claim_insurance_fund_stake_rewards(
+    ctx: Context<ClaimInsuranceFundStakeRewards>,
+    market_index: u16,
+) -> Result<()> {
+    let clock = Clock::get()?;
+    let now = clock.unix_timestamp;
+    let insurance_fund_stake = &mut load_mut!(ctx.accounts.insurance_fund_stake)?;
+    let spot_market = &mut *load_mut!(ctx.accounts.spot_market)?;
+    let state = &ctx.accounts.state;
+
+    validate!(
+        insurance_fund_stake.market_index == market_index,
+        ErrorCode::InvalidInsuranceFundRewardsClaim,
+        "insurance_fund_stake does not match market_index"
+    )?;
+
+    validate!(
+        spot_market.insurance_fund_extension.reward_vault
+            == ctx.accounts.insurance_fund_reward_vault.key(),
+        ErrorCode::InvalidInsuranceFundRewardsClaim,
+        "spot market {} has no insurance fund reward vault",
+        market_index
+    )?;
+
+    let (insurance_fund_reward_vault_amount, mint) = load_insurance_fund_vault(
+        &ctx.accounts.insurance_fund_reward_vault,
+        &ctx.accounts.user_token_account,
+        &ctx.accounts.token_program,
+        ctx.accounts.authority.key,
+        &mut ctx.remaining_accounts.iter().peekable(),
+    )?;
+
+    let amount = controller::insurance::claim_insurance_fund_stake_rewards(
+        load_vault_token_account(&ctx.accounts.insurance_fund_vault)?.amount,
+        insurance_fund_reward_vault_amount,
+        insurance_fund_stake,
+        spot_market,
+        now,
+    )?;
+
+    if amount > 0 {
+        controller::token::send_from_program_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.insurance_fund_reward_vault,
+            &ctx.accounts.user_token_account,
+            &ctx.accounts.drift_signer,
+            state.signer_nonce,
+            amount,
+            &mint,
+        )?;
+    }
+
+    Ok(())
+}
+
+pub fn handle_initialize_perp_market_insurance_fund_stake(
+    ctx: Context<InitializePerpMarketInsuranceFundStake>,
+    market_index: u16,
+) -> Result<()> {
+    validate!(
+        load!(ctx.accounts.perp_market)?.has_insurance_fund(),
+        ErrorCode::InvalidPerpMarketInsuranceFundVault,
+        "perp market {} has no insurance fund",
+        market_index
+    )?;
+
+    let mut if_stake = ctx
+        .accounts
+        .insurance_fund_stake
+        .load_init()
+        .or(Err(ErrorCode::UnableToLoadAccountLoader))?;
+
+    let clock = Clock::get()?;
+    let now = clock.unix_timestamp;
+
+    *if_stake = InsuranceFundStake {
+        market_type: MarketType::Perp,
+        ..InsuranceFundStake::new(*ctx.accounts.authority.key, market_index, now)
+    };
+
+    Ok(())
+}
+
+pub fn handle_add_perp_market_insurance_fund_stake(
+    ctx: Context<AddPerpMarketInsuranceFundStake>,
+    market_index: u16,
+    amount: u64,
+) -> Result<()> {
+    if amount == 0 {
+        return Err(ErrorCode::InsufficientDeposit.into());
+    }
+
+    let clock = Clock::get()?;
+    let insurance_fund_stake = &mut load_mut!(ctx.accounts.insurance_fund_stake)?;
+    let user_stats = &mut load_mut!(ctx.accounts.user_stats)?;
+    let perp_market = &mut *load_mut!(ctx.accounts.perp_market)?;
+
+    validate!(
+        insurance_fund_stake.market_index == market_index,
+        ErrorCode::InvalidInsuranceFundStakeMarket,
+        "insurance_fund_stake does not match market_index"
+    )?;
+
+    let (_, mint) = load_insurance_fund_vault(
+        &ctx.accounts.insurance_fund_vault,
+        &ctx.accounts.user_token_account,
+        &ctx.accounts.token_program,
+        ctx.accounts.authority.key,
+        &mut ctx.remaining_accounts.iter().peekable(),
+    )?;
+
+    add_insurance_fund_stake(
+        amount,
+        &ctx.accounts.insurance_fund_vault,
+        &ctx.accounts.user_token_account,
+        &ctx.accounts.authority,
+        &ctx.accounts.token_program,
+        &mint,
+        insurance_fund_stake,
+        user_stats,
+        perp_market,
+        &clock,
+    )?;
+
+    Ok(())
+}
+
+pub fn handle_request_remove_perp_market_insurance_fund_stake(
+    ctx: Context<RequestRemovePerpMarketInsuranceFundStake>,
+    market_index: u16,
+    amount: u64,
+) -> Result<()> {
+    let clock = Clock::get()?;
+    let insurance_fund_stake = &mut load_mut!(ctx.accounts.insurance_fund_stake)?;
+    let user_stats = &mut load_mut!(ctx.accounts.user_stats)?;
+    let perp_market = &mut *load_mut!(ctx.accounts.perp_market)?;
+
+    validate!(
+        insurance_fund_stake.market_index == market_index,
+        ErrorCode::InvalidInsuranceFundStakeMarket,
+        "insurance_fund_stake does not match market_index"
+    )?;
+
+    let insurance_fund_shares_accounts =
+        get_insurance_fund_shares_burn_accounts(ctx.remaining_accounts, perp_market)?;
+
+    request_remove_insurance_fund_stake(
+        amount,
+        load_vault_token_account(&ctx.accounts.insurance_fund_vault)?.amount,
+        &ctx.accounts.authority,
+        insurance_fund_shares_accounts,
+        insurance_fund_stake,
+        user_stats,
+        perp_market,
+        clock.unix_timestamp,
+    )
+}
+
+pub fn handle_cancel_request_remove_perp_market_insurance_fund_stake(
+    ctx: Context<RequestRemovePerpMarketInsuranceFundStake>,
+    market_index: u16,
+) -> Result<()> {
+    let clock = Clock::get()?;
+    let insurance_fund_stake = &mut load_mut!(ctx.accounts.insurance_fund_stake)?;
+    let user_stats = &mut load_mut!(ctx.accounts.user_stats)?;
+    let perp_market = &mut *load_mut!(ctx.accounts.perp_market)?;
+
+    validate!(
+        insurance_fund_stake.market_index == market_index,
+        ErrorCode::InvalidInsuranceFundStakeMarket,
+        "insurance_fund_stake does not match market_index"
+    )?;
+
+    cancel_request_remove_insurance_fund_stake(
+        load_vault_token_account(&ctx.accounts.insurance_fund_vault)?.amount,
+        insurance_fund_stake,
+        user_stats,
+        perp_market,
+        clock.unix_timestamp,
+    )
+}
+
+#[access_control(
+    withdraw_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_remove_perp_market_insurance_fund_stake(
+    ctx: Context<RemovePerpMarketInsuranceFundStake>,
+    market_index: u16,
+) -> Result<()> {
+    let clock = Clock::get()?;
+    let now = clock.unix_timestamp;
+    let insurance_fund_stake = &mut load_mut!(ctx.accounts.insurance_fund_stake)?;
+    let user_stats = &mut load_mut!(ctx.accounts.user_stats)?;
+    let perp_market = &mut *load_mut!(ctx.accounts.perp_market)?;
+    let state = &ctx.accounts.state;
+
+    validate!(
+        insurance_fund_stake.market_index == market_index,
+        ErrorCode::InvalidInsuranceFundStakeMarket,
+        "insurance_fund_stake does not match market_index"
+    )?;
+
+    let (insurance_fund_vault_amount, mint) = load_insurance_fund_vault(
+        &ctx.accounts.insurance_fund_vault,
+        &ctx.accounts.user_token_account,
+        &ctx.accounts.token_program,
+        ctx.accounts.authority.key,
+        &mut ctx.remaining_accounts.iter().peekable(),
+    )?;
+
+    let amount = controller::insurance::remove_insurance_fund_stake(
+        insurance_fund_vault_amount,
+        insurance_fund_stake,
+        user_stats,
+        perp_market,
+        now,
+    )?;
+
+    send_from_insurance_fund_vault(
+        &ctx.accounts.insurance_fund_vault,
+        &ctx.accounts.user_token_account,
+        &ctx.accounts.drift_signer,
+        &ctx.accounts.token_program,
+        state,
+        amount,
+        &mint,
+    )
+}
+
+#[access_control(
+    withdraw_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_instant_remove_perp_market_insurance_fund_stake(
+    ctx: Context<RemovePerpMarketInsuranceFundStake>,
+    market_index: u16,
+    n_shares: u128,
+) -> Result<()> {
+    let clock = Clock::get()?;
+    let now = clock.unix_timestamp;
+    let insurance_fund_stake = &mut load_mut!(ctx.accounts.insurance_fund_stake)?;
+    let user_stats = &mut load_mut!(ctx.accounts.user_stats)?;
+    let perp_market = &mut *load_mut!(ctx.accounts.perp_market)?;
+    let state = &ctx.accounts.state;
+
+    validate!(
+        insurance_fund_stake.market_index == market_index,
+        ErrorCode::InvalidInsuranceFundStakeMarket,
+        "insurance_fund_stake does not match market_index"
+    )?;
+
+    let (insurance_fund_vault_amount, mint) = load_insurance_fund_vault(
+        &ctx.accounts.insurance_fund_vault,
+        &ctx.accounts.user_token_account,
+        &ctx.accounts.token_program,
+        ctx.accounts.authority.key,
+        &mut ctx.remaining_accounts.iter().peekable(),
+    )?;
+
+    let amount = controller::insurance::instant_remove_insurance_fund_stake(
+        n_shares,
+        insurance_fund_vault_amount,
+        insurance_fund_stake,
+        user_stats,
+        perp_market,
+        now,
+    )?;
+
+    send_from_insurance_fund_vault(
+        &ctx.accounts.insurance_fund_vault,
+        &ctx.accounts.user_token_account,
+        &ctx.accounts.drift_signer,
+        &ctx.accounts.token_program,
+        state,
+        amount,
+        &mint,
+    )
+}
+
+// the helpers below are shared by the spot and perp market insurance fund handlers, which only
+// differ in the market account and vault seeds
+
+// loads the insurance fund vault, checks the user token account moving tokens in or out of it and
+// takes the mint token-2022 transfers require from the remaining accounts
+fn load_insurance_fund_vault<'a>(
+    vault: &AccountInfo<'a>,
+    user_token_account: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    authority: &Pubkey,
+    remaining_accounts_iter: &mut Peekable<Iter<AccountInfo<'a>>>,
+) -> Result<(u64, Option<AccountInfo<'a>>)> {
+    let vault = load_token_account(vault, token_program)?;
+    validate_user_token_account(
+        &load_token_account(user_token_account, token_program)?,
+        &vault.mint,
+        authority,
+    )?;
+    let mint = get_token_mint_for_vault(remaining_accounts_iter, &vault.mint)?;
+
+    Ok((vault.amount, mint))
+}
+
+fn add_insurance_fund_stake<'info>(
+    amount: u64,
+    insurance_fund_vault: &AccountInfo<'info>,
+    user_token_account: &AccountInfo<'info>,
+    authority: &Signer<'info>,
+    token_program: &AccountInfo<'info>,
+    mint: &Option<AccountInfo<'info>>,
+    insurance_fund_stake: &mut InsuranceFundStake,
+    user_stats: &mut UserStats,
+    market: &mut impl InsuranceFundMarket,
+    clock: &Clock,
+) -> Result<u128> {
+    validate!(
+        insurance_fund_stake.last_withdraw_request_shares == 0
+            && insurance_fund_stake.last_withdraw_request_value == 0,
+        ErrorCode::IFWithdrawRequestInProgress,
+        "withdraw request in progress"
+    )?;
 
-        // reload the spot market vault balance so it's up-to-date
-        ctx.accounts.spot_market_vault.reload()?;
-        math::spot_withdraw::validate_spot_market_vault_amount(
-            spot_market,
-            ctx.accounts.spot_market_vault.amount,
-        )?;
-    }
+    // token-2022 transfer fees are withheld from what the vault receives, so only the net is staked
+    let transfer_fee = match mint {
+        Some(mint) => calculate_transfer_fee(&mint.try_borrow_data()?, clock.epoch, amount)?,
+        None => 0,
+    };
 
-    controller::insurance::add_insurance_fund_stake(
-        amount,
-        ctx.accounts.insurance_fund_vault.amount,
+    let n_shares = controller::insurance::add_insurance_fund_stake(
+        amount.safe_sub(transfer_fee)?,
+        load_token_account(insurance_fund_vault, token_program)?.amount,
         insurance_fund_stake,
         user_stats,
-        spot_market,
+        market,
         clock.unix_timestamp,
     )?;
 
     controller::token::receive(
-        &ctx.accounts.token_program,
-        &ctx.accounts.user_token_account,
-        &ctx.accounts.insurance_fund_vault,
-        &ctx.accounts.authority,
+        token_program,
+        user_token_account,
+        insurance_fund_vault,
+        &authority.to_account_info(),
         amount,
+        mint,
     )?;
 
-    Ok(())
+    Ok(n_shares)
 }
 
-pub fn handle_request_remove_insurance_fund_stake(
-    ctx: Context<RequestRemoveInsuranceFundStake>,
-    market_index: u16,
-    amount: u64,
+fn mint_insurance_fund_shares<'info>(
+    n_shares: u128,
+    insurance_fund_vault: &AccountInfo<'info>,
+    token_program: &AccountInfo<'info>,
+    insurance_fund_shares_mint: &AccountInfo<'info>,
+    user_shares_token_account: &AccountInfo<'info>,
+    authority: &Pubkey,
+    drift_signer: &AccountInfo<'info>,
+    state: &State,
+    insurance_fund_stake: &mut InsuranceFundStake,
+    user_stats: &mut UserStats,
+    market: &mut impl InsuranceFundMarket,
+    now: i64,
 ) -> Result<()> {
-    let clock = Clock::get()?;
-    let insurance_fund_stake = &mut load_mut!(ctx.accounts.insurance_fund_stake)?;
-    let user_stats = &mut load_mut!(ctx.accounts.user_stats)?;
-    let spot_market = &mut load_mut!(ctx.accounts.spot_market)?;
+    validate!(
+        token_program.key == &token::ID,
+        ErrorCode::InvalidInsuranceFundSharesTokenization,
+        "insurance fund shares can only be minted for spl token markets"
+    )?;
+
+    validate_user_token_account(
+        &load_token_account(user_shares_token_account, token_program)?,
+        insurance_fund_shares_mint.key,
+        authority,
+    )?;
+
+    // valued against the vault after the staked amount was received
+    let amount = controller::insurance::mint_insurance_fund_shares(
+        n_shares,
+        load_token_account(insurance_fund_vault, token_program)?.amount,
+        insurance_fund_stake,
+        user_stats,
+        market,
+        now,
+    )?;
+
+    controller::token::mint_from_program_mint(
+        token_program,
+        insurance_fund_shares_mint,
+        user_shares_token_account,
+        drift_signer,
+        state.signer_nonce,
+        amount,
+    )
+}
+
+// the shares mint, the authority's shares token account and the spl token program, passed to
+// request more than the stake holds by burning shares tokens back into it
+fn get_insurance_fund_shares_burn_accounts<'a>(
+    remaining_accounts: &[AccountInfo<'a>],
+    market: &impl InsuranceFundMarket,
+) -> Result<Option<(AccountInfo<'a>, AccountInfo<'a>, AccountInfo<'a>)>> {
+    let remaining_accounts_iter = &mut remaining_accounts.iter().peekable();
+    let (insurance_fund_shares_mint, user_shares_token_account) =
+        match get_insurance_fund_shares_accounts(
+            remaining_accounts_iter,
+            &market.insurance_fund_extension().shares_mint,
+        )? {
+            Some(insurance_fund_shares_accounts) => insurance_fund_shares_accounts,
+            None => return Ok(None),
+        };
+
+    let token_program = remaining_accounts_iter.next().ok_or_else(|| {
+        msg!("Could not find token program to burn insurance fund shares");
+        ErrorCode::InvalidInsuranceFundSharesTokenization
+    })?;
 
     validate!(
-        insurance_fund_stake.market_index == market_index,
-        ErrorCode::IncorrectSpotMarketAccountPassed,
-        "insurance_fund_stake does not match market_index"
+        token_program.key == &token::ID,
+        ErrorCode::InvalidInsuranceFundSharesTokenization,
+        "token program {} is not the spl token program",
+        token_program.key
     )?;
 
+    Ok(Some((
+        insurance_fund_shares_mint,
+        user_shares_token_account,
+        token_program.clone(),
+    )))
+}
+
+fn request_remove_insurance_fund_stake<'info>(
+    amount: u64,
+    insurance_fund_vault_amount: u64,
+    authority: &Signer<'info>,
+    insurance_fund_shares_accounts: Option<(
+        AccountInfo<'info>,
+        AccountInfo<'info>,
+        AccountInfo<'info>,
+    )>,
+    insurance_fund_stake: &mut InsuranceFundStake,
+    user_stats: &mut UserStats,
+    market: &mut impl InsuranceFundMarket,
+    now: i64,
+) -> Result<()> {
     validate!(
         insurance_fund_stake.last_withdraw_request_shares == 0,
         ErrorCode::IFWithdrawRequestInProgress,
@@ -121,8 +757,8 @@ pub fn handle_request_remove_insurance_fund_stake(
 
     let n_shares = math::insurance::vault_amount_to_if_shares(
         amount,
-        spot_market.insurance_fund.total_shares,
-        ctx.accounts.insurance_fund_vault.amount,
+        market.insurance_fund().total_shares,
+        insurance_fund_vault_amount,
     )?;
 
     validate!(
@@ -131,37 +767,68 @@ pub fn handle_request_remove_insurance_fund_stake(
         "Requested lp_shares = 0"
     )?;
 
-    let user_if_shares = insurance_fund_stake.checked_if_shares(spot_market)?;
+    let mut user_if_shares = insurance_fund_stake.checked_if_shares(market)?;
+
+    // shares tokens covering what the stake is short are burned back into it, so they go through
+    // the same unstaking period as staked shares
+    if let Some((insurance_fund_shares_mint, user_shares_token_account, token_program)) =
+        insurance_fund_shares_accounts
+    {
+        if user_if_shares < n_shares {
+            validate_user_token_account(
+                &load_token_account(&user_shares_token_account, &token_program)?,
+                insurance_fund_shares_mint.key,
+                authority.key,
+            )?;
+
+            let shares_amount = math::insurance::if_shares_to_shares_mint_amount(
+                n_shares.safe_sub(user_if_shares)?,
+                market.insurance_fund().shares_base,
+                market.insurance_fund_extension().shares_mint_base,
+            )?;
+
+            controller::insurance::redeem_insurance_fund_shares(
+                shares_amount,
+                insurance_fund_vault_amount,
+                insurance_fund_stake,
+                user_stats,
+                market,
+                now,
+            )?;
+
+            controller::token::burn(
+                &token_program,
+                &insurance_fund_shares_mint,
+                &user_shares_token_account,
+                &authority.to_account_info(),
+                shares_amount,
+            )?;
+
+            user_if_shares = insurance_fund_stake.checked_if_shares(market)?;
+        }
+    }
+
     validate!(user_if_shares >= n_shares, ErrorCode::InsufficientIFShares)?;
 
     controller::insurance::request_remove_insurance_fund_stake(
         n_shares,
-        ctx.accounts.insurance_fund_vault.amount,
+        insurance_fund_vault_amount,
         insurance_fund_stake,
         user_stats,
-        spot_market,
-        clock.unix_timestamp,
+        market,
+        now,
     )?;
 
     Ok(())
 }
 
-pub fn handle_cancel_request_remove_insurance_fund_stake(
-    ctx: Context<RequestRemoveInsuranceFundStake>,
-    market_index: u16,
+fn cancel_request_remove_insurance_fund_stake(
+    insurance_fund_vault_amount: u64,
+    insurance_fund_stake: &mut InsuranceFundStake,
+    user_stats: &mut UserStats,
+    market: &mut impl InsuranceFundMarket,
+    now: i64,
 ) -> Result<()> {
-    let clock = Clock::get()?;
-    let now = clock.unix_timestamp;
-    let insurance_fund_stake = &mut load_mut!(ctx.accounts.insurance_fund_stake)?;
-    let user_stats = &mut load_mut!(ctx.accounts.user_stats)?;
-    let spot_market = &mut load_mut!(ctx.accounts.spot_market)?;
-
-    validate!(
-        insurance_fund_stake.market_index == market_index,
-        ErrorCode::IncorrectSpotMarketAccountPassed,
-        "insurance_fund_stake does not match market_index"
-    )?;
-
     validate!(
         insurance_fund_stake.last_withdraw_request_shares != 0,
         ErrorCode::NoIFWithdrawRequestInProgress,
@@ -169,62 +836,41 @@ pub fn handle_cancel_request_remove_insurance_fund_stake(
     )?;
 
     controller::insurance::cancel_request_remove_insurance_fund_stake(
-        ctx.accounts.insurance_fund_vault.amount,
+        insurance_fund_vault_amount,
         insurance_fund_stake,
         user_stats,
-        spot_market,
+        market,
         now,
     )?;
 
     Ok(())
 }
 
-#[access_control(
-    withdraw_not_paused(&ctx.accounts.state)
-)]
-pub fn handle_remove_insurance_fund_stake(
-    ctx: Context<RemoveInsuranceFundStake>,
-    market_index: u16,
+fn send_from_insurance_fund_vault<'info>(
+    insurance_fund_vault: &AccountInfo<'info>,
+    user_token_account: &AccountInfo<'info>,
+    drift_signer: &AccountInfo<'info>,
+    token_program: &AccountInfo<'info>,
+    state: &State,
+    amount: u64,
+    mint: &Option<AccountInfo<'info>>,
 ) -> Result<()> {
-    let clock = Clock::get()?;
-    let now = clock.unix_timestamp;
-    let insurance_fund_stake = &mut load_mut!(ctx.accounts.insurance_fund_stake)?;
-    let user_stats = &mut load_mut!(ctx.accounts.user_stats)?;
-    let spot_market = &mut load_mut!(ctx.accounts.spot_market)?;
-    let state = &ctx.accounts.state;
-
-    validate!(
-        insurance_fund_stake.market_index == market_index,
-        ErrorCode::IncorrectSpotMarketAccountPassed,
-        "insurance_fund_stake does not match market_index"
-    )?;
-
-    let amount = controller::insurance::remove_insurance_fund_stake(
-        ctx.accounts.insurance_fund_vault.amount,
-        insurance_fund_stake,
-        user_stats,
-        spot_market,
-        now,
-    )?;
-
     controller::token::send_from_program_vault(
-        &ctx.accounts.token_program,
-        &ctx.accounts.insurance_fund_vault,
-        &ctx.accounts.user_token_account,
-        &ctx.accounts.drift_signer,
+        token_program,
+        insurance_fund_vault,
+        user_token_account,
+        drift_signer,
         state.signer_nonce,
         amount,
+        mint,
     )?;
 
     validate!(
-        ctx.accounts.insurance_fund_vault.amount > 0,
+        load_token_account(insurance_fund_vault, token_program)?.amount > 0,
         ErrorCode::InvalidIFDetected,
         "insurance_fund_vault.amount must remain > 0"
     )?;
 
-    // validate relevant spot market balances before unstake
-    math::spot_withdraw::validate_spot_balances(spot_market)?;
-
     Ok(())
 }
 
@@ -259,6 +905,19 @@ pub struct InitializeInsuranceFundStake<'info> {
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+pub struct ResizeInsuranceFundStake<'info> {
+    #[account(
+        mut,
+        owner = crate::ID
+    )]
+    /// CHECK: stakes created before fields were appended are too small to load, checked in handler
+    pub insurance_fund_stake: AccountInfo<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
 #[derive(Accounts)]
 #[instruction(market_index: u16)]
 pub struct AddInsuranceFundStake<'info> {
@@ -284,26 +943,29 @@ pub struct AddInsuranceFundStake<'info> {
         seeds = [b"spot_market_vault".as_ref(), market_index.to_le_bytes().as_ref()],
         bump,
     )]
-    pub spot_market_vault: Box<Account<'info, TokenAccount>>,
+    /// CHECK: spl token or token-2022 account, checked in handler
+    pub spot_market_vault: AccountInfo<'info>,
     #[account(
         mut,
         seeds = [b"insurance_fund_vault".as_ref(), market_index.to_le_bytes().as_ref()],
         bump,
     )]
-    pub insurance_fund_vault: Box<Account<'info, TokenAccount>>,
+    /// CHECK: spl token or token-2022 account, checked in handler
+    pub insurance_fund_vault: AccountInfo<'info>,
 
     #[account(
         constraint = state.signer.eq(&drift_signer.key())
     )]
     /// CHECK: forced drift_signer
     pub drift_signer: AccountInfo<'info>,
+    #[account(mut)]
+    /// CHECK: spl token or token-2022 account, checked in handler
+    pub user_token_account: AccountInfo<'info>,
     #[account(
-        mut,
-        token::mint = insurance_fund_vault.mint,
-        token::authority = authority
+        constraint = is_token_program(&token_program)?
     )]
-    pub user_token_account: Box<Account<'info, TokenAccount>>,
-    pub token_program: Program<'info, Token>,
+    /// CHECK: spl token or token-2022 program
+    pub token_program: AccountInfo<'info>,
 }
 
 #[derive(Accounts)]
@@ -330,7 +992,8 @@ pub struct RequestRemoveInsuranceFundStake<'info> {
         seeds = [b"insurance_fund_vault".as_ref(), market_index.to_le_bytes().as_ref()],
         bump,
     )]
-    pub insurance_fund_vault: Box<Account<'info, TokenAccount>>,
+    /// CHECK: spl token or token-2022 account, checked in handler
+    pub insurance_fund_vault: AccountInfo<'info>,
 }
 
 #[derive(Accounts)]
@@ -358,17 +1021,203 @@ pub struct RemoveInsuranceFundStake<'info> {
         seeds = [b"insurance_fund_vault".as_ref(), market_index.to_le_bytes().as_ref()],
         bump,
     )]
-    pub insurance_fund_vault: Box<Account<'info, TokenAccount>>,
+    /// CHECK: spl token or token-2022 account, checked in handler
+    pub insurance_fund_vault: AccountInfo<'info>,
+    #[account(
+        constraint = state.signer.eq(&drift_signer.key())
+    )]
+    /// CHECK: forced drift_signer
+    pub drift_signer: AccountInfo<'info>,
+    #[account(mut)]
+    /// CHECK: spl token or token-2022 account, checked in handler
+    pub user_token_account: AccountInfo<'info>,
+    #[account(
+        constraint = is_token_program(&token_program)?
+    )]
+    /// CHECK: spl token or token-2022 program
+    pub token_program: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(market_index: u16,)]
+pub struct ClaimInsuranceFundStakeRewards<'info> {
+    pub state: Box<Account<'info, State>>,
+    #[account(
+        mut,
+        seeds = [b"spot_market", market_index.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub spot_market: AccountLoader<'info, SpotMarket>,
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub insurance_fund_stake: AccountLoader<'info, InsuranceFundStake>,
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"insurance_fund_vault".as_ref(), market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    /// CHECK: spl token or token-2022 account, checked in handler
+    pub insurance_fund_vault: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"insurance_fund_reward_vault".as_ref(), market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    /// CHECK: spl token or token-2022 account, checked in handler
+    pub insurance_fund_reward_vault: AccountInfo<'info>,
     #[account(
         constraint = state.signer.eq(&drift_signer.key())
     )]
     /// CHECK: forced drift_signer
     pub drift_signer: AccountInfo<'info>,
+    #[account(mut)]
+    /// CHECK: spl token or token-2022 account, checked in handler
+    pub user_token_account: AccountInfo<'info>,
+    #[account(
+        constraint = is_token_program(&token_program)?
+    )]
+    /// CHECK: spl token or token-2022 program
+    pub token_program: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(
+    market_index: u16,
+)]
+pub struct InitializePerpMarketInsuranceFundStake<'info> {
+    #[account(
+        seeds = [b"perp_market", market_index.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub perp_market: AccountLoader<'info, PerpMarket>,
+    #[account(
+        init,
+        seeds = [b"perp_market_insurance_fund_stake", authority.key.as_ref(), market_index.to_le_bytes().as_ref()],
+        space = std::mem::size_of::<InsuranceFundStake>() + 8,
+        bump,
+        payer = payer
+    )]
+    pub insurance_fund_stake: AccountLoader<'info, InsuranceFundStake>,
+    #[account(
+        mut,
+        has_one = authority
+    )]
+    pub user_stats: AccountLoader<'info, UserStats>,
+    pub state: Box<Account<'info, State>>,
+    pub authority: Signer<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(market_index: u16)]
+pub struct AddPerpMarketInsuranceFundStake<'info> {
+    pub state: Box<Account<'info, State>>,
+    #[account(
+        mut,
+        seeds = [b"perp_market", market_index.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub perp_market: AccountLoader<'info, PerpMarket>,
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub insurance_fund_stake: AccountLoader<'info, InsuranceFundStake>,
     #[account(
         mut,
-        token::mint = insurance_fund_vault.mint,
-        token::authority = authority
+        has_one = authority,
+    )]
+    pub user_stats: AccountLoader<'info, UserStats>,
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"perp_market_insurance_fund_vault".as_ref(), market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    /// CHECK: spl token or token-2022 account, checked in handler
+    pub insurance_fund_vault: AccountInfo<'info>,
+    #[account(mut)]
+    /// CHECK: spl token or token-2022 account, checked in handler
+    pub user_token_account: AccountInfo<'info>,
+    #[account(
+        constraint = is_token_program(&token_program)?
+    )]
+    /// CHECK: spl token or token-2022 program
+    pub token_program: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(market_index: u16,)]
+pub struct RequestRemovePerpMarketInsuranceFundStake<'info> {
+    #[account(
+        mut,
+        seeds = [b"perp_market", market_index.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub perp_market: AccountLoader<'info, PerpMarket>,
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub insurance_fund_stake: AccountLoader<'info, InsuranceFundStake>,
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub user_stats: AccountLoader<'info, UserStats>,
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"perp_market_insurance_fund_vault".as_ref(), market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    /// CHECK: spl token or token-2022 account, checked in handler
+    pub insurance_fund_vault: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(market_index: u16,)]
+pub struct RemovePerpMarketInsuranceFundStake<'info> {
+    pub state: Box<Account<'info, State>>,
+    #[account(
+        mut,
+        seeds = [b"perp_market", market_index.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub perp_market: AccountLoader<'info, PerpMarket>,
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub insurance_fund_stake: AccountLoader<'info, InsuranceFundStake>,
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub user_stats: AccountLoader<'info, UserStats>,
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"perp_market_insurance_fund_vault".as_ref(), market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    /// CHECK: spl token or token-2022 account, checked in handler
+    pub insurance_fund_vault: AccountInfo<'info>,
+    #[account(
+        constraint = state.signer.eq(&drift_signer.key())
+    )]
+    /// CHECK: forced drift_signer
+    pub drift_signer: AccountInfo<'info>,
+    #[account(mut)]
+    /// CHECK: spl token or token-2022 account, checked in handler
+    pub user_token_account: AccountInfo<'info>,
+    #[account(
+        constraint = is_token_program(&token_program)?
     )]
-    pub user_token_account: Box<Account<'info, TokenAccount>>,
-    pub token_program: Program<'info, Token>,
+    /// CHECK: spl token or token-2022 program
+    pub token_program: AccountInfo<'info>,
 }
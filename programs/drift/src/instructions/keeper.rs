@@ -1,17 +1,24 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{Token, TokenAccount};
+use anchor_lang::solana_program::program::set_return_data;
 
+use crate::controller::token::{load_token_account, load_vault_token_account};
 use crate::error::ErrorCode;
 use crate::instructions::constraints::*;
 use crate::instructions::optional_accounts::{
-    get_maker_and_maker_stats, get_referrer_and_referrer_stats, get_serum_fulfillment_accounts,
-    get_spot_market_vaults, load_maps, AccountMaps,
+    get_maker_and_maker_stats, get_perp_market_insurance_fund_vault,
+    get_referrer_and_referrer_stats, get_serum_fulfillment_accounts, get_spot_market_vaults,
+    get_token_mint_for_vault, get_user_liquidation_history, get_writable_users, load_maps,
+    AccountMaps,
 };
+use crate::instructions::OrderParams;
 use crate::load_mut;
-use crate::math::constants::QUOTE_SPOT_MARKET_INDEX;
+use crate::math::constants::{MAX_USERS_PER_FUNDING_SETTLE, QUOTE_SPOT_MARKET_INDEX};
 use crate::math::insurance::if_shares_to_vault_amount;
 use crate::math::spot_withdraw::validate_spot_market_vault_amount;
+use crate::state::backstop_vault::BackstopVault;
+use crate::state::events::{CurveRecord, CurveUpdateReason};
 use crate::state::insurance_fund_stake::InsuranceFundStake;
+use crate::state::liquidation_history::UserLiquidationHistory;
 use crate::state::oracle_map::OracleMap;
 use crate::state::perp_market::{MarketStatus, PerpMarket};
 use crate::state::perp_market_map::{
@@ -23,9 +30,9 @@ use crate::state::spot_market_map::{
     get_writable_spot_market_set, get_writable_spot_market_set_from_many,
 };
 use crate::state::state::{ExchangeStatus, State};
-use crate::state::user::{MarketType, User, UserStats};
+use crate::state::user::{MarketType, OrderType, User, UserStats};
 use crate::validate;
-use crate::{controller, load, math};
+use crate::{controller, get_then_update_id, load, math};
 
 #[access_control(
     fill_not_paused(&ctx.accounts.state)
@@ -367,7 +374,10 @@ pub fn handle_settle_pnl(ctx: Context<SettlePNL>, market_index: u16) -> Result<(
     }
 
     let spot_market = spot_market_map.get_quote_spot_market()?;
-    validate_spot_market_vault_amount(&spot_market, ctx.accounts.spot_market_vault.amount)?;
+    validate_spot_market_vault_amount(
+        &spot_market,
+        load_vault_token_account(&ctx.accounts.spot_market_vault)?.amount,
+    )?;
 
     Ok(())
 }
@@ -396,6 +406,29 @@ pub fn handle_settle_funding_payment(ctx: Context<SettleFunding>) -> Result<()>
     Ok(())
 }
 
+/// Settles funding for every user passed in remaining accounts so idle accounts
+/// don't carry unsettled funding until they next touch the market.
+#[access_control(
+    funding_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_settle_funding_payments_for_users(
+    ctx: Context<SettleFundingForUsers>,
+    _market_index: u16,
+) -> Result<()> {
+    let clock = Clock::get()?;
+    let now = clock.unix_timestamp;
+
+    let market = &mut load_mut!(ctx.accounts.perp_market)?;
+    let users = get_writable_users(
+        &mut ctx.remaining_accounts.iter().peekable(),
+        MAX_USERS_PER_FUNDING_SETTLE,
+    )?;
+
+    controller::funding::settle_funding_payments_for_users(&users, market, now)?;
+
+    Ok(())
+}
+
 #[access_control(
     amm_not_paused(&ctx.accounts.state)
 )]
@@ -423,6 +456,69 @@ pub fn handle_settle_lp<'info>(ctx: Context<SettleLP>, market_index: u16) -> Res
     Ok(())
 }
 
+#[access_control(
+    fill_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_hedge_lp_position<'info>(
+    ctx: Context<FillOrder>,
+    market_index: u16,
+    maker_order_id: Option<u32>,
+) -> Result<()> {
+    let clock = &Clock::get()?;
+    let state = &ctx.accounts.state;
+
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
+    let AccountMaps {
+        perp_market_map,
+        spot_market_map,
+        mut oracle_map,
+    } = load_maps(
+        remaining_accounts_iter,
+        &get_writable_perp_market_set(market_index),
+        &MarketSet::new(),
+        clock.slot,
+        Some(state.oracle_guard_rails),
+    )?;
+
+    let (maker, maker_stats) = match maker_order_id {
+        Some(_) => {
+            let (user, user_stats) = get_maker_and_maker_stats(remaining_accounts_iter)?;
+            (Some(user), Some(user_stats))
+        }
+        None => (None, None),
+    };
+
+    let (referrer, referrer_stats) = get_referrer_and_referrer_stats(remaining_accounts_iter)?;
+
+    controller::repeg::update_amm(
+        market_index,
+        &perp_market_map,
+        &mut oracle_map,
+        state,
+        clock,
+    )?;
+
+    controller::lp::hedge_lp_position(
+        market_index,
+        state,
+        &ctx.accounts.user,
+        &ctx.accounts.user_stats,
+        &perp_market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        &ctx.accounts.filler,
+        &ctx.accounts.filler_stats,
+        maker.as_ref(),
+        maker_stats.as_ref(),
+        maker_order_id,
+        referrer.as_ref(),
+        referrer_stats.as_ref(),
+        clock,
+    )?;
+
+    Ok(())
+}
+
 #[access_control(
     withdraw_not_paused(&ctx.accounts.state)
 )]
@@ -490,18 +586,26 @@ pub fn handle_liquidate_perp(
     let liquidator = &mut load_mut!(ctx.accounts.liquidator)?;
     let liquidator_stats = &mut load_mut!(ctx.accounts.liquidator_stats)?;
 
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
     let AccountMaps {
         perp_market_map,
         spot_market_map,
         mut oracle_map,
     } = load_maps(
-        &mut ctx.remaining_accounts.iter().peekable(),
+        remaining_accounts_iter,
         &get_writable_perp_market_set(market_index),
         &MarketSet::new(),
         clock.slot,
         Some(state.oracle_guard_rails),
     )?;
 
+    let user_liquidation_history =
+        get_user_liquidation_history(remaining_accounts_iter, &user.authority)?;
+    let mut liquidation_history = match &user_liquidation_history {
+        Some(user_liquidation_history) => Some(load_mut!(user_liquidation_history)?),
+        None => None,
+    };
+
     controller::liquidation::liquidate_perp(
         market_index,
         liquidator_max_base_asset_amount,
@@ -518,6 +622,68 @@ pub fn handle_liquidate_perp(
         slot,
         now,
         state,
+        liquidation_history.as_deref_mut(),
+    )?;
+
+    Ok(())
+}
+
+#[access_control(
+    liq_not_paused(&ctx.accounts.state)
+    amm_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_liquidate_perp_with_amm(
+    ctx: Context<LiquidatePerpWithAmm>,
+    market_index: u16,
+) -> Result<()> {
+    let clock = Clock::get()?;
+    let now = clock.unix_timestamp;
+    let slot = clock.slot;
+    let state = &ctx.accounts.state;
+
+    let user_key = ctx.accounts.user.key();
+    let filler_key = ctx.accounts.filler.key();
+
+    validate!(user_key != filler_key, ErrorCode::UserCantLiquidateThemself)?;
+
+    let user = &mut load_mut!(ctx.accounts.user)?;
+    let user_stats = &mut load_mut!(ctx.accounts.user_stats)?;
+    let filler = &mut load_mut!(ctx.accounts.filler)?;
+
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
+    let AccountMaps {
+        perp_market_map,
+        spot_market_map,
+        mut oracle_map,
+    } = load_maps(
+        remaining_accounts_iter,
+        &get_writable_perp_market_set(market_index),
+        &MarketSet::new(),
+        clock.slot,
+        Some(state.oracle_guard_rails),
+    )?;
+
+    let user_liquidation_history =
+        get_user_liquidation_history(remaining_accounts_iter, &user.authority)?;
+    let mut liquidation_history = match &user_liquidation_history {
+        Some(user_liquidation_history) => Some(load_mut!(user_liquidation_history)?),
+        None => None,
+    };
+
+    controller::liquidation::liquidate_perp_with_amm(
+        market_index,
+        user,
+        &user_key,
+        user_stats,
+        filler,
+        &filler_key,
+        &perp_market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        slot,
+        now,
+        state,
+        liquidation_history.as_deref_mut(),
     )?;
 
     Ok(())
@@ -547,18 +713,26 @@ pub fn handle_liquidate_spot(
     let user = &mut load_mut!(ctx.accounts.user)?;
     let liquidator = &mut load_mut!(ctx.accounts.liquidator)?;
 
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
     let AccountMaps {
         perp_market_map,
         spot_market_map,
         mut oracle_map,
     } = load_maps(
-        &mut ctx.remaining_accounts.iter().peekable(),
+        remaining_accounts_iter,
         &MarketSet::new(),
         &get_writable_spot_market_set_from_many(vec![asset_market_index, liability_market_index]),
         clock.slot,
         Some(state.oracle_guard_rails),
     )?;
 
+    let user_liquidation_history =
+        get_user_liquidation_history(remaining_accounts_iter, &user.authority)?;
+    let mut liquidation_history = match &user_liquidation_history {
+        Some(user_liquidation_history) => Some(load_mut!(user_liquidation_history)?),
+        None => None,
+    };
+
     controller::liquidation::liquidate_spot(
         asset_market_index,
         liability_market_index,
@@ -573,11 +747,267 @@ pub fn handle_liquidate_spot(
         now,
         clock.slot,
         state.liquidation_margin_buffer_ratio,
+        liquidation_history.as_deref_mut(),
+    )?;
+
+    Ok(())
+}
+
+#[access_control(
+    liq_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_liquidate_perp_with_backstop(
+    ctx: Context<LiquidatePerpWithBackstop>,
+    market_index: u16,
+    liquidator_max_base_asset_amount: u64,
+    limit_price: Option<u64>,
+) -> Result<()> {
+    let clock = Clock::get()?;
+    let now = clock.unix_timestamp;
+    let slot = clock.slot;
+    let state = &ctx.accounts.state;
+
+    let user_key = ctx.accounts.user.key();
+    let backstop_user_key = ctx.accounts.backstop_user.key();
+
+    let backstop_vault = load!(ctx.accounts.backstop_vault)?;
+    let user = &mut load_mut!(ctx.accounts.user)?;
+    let user_stats = &mut load_mut!(ctx.accounts.user_stats)?;
+    let backstop_user = &mut load_mut!(ctx.accounts.backstop_user)?;
+    let backstop_user_stats = &mut load_mut!(ctx.accounts.backstop_user_stats)?;
+
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
+    let AccountMaps {
+        perp_market_map,
+        spot_market_map,
+        mut oracle_map,
+    } = load_maps(
+        remaining_accounts_iter,
+        &get_writable_perp_market_set(market_index),
+        &MarketSet::new(),
+        clock.slot,
+        Some(state.oracle_guard_rails),
+    )?;
+
+    let user_liquidation_history =
+        get_user_liquidation_history(remaining_accounts_iter, &user.authority)?;
+    let mut liquidation_history = match &user_liquidation_history {
+        Some(user_liquidation_history) => Some(load_mut!(user_liquidation_history)?),
+        None => None,
+    };
+
+    controller::liquidation::liquidate_perp_with_backstop(
+        market_index,
+        liquidator_max_base_asset_amount,
+        limit_price,
+        user,
+        &user_key,
+        user_stats,
+        &backstop_vault,
+        backstop_user,
+        &backstop_user_key,
+        backstop_user_stats,
+        &perp_market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        slot,
+        now,
+        state,
+        liquidation_history.as_deref_mut(),
     )?;
 
     Ok(())
 }
 
+#[access_control(
+    liq_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_liquidate_spot_with_backstop(
+    ctx: Context<LiquidateSpotWithBackstop>,
+    asset_market_index: u16,
+    liability_market_index: u16,
+    liquidator_max_liability_transfer: u128,
+) -> Result<()> {
+    let clock = Clock::get()?;
+    let now = clock.unix_timestamp;
+    let state = &ctx.accounts.state;
+
+    let user_key = ctx.accounts.user.key();
+    let backstop_user_key = ctx.accounts.backstop_user.key();
+
+    let backstop_vault = load!(ctx.accounts.backstop_vault)?;
+    let user = &mut load_mut!(ctx.accounts.user)?;
+    let backstop_user = &mut load_mut!(ctx.accounts.backstop_user)?;
+
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
+    let AccountMaps {
+        perp_market_map,
+        spot_market_map,
+        mut oracle_map,
+    } = load_maps(
+        remaining_accounts_iter,
+        &MarketSet::new(),
+        &get_writable_spot_market_set_from_many(vec![asset_market_index, liability_market_index]),
+        clock.slot,
+        Some(state.oracle_guard_rails),
+    )?;
+
+    let user_liquidation_history =
+        get_user_liquidation_history(remaining_accounts_iter, &user.authority)?;
+    let mut liquidation_history = match &user_liquidation_history {
+        Some(user_liquidation_history) => Some(load_mut!(user_liquidation_history)?),
+        None => None,
+    };
+
+    controller::liquidation::liquidate_spot_with_backstop(
+        asset_market_index,
+        liability_market_index,
+        liquidator_max_liability_transfer,
+        user,
+        &user_key,
+        &backstop_vault,
+        backstop_user,
+        &backstop_user_key,
+        &perp_market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        now,
+        clock.slot,
+        state.liquidation_margin_buffer_ratio,
+        liquidation_history.as_deref_mut(),
+    )?;
+
+    Ok(())
+}
+
+// positions absorbed by the backstop are closed against the amm so the vault doesnt hold directional risk
+#[access_control(
+    fill_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_unwind_backstop_perp_position(
+    ctx: Context<UnwindBackstopPerpPosition>,
+    market_index: u16,
+) -> Result<()> {
+    let clock = &Clock::get()?;
+    let state = &ctx.accounts.state;
+
+    let AccountMaps {
+        perp_market_map,
+        spot_market_map,
+        mut oracle_map,
+    } = load_maps(
+        &mut ctx.remaining_accounts.iter().peekable(),
+        &get_writable_perp_market_set(market_index),
+        &MarketSet::new(),
+        clock.slot,
+        Some(state.oracle_guard_rails),
+    )?;
+
+    controller::repeg::update_amm(
+        market_index,
+        &perp_market_map,
+        &mut oracle_map,
+        state,
+        clock,
+    )?;
+
+    let params = {
+        let backstop_user_key = ctx.accounts.backstop_user.key();
+        let backstop_user = &mut load_mut!(ctx.accounts.backstop_user)?;
+        let market = &mut perp_market_map.get_ref_mut(&market_index)?;
+
+        controller::funding::settle_funding_payment(
+            backstop_user,
+            &backstop_user_key,
+            market,
+            clock.unix_timestamp,
+        )?;
+
+        let position = backstop_user.get_perp_position(market_index)?;
+        let base_asset_amount = math::orders::standardize_base_asset_amount(
+            position.base_asset_amount.unsigned_abs(),
+            market.amm.order_step_size,
+        )?;
+
+        validate!(
+            base_asset_amount > 0,
+            ErrorCode::BackstopHasNoPositionToUnwind,
+            "backstop user base asset amount {} in market {}",
+            position.base_asset_amount,
+            market_index
+        )?;
+
+        // accept the same slippage from oracle as a default market order auction
+        let direction = position.get_direction_to_close();
+        let (_, limit_price) = math::auction::calculate_auction_prices(
+            oracle_map.get_price_data(&market.amm.oracle)?,
+            direction,
+            0,
+        )?;
+
+        OrderParams {
+            order_type: OrderType::Limit,
+            market_type: MarketType::Perp,
+            direction,
+            base_asset_amount,
+            price: limit_price,
+            market_index,
+            reduce_only: true,
+            immediate_or_cancel: true,
+            ..OrderParams::default()
+        }
+    };
+
+    controller::orders::place_perp_order(
+        state,
+        &ctx.accounts.backstop_user,
+        &perp_market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        clock,
+        params,
+    )?;
+
+    let order_id = load!(ctx.accounts.backstop_user)?.get_last_order_id();
+
+    // no maker, the backstop only unwinds against the amm
+    controller::orders::fill_perp_order(
+        order_id,
+        state,
+        &ctx.accounts.backstop_user,
+        &ctx.accounts.backstop_user_stats,
+        &spot_market_map,
+        &perp_market_map,
+        &mut oracle_map,
+        &ctx.accounts.filler,
+        &ctx.accounts.filler_stats,
+        None,
+        None,
+        None,
+        None,
+        None,
+        clock,
+    )?;
+
+    let order_exists = load!(ctx.accounts.backstop_user)?
+        .orders
+        .iter()
+        .any(|order| order.order_id == order_id);
+
+    if order_exists {
+        controller::orders::cancel_order_by_order_id(
+            order_id,
+            &ctx.accounts.backstop_user,
+            &perp_market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            clock,
+        )?;
+    }
+
+    Ok(())
+}
+
 #[access_control(
     liq_not_paused(&ctx.accounts.state)
 )]
@@ -602,18 +1032,26 @@ pub fn handle_liquidate_borrow_for_perp_pnl(
     let user = &mut load_mut!(ctx.accounts.user)?;
     let liquidator = &mut load_mut!(ctx.accounts.liquidator)?;
 
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
     let AccountMaps {
         perp_market_map,
         spot_market_map,
         mut oracle_map,
     } = load_maps(
-        &mut ctx.remaining_accounts.iter().peekable(),
+        remaining_accounts_iter,
         &MarketSet::new(),
         &get_writable_spot_market_set(spot_market_index),
         clock.slot,
         Some(state.oracle_guard_rails),
     )?;
 
+    let user_liquidation_history =
+        get_user_liquidation_history(remaining_accounts_iter, &user.authority)?;
+    let mut liquidation_history = match &user_liquidation_history {
+        Some(user_liquidation_history) => Some(load_mut!(user_liquidation_history)?),
+        None => None,
+    };
+
     controller::liquidation::liquidate_borrow_for_perp_pnl(
         perp_market_index,
         spot_market_index,
@@ -628,6 +1066,7 @@ pub fn handle_liquidate_borrow_for_perp_pnl(
         now,
         clock.slot,
         state.liquidation_margin_buffer_ratio,
+        liquidation_history.as_deref_mut(),
     )?;
 
     Ok(())
@@ -657,18 +1096,26 @@ pub fn handle_liquidate_perp_pnl_for_deposit(
     let user = &mut load_mut!(ctx.accounts.user)?;
     let liquidator = &mut load_mut!(ctx.accounts.liquidator)?;
 
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
     let AccountMaps {
         perp_market_map,
         spot_market_map,
         mut oracle_map,
     } = load_maps(
-        &mut ctx.remaining_accounts.iter().peekable(),
+        remaining_accounts_iter,
         &MarketSet::new(),
         &get_writable_spot_market_set(spot_market_index),
         clock.slot,
         Some(state.oracle_guard_rails),
     )?;
 
+    let user_liquidation_history =
+        get_user_liquidation_history(remaining_accounts_iter, &user.authority)?;
+    let mut liquidation_history = match &user_liquidation_history {
+        Some(user_liquidation_history) => Some(load_mut!(user_liquidation_history)?),
+        None => None,
+    };
+
     controller::liquidation::liquidate_perp_pnl_for_deposit(
         perp_market_index,
         spot_market_index,
@@ -683,6 +1130,44 @@ pub fn handle_liquidate_perp_pnl_for_deposit(
         now,
         clock.slot,
         state.liquidation_margin_buffer_ratio,
+        liquidation_history.as_deref_mut(),
+    )?;
+
+    Ok(())
+}
+
+fn send_from_insurance_fund_vault<'info>(
+    insurance_fund_vault: &AccountInfo<'info>,
+    spot_market_vault: &AccountInfo<'info>,
+    drift_signer: &AccountInfo<'info>,
+    token_program: &AccountInfo<'info>,
+    state: &State,
+    amount: u64,
+    mint: &Option<AccountInfo<'info>>,
+) -> Result<()> {
+    let vault_amount = load_token_account(insurance_fund_vault, token_program)?.amount;
+    validate!(
+        amount < vault_amount,
+        ErrorCode::InsufficientCollateral,
+        "Insurance Fund balance InsufficientCollateral for payment: !{} < {}",
+        amount,
+        vault_amount
+    )?;
+
+    controller::token::send_between_program_vaults(
+        token_program,
+        insurance_fund_vault,
+        spot_market_vault,
+        drift_signer,
+        state.signer_nonce,
+        amount,
+        mint,
+    )?;
+
+    validate!(
+        load_token_account(insurance_fund_vault, token_program)?.amount > 0,
+        ErrorCode::InvalidIFDetected,
+        "insurance_fund_vault.amount must remain > 0"
     )?;
 
     Ok(())
@@ -702,12 +1187,13 @@ pub fn handle_resolve_perp_pnl_deficit(
     validate!(spot_market_index == 0, ErrorCode::InvalidSpotMarketAccount)?;
     let state = &ctx.accounts.state;
 
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
     let AccountMaps {
         perp_market_map,
         spot_market_map,
         mut oracle_map,
     } = load_maps(
-        &mut ctx.remaining_accounts.iter().peekable(),
+        remaining_accounts_iter,
         &get_writable_perp_market_set(perp_market_index),
         &get_writable_spot_market_set(spot_market_index),
         clock.slot,
@@ -722,6 +1208,15 @@ pub fn handle_resolve_perp_pnl_deficit(
         &clock,
     )?;
 
+    let perp_market_insurance_fund_vault = get_perp_market_insurance_fund_vault(
+        remaining_accounts_iter,
+        &perp_market_map.get_ref(&perp_market_index)?,
+    )?;
+    let mint = get_token_mint_for_vault(
+        remaining_accounts_iter,
+        &load_token_account(&ctx.accounts.spot_market_vault, &ctx.accounts.token_program)?.mint,
+    )?;
+
     {
         let spot_market = &mut spot_market_map.get_ref_mut(&spot_market_index)?;
         controller::insurance::attempt_settle_revenue_to_insurance_fund(
@@ -732,20 +1227,26 @@ pub fn handle_resolve_perp_pnl_deficit(
             &ctx.accounts.token_program,
             &ctx.accounts.drift_signer,
             state,
+            &mint,
         )?;
 
         // reload the spot market vault balance so it's up-to-date
-        ctx.accounts.spot_market_vault.reload()?;
         math::spot_withdraw::validate_spot_market_vault_amount(
             spot_market,
-            ctx.accounts.spot_market_vault.amount,
+            load_token_account(&ctx.accounts.spot_market_vault, &ctx.accounts.token_program)?
+                .amount,
         )?;
     }
 
-    let insurance_vault_amount = ctx.accounts.insurance_fund_vault.amount;
-    let spot_market_vault_amount = ctx.accounts.spot_market_vault.amount;
+    let insurance_vault_amount = load_token_account(
+        &ctx.accounts.insurance_fund_vault,
+        &ctx.accounts.token_program,
+    )?
+    .amount;
+    let spot_market_vault_amount =
+        load_token_account(&ctx.accounts.spot_market_vault, &ctx.accounts.token_program)?.amount;
 
-    let pay_from_insurance = {
+    let (perp_market_pay_from_insurance, pay_from_insurance) = {
         let spot_market = &mut spot_market_map.get_ref_mut(&spot_market_index)?;
         let perp_market = &mut perp_market_map.get_ref_mut(&perp_market_index)?;
 
@@ -771,37 +1272,53 @@ pub fn handle_resolve_perp_pnl_deficit(
 
         controller::orders::validate_market_within_price_band(perp_market, state, true, None)?;
 
-        controller::insurance::resolve_perp_pnl_deficit(
-            spot_market_vault_amount,
-            insurance_vault_amount,
-            spot_market,
-            perp_market,
-            clock.unix_timestamp,
-        )?
+        let perp_market_insurance_vault_amount = match &perp_market_insurance_fund_vault {
+            Some(perp_market_insurance_fund_vault) => Some(
+                load_token_account(
+                    perp_market_insurance_fund_vault,
+                    &ctx.accounts.token_program,
+                )?
+                .amount,
+            ),
+            None => None,
+        };
+
+        let (perp_market_pay_from_insurance, pay_from_insurance) =
+            controller::insurance::resolve_perp_pnl_deficit(
+                spot_market_vault_amount,
+                insurance_vault_amount,
+                perp_market_insurance_vault_amount,
+                spot_market,
+                perp_market,
+                clock.unix_timestamp,
+            )?;
+
+        (perp_market_pay_from_insurance, pay_from_insurance)
     };
 
-    if pay_from_insurance > 0 {
-        validate!(
-            pay_from_insurance < ctx.accounts.insurance_fund_vault.amount,
-            ErrorCode::InsufficientCollateral,
-            "Insurance Fund balance InsufficientCollateral for payment: !{} < {}",
-            pay_from_insurance,
-            ctx.accounts.insurance_fund_vault.amount
-        )?;
+    if let Some(perp_market_insurance_fund_vault) = &perp_market_insurance_fund_vault {
+        if perp_market_pay_from_insurance > 0 {
+            send_from_insurance_fund_vault(
+                perp_market_insurance_fund_vault,
+                &ctx.accounts.spot_market_vault,
+                &ctx.accounts.drift_signer,
+                &ctx.accounts.token_program,
+                state,
+                perp_market_pay_from_insurance,
+                &mint,
+            )?;
+        }
+    }
 
-        controller::token::send_from_program_vault(
-            &ctx.accounts.token_program,
+    if pay_from_insurance > 0 {
+        send_from_insurance_fund_vault(
             &ctx.accounts.insurance_fund_vault,
             &ctx.accounts.spot_market_vault,
             &ctx.accounts.drift_signer,
-            state.signer_nonce,
+            &ctx.accounts.token_program,
+            state,
             pay_from_insurance,
-        )?;
-
-        validate!(
-            ctx.accounts.insurance_fund_vault.amount > 0,
-            ErrorCode::InvalidIFDetected,
-            "insurance_fund_vault.amount must remain > 0"
+            &mint,
         )?;
     }
 
@@ -838,18 +1355,28 @@ pub fn handle_resolve_perp_bankruptcy(
     let liquidator = &mut load_mut!(ctx.accounts.liquidator)?;
     let state = &ctx.accounts.state;
 
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
     let AccountMaps {
         perp_market_map,
         spot_market_map,
         mut oracle_map,
     } = load_maps(
-        &mut ctx.remaining_accounts.iter().peekable(),
+        remaining_accounts_iter,
         &get_writable_perp_market_set(market_index),
         &get_writable_spot_market_set(quote_spot_market_index),
         clock.slot,
         Some(state.oracle_guard_rails),
     )?;
 
+    let perp_market_insurance_fund_vault = get_perp_market_insurance_fund_vault(
+        remaining_accounts_iter,
+        &perp_market_map.get_ref(&market_index)?,
+    )?;
+    let mint = get_token_mint_for_vault(
+        remaining_accounts_iter,
+        &load_token_account(&ctx.accounts.spot_market_vault, &ctx.accounts.token_program)?.mint,
+    )?;
+
     {
         let spot_market = &mut spot_market_map.get_ref_mut(&quote_spot_market_index)?;
         controller::insurance::attempt_settle_revenue_to_insurance_fund(
@@ -860,61 +1387,72 @@ pub fn handle_resolve_perp_bankruptcy(
             &ctx.accounts.token_program,
             &ctx.accounts.drift_signer,
             state,
+            &mint,
         )?;
 
         // reload the spot market vault balance so it's up-to-date
-        ctx.accounts.spot_market_vault.reload()?;
         math::spot_withdraw::validate_spot_market_vault_amount(
             spot_market,
-            ctx.accounts.spot_market_vault.amount,
+            load_token_account(&ctx.accounts.spot_market_vault, &ctx.accounts.token_program)?
+                .amount,
         )?;
     }
 
-    let pay_from_insurance = controller::liquidation::resolve_perp_bankruptcy(
-        market_index,
-        user,
-        &user_key,
-        liquidator,
-        &liquidator_key,
-        &perp_market_map,
-        &spot_market_map,
-        &mut oracle_map,
-        now,
-        ctx.accounts.insurance_fund_vault.amount,
-    )?;
-
-    if pay_from_insurance > 0 {
-        validate!(
-            pay_from_insurance < ctx.accounts.insurance_fund_vault.amount,
-            ErrorCode::InsufficientCollateral,
-            "Insurance Fund balance InsufficientCollateral for payment: !{} < {}",
-            pay_from_insurance,
-            ctx.accounts.insurance_fund_vault.amount
+    let (perp_market_pay_from_insurance, pay_from_insurance) =
+        controller::liquidation::resolve_perp_bankruptcy(
+            market_index,
+            user,
+            &user_key,
+            liquidator,
+            &liquidator_key,
+            &perp_market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            now,
+            load_token_account(
+                &ctx.accounts.insurance_fund_vault,
+                &ctx.accounts.token_program,
+            )?
+            .amount,
+            match &perp_market_insurance_fund_vault {
+                Some(vault) => load_token_account(vault, &ctx.accounts.token_program)?.amount,
+                None => 0,
+            },
         )?;
 
-        controller::token::send_from_program_vault(
-            &ctx.accounts.token_program,
+    if let Some(perp_market_insurance_fund_vault) = &perp_market_insurance_fund_vault {
+        if perp_market_pay_from_insurance > 0 {
+            send_from_insurance_fund_vault(
+                perp_market_insurance_fund_vault,
+                &ctx.accounts.spot_market_vault,
+                &ctx.accounts.drift_signer,
+                &ctx.accounts.token_program,
+                state,
+                perp_market_pay_from_insurance,
+                &mint,
+            )?;
+        }
+    }
+
+    if pay_from_insurance > 0 {
+        send_from_insurance_fund_vault(
             &ctx.accounts.insurance_fund_vault,
             &ctx.accounts.spot_market_vault,
             &ctx.accounts.drift_signer,
-            state.signer_nonce,
+            &ctx.accounts.token_program,
+            state,
             pay_from_insurance,
-        )?;
-
-        validate!(
-            ctx.accounts.insurance_fund_vault.amount > 0,
-            ErrorCode::InvalidIFDetected,
-            "insurance_fund_vault.amount must remain > 0"
+            &mint,
         )?;
     }
 
     {
         let spot_market = &mut spot_market_map.get_ref_mut(&quote_spot_market_index)?;
         // reload the spot market vault balance so it's up-to-date
-        ctx.accounts.spot_market_vault.reload()?;
         math::spot_withdraw::validate_spot_market_vault_amount(
             spot_market,
-            ctx.accounts.spot_market_vault.amount,
+            load_token_account(&ctx.accounts.spot_market_vault, &ctx.accounts.token_program)?
+                .amount,
         )?;
     }
 
@@ -943,17 +1481,22 @@ pub fn handle_resolve_spot_bankruptcy(
     let user = &mut load_mut!(ctx.accounts.user)?;
     let liquidator = &mut load_mut!(ctx.accounts.liquidator)?;
 
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
     let AccountMaps {
         perp_market_map,
         spot_market_map,
         mut oracle_map,
     } = load_maps(
-        &mut ctx.remaining_accounts.iter().peekable(),
+        remaining_accounts_iter,
         &MarketSet::new(),
         &get_writable_spot_market_set(market_index),
         clock.slot,
         Some(state.oracle_guard_rails),
     )?;
+    let mint = get_token_mint_for_vault(
+        remaining_accounts_iter,
+        &load_token_account(&ctx.accounts.spot_market_vault, &ctx.accounts.token_program)?.mint,
+    )?;
 
     {
         let spot_market = &mut spot_market_map.get_ref_mut(&market_index)?;
@@ -965,13 +1508,14 @@ pub fn handle_resolve_spot_bankruptcy(
             &ctx.accounts.token_program,
             &ctx.accounts.drift_signer,
             state,
+            &mint,
         )?;
 
         // reload the spot market vault balance so it's up-to-date
-        ctx.accounts.spot_market_vault.reload()?;
         math::spot_withdraw::validate_spot_market_vault_amount(
             spot_market,
-            ctx.accounts.spot_market_vault.amount,
+            load_token_account(&ctx.accounts.spot_market_vault, &ctx.accounts.token_program)?
+                .amount,
         )?;
     }
 
@@ -985,33 +1529,32 @@ pub fn handle_resolve_spot_bankruptcy(
         &spot_market_map,
         &mut oracle_map,
         now,
-        ctx.accounts.insurance_fund_vault.amount,
+        load_token_account(
+            &ctx.accounts.insurance_fund_vault,
+            &ctx.accounts.token_program,
+        )?
+        .amount,
     )?;
 
     if pay_from_insurance > 0 {
-        controller::token::send_from_program_vault(
-            &ctx.accounts.token_program,
+        send_from_insurance_fund_vault(
             &ctx.accounts.insurance_fund_vault,
             &ctx.accounts.spot_market_vault,
             &ctx.accounts.drift_signer,
-            ctx.accounts.state.signer_nonce,
+            &ctx.accounts.token_program,
+            state,
             pay_from_insurance,
-        )?;
-
-        validate!(
-            ctx.accounts.insurance_fund_vault.amount > 0,
-            ErrorCode::InvalidIFDetected,
-            "insurance_fund_vault.amount must remain > 0"
+            &mint,
         )?;
     }
 
     {
         let spot_market = &mut spot_market_map.get_ref_mut(&market_index)?;
         // reload the spot market vault balance so it's up-to-date
-        ctx.accounts.spot_market_vault.reload()?;
         math::spot_withdraw::validate_spot_market_vault_amount(
             spot_market,
-            ctx.accounts.spot_market_vault.amount,
+            load_token_account(&ctx.accounts.spot_market_vault, &ctx.accounts.token_program)?
+                .amount,
         )?;
     }
 
@@ -1054,55 +1597,155 @@ pub fn handle_update_funding_rate(
         "AMM must be updated in a prior instruction within same slot"
     )?;
 
-    let is_updated = controller::funding::update_funding_rate(
-        perp_market_index,
-        perp_market,
-        &mut oracle_map,
-        now,
-        &state.oracle_guard_rails,
-        matches!(state.exchange_status, ExchangeStatus::FundingPaused),
-        None,
+    let is_updated = controller::funding::update_funding_rate(
+        perp_market_index,
+        perp_market,
+        &mut oracle_map,
+        now,
+        &state.oracle_guard_rails,
+        matches!(state.exchange_status, ExchangeStatus::FundingPaused),
+        None,
+    )?;
+
+    if !is_updated {
+        return Err(ErrorCode::InvalidFundingProfitability.into());
+    }
+
+    Ok(())
+}
+
+pub fn handle_view_predicted_funding_rate(
+    ctx: Context<ViewPerpMarket>,
+    _market_index: u16,
+) -> Result<()> {
+    let perp_market = &load!(ctx.accounts.perp_market)?;
+    let predicted_funding_rate = math::funding::calculate_predicted_funding_rate(perp_market)?;
+
+    msg!(
+        "perp market {} predicted funding rate {}",
+        perp_market.market_index,
+        predicted_funding_rate
+    );
+
+    set_return_data(&predicted_funding_rate.to_le_bytes());
+
+    Ok(())
+}
+
+#[access_control(
+    withdraw_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_settle_revenue_to_insurance_fund(
+    ctx: Context<SettleRevenueToInsuranceFund>,
+    spot_market_index: u16,
+) -> Result<()> {
+    let state = &ctx.accounts.state;
+    let spot_market = &mut load_mut!(ctx.accounts.spot_market)?;
+
+    validate!(
+        spot_market_index == spot_market.market_index,
+        ErrorCode::InvalidSpotMarketAccount,
+        "invalid spot_market passed"
+    )?;
+
+    validate!(
+        spot_market.insurance_fund.revenue_settle_period > 0,
+        ErrorCode::RevenueSettingsCannotSettleToIF,
+        "invalid revenue_settle_period settings on spot market"
+    )?;
+
+    let token_program = &ctx.accounts.token_program;
+    let spot_market_vault = load_token_account(&ctx.accounts.spot_market_vault, token_program)?;
+    let spot_vault_amount = spot_market_vault.amount;
+    let insurance_vault_amount =
+        load_token_account(&ctx.accounts.insurance_fund_vault, token_program)?.amount;
+    let mint = get_token_mint_for_vault(
+        &mut ctx.remaining_accounts.iter().peekable(),
+        &spot_market_vault.mint,
+    )?;
+
+    let clock = Clock::get()?;
+    let now = clock.unix_timestamp;
+
+    let time_until_next_update = math::helpers::on_the_hour_update(
+        now,
+        spot_market.insurance_fund.last_revenue_settle_ts,
+        spot_market.insurance_fund.revenue_settle_period,
+    )?;
+
+    validate!(
+        time_until_next_update == 0,
+        ErrorCode::RevenueSettingsCannotSettleToIF,
+        "Must wait {} seconds until next available settlement time",
+        time_until_next_update
+    )?;
+
+    // uses proportion of revenue pool allocated to insurance fund
+    let token_amount = controller::insurance::settle_revenue_to_insurance_fund(
+        spot_vault_amount,
+        insurance_vault_amount,
+        spot_market,
+        now,
+    )?;
+
+    spot_market.insurance_fund.last_revenue_settle_ts = now;
+
+    controller::token::send_from_program_vault(
+        token_program,
+        &ctx.accounts.spot_market_vault,
+        &ctx.accounts.insurance_fund_vault,
+        &ctx.accounts.drift_signer,
+        state.signer_nonce,
+        token_amount as u64,
+        &mint,
+    )?;
+
+    // reload the spot market vault balance so it's up-to-date
+    math::spot_withdraw::validate_spot_market_vault_amount(
+        spot_market,
+        load_token_account(&ctx.accounts.spot_market_vault, token_program)?.amount,
     )?;
 
-    if !is_updated {
-        return Err(ErrorCode::InvalidFundingProfitability.into());
-    }
-
     Ok(())
 }
 
-#[access_control(
-    withdraw_not_paused(&ctx.accounts.state)
-)]
-pub fn handle_settle_revenue_to_insurance_fund(
-    ctx: Context<SettleRevenueToInsuranceFund>,
-    spot_market_index: u16,
+pub fn handle_settle_revenue_to_perp_market_insurance_fund(
+    ctx: Context<SettleRevenueToPerpMarketInsuranceFund>,
+    perp_market_index: u16,
 ) -> Result<()> {
     let state = &ctx.accounts.state;
     let spot_market = &mut load_mut!(ctx.accounts.spot_market)?;
+    let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
 
     validate!(
-        spot_market_index == spot_market.market_index,
-        ErrorCode::InvalidSpotMarketAccount,
-        "invalid spot_market passed"
+        perp_market_index == perp_market.market_index,
+        ErrorCode::InvalidMarketAccount,
+        "invalid perp_market passed"
     )?;
 
     validate!(
-        spot_market.insurance_fund.revenue_settle_period > 0,
+        perp_market.insurance_fund.revenue_settle_period > 0,
         ErrorCode::RevenueSettingsCannotSettleToIF,
-        "invalid revenue_settle_period settings on spot market"
+        "invalid revenue_settle_period settings on perp market"
     )?;
 
-    let spot_vault_amount = ctx.accounts.spot_market_vault.amount;
-    let insurance_vault_amount = ctx.accounts.insurance_fund_vault.amount;
+    let token_program = &ctx.accounts.token_program;
+    let spot_market_vault = load_token_account(&ctx.accounts.spot_market_vault, token_program)?;
+    let spot_vault_amount = spot_market_vault.amount;
+    let insurance_vault_amount =
+        load_token_account(&ctx.accounts.insurance_fund_vault, token_program)?.amount;
+    let mint = get_token_mint_for_vault(
+        &mut ctx.remaining_accounts.iter().peekable(),
+        &spot_market_vault.mint,
+    )?;
 
     let clock = Clock::get()?;
     let now = clock.unix_timestamp;
 
     let time_until_next_update = math::helpers::on_the_hour_update(
         now,
-        spot_market.insurance_fund.last_revenue_settle_ts,
-        spot_market.insurance_fund.revenue_settle_period,
+        perp_market.insurance_fund.last_revenue_settle_ts,
+        perp_market.insurance_fund.revenue_settle_period,
     )?;
 
     validate!(
@@ -1112,30 +1755,28 @@ pub fn handle_settle_revenue_to_insurance_fund(
         time_until_next_update
     )?;
 
-    // uses proportion of revenue pool allocated to insurance fund
-    let token_amount = controller::insurance::settle_revenue_to_insurance_fund(
+    let token_amount = controller::insurance::settle_revenue_to_perp_market_insurance_fund(
         spot_vault_amount,
         insurance_vault_amount,
         spot_market,
+        perp_market,
         now,
     )?;
 
-    spot_market.insurance_fund.last_revenue_settle_ts = now;
-
     controller::token::send_from_program_vault(
-        &ctx.accounts.token_program,
+        token_program,
         &ctx.accounts.spot_market_vault,
         &ctx.accounts.insurance_fund_vault,
         &ctx.accounts.drift_signer,
         state.signer_nonce,
-        token_amount as u64,
+        token_amount,
+        &mint,
     )?;
 
     // reload the spot market vault balance so it's up-to-date
-    ctx.accounts.spot_market_vault.reload()?;
     math::spot_withdraw::validate_spot_market_vault_amount(
         spot_market,
-        ctx.accounts.spot_market_vault.amount,
+        load_token_account(&ctx.accounts.spot_market_vault, token_program)?.amount,
     )?;
 
     Ok(())
@@ -1202,12 +1843,83 @@ pub fn handle_update_amms(ctx: Context<UpdateAMM>, market_indexes: [u16; 5]) ->
     Ok(())
 }
 
+#[access_control(
+    amm_not_paused(&ctx.accounts.state)
+    valid_oracle_for_perp_market(&ctx.accounts.oracle, &ctx.accounts.perp_market)
+)]
+pub fn handle_repeg(ctx: Context<Repeg>, _market_index: u16) -> Result<()> {
+    let clock = Clock::get()?;
+    let state = &ctx.accounts.state;
+    let keeper = &mut load_mut!(ctx.accounts.keeper)?;
+    let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
+
+    validate!(
+        matches!(perp_market.status, MarketStatus::Active),
+        ErrorCode::MarketActionPaused,
+        "Market repeg is paused",
+    )?;
+
+    let mut oracle_map = OracleMap::load_one(
+        &ctx.accounts.oracle,
+        clock.slot,
+        Some(state.oracle_guard_rails),
+    )?;
+    let oracle_price_data = oracle_map.get_price_data(&perp_market.amm.oracle)?;
+
+    let peg_multiplier_before = perp_market.amm.peg_multiplier;
+    let base_asset_reserve_before = perp_market.amm.base_asset_reserve;
+    let quote_asset_reserve_before = perp_market.amm.quote_asset_reserve;
+    let sqrt_k_before = perp_market.amm.sqrt_k;
+
+    let (adjustment_cost, keeper_reward) = controller::repeg::keeper_repeg(
+        perp_market,
+        keeper,
+        oracle_price_data,
+        state,
+        state.perp_fee_structure.flat_filler_fee,
+        clock.slot,
+    )?;
+
+    msg!(
+        "perp market {} repegged for cost {}, keeper reward {}",
+        perp_market.market_index,
+        adjustment_cost,
+        keeper_reward
+    );
+
+    emit!(CurveRecord {
+        ts: clock.unix_timestamp,
+        record_id: get_then_update_id!(perp_market, next_curve_record_id),
+        market_index: perp_market.market_index,
+        peg_multiplier_before,
+        base_asset_reserve_before,
+        quote_asset_reserve_before,
+        sqrt_k_before,
+        peg_multiplier_after: perp_market.amm.peg_multiplier,
+        base_asset_reserve_after: perp_market.amm.base_asset_reserve,
+        quote_asset_reserve_after: perp_market.amm.quote_asset_reserve,
+        sqrt_k_after: perp_market.amm.sqrt_k,
+        base_asset_amount_long: perp_market.amm.base_asset_amount_long.unsigned_abs(),
+        base_asset_amount_short: perp_market.amm.base_asset_amount_short.unsigned_abs(),
+        base_asset_amount_with_amm: perp_market.amm.base_asset_amount_with_amm,
+        number_of_users: perp_market.number_of_users,
+        total_fee: perp_market.amm.total_fee,
+        total_fee_minus_distributions: perp_market.amm.total_fee_minus_distributions,
+        adjustment_cost,
+        oracle_price: oracle_price_data.price,
+        fill_record: perp_market.next_fill_record_id as u128,
+        reason: CurveUpdateReason::KeeperRepeg,
+    });
+
+    Ok(())
+}
+
 pub fn handle_update_user_quote_asset_insurance_stake(
     ctx: Context<UpdateUserQuoteAssetInsuranceStake>,
 ) -> Result<()> {
     let insurance_fund_stake = &mut load_mut!(ctx.accounts.insurance_fund_stake)?;
     let user_stats = &mut load_mut!(ctx.accounts.user_stats)?;
-    let quote_spot_market = &mut load_mut!(ctx.accounts.spot_market)?;
+    let quote_spot_market = &mut *load_mut!(ctx.accounts.spot_market)?;
 
     validate!(
         insurance_fund_stake.market_index == 0,
@@ -1218,12 +1930,48 @@ pub fn handle_update_user_quote_asset_insurance_stake(
     user_stats.if_staked_quote_asset_amount = if_shares_to_vault_amount(
         insurance_fund_stake.checked_if_shares(quote_spot_market)?,
         quote_spot_market.insurance_fund.total_shares,
-        ctx.accounts.insurance_fund_vault.amount,
+        load_vault_token_account(&ctx.accounts.insurance_fund_vault)?.amount,
+    )?;
+
+    Ok(())
+}
+
+pub fn handle_update_perp_market_insurance_coverage(
+    ctx: Context<UpdatePerpMarketInsuranceCoverage>,
+    market_index: u16,
+) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
+
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
+    let perp_market_insurance_fund_vault =
+        get_perp_market_insurance_fund_vault(remaining_accounts_iter, perp_market)?;
+
+    controller::insurance::update_perp_market_insurance_coverage(
+        perp_market,
+        load_vault_token_account(&ctx.accounts.insurance_fund_vault)?.amount,
+        match &perp_market_insurance_fund_vault {
+            Some(vault) => load_vault_token_account(vault)?.amount,
+            None => 0,
+        },
+        now,
     )?;
 
     Ok(())
 }
 
+pub fn handle_update_user_liquidation_margin_ratio(
+    ctx: Context<UpdateUserLiquidationMarginRatio>,
+) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let user = &mut load_mut!(ctx.accounts.user)?;
+    let liquidation_history = load!(ctx.accounts.user_liquidation_history)?;
+
+    user.liquidation_margin_ratio = liquidation_history.get_liquidation_margin_ratio(now)?;
+
+    Ok(())
+}
+
 #[derive(Accounts)]
 pub struct FillOrder<'info> {
     pub state: Box<Account<'info, State>>,
@@ -1270,7 +2018,8 @@ pub struct SettlePNL<'info> {
         seeds = [b"spot_market_vault".as_ref(), 0_u16.to_le_bytes().as_ref()],
         bump
     )]
-    pub spot_market_vault: Box<Account<'info, TokenAccount>>,
+    /// CHECK: spl token or token-2022 account, checked in handler
+    pub spot_market_vault: AccountInfo<'info>,
 }
 
 #[derive(Accounts)]
@@ -1280,6 +2029,18 @@ pub struct SettleFunding<'info> {
     pub user: AccountLoader<'info, User>,
 }
 
+#[derive(Accounts)]
+#[instruction(market_index: u16,)]
+pub struct SettleFundingForUsers<'info> {
+    pub state: Box<Account<'info, State>>,
+    #[account(
+        mut,
+        seeds = [b"perp_market", market_index.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub perp_market: AccountLoader<'info, PerpMarket>,
+}
+
 #[derive(Accounts)]
 pub struct SettleLP<'info> {
     pub state: Box<Account<'info, State>>,
@@ -1310,6 +2071,101 @@ pub struct LiquidatePerp<'info> {
     pub user_stats: AccountLoader<'info, UserStats>,
 }
 
+#[derive(Accounts)]
+pub struct LiquidatePerpWithAmm<'info> {
+    pub state: Box<Account<'info, State>>,
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        constraint = can_sign_for_user(&filler, &authority)?
+    )]
+    pub filler: AccountLoader<'info, User>,
+    #[account(mut)]
+    pub user: AccountLoader<'info, User>,
+    #[account(
+        mut,
+        constraint = is_stats_for_user(&user, &user_stats)?
+    )]
+    pub user_stats: AccountLoader<'info, UserStats>,
+}
+
+#[derive(Accounts)]
+pub struct LiquidatePerpWithBackstop<'info> {
+    pub state: Box<Account<'info, State>>,
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"backstop_vault".as_ref()],
+        bump
+    )]
+    pub backstop_vault: AccountLoader<'info, BackstopVault>,
+    #[account(
+        mut,
+        constraint = is_backstop_user(&backstop_vault, &backstop_user)?
+    )]
+    pub backstop_user: AccountLoader<'info, User>,
+    #[account(
+        mut,
+        constraint = is_stats_for_user(&backstop_user, &backstop_user_stats)?
+    )]
+    pub backstop_user_stats: AccountLoader<'info, UserStats>,
+    #[account(mut)]
+    pub user: AccountLoader<'info, User>,
+    #[account(
+        mut,
+        constraint = is_stats_for_user(&user, &user_stats)?
+    )]
+    pub user_stats: AccountLoader<'info, UserStats>,
+}
+
+#[derive(Accounts)]
+pub struct LiquidateSpotWithBackstop<'info> {
+    pub state: Box<Account<'info, State>>,
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"backstop_vault".as_ref()],
+        bump
+    )]
+    pub backstop_vault: AccountLoader<'info, BackstopVault>,
+    #[account(
+        mut,
+        constraint = is_backstop_user(&backstop_vault, &backstop_user)?
+    )]
+    pub backstop_user: AccountLoader<'info, User>,
+    #[account(mut)]
+    pub user: AccountLoader<'info, User>,
+}
+
+#[derive(Accounts)]
+pub struct UnwindBackstopPerpPosition<'info> {
+    pub state: Box<Account<'info, State>>,
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        constraint = can_sign_for_user(&filler, &authority)?
+    )]
+    pub filler: AccountLoader<'info, User>,
+    #[account(
+        mut,
+        constraint = is_stats_for_user(&filler, &filler_stats)?
+    )]
+    pub filler_stats: AccountLoader<'info, UserStats>,
+    #[account(
+        seeds = [b"backstop_vault".as_ref()],
+        bump
+    )]
+    pub backstop_vault: AccountLoader<'info, BackstopVault>,
+    #[account(
+        mut,
+        constraint = is_backstop_user(&backstop_vault, &backstop_user)?
+    )]
+    pub backstop_user: AccountLoader<'info, User>,
+    #[account(
+        mut,
+        constraint = is_stats_for_user(&backstop_user, &backstop_user_stats)?
+    )]
+    pub backstop_user_stats: AccountLoader<'info, UserStats>,
+}
+
 #[derive(Accounts)]
 pub struct LiquidateSpot<'info> {
     pub state: Box<Account<'info, State>>,
@@ -1406,19 +2262,25 @@ pub struct ResolveBankruptcy<'info> {
         seeds = [b"spot_market_vault".as_ref(), spot_market_index.to_le_bytes().as_ref()],
         bump,
     )]
-    pub spot_market_vault: Box<Account<'info, TokenAccount>>,
+    /// CHECK: spl token or token-2022 account, checked in handler
+    pub spot_market_vault: AccountInfo<'info>,
     #[account(
         mut,
         seeds = [b"insurance_fund_vault".as_ref(), spot_market_index.to_le_bytes().as_ref()], // todo: market_index=0 hardcode for perps?
         bump,
     )]
-    pub insurance_fund_vault: Box<Account<'info, TokenAccount>>,
+    /// CHECK: spl token or token-2022 account, checked in handler
+    pub insurance_fund_vault: AccountInfo<'info>,
     #[account(
         constraint = state.signer.eq(&drift_signer.key())
     )]
     /// CHECK: forced drift_signer
     pub drift_signer: AccountInfo<'info>,
-    pub token_program: Program<'info, Token>,
+    #[account(
+        constraint = is_token_program(&token_program)?
+    )]
+    /// CHECK: spl token or token-2022 program
+    pub token_program: AccountInfo<'info>,
 }
 
 #[derive(Accounts)]
@@ -1431,19 +2293,25 @@ pub struct ResolvePerpPnlDeficit<'info> {
         seeds = [b"spot_market_vault".as_ref(), spot_market_index.to_le_bytes().as_ref()],
         bump,
     )]
-    pub spot_market_vault: Box<Account<'info, TokenAccount>>,
+    /// CHECK: spl token or token-2022 account, checked in handler
+    pub spot_market_vault: AccountInfo<'info>,
     #[account(
         mut,
         seeds = [b"insurance_fund_vault".as_ref(), spot_market_index.to_le_bytes().as_ref()], // todo: market_index=0 hardcode for perps?
         bump,
     )]
-    pub insurance_fund_vault: Box<Account<'info, TokenAccount>>,
+    /// CHECK: spl token or token-2022 account, checked in handler
+    pub insurance_fund_vault: AccountInfo<'info>,
     #[account(
         constraint = state.signer.eq(&drift_signer.key())
     )]
     /// CHECK: forced drift_signer
     pub drift_signer: AccountInfo<'info>,
-    pub token_program: Program<'info, Token>,
+    #[account(
+        constraint = is_token_program(&token_program)?
+    )]
+    /// CHECK: spl token or token-2022 program
+    pub token_program: AccountInfo<'info>,
 }
 
 #[derive(Accounts)]
@@ -1460,7 +2328,8 @@ pub struct SettleRevenueToInsuranceFund<'info> {
         seeds = [b"spot_market_vault".as_ref(), market_index.to_le_bytes().as_ref()],
         bump,
     )]
-    pub spot_market_vault: Box<Account<'info, TokenAccount>>,
+    /// CHECK: spl token or token-2022 account, checked in handler
+    pub spot_market_vault: AccountInfo<'info>,
     #[account(
         constraint = state.signer.eq(&drift_signer.key())
     )]
@@ -1471,8 +2340,55 @@ pub struct SettleRevenueToInsuranceFund<'info> {
         seeds = [b"insurance_fund_vault".as_ref(), market_index.to_le_bytes().as_ref()],
         bump,
     )]
-    pub insurance_fund_vault: Box<Account<'info, TokenAccount>>,
-    pub token_program: Program<'info, Token>,
+    /// CHECK: spl token or token-2022 account, checked in handler
+    pub insurance_fund_vault: AccountInfo<'info>,
+    #[account(
+        constraint = is_token_program(&token_program)?
+    )]
+    /// CHECK: spl token or token-2022 program
+    pub token_program: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(perp_market_index: u16,)]
+pub struct SettleRevenueToPerpMarketInsuranceFund<'info> {
+    pub state: Box<Account<'info, State>>,
+    #[account(
+        mut,
+        seeds = [b"spot_market", 0_u16.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub spot_market: AccountLoader<'info, SpotMarket>,
+    #[account(
+        mut,
+        seeds = [b"perp_market", perp_market_index.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub perp_market: AccountLoader<'info, PerpMarket>,
+    #[account(
+        mut,
+        seeds = [b"spot_market_vault".as_ref(), 0_u16.to_le_bytes().as_ref()],
+        bump,
+    )]
+    /// CHECK: spl token or token-2022 account, checked in handler
+    pub spot_market_vault: AccountInfo<'info>,
+    #[account(
+        constraint = state.signer.eq(&drift_signer.key())
+    )]
+    /// CHECK: forced drift_signer
+    pub drift_signer: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"perp_market_insurance_fund_vault".as_ref(), perp_market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    /// CHECK: spl token or token-2022 account, checked in handler
+    pub insurance_fund_vault: AccountInfo<'info>,
+    #[account(
+        constraint = is_token_program(&token_program)?
+    )]
+    /// CHECK: spl token or token-2022 program
+    pub token_program: AccountInfo<'info>,
 }
 
 #[derive(Accounts)]
@@ -1490,6 +2406,37 @@ pub struct UpdateAMM<'info> {
     pub authority: Signer<'info>,
 }
 
+#[derive(Accounts)]
+#[instruction(market_index: u16,)]
+pub struct Repeg<'info> {
+    pub state: Box<Account<'info, State>>,
+    #[account(
+        mut,
+        seeds = [b"perp_market", market_index.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub perp_market: AccountLoader<'info, PerpMarket>,
+    /// CHECK: checked in `repeg` ix constraint
+    pub oracle: AccountInfo<'info>,
+    #[account(
+        mut,
+        constraint = can_sign_for_user(&keeper, &authority)?
+    )]
+    pub keeper: AccountLoader<'info, User>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(market_index: u16,)]
+pub struct ViewPerpMarket<'info> {
+    pub state: Box<Account<'info, State>>,
+    #[account(
+        seeds = [b"perp_market", market_index.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub perp_market: AccountLoader<'info, PerpMarket>,
+}
+
 #[derive(Accounts)]
 pub struct UpdateFundingRate<'info> {
     pub state: Box<Account<'info, State>>,
@@ -1499,6 +2446,34 @@ pub struct UpdateFundingRate<'info> {
     pub oracle: AccountInfo<'info>,
 }
 
+#[derive(Accounts)]
+#[instruction(market_index: u16,)]
+pub struct UpdatePerpMarketInsuranceCoverage<'info> {
+    pub state: Box<Account<'info, State>>,
+    #[account(
+        mut,
+        seeds = [b"perp_market", market_index.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub perp_market: AccountLoader<'info, PerpMarket>,
+    #[account(
+        seeds = [b"insurance_fund_vault".as_ref(), 0_u16.to_le_bytes().as_ref()],
+        bump,
+    )]
+    /// CHECK: spl token or token-2022 account, checked in handler
+    pub insurance_fund_vault: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateUserLiquidationMarginRatio<'info> {
+    #[account(mut)]
+    pub user: AccountLoader<'info, User>,
+    #[account(
+        constraint = is_liquidation_history_for_user(&user, &user_liquidation_history)?
+    )]
+    pub user_liquidation_history: AccountLoader<'info, UserLiquidationHistory>,
+}
+
 #[derive(Accounts)]
 pub struct UpdateUserQuoteAssetInsuranceStake<'info> {
     pub state: Box<Account<'info, State>>,
@@ -1523,5 +2498,6 @@ pub struct UpdateUserQuoteAssetInsuranceStake<'info> {
         seeds = [b"insurance_fund_vault".as_ref(), 0_u16.to_le_bytes().as_ref()],
         bump,
     )]
-    pub insurance_fund_vault: Box<Account<'info, TokenAccount>>,
+    /// CHECK: spl token or token-2022 account, checked in handler
+    pub insurance_fund_vault: AccountInfo<'info>,
 }
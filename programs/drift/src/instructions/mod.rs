@@ -1,10 +1,12 @@
 pub use admin::*;
+pub use backstop_vault::*;
 pub use constraints::*;
 pub use if_staker::*;
 pub use keeper::*;
 pub use user::*;
 
 mod admin;
+mod backstop_vault;
 mod constraints;
 mod if_staker;
 mod keeper;
@@ -1,8 +1,11 @@
 use crate::controller::serum::SerumFulfillmentParams;
 use crate::error::{DriftResult, ErrorCode};
+use crate::ids::token_2022_program;
 use crate::load;
 
+use crate::state::liquidation_history::UserLiquidationHistory;
 use crate::state::oracle_map::OracleMap;
+use crate::state::perp_market::PerpMarket;
 use crate::state::perp_market_map::{MarketSet, PerpMarketMap};
 use crate::state::spot_market::{SerumV3FulfillmentConfig, SpotMarket};
 use crate::state::spot_market_map::SpotMarketMap;
@@ -11,7 +14,7 @@ use crate::state::user::{User, UserStats};
 use crate::validate;
 use anchor_lang::accounts::account::Account;
 use anchor_lang::prelude::AccountLoader;
-use anchor_lang::prelude::{AccountInfo, Program};
+use anchor_lang::prelude::{AccountInfo, Program, Pubkey};
 use anchor_lang::Discriminator;
 use anchor_spl::token::{Token, TokenAccount};
 use arrayref::array_ref;
@@ -73,6 +76,35 @@ pub fn get_maker_and_maker_stats<'a>(
     Ok((maker, maker_stats))
 }
 
+pub fn get_writable_users<'a>(
+    account_info_iter: &mut Peekable<Iter<AccountInfo<'a>>>,
+    max_users: usize,
+) -> DriftResult<Vec<AccountLoader<'a, User>>> {
+    let mut users = vec![];
+    for user_account_info in account_info_iter {
+        validate!(
+            users.len() < max_users,
+            ErrorCode::TooManyUserAccounts,
+            "can't pass more than {} users",
+            max_users
+        )?;
+
+        validate!(
+            user_account_info.is_writable,
+            ErrorCode::UserMustBeWritable,
+            "user {} must be writable",
+            user_account_info.key
+        )?;
+
+        let user: AccountLoader<User> = AccountLoader::try_from(user_account_info)
+            .or(Err(ErrorCode::CouldNotDeserializeUser))?;
+
+        users.push(user);
+    }
+
+    Ok(users)
+}
+
 #[allow(clippy::type_complexity)]
 pub fn get_referrer_and_referrer_stats<'a>(
     account_info_iter: &mut Peekable<Iter<AccountInfo<'a>>>,
@@ -146,6 +178,58 @@ pub fn get_referrer_and_referrer_stats<'a>(
     Ok((Some(referrer), Some(referrer_stats)))
 }
 
+// optional, passed after the markets so users without a liquidation history can still be liquidated
+pub fn get_user_liquidation_history<'a>(
+    account_info_iter: &mut Peekable<Iter<AccountInfo<'a>>>,
+    user_authority: &Pubkey,
+) -> DriftResult<Option<AccountLoader<'a, UserLiquidationHistory>>> {
+    let liquidation_history_account_info = account_info_iter.peek();
+    if liquidation_history_account_info.is_none() {
+        return Ok(None);
+    }
+
+    let liquidation_history_account_info = liquidation_history_account_info.unwrap();
+    let data = liquidation_history_account_info
+        .try_borrow_data()
+        .map_err(|e| {
+            msg!("{:?}", e);
+            ErrorCode::InvalidUserLiquidationHistory
+        })?;
+
+    if data.len() < std::mem::size_of::<UserLiquidationHistory>() + 8 {
+        return Ok(None);
+    }
+
+    let liquidation_history_discriminator: [u8; 8] = UserLiquidationHistory::discriminator();
+    let account_discriminator = array_ref![data, 0, 8];
+    if account_discriminator != &liquidation_history_discriminator {
+        return Ok(None);
+    }
+
+    let liquidation_history_account_info = next_account_info(account_info_iter).unwrap();
+
+    validate!(
+        liquidation_history_account_info.is_writable,
+        ErrorCode::InvalidUserLiquidationHistory,
+        "user liquidation history must be writable"
+    )?;
+
+    let liquidation_history: AccountLoader<UserLiquidationHistory> =
+        AccountLoader::try_from(liquidation_history_account_info)
+            .or(Err(ErrorCode::InvalidUserLiquidationHistory))?;
+
+    let authority = load!(liquidation_history)?.authority;
+    validate!(
+        authority == *user_authority,
+        ErrorCode::InvalidUserLiquidationHistory,
+        "user liquidation history authority {} does not match user authority {}",
+        authority,
+        user_authority
+    )?;
+
+    Ok(Some(liquidation_history))
+}
+
 #[allow(clippy::type_complexity)]
 pub fn get_serum_fulfillment_accounts<'a, 'b, 'c>(
     account_info_iter: &'a mut std::iter::Peekable<std::slice::Iter<'b, AccountInfo<'c>>>,
@@ -283,6 +367,34 @@ pub fn get_spot_market_vaults<'a, 'b, 'c>(
     Ok((base_market_vault, quote_market_vault))
 }
 
+pub fn get_perp_market_insurance_fund_vault<'a>(
+    account_info_iter: &mut Peekable<Iter<AccountInfo<'a>>>,
+    perp_market: &PerpMarket,
+) -> DriftResult<Option<AccountInfo<'a>>> {
+    if !perp_market.has_insurance_fund() {
+        return Ok(None);
+    }
+
+    let vault_account_info = account_info_iter.next().ok_or_else(|| {
+        msg!(
+            "Could not find insurance fund vault for perp market {}",
+            perp_market.market_index
+        );
+        ErrorCode::InvalidPerpMarketInsuranceFundVault
+    })?;
+
+    validate!(
+        vault_account_info.key == &perp_market.insurance_fund.vault,
+        ErrorCode::InvalidPerpMarketInsuranceFundVault,
+        "insurance fund vault {} does not match perp market {} vault {}",
+        vault_account_info.key,
+        perp_market.market_index,
+        perp_market.insurance_fund.vault
+    )?;
+
+    Ok(Some(vault_account_info.clone()))
+}
+
 pub fn get_whitelist_token<'a>(
     account_info_iter: &mut Peekable<Iter<AccountInfo<'a>>>,
 ) -> DriftResult<Account<'a, TokenAccount>> {
@@ -302,3 +414,90 @@ pub fn get_whitelist_token<'a>(
 
     Ok(whitelist_token)
 }
+
+pub fn get_token_mint<'a>(
+    account_info_iter: &mut Peekable<Iter<AccountInfo<'a>>>,
+) -> DriftResult<Option<AccountInfo<'a>>> {
+    let mint_account_info = account_info_iter.peek();
+    if mint_account_info.is_none() {
+        return Ok(None);
+    }
+
+    let mint_account_info = account_info_iter.next().unwrap();
+
+    validate!(
+        mint_account_info.owner == &anchor_spl::token::ID
+            || mint_account_info.owner == &token_2022_program::id(),
+        ErrorCode::InvalidTokenMint,
+        "mint {} not owned by a token program",
+        mint_account_info.key
+    )?;
+
+    Ok(Some(mint_account_info.clone()))
+}
+
+pub fn get_token_mint_for_vault<'a>(
+    account_info_iter: &mut Peekable<Iter<AccountInfo<'a>>>,
+    vault_mint: &Pubkey,
+) -> DriftResult<Option<AccountInfo<'a>>> {
+    let mint = get_token_mint(account_info_iter)?;
+
+    if let Some(mint) = &mint {
+        validate!(
+            mint.key == vault_mint,
+            ErrorCode::InvalidTokenMint,
+            "mint {} does not match vault mint {}",
+            mint.key,
+            vault_mint
+        )?;
+    }
+
+    Ok(mint)
+}
+
+// optional, passed after the vault mint to mint/burn stake as insurance fund shares tokens
+pub fn get_insurance_fund_shares_accounts<'a>(
+    account_info_iter: &mut Peekable<Iter<AccountInfo<'a>>>,
+    shares_mint: &Pubkey,
+) -> DriftResult<Option<(AccountInfo<'a>, AccountInfo<'a>)>> {
+    let shares_mint_account_info = match account_info_iter.next() {
+        Some(shares_mint_account_info) => shares_mint_account_info,
+        None => return Ok(None),
+    };
+
+    validate!(
+        *shares_mint != Pubkey::default() && shares_mint_account_info.key == shares_mint,
+        ErrorCode::InvalidInsuranceFundSharesMint,
+        "shares mint {} does not match market shares mint {}",
+        shares_mint_account_info.key,
+        shares_mint
+    )?;
+
+    validate!(
+        shares_mint_account_info.owner == &anchor_spl::token::ID,
+        ErrorCode::InvalidInsuranceFundSharesTokenization,
+        "shares mint {} not owned by the spl token program",
+        shares_mint_account_info.key
+    )?;
+
+    let user_shares_token_account_info = account_info_iter.next().ok_or_else(|| {
+        msg!("Could not find user insurance fund shares token account");
+        ErrorCode::InvalidInsuranceFundSharesTokenization
+    })?;
+
+    Ok(Some((
+        shares_mint_account_info.clone(),
+        user_shares_token_account_info.clone(),
+    )))
+}
+
+pub fn validate_token_mint(mint: &AccountInfo, spot_market: &SpotMarket) -> DriftResult {
+    validate!(
+        mint.key == &spot_market.mint,
+        ErrorCode::InvalidTokenMint,
+        "mint {} does not match spot market {} mint {}",
+        mint.key,
+        spot_market.market_index,
+        spot_market.mint
+    )
+}
@@ -1,5 +1,6 @@
-use anchor_lang::{prelude::*, AnchorDeserialize, AnchorSerialize};
-use anchor_spl::token::{Token, TokenAccount};
+use anchor_lang::{prelude::*, AnchorDeserialize, AnchorSerialize, Discriminator};
+use anchor_spl::token::spl_token::native_mint;
+use anchor_spl::token::{Mint, Token, TokenAccount};
 
 use crate::controller::orders::cancel_orders;
 use crate::controller::position::PositionDirection;
@@ -8,7 +9,8 @@ use crate::get_then_update_id;
 use crate::instructions::constraints::*;
 use crate::instructions::optional_accounts::{
     get_maker_and_maker_stats, get_referrer_and_referrer_stats, get_serum_fulfillment_accounts,
-    get_spot_market_vaults, get_whitelist_token, load_maps, AccountMaps,
+    get_spot_market_vaults, get_token_mint, get_user_liquidation_history, get_whitelist_token,
+    load_maps, validate_token_mint, AccountMaps,
 };
 use crate::instructions::SpotFulfillmentType;
 use crate::load;
@@ -19,23 +21,31 @@ use crate::math::margin::{
     calculate_max_withdrawable_amount, meets_initial_margin_requirement,
     meets_withdraw_margin_requirement, validate_spot_margin_trading,
 };
+use crate::math::oracle::DriftAction;
 use crate::math::safe_math::SafeMath;
 use crate::math::spot_balance::get_token_amount;
 use crate::math::spot_withdraw::validate_spot_market_vault_amount;
+use crate::math::token_2022::calculate_transfer_fee;
 use crate::math_error;
 use crate::print_error;
 use crate::safe_decrement;
 use crate::safe_increment;
 use crate::state::events::{
     DepositDirection, DepositExplanation, DepositRecord, LPAction, LPRecord, NewUserRecord,
-    OrderActionExplanation,
+    OrderActionExplanation, TransferPerpPositionRecord,
 };
+use crate::state::liquidation_history::UserLiquidationHistory;
 use crate::state::perp_market::MarketStatus;
-use crate::state::perp_market_map::{get_writable_perp_market_set, MarketSet};
+use crate::state::perp_market_map::{
+    get_writable_perp_market_set, get_writable_perp_market_set_from_many, MarketSet,
+};
 use crate::state::spot_market::SpotBalanceType;
 use crate::state::spot_market_map::get_writable_spot_market_set;
 use crate::state::state::State;
-use crate::state::user::{MarketType, OrderTriggerCondition, OrderType, User, UserStats};
+use crate::state::user::{
+    get_legacy_user_size, migrate_legacy_user_data, MarketType, OrderTriggerCondition, OrderType,
+    User, UserStats,
+};
 use crate::validate;
 use crate::validation::user::validate_user_deletion;
 use crate::validation::whitelist::validate_whitelist_token;
@@ -143,30 +153,138 @@ pub fn handle_initialize_user_stats(ctx: Context<InitializeUserStats>) -> Result
     Ok(())
 }
 
+pub fn handle_initialize_user_liquidation_history(
+    ctx: Context<InitializeUserLiquidationHistory>,
+) -> Result<()> {
+    let mut liquidation_history = ctx
+        .accounts
+        .user_liquidation_history
+        .load_init()
+        .or(Err(ErrorCode::UnableToLoadAccountLoader))?;
+
+    *liquidation_history = UserLiquidationHistory::new(ctx.accounts.authority.key());
+
+    Ok(())
+}
+
 pub fn handle_deposit(
     ctx: Context<Deposit>,
     market_index: u16,
     amount: u64,
     reduce_only: bool,
 ) -> Result<()> {
-    let user_key = ctx.accounts.user.key();
-    let user = &mut load_mut!(ctx.accounts.user)?;
+    let token_program = &ctx.accounts.token_program;
+    let user_token_account = &ctx.accounts.user_token_account;
+    let spot_market_vault = &ctx.accounts.spot_market_vault;
+    let authority = ctx.accounts.authority.to_account_info();
+
+    let spot_market_vault_mint =
+        controller::token::load_token_account(spot_market_vault, token_program)?.mint;
+    let user_token = controller::token::load_token_account(user_token_account, token_program)?;
+    validate!(
+        user_token.mint == spot_market_vault_mint && user_token.owner == *authority.key,
+        ErrorCode::InvalidTokenAccount,
+        "user token account must be owned by authority and match spot market vault mint"
+    )?;
+
+    _deposit(
+        &ctx.accounts.user,
+        &ctx.accounts.state,
+        ctx.remaining_accounts,
+        market_index,
+        amount,
+        reduce_only,
+        |amount, mint| {
+            controller::token::receive(
+                token_program,
+                user_token_account,
+                spot_market_vault,
+                &authority,
+                amount,
+                mint,
+            )
+        },
+    )
+}
+
+pub fn handle_deposit_native_sol(
+    ctx: Context<DepositNativeSol>,
+    market_index: u16,
+    amount: u64,
+    reduce_only: bool,
+) -> Result<()> {
+    let token_program = &ctx.accounts.token_program;
+    let system_program = &ctx.accounts.system_program;
+    let wrapped_sol_account = &ctx.accounts.wrapped_sol_account;
+    let spot_market_vault = &ctx.accounts.spot_market_vault;
+    let drift_signer = &ctx.accounts.drift_signer;
+    let authority = ctx.accounts.authority.to_account_info();
+    let signer_nonce = ctx.accounts.state.signer_nonce;
+
+    _deposit(
+        &ctx.accounts.user,
+        &ctx.accounts.state,
+        ctx.remaining_accounts,
+        market_index,
+        amount,
+        reduce_only,
+        |amount, _| {
+            controller::token::wrap_native_sol(
+                token_program,
+                system_program,
+                &authority,
+                wrapped_sol_account,
+                amount,
+            )?;
+            controller::token::send_from_program_vault(
+                &token_program.to_account_info(),
+                &wrapped_sol_account.to_account_info(),
+                &spot_market_vault.to_account_info(),
+                drift_signer,
+                signer_nonce,
+                amount,
+                &None,
+            )?;
+            // return the temporary account's rent to the depositor
+            controller::token::close_program_token_account(
+                token_program,
+                wrapped_sol_account,
+                &authority,
+                drift_signer,
+                signer_nonce,
+            )
+        },
+    )
+}
+
+fn _deposit<'info>(
+    user_loader: &AccountLoader<'info, User>,
+    state: &State,
+    remaining_accounts: &[AccountInfo<'info>],
+    market_index: u16,
+    amount: u64,
+    reduce_only: bool,
+    transfer_to_vault: impl FnOnce(u64, &Option<AccountInfo<'info>>) -> Result<()>,
+) -> Result<()> {
+    let user_key = user_loader.key();
+    let user = &mut load_mut!(user_loader)?;
 
-    let state = &ctx.accounts.state;
     let clock = Clock::get()?;
     let now = clock.unix_timestamp;
 
+    let remaining_accounts_iter = &mut remaining_accounts.iter().peekable();
     let AccountMaps {
         perp_market_map,
         spot_market_map,
         mut oracle_map,
     } = load_maps(
-        &mut ctx.remaining_accounts.iter().peekable(),
+        remaining_accounts_iter,
         &MarketSet::new(),
         &get_writable_spot_market_set(market_index),
         clock.slot,
         Some(state.oracle_guard_rails),
     )?;
+    let mint = get_token_mint(remaining_accounts_iter)?;
 
     if amount == 0 {
         return Err(ErrorCode::InsufficientDeposit.into());
@@ -205,8 +323,22 @@ pub fn handle_deposit(
         amount
     };
 
+    // token-2022 transfer fees are withheld from what the vault receives, so only the net is credited
+    let transfer_fee = match &mint {
+        Some(mint) => {
+            validate_token_mint(mint, spot_market)?;
+            calculate_transfer_fee(&mint.try_borrow_data()?, clock.epoch, amount)?
+        }
+        None => 0,
+    };
+    let deposit_amount = amount.safe_sub(transfer_fee)?;
+
+    if deposit_amount == 0 {
+        return Err(ErrorCode::InsufficientDeposit.into());
+    }
+
     user.increment_total_deposits(
-        amount,
+        deposit_amount,
         oracle_price_data.price,
         spot_market.get_precision().cast()?,
     )?;
@@ -216,7 +348,7 @@ pub fn handle_deposit(
 
     let spot_position = &mut user.spot_positions[position_index];
     controller::spot_position::update_spot_balances_and_cumulative_deposits(
-        amount as u128,
+        deposit_amount as u128,
         &SpotBalanceType::Deposit,
         spot_market,
         spot_position,
@@ -250,14 +382,7 @@ pub fn handle_deposit(
         )?;
     }
 
-    controller::token::receive(
-        &ctx.accounts.token_program,
-        &ctx.accounts.user_token_account,
-        &ctx.accounts.spot_market_vault,
-        &ctx.accounts.authority,
-        amount,
-    )?;
-    ctx.accounts.spot_market_vault.reload()?;
+    transfer_to_vault(amount, &mint)?;
 
     let deposit_record_id = get_then_update_id!(spot_market, next_deposit_record_id);
     let oracle_price = oracle_price_data.price;
@@ -267,7 +392,7 @@ pub fn handle_deposit(
         user_authority: user.authority,
         user: user_key,
         direction: DepositDirection::Deposit,
-        amount,
+        amount: deposit_amount,
         oracle_price,
         market_deposit_balance: spot_market.deposit_balance,
         market_withdraw_balance: spot_market.borrow_balance,
@@ -308,23 +433,122 @@ pub fn handle_withdraw(
     amount: u64,
     reduce_only: bool,
 ) -> anchor_lang::Result<()> {
-    let user_key = ctx.accounts.user.key();
-    let user = &mut load_mut!(ctx.accounts.user)?;
+    let token_program = &ctx.accounts.token_program;
+    let user_token_account = &ctx.accounts.user_token_account;
+    let spot_market_vault = &ctx.accounts.spot_market_vault;
+    let drift_signer = &ctx.accounts.drift_signer;
+    let signer_nonce = ctx.accounts.state.signer_nonce;
+
+    let spot_market_vault_mint =
+        controller::token::load_token_account(spot_market_vault, token_program)?.mint;
+    let user_token = controller::token::load_token_account(user_token_account, token_program)?;
+    validate!(
+        user_token.mint == spot_market_vault_mint,
+        ErrorCode::InvalidTokenAccount,
+        "user token account must match spot market vault mint"
+    )?;
+
+    _withdraw(
+        &ctx.accounts.user,
+        &ctx.accounts.state,
+        ctx.remaining_accounts,
+        market_index,
+        amount,
+        reduce_only,
+        |amount, mint| {
+            controller::token::send_from_program_vault(
+                token_program,
+                spot_market_vault,
+                user_token_account,
+                drift_signer,
+                signer_nonce,
+                amount,
+                mint,
+            )?;
+
+            // reload the spot market vault balance so it's up-to-date
+            Ok(controller::token::load_token_account(spot_market_vault, token_program)?.amount)
+        },
+    )
+}
+
+#[access_control(
+    withdraw_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_withdraw_native_sol(
+    ctx: Context<WithdrawNativeSol>,
+    market_index: u16,
+    amount: u64,
+    reduce_only: bool,
+) -> anchor_lang::Result<()> {
+    let accounts = &mut *ctx.accounts;
+    let token_program = &accounts.token_program;
+    let wrapped_sol_account = &accounts.wrapped_sol_account;
+    let spot_market_vault = &mut accounts.spot_market_vault;
+    let drift_signer = &accounts.drift_signer;
+    let authority = accounts.authority.to_account_info();
+    let signer_nonce = accounts.state.signer_nonce;
+
+    _withdraw(
+        &accounts.user,
+        &accounts.state,
+        ctx.remaining_accounts,
+        market_index,
+        amount,
+        reduce_only,
+        |amount, _| {
+            controller::token::send_from_program_vault(
+                &token_program.to_account_info(),
+                &spot_market_vault.to_account_info(),
+                &wrapped_sol_account.to_account_info(),
+                drift_signer,
+                signer_nonce,
+                amount,
+                &None,
+            )?;
+            // closing the wrapped account unwraps the withdrawn sol (plus rent) to the authority
+            controller::token::close_program_token_account(
+                token_program,
+                wrapped_sol_account,
+                &authority,
+                drift_signer,
+                signer_nonce,
+            )?;
+
+            // reload the spot market vault balance so it's up-to-date
+            spot_market_vault.reload()?;
+            Ok(spot_market_vault.amount)
+        },
+    )
+}
+
+fn _withdraw<'info>(
+    user_loader: &AccountLoader<'info, User>,
+    state: &State,
+    remaining_accounts: &[AccountInfo<'info>],
+    market_index: u16,
+    amount: u64,
+    reduce_only: bool,
+    transfer_from_vault: impl FnOnce(u64, &Option<AccountInfo<'info>>) -> Result<u64>,
+) -> anchor_lang::Result<()> {
+    let user_key = user_loader.key();
+    let user = &mut load_mut!(user_loader)?;
     let clock = Clock::get()?;
     let now = clock.unix_timestamp;
-    let state = &ctx.accounts.state;
 
+    let remaining_accounts_iter = &mut remaining_accounts.iter().peekable();
     let AccountMaps {
         perp_market_map,
         spot_market_map,
         mut oracle_map,
     } = load_maps(
-        &mut ctx.remaining_accounts.iter().peekable(),
+        remaining_accounts_iter,
         &MarketSet::new(),
         &get_writable_spot_market_set(market_index),
         clock.slot,
         Some(state.oracle_guard_rails),
     )?;
+    let mint = get_token_mint(remaining_accounts_iter)?;
 
     validate!(!user.is_bankrupt, ErrorCode::UserBankrupt)?;
 
@@ -423,21 +647,12 @@ pub fn handle_withdraw(
     };
     emit!(deposit_record);
 
-    controller::token::send_from_program_vault(
-        &ctx.accounts.token_program,
-        &ctx.accounts.spot_market_vault,
-        &ctx.accounts.user_token_account,
-        &ctx.accounts.drift_signer,
-        state.signer_nonce,
-        amount,
-    )?;
+    if let Some(mint) = &mint {
+        validate_token_mint(mint, &spot_market)?;
+    }
 
-    // reload the spot market vault balance so it's up-to-date
-    ctx.accounts.spot_market_vault.reload()?;
-    math::spot_withdraw::validate_spot_market_vault_amount(
-        &spot_market,
-        ctx.accounts.spot_market_vault.amount,
-    )?;
+    let spot_market_vault_amount = transfer_from_vault(amount, &mint)?;
+    math::spot_withdraw::validate_spot_market_vault_amount(&spot_market, spot_market_vault_amount)?;
 
     Ok(())
 }
@@ -620,9 +835,168 @@ pub fn handle_transfer_deposit(
     let spot_market = spot_market_map.get_ref(&market_index)?;
     math::spot_withdraw::validate_spot_market_vault_amount(
         &spot_market,
-        ctx.accounts.spot_market_vault.amount,
+        controller::token::load_vault_token_account(&ctx.accounts.spot_market_vault)?.amount,
+    )?;
+
+    Ok(())
+}
+
+#[access_control(
+    fill_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_transfer_perp_position(
+    ctx: Context<TransferPerpPosition>,
+    market_index: u16,
+    base_asset_amount: u64,
+) -> anchor_lang::Result<()> {
+    let authority_key = ctx.accounts.authority.key;
+    let to_user_key = ctx.accounts.to_user.key();
+    let from_user_key = ctx.accounts.from_user.key();
+
+    validate!(
+        from_user_key != to_user_key,
+        ErrorCode::InvalidTransferPerpPosition,
+        "from_user and to_user must be different"
+    )?;
+
+    let state = &ctx.accounts.state;
+    let clock = Clock::get()?;
+    let now = clock.unix_timestamp;
+
+    let to_user = &mut load_mut!(ctx.accounts.to_user)?;
+    let from_user = &mut load_mut!(ctx.accounts.from_user)?;
+
+    validate!(
+        !to_user.is_bankrupt,
+        ErrorCode::UserBankrupt,
+        "to_user bankrupt"
+    )?;
+    validate!(
+        !from_user.is_bankrupt,
+        ErrorCode::UserBankrupt,
+        "from_user bankrupt"
+    )?;
+
+    let AccountMaps {
+        perp_market_map,
+        spot_market_map,
+        mut oracle_map,
+    } = load_maps(
+        &mut ctx.remaining_accounts.iter().peekable(),
+        &get_writable_perp_market_set(market_index),
+        &MarketSet::new(),
+        clock.slot,
+        Some(state.oracle_guard_rails),
+    )?;
+
+    math::liquidation::validate_user_not_being_liquidated(
+        from_user,
+        &perp_market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        state.liquidation_margin_buffer_ratio,
+    )?;
+
+    math::liquidation::validate_user_not_being_liquidated(
+        to_user,
+        &perp_market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        state.liquidation_margin_buffer_ratio,
+    )?;
+
+    {
+        let market = &mut perp_market_map.get_ref_mut(&market_index)?;
+
+        validate!(
+            matches!(
+                market.status,
+                MarketStatus::Active
+                    | MarketStatus::FundingPaused
+                    | MarketStatus::AmmPaused
+                    | MarketStatus::FillPaused
+                    | MarketStatus::WithdrawPaused
+            ),
+            ErrorCode::MarketActionPaused,
+            "Perp Market {} status doesnt allow position transfers",
+            market_index
+        )?;
+
+        let oracle_price_data = oracle_map.get_price_data(&market.amm.oracle)?;
+        controller::repeg::update_amm_and_check_validity(
+            market,
+            oracle_price_data,
+            state,
+            now,
+            clock.slot,
+            Some(DriftAction::FillOrderMatch),
+        )?;
+
+        controller::lp::settle_funding_payment_then_lp(from_user, &from_user_key, market, now)?;
+        controller::lp::settle_funding_payment_then_lp(to_user, &to_user_key, market, now)?;
+    }
+
+    let (from_position_delta, from_base_asset_amount_after, to_base_asset_amount_after) = {
+        let market = &mut perp_market_map.get_ref_mut(&market_index)?;
+
+        let from_position = from_user.get_perp_position_mut(market_index).map_err(|e| {
+            msg!(
+                "from_user does not have a position for perp market {}",
+                market_index
+            );
+            e
+        })?;
+
+        let to_position = to_user.force_get_perp_position_mut(market_index)?;
+
+        let (from_position_delta, _) = controller::position::transfer_perp_position(
+            from_position,
+            to_position,
+            market,
+            base_asset_amount,
+        )?;
+
+        (
+            from_position_delta,
+            from_position.base_asset_amount,
+            to_position.base_asset_amount,
+        )
+    };
+
+    validate!(
+        meets_initial_margin_requirement(
+            from_user,
+            &perp_market_map,
+            &spot_market_map,
+            &mut oracle_map,
+        )?,
+        ErrorCode::InsufficientCollateral,
+        "From user does not meet initial margin requirement"
+    )?;
+
+    validate!(
+        meets_initial_margin_requirement(
+            to_user,
+            &perp_market_map,
+            &spot_market_map,
+            &mut oracle_map,
+        )?,
+        ErrorCode::InsufficientCollateral,
+        "To user does not meet initial margin requirement"
     )?;
 
+    emit!(TransferPerpPositionRecord {
+        ts: now,
+        user_authority: *authority_key,
+        from_user: from_user_key,
+        to_user: to_user_key,
+        market_index,
+        base_asset_amount: from_position_delta.base_asset_amount,
+        quote_asset_amount: from_position_delta.quote_asset_amount,
+        from_user_base_asset_amount_after: from_base_asset_amount_after,
+        to_user_base_asset_amount_after: to_base_asset_amount_after,
+    });
+
     Ok(())
 }
 
@@ -1295,46 +1669,14 @@ pub fn handle_add_perp_lp_shares<'info>(
         state.liquidation_margin_buffer_ratio,
     )?;
 
-    {
-        let mut market = perp_market_map.get_ref_mut(&market_index)?;
-
-        validate!(
-            matches!(
-                market.status,
-                MarketStatus::Active
-                    | MarketStatus::FundingPaused
-                    | MarketStatus::FillPaused
-                    | MarketStatus::WithdrawPaused
-            ),
-            ErrorCode::MarketStatusInvalidForNewLP,
-            "Market Status doesn't allow for new LP liquidity"
-        )?;
-
-        validate!(
-            n_shares >= market.amm.order_step_size,
-            ErrorCode::NewLPSizeTooSmall,
-            "minting {} shares is less than step size {}",
-            n_shares,
-            market.amm.order_step_size,
-        )?;
-
-        controller::funding::settle_funding_payment(user, &user_key, &mut market, now)?;
-
-        // standardize n shares to mint
-        let n_shares = crate::math::orders::standardize_base_asset_amount(
-            n_shares.cast()?,
-            market.amm.order_step_size,
-        )?
-        .cast::<u64>()?;
-
-        controller::lp::mint_lp_shares(
-            user.force_get_perp_position_mut(market_index)?,
-            &mut market,
-            n_shares,
-        )?;
-
-        user.last_add_perp_lp_shares_ts = now;
-    }
+    controller::lp::add_perp_lp_shares(
+        &perp_market_map,
+        user,
+        user_key,
+        n_shares,
+        market_index,
+        now,
+    )?;
 
     // check margin requirements
     validate!(
@@ -1348,18 +1690,189 @@ pub fn handle_add_perp_lp_shares<'info>(
         "User does not meet initial margin requirement"
     )?;
 
+    Ok(())
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct LPSharesParams {
+    pub market_index: u16,
+    pub n_shares: u64,
+}
+
+#[access_control(
+    amm_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_add_perp_lp_shares_batch<'info>(
+    ctx: Context<AddRemoveLiquidity>,
+    params: Vec<LPSharesParams>,
+) -> Result<()> {
+    let user_key = ctx.accounts.user.key();
+    let user = &mut load_mut!(ctx.accounts.user)?;
+    let state = &ctx.accounts.state;
+    let clock = Clock::get()?;
+    let now = clock.unix_timestamp;
+
+    let AccountMaps {
+        perp_market_map,
+        spot_market_map,
+        mut oracle_map,
+    } = load_maps(
+        &mut ctx.remaining_accounts.iter().peekable(),
+        &get_writable_perp_market_set_from_many(
+            params.iter().map(|param| param.market_index).collect(),
+        ),
+        &MarketSet::new(),
+        clock.slot,
+        Some(state.oracle_guard_rails),
+    )?;
+
+    validate!(!user.is_bankrupt, ErrorCode::UserBankrupt)?;
+    math::liquidation::validate_user_not_being_liquidated(
+        user,
+        &perp_market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        state.liquidation_margin_buffer_ratio,
+    )?;
+
+    controller::lp::add_perp_lp_shares_batch(
+        &perp_market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        user,
+        user_key,
+        &params,
+        now,
+    )?;
+
+    Ok(())
+}
+
+#[access_control(
+    amm_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_remove_perp_lp_shares_batch(
+    ctx: Context<AddRemoveLiquidity>,
+    params: Vec<LPSharesParams>,
+) -> Result<()> {
+    let user_key = ctx.accounts.user.key();
+    let user = &mut load_mut!(ctx.accounts.user)?;
+
+    let state = &ctx.accounts.state;
+    let clock = Clock::get()?;
+    let now = clock.unix_timestamp;
+
+    controller::lp::validate_lp_shares_batch(&params)?;
+
+    let AccountMaps {
+        perp_market_map,
+        mut oracle_map,
+        ..
+    } = load_maps(
+        &mut ctx.remaining_accounts.iter().peekable(),
+        &get_writable_perp_market_set_from_many(
+            params.iter().map(|param| param.market_index).collect(),
+        ),
+        &MarketSet::new(),
+        clock.slot,
+        Some(state.oracle_guard_rails),
+    )?;
+
+    for param in params.iter() {
+        controller::lp::remove_perp_lp_shares(
+            &perp_market_map,
+            &mut oracle_map,
+            state,
+            user,
+            user_key,
+            param.n_shares,
+            param.market_index,
+            now,
+        )?;
+    }
+
+    Ok(())
+}
+
+#[access_control(
+    amm_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_update_perp_lp_range(
+    ctx: Context<AddRemoveLiquidity>,
+    market_index: u16,
+    min_reserve_price: u64,
+    max_reserve_price: u64,
+) -> Result<()> {
+    let user_key = ctx.accounts.user.key();
+    let user = &mut load_mut!(ctx.accounts.user)?;
+
+    let state = &ctx.accounts.state;
+    let clock = Clock::get()?;
+    let now = clock.unix_timestamp;
+
+    let AccountMaps {
+        perp_market_map, ..
+    } = load_maps(
+        &mut ctx.remaining_accounts.iter().peekable(),
+        &get_writable_perp_market_set(market_index),
+        &MarketSet::new(),
+        clock.slot,
+        Some(state.oracle_guard_rails),
+    )?;
+
+    let mut market = perp_market_map.get_ref_mut(&market_index)?;
+
+    controller::funding::settle_funding_payment(user, &user_key, &mut market, now)?;
+
+    let (position_delta, pnl, fee) = controller::lp::update_lp_range(
+        user.get_perp_position_mut(market_index)?,
+        &mut market,
+        min_reserve_price,
+        max_reserve_price,
+    )?;
+
     emit!(LPRecord {
         ts: now,
-        action: LPAction::AddLiquidity,
+        action: LPAction::SettleLiquidity,
         user: user_key,
-        n_shares,
         market_index,
-        ..LPRecord::default()
+        delta_base_asset_amount: position_delta.base_asset_amount,
+        delta_quote_asset_amount: position_delta.quote_asset_amount,
+        pnl,
+        fee,
+        n_shares: 0,
     });
 
     Ok(())
 }
 
+pub fn handle_update_perp_lp_hedge_threshold(
+    ctx: Context<AddRemoveLiquidity>,
+    market_index: u16,
+    hedge_threshold: u64,
+) -> Result<()> {
+    let user = &mut load_mut!(ctx.accounts.user)?;
+
+    let position = user.get_perp_position_mut(market_index)?;
+
+    validate!(
+        position.is_lp(),
+        ErrorCode::InvalidLPHedgeThreshold,
+        "position has no lp shares in market {}",
+        market_index
+    )?;
+
+    msg!(
+        "lp hedge threshold {} -> {}",
+        position.lp_hedge_threshold,
+        hedge_threshold
+    );
+
+    position.lp_hedge_threshold = hedge_threshold;
+
+    Ok(())
+}
+
 pub fn handle_remove_perp_lp_shares_in_expiring_market(
     ctx: Context<RemoveLiquidityInExpiredMarket>,
     shares_to_burn: u64,
@@ -1395,7 +1908,7 @@ pub fn handle_remove_perp_lp_shares_in_expiring_market(
     }
 
     controller::lp::remove_perp_lp_shares(
-        perp_market_map,
+        &perp_market_map,
         &mut oracle_map,
         state,
         user,
@@ -1436,7 +1949,7 @@ pub fn handle_remove_perp_lp_shares(
     )?;
 
     controller::lp::remove_perp_lp_shares(
-        perp_market_map,
+        &perp_market_map,
         &mut oracle_map,
         state,
         user,
@@ -1449,6 +1962,34 @@ pub fn handle_remove_perp_lp_shares(
     Ok(())
 }
 
+pub fn handle_resize_user(ctx: Context<ResizeUser>) -> Result<()> {
+    let user_account_info = ctx.accounts.user.to_account_info();
+
+    let legacy_account_size = get_legacy_user_size().safe_add(8)?;
+    validate!(
+        user_account_info.data_len() == legacy_account_size,
+        ErrorCode::InvalidUserAccountSize,
+        "user account size {} is not the legacy size {}",
+        user_account_info.data_len(),
+        legacy_account_size
+    )?;
+
+    controller::pda::resize_pda(
+        &ctx.accounts.payer.to_account_info(),
+        &Rent::get()?,
+        std::mem::size_of::<User>().safe_add(8)?,
+        &User::discriminator(),
+        &ctx.accounts.system_program.to_account_info(),
+        &user_account_info,
+        ErrorCode::InvalidUserAccountSize,
+    )?;
+
+    let mut data = user_account_info.try_borrow_mut_data()?;
+    migrate_legacy_user_data(&mut data[8..])?;
+
+    Ok(())
+}
+
 pub fn handle_update_user_name(
     ctx: Context<UpdateUser>,
     _sub_account_id: u16,
@@ -1487,6 +2028,20 @@ pub fn handle_delete_user(ctx: Context<DeleteUser>) -> Result<()> {
 
     safe_decrement!(user_stats.number_of_sub_accounts, 1);
 
+    // the liquidation history is shared by the authority's sub accounts, close it with the last one
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
+    if let Some(user_liquidation_history) =
+        get_user_liquidation_history(remaining_accounts_iter, &user.authority)?
+    {
+        validate!(
+            user_stats.number_of_sub_accounts == 0,
+            ErrorCode::UserCantBeDeleted,
+            "user liquidation history can only be closed with the last sub account"
+        )?;
+
+        user_liquidation_history.close(ctx.accounts.authority.to_account_info())?;
+    }
+
     Ok(())
 }
 
@@ -1535,6 +2090,28 @@ pub struct InitializeUserStats<'info> {
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+pub struct InitializeUserLiquidationHistory<'info> {
+    #[account(
+        init,
+        seeds = [b"user_liquidation_history", authority.key.as_ref()],
+        space = std::mem::size_of::<UserLiquidationHistory>() + 8,
+        bump,
+        payer = payer
+    )]
+    pub user_liquidation_history: AccountLoader<'info, UserLiquidationHistory>,
+    #[account(
+        has_one = authority
+    )]
+    pub user_stats: AccountLoader<'info, UserStats>,
+    /// CHECK: checked against user_stats, anyone can pay to create the history
+    pub authority: AccountInfo<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
 #[derive(Accounts)]
 #[instruction(market_index: u16,)]
 pub struct Deposit<'info> {
@@ -1555,14 +2132,16 @@ pub struct Deposit<'info> {
         seeds = [b"spot_market_vault".as_ref(), market_index.to_le_bytes().as_ref()],
         bump,
     )]
-    pub spot_market_vault: Box<Account<'info, TokenAccount>>,
+    /// CHECK: spl token or token-2022 account, checked in `deposit`
+    pub spot_market_vault: AccountInfo<'info>,
+    #[account(mut)]
+    /// CHECK: spl token or token-2022 account, checked in `deposit`
+    pub user_token_account: AccountInfo<'info>,
     #[account(
-        mut,
-        constraint = &spot_market_vault.mint.eq(&user_token_account.mint),
-        token::authority = authority
+        constraint = is_token_program(&token_program)?
     )]
-    pub user_token_account: Box<Account<'info, TokenAccount>>,
-    pub token_program: Program<'info, Token>,
+    /// CHECK: spl token or token-2022 program
+    pub token_program: AccountInfo<'info>,
 }
 
 #[derive(Accounts)]
@@ -1585,17 +2164,108 @@ pub struct Withdraw<'info> {
         seeds = [b"spot_market_vault".as_ref(), market_index.to_le_bytes().as_ref()],
         bump,
     )]
+    /// CHECK: spl token or token-2022 account, checked in `withdraw`
+    pub spot_market_vault: AccountInfo<'info>,
+    #[account(
+        constraint = state.signer.eq(&drift_signer.key())
+    )]
+    /// CHECK: forced drift_signer
+    pub drift_signer: AccountInfo<'info>,
+    #[account(mut)]
+    /// CHECK: spl token or token-2022 account, checked in `withdraw`
+    pub user_token_account: AccountInfo<'info>,
+    #[account(
+        constraint = is_token_program(&token_program)?
+    )]
+    /// CHECK: spl token or token-2022 program
+    pub token_program: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(market_index: u16,)]
+pub struct DepositNativeSol<'info> {
+    pub state: Box<Account<'info, State>>,
+    #[account(
+        mut,
+        constraint = can_sign_for_user(&user, &authority)?
+    )]
+    pub user: AccountLoader<'info, User>,
+    #[account(
+        mut,
+        constraint = is_stats_for_user(&user, &user_stats)?
+    )]
+    pub user_stats: AccountLoader<'info, UserStats>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"spot_market_vault".as_ref(), market_index.to_le_bytes().as_ref()],
+        bump,
+        constraint = spot_market_vault.mint.eq(&native_mint.key())
+    )]
     pub spot_market_vault: Box<Account<'info, TokenAccount>>,
+    #[account(
+        init,
+        seeds = [b"wrapped_sol".as_ref(), authority.key.as_ref()],
+        bump,
+        payer = authority,
+        token::mint = native_mint,
+        token::authority = drift_signer
+    )]
+    pub wrapped_sol_account: Box<Account<'info, TokenAccount>>,
+    #[account(address = native_mint::ID)]
+    pub native_mint: Box<Account<'info, Mint>>,
     #[account(
         constraint = state.signer.eq(&drift_signer.key())
     )]
     /// CHECK: forced drift_signer
     pub drift_signer: AccountInfo<'info>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(market_index: u16,)]
+pub struct WithdrawNativeSol<'info> {
+    pub state: Box<Account<'info, State>>,
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub user: AccountLoader<'info, User>,
     #[account(
         mut,
-        constraint = &spot_market_vault.mint.eq(&user_token_account.mint)
+        has_one = authority
     )]
-    pub user_token_account: Box<Account<'info, TokenAccount>>,
+    pub user_stats: AccountLoader<'info, UserStats>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"spot_market_vault".as_ref(), market_index.to_le_bytes().as_ref()],
+        bump,
+        constraint = spot_market_vault.mint.eq(&native_mint.key())
+    )]
+    pub spot_market_vault: Box<Account<'info, TokenAccount>>,
+    #[account(
+        init,
+        seeds = [b"wrapped_sol".as_ref(), authority.key.as_ref()],
+        bump,
+        payer = authority,
+        token::mint = native_mint,
+        token::authority = drift_signer
+    )]
+    pub wrapped_sol_account: Box<Account<'info, TokenAccount>>,
+    #[account(address = native_mint::ID)]
+    pub native_mint: Box<Account<'info, Mint>>,
+    #[account(
+        constraint = state.signer.eq(&drift_signer.key())
+    )]
+    /// CHECK: forced drift_signer
+    pub drift_signer: AccountInfo<'info>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
     pub token_program: Program<'info, Token>,
 }
 
@@ -1623,7 +2293,28 @@ pub struct TransferDeposit<'info> {
         seeds = [b"spot_market_vault".as_ref(), market_index.to_le_bytes().as_ref()],
         bump,
     )]
-    pub spot_market_vault: Box<Account<'info, TokenAccount>>,
+    /// CHECK: spl token or token-2022 account, checked in handler
+    pub spot_market_vault: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct TransferPerpPosition<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub from_user: AccountLoader<'info, User>,
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub to_user: AccountLoader<'info, User>,
+    #[account(
+        has_one = authority
+    )]
+    pub user_stats: AccountLoader<'info, UserStats>,
+    pub authority: Signer<'info>,
+    pub state: Box<Account<'info, State>>,
 }
 
 #[derive(Accounts)]
@@ -1705,6 +2396,19 @@ pub struct RemoveLiquidityInExpiredMarket<'info> {
     pub user: AccountLoader<'info, User>,
 }
 
+#[derive(Accounts)]
+pub struct ResizeUser<'info> {
+    #[account(
+        mut,
+        owner = crate::ID
+    )]
+    /// CHECK: legacy user accounts are too small to load, size and discriminator checked in handler
+    pub user: AccountInfo<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
 #[derive(Accounts)]
 #[instruction(
     sub_account_id: u16,
@@ -13,8 +13,10 @@ use math::{bn, constants::*};
 use state::oracle::OracleSource;
 
 use crate::controller::position::PositionDirection;
-use crate::state::perp_market::{ContractTier, MarketStatus};
-use crate::state::spot_market::AssetTier;
+use crate::state::perp_market::{
+    AmmMode, ContractTier, FundingPremiumSampling, MarketStatus, SpreadModel,
+};
+use crate::state::spot_market::{AssetTier, BorrowRateKink};
 use crate::state::state::FeeStructure;
 use crate::state::state::*;
 use crate::state::user::MarketType;
@@ -54,6 +56,12 @@ pub mod drift {
         handle_initialize_user_stats(ctx)
     }
 
+    pub fn initialize_user_liquidation_history(
+        ctx: Context<InitializeUserLiquidationHistory>,
+    ) -> Result<()> {
+        handle_initialize_user_liquidation_history(ctx)
+    }
+
     pub fn deposit(
         ctx: Context<Deposit>,
         market_index: u16,
@@ -72,6 +80,24 @@ pub mod drift {
         handle_withdraw(ctx, market_index, amount, reduce_only)
     }
 
+    pub fn deposit_native_sol(
+        ctx: Context<DepositNativeSol>,
+        market_index: u16,
+        amount: u64,
+        reduce_only: bool,
+    ) -> Result<()> {
+        handle_deposit_native_sol(ctx, market_index, amount, reduce_only)
+    }
+
+    pub fn withdraw_native_sol(
+        ctx: Context<WithdrawNativeSol>,
+        market_index: u16,
+        amount: u64,
+        reduce_only: bool,
+    ) -> anchor_lang::Result<()> {
+        handle_withdraw_native_sol(ctx, market_index, amount, reduce_only)
+    }
+
     pub fn transfer_deposit(
         ctx: Context<TransferDeposit>,
         market_index: u16,
@@ -80,6 +106,14 @@ pub mod drift {
         handle_transfer_deposit(ctx, market_index, amount)
     }
 
+    pub fn transfer_perp_position(
+        ctx: Context<TransferPerpPosition>,
+        market_index: u16,
+        base_asset_amount: u64,
+    ) -> anchor_lang::Result<()> {
+        handle_transfer_perp_position(ctx, market_index, base_asset_amount)
+    }
+
     pub fn place_perp_order(ctx: Context<PlaceOrder>, params: OrderParams) -> Result<()> {
         handle_place_perp_order(ctx, params)
     }
@@ -147,6 +181,37 @@ pub mod drift {
         handle_add_perp_lp_shares(ctx, n_shares, market_index)
     }
 
+    pub fn update_perp_lp_range(
+        ctx: Context<AddRemoveLiquidity>,
+        market_index: u16,
+        min_reserve_price: u64,
+        max_reserve_price: u64,
+    ) -> Result<()> {
+        handle_update_perp_lp_range(ctx, market_index, min_reserve_price, max_reserve_price)
+    }
+
+    pub fn add_perp_lp_shares_batch(
+        ctx: Context<AddRemoveLiquidity>,
+        params: Vec<LPSharesParams>,
+    ) -> Result<()> {
+        handle_add_perp_lp_shares_batch(ctx, params)
+    }
+
+    pub fn remove_perp_lp_shares_batch(
+        ctx: Context<AddRemoveLiquidity>,
+        params: Vec<LPSharesParams>,
+    ) -> Result<()> {
+        handle_remove_perp_lp_shares_batch(ctx, params)
+    }
+
+    pub fn update_perp_lp_hedge_threshold(
+        ctx: Context<AddRemoveLiquidity>,
+        market_index: u16,
+        hedge_threshold: u64,
+    ) -> Result<()> {
+        handle_update_perp_lp_hedge_threshold(ctx, market_index, hedge_threshold)
+    }
+
     pub fn remove_perp_lp_shares(
         ctx: Context<AddRemoveLiquidity>,
         shares_to_burn: u64,
@@ -187,6 +252,10 @@ pub mod drift {
         handle_update_user_delegate(ctx, _sub_account_id, delegate)
     }
 
+    pub fn resize_user(ctx: Context<ResizeUser>) -> Result<()> {
+        handle_resize_user(ctx)
+    }
+
     pub fn delete_user(ctx: Context<DeleteUser>) -> Result<()> {
         handle_delete_user(ctx)
     }
@@ -222,10 +291,25 @@ pub mod drift {
         handle_settle_funding_payment(ctx)
     }
 
+    pub fn settle_funding_payments_for_users(
+        ctx: Context<SettleFundingForUsers>,
+        market_index: u16,
+    ) -> Result<()> {
+        handle_settle_funding_payments_for_users(ctx, market_index)
+    }
+
     pub fn settle_lp(ctx: Context<SettleLP>, market_index: u16) -> Result<()> {
         handle_settle_lp(ctx, market_index)
     }
 
+    pub fn hedge_lp_position(
+        ctx: Context<FillOrder>,
+        market_index: u16,
+        maker_order_id: Option<u32>,
+    ) -> Result<()> {
+        handle_hedge_lp_position(ctx, market_index, maker_order_id)
+    }
+
     pub fn settle_expired_market(ctx: Context<UpdateAMM>, market_index: u16) -> Result<()> {
         handle_settle_expired_market(ctx, market_index)
     }
@@ -244,6 +328,13 @@ pub mod drift {
         )
     }
 
+    pub fn liquidate_perp_with_amm(
+        ctx: Context<LiquidatePerpWithAmm>,
+        market_index: u16,
+    ) -> Result<()> {
+        handle_liquidate_perp_with_amm(ctx, market_index)
+    }
+
     pub fn liquidate_spot(
         ctx: Context<LiquidateSpot>,
         asset_market_index: u16,
@@ -258,6 +349,41 @@ pub mod drift {
         )
     }
 
+    pub fn liquidate_perp_with_backstop(
+        ctx: Context<LiquidatePerpWithBackstop>,
+        market_index: u16,
+        liquidator_max_base_asset_amount: u64,
+        limit_price: Option<u64>,
+    ) -> Result<()> {
+        handle_liquidate_perp_with_backstop(
+            ctx,
+            market_index,
+            liquidator_max_base_asset_amount,
+            limit_price,
+        )
+    }
+
+    pub fn liquidate_spot_with_backstop(
+        ctx: Context<LiquidateSpotWithBackstop>,
+        asset_market_index: u16,
+        liability_market_index: u16,
+        liquidator_max_liability_transfer: u128,
+    ) -> Result<()> {
+        handle_liquidate_spot_with_backstop(
+            ctx,
+            asset_market_index,
+            liability_market_index,
+            liquidator_max_liability_transfer,
+        )
+    }
+
+    pub fn unwind_backstop_perp_position(
+        ctx: Context<UnwindBackstopPerpPosition>,
+        market_index: u16,
+    ) -> Result<()> {
+        handle_unwind_backstop_perp_position(ctx, market_index)
+    }
+
     pub fn liquidate_borrow_for_perp_pnl(
         ctx: Context<LiquidateBorrowForPerpPnl>,
         perp_market_index: u16,
@@ -316,6 +442,20 @@ pub mod drift {
         handle_settle_revenue_to_insurance_fund(ctx, spot_market_index)
     }
 
+    pub fn settle_revenue_to_perp_market_insurance_fund(
+        ctx: Context<SettleRevenueToPerpMarketInsuranceFund>,
+        perp_market_index: u16,
+    ) -> Result<()> {
+        handle_settle_revenue_to_perp_market_insurance_fund(ctx, perp_market_index)
+    }
+
+    pub fn view_predicted_funding_rate(
+        ctx: Context<ViewPerpMarket>,
+        market_index: u16,
+    ) -> Result<()> {
+        handle_view_predicted_funding_rate(ctx, market_index)
+    }
+
     pub fn update_funding_rate(ctx: Context<UpdateFundingRate>, market_index: u16) -> Result<()> {
         handle_update_funding_rate(ctx, market_index)
     }
@@ -330,6 +470,10 @@ pub mod drift {
         handle_update_amms(ctx, market_indexes)
     }
 
+    pub fn repeg(ctx: Context<Repeg>, market_index: u16) -> Result<()> {
+        handle_repeg(ctx, market_index)
+    }
+
     pub fn update_spot_market_expiry(
         ctx: Context<AdminUpdateSpotMarket>,
         expiry_ts: i64,
@@ -343,6 +487,19 @@ pub mod drift {
         handle_update_user_quote_asset_insurance_stake(ctx)
     }
 
+    pub fn update_user_liquidation_margin_ratio(
+        ctx: Context<UpdateUserLiquidationMarginRatio>,
+    ) -> Result<()> {
+        handle_update_user_liquidation_margin_ratio(ctx)
+    }
+
+    pub fn update_perp_market_insurance_coverage(
+        ctx: Context<UpdatePerpMarketInsuranceCoverage>,
+        market_index: u16,
+    ) -> Result<()> {
+        handle_update_perp_market_insurance_coverage(ctx, market_index)
+    }
+
     // IF stakers
 
     pub fn initialize_insurance_fund_stake(
@@ -352,6 +509,10 @@ pub mod drift {
         handle_initialize_insurance_fund_stake(ctx, market_index)
     }
 
+    pub fn resize_insurance_fund_stake(ctx: Context<ResizeInsuranceFundStake>) -> Result<()> {
+        handle_resize_insurance_fund_stake(ctx)
+    }
+
     pub fn add_insurance_fund_stake(
         ctx: Context<AddInsuranceFundStake>,
         market_index: u16,
@@ -382,6 +543,96 @@ pub mod drift {
         handle_remove_insurance_fund_stake(ctx, market_index)
     }
 
+    pub fn instant_remove_insurance_fund_stake(
+        ctx: Context<RemoveInsuranceFundStake>,
+        market_index: u16,
+        n_shares: u128,
+    ) -> Result<()> {
+        handle_instant_remove_insurance_fund_stake(ctx, market_index, n_shares)
+    }
+
+    pub fn claim_insurance_fund_stake_rewards(
+        ctx: Context<ClaimInsuranceFundStakeRewards>,
+        market_index: u16,
+    ) -> Result<()> {
+        handle_claim_insurance_fund_stake_rewards(ctx, market_index)
+    }
+
+    pub fn initialize_perp_market_insurance_fund_stake(
+        ctx: Context<InitializePerpMarketInsuranceFundStake>,
+        market_index: u16,
+    ) -> Result<()> {
+        handle_initialize_perp_market_insurance_fund_stake(ctx, market_index)
+    }
+
+    pub fn add_perp_market_insurance_fund_stake(
+        ctx: Context<AddPerpMarketInsuranceFundStake>,
+        market_index: u16,
+        amount: u64,
+    ) -> Result<()> {
+        handle_add_perp_market_insurance_fund_stake(ctx, market_index, amount)
+    }
+
+    pub fn request_remove_perp_market_insurance_fund_stake(
+        ctx: Context<RequestRemovePerpMarketInsuranceFundStake>,
+        market_index: u16,
+        amount: u64,
+    ) -> Result<()> {
+        handle_request_remove_perp_market_insurance_fund_stake(ctx, market_index, amount)
+    }
+
+    pub fn cancel_request_remove_perp_market_insurance_fund_stake(
+        ctx: Context<RequestRemovePerpMarketInsuranceFundStake>,
+        market_index: u16,
+    ) -> Result<()> {
+        handle_cancel_request_remove_perp_market_insurance_fund_stake(ctx, market_index)
+    }
+
+    pub fn remove_perp_market_insurance_fund_stake(
+        ctx: Context<RemovePerpMarketInsuranceFundStake>,
+        market_index: u16,
+    ) -> Result<()> {
+        handle_remove_perp_market_insurance_fund_stake(ctx, market_index)
+    }
+
+    pub fn instant_remove_perp_market_insurance_fund_stake(
+        ctx: Context<RemovePerpMarketInsuranceFundStake>,
+        market_index: u16,
+        n_shares: u128,
+    ) -> Result<()> {
+        handle_instant_remove_perp_market_insurance_fund_stake(ctx, market_index, n_shares)
+    }
+
+    pub fn initialize_backstop_vault_stake(
+        ctx: Context<InitializeBackstopVaultStake>,
+    ) -> Result<()> {
+        handle_initialize_backstop_vault_stake(ctx)
+    }
+
+    pub fn add_backstop_vault_stake(
+        ctx: Context<AddBackstopVaultStake>,
+        amount: u64,
+    ) -> Result<()> {
+        handle_add_backstop_vault_stake(ctx, amount)
+    }
+
+    pub fn request_remove_backstop_vault_stake(
+        ctx: Context<RequestRemoveBackstopVaultStake>,
+        shares: u128,
+    ) -> Result<()> {
+        handle_request_remove_backstop_vault_stake(ctx, shares)
+    }
+
+    pub fn cancel_request_remove_backstop_vault_stake(
+        ctx: Context<RequestRemoveBackstopVaultStake>,
+    ) -> Result<()> {
+        handle_cancel_request_remove_backstop_vault_stake(ctx)
+    }
+
+    pub fn remove_backstop_vault_stake(ctx: Context<RemoveBackstopVaultStake>) -> Result<()> {
+        handle_remove_backstop_vault_stake(ctx)
+    }
+
     // Admin Instructions
 
     pub fn initialize(ctx: Context<Initialize>) -> Result<()> {
@@ -420,6 +671,14 @@ pub mod drift {
         )
     }
 
+    pub fn resize_spot_market(ctx: Context<ResizeSpotMarket>, market_index: u16) -> Result<()> {
+        handle_resize_spot_market(ctx, market_index)
+    }
+
+    pub fn resize_perp_market(ctx: Context<ResizePerpMarket>, market_index: u16) -> Result<()> {
+        handle_resize_perp_market(ctx, market_index)
+    }
+
     pub fn initialize_serum_fulfillment_config(
         ctx: Context<InitializeSerumFulfillmentConfig>,
         market_index: u16,
@@ -534,6 +793,13 @@ pub mod drift {
         handle_update_perp_liquidation_fee(ctx, liquidator_fee, if_liquidation_fee)
     }
 
+    pub fn update_perp_market_liquidator_fee_ramp_slots(
+        ctx: Context<AdminUpdatePerpMarket>,
+        liquidator_fee_ramp_slots: u32,
+    ) -> Result<()> {
+        handle_update_perp_market_liquidator_fee_ramp_slots(ctx, liquidator_fee_ramp_slots)
+    }
+
     pub fn update_insurance_fund_unstaking_period(
         ctx: Context<AdminUpdateSpotMarket>,
         insurance_fund_unstaking_period: i64,
@@ -541,6 +807,94 @@ pub mod drift {
         handle_update_insurance_fund_unstaking_period(ctx, insurance_fund_unstaking_period)
     }
 
+    pub fn update_insurance_fund_instant_unstake_haircut(
+        ctx: Context<AdminUpdateSpotMarket>,
+        instant_unstake_haircut: u32,
+    ) -> Result<()> {
+        handle_update_insurance_fund_instant_unstake_haircut(ctx, instant_unstake_haircut)
+    }
+
+    pub fn initialize_insurance_fund_reward_vault(
+        ctx: Context<InitializeInsuranceFundRewardVault>,
+        market_index: u16,
+    ) -> Result<()> {
+        handle_initialize_insurance_fund_reward_vault(ctx, market_index)
+    }
+
+    pub fn update_insurance_fund_reward_emission(
+        ctx: Context<AdminUpdateSpotMarket>,
+        reward_emission_rate: u64,
+        reward_emission_end_ts: i64,
+    ) -> Result<()> {
+        handle_update_insurance_fund_reward_emission(
+            ctx,
+            reward_emission_rate,
+            reward_emission_end_ts,
+        )
+    }
+
+    pub fn initialize_insurance_fund_shares_mint(
+        ctx: Context<InitializeInsuranceFundSharesMint>,
+        market_index: u16,
+        decimals: u8,
+    ) -> Result<()> {
+        handle_initialize_insurance_fund_shares_mint(ctx, market_index, decimals)
+    }
+
+    pub fn update_perp_market_min_insurance_coverage_ratio(
+        ctx: Context<AdminUpdatePerpMarketInsuranceCoverage>,
+        min_insurance_coverage_ratio: u32,
+    ) -> Result<()> {
+        handle_update_perp_market_min_insurance_coverage_ratio(ctx, min_insurance_coverage_ratio)
+    }
+
+    pub fn initialize_perp_market_insurance_fund(
+        ctx: Context<InitializePerpMarketInsuranceFund>,
+        market_index: u16,
+        insurance_fund_unstaking_period: i64,
+    ) -> Result<()> {
+        handle_initialize_perp_market_insurance_fund(
+            ctx,
+            market_index,
+            insurance_fund_unstaking_period,
+        )
+    }
+
+    pub fn update_perp_market_insurance_fund_unstaking_period(
+        ctx: Context<AdminUpdatePerpMarket>,
+        insurance_fund_unstaking_period: i64,
+    ) -> Result<()> {
+        handle_update_perp_market_insurance_fund_unstaking_period(
+            ctx,
+            insurance_fund_unstaking_period,
+        )
+    }
+
+    pub fn update_perp_market_insurance_fund_instant_unstake_haircut(
+        ctx: Context<AdminUpdatePerpMarket>,
+        instant_unstake_haircut: u32,
+    ) -> Result<()> {
+        handle_update_perp_market_insurance_fund_instant_unstake_haircut(
+            ctx,
+            instant_unstake_haircut,
+        )
+    }
+
+    pub fn update_perp_market_if_factor(
+        ctx: Context<AdminUpdatePerpMarket>,
+        user_if_factor: u32,
+        total_if_factor: u32,
+    ) -> Result<()> {
+        handle_update_perp_market_if_factor(ctx, user_if_factor, total_if_factor)
+    }
+
+    pub fn update_perp_market_revenue_settle_period(
+        ctx: Context<AdminUpdatePerpMarket>,
+        revenue_settle_period: i64,
+    ) -> Result<()> {
+        handle_update_perp_market_revenue_settle_period(ctx, revenue_settle_period)
+    }
+
     pub fn update_spot_market_liquidation_fee(
         ctx: Context<AdminUpdateSpotMarket>,
         liquidator_fee: u32,
@@ -549,6 +903,13 @@ pub mod drift {
         handle_update_spot_market_liquidation_fee(ctx, liquidator_fee, if_liquidation_fee)
     }
 
+    pub fn update_spot_market_liquidator_fee_ramp_slots(
+        ctx: Context<AdminUpdateSpotMarket>,
+        liquidator_fee_ramp_slots: u32,
+    ) -> Result<()> {
+        handle_update_spot_market_liquidator_fee_ramp_slots(ctx, liquidator_fee_ramp_slots)
+    }
+
     pub fn update_withdraw_guard_threshold(
         ctx: Context<AdminUpdateSpotMarket>,
         withdraw_guard_threshold: u64,
@@ -609,12 +970,16 @@ pub mod drift {
         optimal_utilization: u32,
         optimal_borrow_rate: u32,
         max_borrow_rate: u32,
+        min_borrow_rate: u32,
+        borrow_rate_kinks: Vec<BorrowRateKink>,
     ) -> Result<()> {
         handle_update_spot_market_borrow_rate(
             ctx,
             optimal_utilization,
             optimal_borrow_rate,
             max_borrow_rate,
+            min_borrow_rate,
+            borrow_rate_kinks,
         )
     }
 
@@ -774,6 +1139,41 @@ pub mod drift {
         handle_update_perp_market_max_spread(ctx, max_spread)
     }
 
+    pub fn update_perp_market_spread_model(
+        ctx: Context<AdminUpdatePerpMarket>,
+        spread_model: SpreadModel,
+        volatility_spread_scale: u32,
+        intensity_spread_scale: u32,
+    ) -> Result<()> {
+        handle_update_perp_market_spread_model(
+            ctx,
+            spread_model,
+            volatility_spread_scale,
+            intensity_spread_scale,
+        )
+    }
+
+    pub fn update_perp_market_funding_params(
+        ctx: Context<AdminUpdatePerpMarket>,
+        funding_max_price_spread: u32,
+        funding_interest_rate: i32,
+        funding_premium_sampling: FundingPremiumSampling,
+    ) -> Result<()> {
+        handle_update_perp_market_funding_params(
+            ctx,
+            funding_max_price_spread,
+            funding_interest_rate,
+            funding_premium_sampling,
+        )
+    }
+
+    pub fn update_perp_market_amm_mode(
+        ctx: Context<AdminUpdatePerpMarket>,
+        amm_mode: AmmMode,
+    ) -> Result<()> {
+        handle_update_perp_market_amm_mode(ctx, amm_mode)
+    }
+
     pub fn update_perp_market_step_size_and_tick_size(
         ctx: Context<AdminUpdatePerpMarket>,
         step_size: u64,
@@ -866,4 +1266,26 @@ pub mod drift {
     ) -> Result<()> {
         handle_admin_remove_insurance_fund_stake(ctx, market_index, amount)
     }
+
+    pub fn initialize_backstop_vault(
+        ctx: Context<InitializeBackstopVault>,
+        liquidation_delay_slots: u64,
+        unstaking_period: i64,
+    ) -> Result<()> {
+        handle_initialize_backstop_vault(ctx, liquidation_delay_slots, unstaking_period)
+    }
+
+    pub fn update_backstop_vault_liquidation_delay(
+        ctx: Context<AdminUpdateBackstopVault>,
+        liquidation_delay_slots: u64,
+    ) -> Result<()> {
+        handle_update_backstop_vault_liquidation_delay(ctx, liquidation_delay_slots)
+    }
+
+    pub fn update_backstop_vault_unstaking_period(
+        ctx: Context<AdminUpdateBackstopVault>,
+        unstaking_period: i64,
+    ) -> Result<()> {
+        handle_update_backstop_vault_unstaking_period(ctx, unstaking_period)
+    }
 }
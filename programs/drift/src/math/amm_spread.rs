@@ -209,6 +209,96 @@ pub fn calculate_spread(
     Ok((long_spread.cast::<u32>()?, short_spread.cast::<u32>()?))
 }
 
+// widens the legacy spread with realized volatility on both sides and with
+// one-sided flow intensity on the side takers have been hitting
+pub fn calculate_volatility_intensity_spread(
+    long_spread: u32,
+    short_spread: u32,
+    mark_std: u64,
+    oracle_std: u64,
+    long_intensity_volume: u64,
+    short_intensity_volume: u64,
+    reserve_price: u64,
+    volatility_spread_scale: u32,
+    intensity_spread_scale: u32,
+    max_spread: u64,
+) -> DriftResult<(u32, u32)> {
+    let volatility_spread =
+        calculate_volatility_spread(mark_std, oracle_std, reserve_price, volatility_spread_scale)?;
+
+    let (long_intensity_spread, short_intensity_spread) = calculate_intensity_spread(
+        long_intensity_volume,
+        short_intensity_volume,
+        intensity_spread_scale,
+    )?;
+
+    let (long_spread, short_spread) = cap_to_max_spread(
+        long_spread
+            .cast::<u64>()?
+            .safe_add(volatility_spread)?
+            .safe_add(long_intensity_spread)?,
+        short_spread
+            .cast::<u64>()?
+            .safe_add(volatility_spread)?
+            .safe_add(short_intensity_spread)?,
+        max_spread,
+    )?;
+
+    Ok((long_spread.cast::<u32>()?, short_spread.cast::<u32>()?))
+}
+
+pub fn calculate_volatility_spread(
+    mark_std: u64,
+    oracle_std: u64,
+    reserve_price: u64,
+    volatility_spread_scale: u32,
+) -> DriftResult<u64> {
+    if reserve_price == 0 {
+        return Ok(0);
+    }
+
+    let volatility_pct = mark_std
+        .max(oracle_std)
+        .cast::<u128>()?
+        .safe_mul(BID_ASK_SPREAD_PRECISION_U128)?
+        .safe_div(reserve_price.cast()?)?;
+
+    volatility_pct
+        .safe_mul(volatility_spread_scale.cast()?)?
+        .safe_div(BID_ASK_SPREAD_PRECISION_U128)?
+        .cast()
+}
+
+pub fn calculate_intensity_spread(
+    long_intensity_volume: u64,
+    short_intensity_volume: u64,
+    intensity_spread_scale: u32,
+) -> DriftResult<(u64, u64)> {
+    let total_intensity_volume = long_intensity_volume
+        .cast::<u128>()?
+        .safe_add(short_intensity_volume.cast()?)?;
+
+    if total_intensity_volume == 0 {
+        return Ok((0, 0));
+    }
+
+    let imbalance = long_intensity_volume
+        .cast::<i128>()?
+        .safe_sub(short_intensity_volume.cast()?)?;
+
+    let intensity_spread = imbalance
+        .unsigned_abs()
+        .safe_mul(intensity_spread_scale.cast()?)?
+        .safe_div(total_intensity_volume)?
+        .cast::<u64>()?;
+
+    if imbalance > 0 {
+        Ok((intensity_spread, 0))
+    } else {
+        Ok((0, intensity_spread))
+    }
+}
+
 pub fn get_spread_reserves(amm: &AMM, direction: PositionDirection) -> DriftResult<(u128, u128)> {
     let (base_asset_reserve, quote_asset_reserve) = match direction {
         PositionDirection::Long => (amm.ask_base_asset_reserve, amm.ask_quote_asset_reserve),
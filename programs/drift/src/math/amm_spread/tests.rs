@@ -2,9 +2,11 @@
 mod test {
     use crate::math::amm_spread::*;
     use crate::math::constants::{
-        AMM_RESERVE_PRECISION, BID_ASK_SPREAD_PRECISION, BID_ASK_SPREAD_PRECISION_I64,
-        QUOTE_PRECISION, QUOTE_PRECISION_I128,
+        AMM_RESERVE_PRECISION, BID_ASK_SPREAD_PRECISION, BID_ASK_SPREAD_PRECISION_I64, ONE_HOUR,
+        PRICE_PRECISION_U64, QUOTE_PRECISION, QUOTE_PRECISION_I128,
     };
+    use crate::math::stats::calculate_rolling_sum;
+    use crate::state::perp_market::SpreadModel;
 
     #[test]
     fn max_spread_tests() {
@@ -414,4 +416,168 @@ mod test {
         assert_eq!(long_spread1, 18330);
         assert_eq!(short_spread1, 500);
     }
+
+    #[test]
+    fn volatility_intensity_spread_tests() {
+        let reserve_price = 100 * PRICE_PRECISION_U64;
+
+        // std of $1 on a $100 market at 1x scale adds 1% to each side
+        let volatility_spread = calculate_volatility_spread(
+            PRICE_PRECISION_U64,
+            PRICE_PRECISION_U64 / 2,
+            reserve_price,
+            BID_ASK_SPREAD_PRECISION as u32,
+        )
+        .unwrap();
+        assert_eq!(volatility_spread, 10000);
+
+        // uses the larger of mark and oracle std
+        let volatility_spread = calculate_volatility_spread(
+            PRICE_PRECISION_U64 / 2,
+            PRICE_PRECISION_U64,
+            reserve_price,
+            2 * BID_ASK_SPREAD_PRECISION as u32,
+        )
+        .unwrap();
+        assert_eq!(volatility_spread, 20000);
+
+        let volatility_spread =
+            calculate_volatility_spread(PRICE_PRECISION_U64, 0, 0, BID_ASK_SPREAD_PRECISION as u32)
+                .unwrap();
+        assert_eq!(volatility_spread, 0);
+
+        // 3:1 long flow widens the ask by half the scale
+        let (long_intensity_spread, short_intensity_spread) =
+            calculate_intensity_spread(300, 100, 1000).unwrap();
+        assert_eq!(long_intensity_spread, 500);
+        assert_eq!(short_intensity_spread, 0);
+
+        let (long_intensity_spread, short_intensity_spread) =
+            calculate_intensity_spread(100, 300, 1000).unwrap();
+        assert_eq!(long_intensity_spread, 0);
+        assert_eq!(short_intensity_spread, 500);
+
+        let (long_intensity_spread, short_intensity_spread) =
+            calculate_intensity_spread(0, 0, 1000).unwrap();
+        assert_eq!(long_intensity_spread, 0);
+        assert_eq!(short_intensity_spread, 0);
+
+        let (long_spread, short_spread) = calculate_volatility_intensity_spread(
+            250,
+            250,
+            PRICE_PRECISION_U64,
+            0,
+            300,
+            100,
+            reserve_price,
+            (BID_ASK_SPREAD_PRECISION / 10) as u32,
+            1000,
+            20000,
+        )
+        .unwrap();
+        assert_eq!(long_spread, 1750);
+        assert_eq!(short_spread, 1250);
+
+        // still respects max spread
+        let (long_spread, short_spread) = calculate_volatility_intensity_spread(
+            250,
+            250,
+            PRICE_PRECISION_U64,
+            0,
+            300,
+            100,
+            reserve_price,
+            (BID_ASK_SPREAD_PRECISION / 10) as u32,
+            1000,
+            2000,
+        )
+        .unwrap();
+        assert_eq!(long_spread, 1750);
+        assert_eq!(short_spread, 250);
+    }
+
+    #[test]
+    fn volatility_intensity_spread_reduces_adverse_selection() {
+        // replay a path that trends up, chops, then trends down, quoting off the last price.
+        // takers only trade when the next price is through the quote, so every fill is
+        // adverse selection for the amm
+        let mut prices = vec![100 * PRICE_PRECISION_U64];
+        for i in 0..120 {
+            let last_price = *prices.last().unwrap();
+            let next_price = if i < 40 {
+                last_price * 1005 / 1000
+            } else if i < 80 {
+                if i % 2 == 0 {
+                    last_price * 1001 / 1000
+                } else {
+                    last_price * 1000 / 1001
+                }
+            } else {
+                last_price * 995 / 1000
+            };
+            prices.push(next_price);
+        }
+
+        let base_spread = 1000_u32;
+        let max_spread = 50000_u64;
+
+        let replay = |spread_model: SpreadModel| -> u64 {
+            let mut adverse_selection = 0_u64;
+            let mut mark_std = 0_u64;
+            let mut long_intensity_volume = 0_u64;
+            let mut short_intensity_volume = 0_u64;
+
+            for window in prices.windows(2) {
+                let (price, next_price) = (window[0], window[1]);
+
+                let (long_spread, short_spread) = match spread_model {
+                    SpreadModel::Legacy => (base_spread / 2, base_spread / 2),
+                    SpreadModel::VolatilityIntensity => calculate_volatility_intensity_spread(
+                        base_spread / 2,
+                        base_spread / 2,
+                        mark_std,
+                        0,
+                        long_intensity_volume,
+                        short_intensity_volume,
+                        price,
+                        BID_ASK_SPREAD_PRECISION as u32,
+                        base_spread,
+                        max_spread,
+                    )
+                    .unwrap(),
+                };
+
+                let ask = price * (BID_ASK_SPREAD_PRECISION + long_spread as u64)
+                    / BID_ASK_SPREAD_PRECISION;
+                let bid = price * (BID_ASK_SPREAD_PRECISION - short_spread as u64)
+                    / BID_ASK_SPREAD_PRECISION;
+
+                let (long_quote_amount, short_quote_amount) = if next_price > ask {
+                    adverse_selection += next_price - ask;
+                    (ask, 0)
+                } else if next_price < bid {
+                    adverse_selection += bid - next_price;
+                    (0, bid)
+                } else {
+                    (0, 0)
+                };
+
+                long_intensity_volume =
+                    calculate_rolling_sum(long_intensity_volume, long_quote_amount, 60, ONE_HOUR)
+                        .unwrap();
+                short_intensity_volume =
+                    calculate_rolling_sum(short_intensity_volume, short_quote_amount, 60, ONE_HOUR)
+                        .unwrap();
+                mark_std = (next_price as i64 - price as i64).unsigned_abs();
+            }
+
+            adverse_selection
+        };
+
+        let legacy_adverse_selection = replay(SpreadModel::Legacy);
+        let v2_adverse_selection = replay(SpreadModel::VolatilityIntensity);
+
+        assert!(legacy_adverse_selection > 0);
+        assert!(v2_adverse_selection * 4 < legacy_adverse_selection);
+    }
 }
@@ -6,9 +6,13 @@ pub const LAMPORTS_PER_SOL_I64: i64 = LAMPORTS_PER_SOL as i64;
 pub const QUOTE_SPOT_MARKET_INDEX: u16 = 0;
 
 // USER ACCOUNT CONSTANTS
-pub const MAX_SPOT_POSITIONS: u8 = 8;
-pub const MAX_PERP_POSITIONS: u8 = 8;
-pub const MAX_OPEN_ORDERS: u8 = 32;
+pub const MAX_SPOT_POSITIONS: u8 = 16;
+pub const MAX_PERP_POSITIONS: u8 = 16;
+pub const MAX_OPEN_ORDERS: u8 = 64;
+// capacity of user accounts created before MAX_* were raised, see resize_user
+pub const LEGACY_MAX_SPOT_POSITIONS: u8 = 8;
+pub const LEGACY_MAX_PERP_POSITIONS: u8 = 8;
+pub const LEGACY_MAX_OPEN_ORDERS: u8 = 32;
 
 // PRECISIONS
 pub const AMM_RESERVE_PRECISION: u128 = 1_000_000_000; //expo = -9;
@@ -43,6 +47,7 @@ pub const SPOT_WEIGHT_PRECISION_U128: u128 = SPOT_WEIGHT_PRECISION as u128; // e
 pub const SPOT_BALANCE_PRECISION: u128 = 1_000_000_000; // expo = -9
 pub const SPOT_BALANCE_PRECISION_U64: u64 = 1_000_000_000; // expo = -9
 pub const SPOT_CUMULATIVE_INTEREST_PRECISION: u128 = 10_000_000_000; // expo = -10
+pub const IF_REWARD_PER_SHARE_PRECISION: u128 = 1_000_000_000_000; // expo = -12
 
 pub const PERCENTAGE_PRECISION: u128 = 1_000_000; // expo -6 (represents 100%)
 pub const PERCENTAGE_PRECISION_I128: i128 = PERCENTAGE_PRECISION as i128;
@@ -63,6 +68,7 @@ pub const SPOT_UTILIZATION_PRECISION: u128 = PERCENTAGE_PRECISION; // expo = -6
 pub const SPOT_UTILIZATION_PRECISION_U32: u32 = PERCENTAGE_PRECISION as u32; // expo = -6
 pub const SPOT_RATE_PRECISION: u128 = PERCENTAGE_PRECISION; // expo = -6
 pub const SPOT_RATE_PRECISION_U32: u32 = PERCENTAGE_PRECISION as u32; // expo = -6
+pub const MAX_BORROW_RATE_KINKS: usize = 4;
 pub const LIQUIDATION_FEE_PRECISION: u32 = PERCENTAGE_PRECISION as u32; // expo = -6
 pub const LIQUIDATION_FEE_PRECISION_U128: u128 = LIQUIDATION_FEE_PRECISION as u128; // expo = -6
 pub const SPOT_IMF_PRECISION: u32 = PERCENTAGE_PRECISION as u32; // expo = -6
@@ -109,6 +115,8 @@ pub const SHARE_OF_IF_ESCROW_ALLOCATED_TO_PROTOCOL_DENOMINATOR: u128 = 2;
 pub const SHARE_OF_REVENUE_ALLOCATED_TO_INSURANCE_FUND_VAULT_NUMERATOR: u128 = 1;
 pub const SHARE_OF_REVENUE_ALLOCATED_TO_INSURANCE_FUND_VAULT_DENOMINATOR: u128 = 1;
 
+pub const INSURANCE_COVERAGE_MAX_STALENESS: i64 = ONE_HOUR; // coverage ratio older than this can't be trusted to gate orders
+
 // TIME PERIODS
 pub const FIVE_MINUTE: i128 = (60 * 5) as i128;
 pub const ONE_HOUR: i64 = 3600;
@@ -146,6 +154,7 @@ pub const ONE_BPS_DENOMINATOR: u32 = 10000;
 pub const MAX_REFERRER_REWARD_EPOCH_UPPER_BOUND: u64 = (4000 * QUOTE_PRECISION) as u64;
 pub const LP_FEE_SLICE_NUMERATOR: u128 = 8;
 pub const LP_FEE_SLICE_DENOMINATOR: u128 = 10;
+pub const MAX_LP_RANGES: u8 = 8;
 pub const FEE_DENOMINATOR: u32 = 10 * ONE_BPS_DENOMINATOR;
 pub const FEE_PERCENTAGE_DENOMINATOR: u32 = 100;
 
@@ -165,12 +174,22 @@ pub const MAX_APR_PER_REVENUE_SETTLE_TO_INSURANCE_FUND_VAULT: u64 = 10 * PERCENT
 pub const MAX_CONCENTRATION_COEFFICIENT: u128 = 1_414_200;
 pub const MAX_LIQUIDATION_SLIPPAGE: i128 = 10_000; // expo = -2
 pub const MAX_LIQUIDATION_SLIPPAGE_U128: u128 = 10_000; // expo = -2
+pub const MAX_LIQUIDATOR_FEE_RAMP_SLOTS: u32 = 9_000; // ~1 hour of slots
 pub const MAX_MARK_TWAP_DIVERGENCE: u128 = 500_000; // expo = -3
+pub const KEEPER_REPEG_TERMINAL_DIVERGENCE: u64 = PERCENTAGE_PRECISION_U64 / 100; // 1%
+pub const KEEPER_REPEG_MIN_SLOTS: u64 = 150; // ~1 minute of slots
+pub const LP_HEDGE_MAX_ORACLE_SLIPPAGE: u64 = PERCENTAGE_PRECISION_U64 / 200; // .5%
 
 pub const MAX_MARGIN_RATIO: u32 = MARGIN_PRECISION as u32; // 1x leverage
 pub const MIN_MARGIN_RATIO: u32 = MARGIN_PRECISION as u32 / 50; // 50x leverage
 
 pub const MAX_BID_ASK_INVENTORY_SKEW_FACTOR: u64 = 10 * BID_ASK_SPREAD_PRECISION;
+pub const MAX_VOLATILITY_SPREAD_SCALE: u64 = 10 * BID_ASK_SPREAD_PRECISION;
+
+// FUNDING
+pub const MAX_FUNDING_PRICE_SPREAD: u32 = (PERCENTAGE_PRECISION / 10) as u32; // 10%
+pub const MAX_FUNDING_INTEREST_RATE: u32 = (PERCENTAGE_PRECISION / 100) as u32; // 1% per 24h
+pub const MAX_USERS_PER_FUNDING_SETTLE: usize = 20;
 
 pub const MAX_POSITIVE_UPNL_FOR_INITIAL_MARGIN: i128 = 100 * QUOTE_PRECISION_I128; // max upnl for initial margin calc
 pub const DEFAULT_MAX_TWAP_UPDATE_PRICE_BAND_DENOMINATOR: i64 = 3; // '3' here means clamp new data point to 33% (1/3) divergence from current twap (if twap > 0)
@@ -341,7 +341,7 @@ fn calculate_k_with_lps_tests() {
     market.amm.quote_asset_amount_per_lp = -QUOTE_PRECISION_I64 as i128;
 
     let reserve_price = market.amm.reserve_price().unwrap();
-    update_spreads(&mut market.amm, reserve_price).unwrap();
+    update_spreads(&mut market, reserve_price).unwrap();
 
     settle_lp_position(&mut position, &mut market).unwrap();
 
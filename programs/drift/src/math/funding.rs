@@ -2,17 +2,18 @@ use std::cmp::{max, min};
 
 use solana_program::msg;
 
+use crate::controller::position::PositionDirection;
 use crate::error::{DriftResult, ErrorCode};
 use crate::math::bn;
 use crate::math::casting::Cast;
 use crate::math::constants::{
     AMM_TO_QUOTE_PRECISION_RATIO, AMM_TO_QUOTE_PRECISION_RATIO_I128, FUNDING_RATE_BUFFER,
-    ONE_HOUR_I128, PRICE_PRECISION, QUOTE_TO_BASE_AMT_FUNDING_PRECISION,
+    ONE_HOUR_I128, PERCENTAGE_PRECISION_I128, PRICE_PRECISION, QUOTE_TO_BASE_AMT_FUNDING_PRECISION,
 };
 use crate::math::repeg::{calculate_fee_pool, get_total_fee_lower_bound};
 use crate::math::safe_math::SafeMath;
 
-use crate::state::perp_market::PerpMarket;
+use crate::state::perp_market::{FundingPremiumSampling, PerpMarket, AMM};
 use crate::state::user::PerpPosition;
 
 #[cfg(test)]
@@ -22,6 +23,19 @@ pub fn calculate_funding_rate(
     mid_price_twap: u128,
     oracle_price_twap: i128,
     funding_period: i64,
+) -> DriftResult<i128> {
+    calculate_funding_rate_with_params(mid_price_twap, oracle_price_twap, funding_period, 0, 0)
+}
+
+/// Funding rate from the mark/oracle premium plus an interest rate component.
+/// max_price_spread and interest_rate are PERCENTAGE_PRECISION of the oracle price,
+/// a max_price_spread of 0 keeps the default 1/33 (~3%) clamp.
+pub fn calculate_funding_rate_with_params(
+    mark_price: u128,
+    oracle_price: i128,
+    funding_period: i64,
+    max_price_spread: u32,
+    interest_rate: i32,
 ) -> DriftResult<i128> {
     // funding period = 1 hour, window = 1 day
     // low periodicity => quickly updating/settled funding rates
@@ -30,10 +44,23 @@ pub fn calculate_funding_rate(
         .safe_mul(ONE_HOUR_I128)?
         .safe_div(max(ONE_HOUR_I128, funding_period as i128))?;
 
-    let price_spread = mid_price_twap.cast::<i128>()?.safe_sub(oracle_price_twap)?;
+    let interest_spread = oracle_price
+        .safe_mul(interest_rate.cast()?)?
+        .safe_div(PERCENTAGE_PRECISION_I128)?;
+
+    let price_spread = mark_price
+        .cast::<i128>()?
+        .safe_sub(oracle_price)?
+        .safe_add(interest_spread)?;
 
-    // clamp price divergence to 3% for funding rate calculation
-    let max_price_spread = oracle_price_twap.safe_div(33)?; // 3%
+    // clamp price divergence for funding rate calculation
+    let max_price_spread = if max_price_spread == 0 {
+        oracle_price.safe_div(33)? // 3%
+    } else {
+        oracle_price
+            .safe_mul(max_price_spread.cast()?)?
+            .safe_div(PERCENTAGE_PRECISION_I128)?
+    };
     let clamped_price_spread = max(-max_price_spread, min(price_spread, max_price_spread));
 
     let funding_rate = clamped_price_spread
@@ -43,6 +70,61 @@ pub fn calculate_funding_rate(
     Ok(funding_rate)
 }
 
+// price relates to execution premium / direction
+pub fn calculate_execution_premium_price(
+    amm: &AMM,
+    reserve_price: u64,
+) -> DriftResult<(u64, Option<PositionDirection>)> {
+    Ok(if amm.long_spread > amm.short_spread {
+        (amm.ask_price(reserve_price)?, Some(PositionDirection::Long))
+    } else if amm.long_spread < amm.short_spread {
+        (
+            amm.bid_price(reserve_price)?,
+            Some(PositionDirection::Short),
+        )
+    } else {
+        (reserve_price, None)
+    })
+}
+
+/// Funding rate using the market's premium sampling mode and funding params.
+pub fn calculate_funding_rate_for_market(
+    market: &PerpMarket,
+    mark_price_twap: u64,
+    oracle_price_twap: i64,
+    execution_premium_price: u64,
+    oracle_price: i64,
+) -> DriftResult<i64> {
+    let (premium_mark_price, premium_oracle_price) = match market.funding_premium_sampling {
+        FundingPremiumSampling::TimeWeighted => (mark_price_twap, oracle_price_twap),
+        FundingPremiumSampling::Last => (execution_premium_price, oracle_price),
+    };
+
+    calculate_funding_rate_with_params(
+        premium_mark_price.cast()?,
+        premium_oracle_price.cast()?,
+        market.amm.funding_period,
+        market.funding_max_price_spread,
+        market.funding_interest_rate,
+    )?
+    .cast()
+}
+
+/// Funding rate the next funding update would apply if the mark and oracle
+/// stayed at their last recorded values.
+pub fn calculate_predicted_funding_rate(market: &PerpMarket) -> DriftResult<i64> {
+    let (execution_premium_price, _) =
+        calculate_execution_premium_price(&market.amm, market.amm.reserve_price()?)?;
+
+    calculate_funding_rate_for_market(
+        market,
+        market.amm.last_mark_price_twap,
+        market.amm.historical_oracle_data.last_oracle_price_twap,
+        execution_premium_price,
+        market.amm.historical_oracle_data.last_oracle_price,
+    )
+}
+
 /// With a virtual AMM, there can be an imbalance between longs and shorts and thus funding can be asymmetric.
 /// To account for this, amm keeps track of the cumulative funding rate for both longs and shorts.
 /// When there is a period with asymmetric funding, the protocol will pay/receive funding from/to it's collected fees.
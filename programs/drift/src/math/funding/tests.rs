@@ -3,7 +3,7 @@ use crate::math::constants::{
 };
 use crate::math::funding::*;
 use crate::state::oracle::HistoricalOracleData;
-use crate::state::perp_market::{PerpMarket, AMM};
+use crate::state::perp_market::{FundingPremiumSampling, PerpMarket, AMM};
 
 #[test]
 fn capped_sym_funding_test() {
@@ -328,3 +328,77 @@ fn funding_unsettled_lps_amm_lose_test() {
     let new_fees = market.amm.total_fee_minus_distributions;
     assert_eq!(new_fees, 416667); // lost
 }
+
+#[test]
+fn funding_rate_with_params_test() {
+    let mark_price = 50 * PRICE_PRECISION;
+    let oracle_price = (49 * PRICE_PRECISION) as i128;
+
+    // defaults match the legacy formula
+    let funding_rate =
+        calculate_funding_rate_with_params(mark_price, oracle_price, 3600, 0, 0).unwrap();
+    assert_eq!(funding_rate, 41666666);
+    assert_eq!(
+        funding_rate,
+        calculate_funding_rate(mark_price, oracle_price, 3600).unwrap()
+    );
+
+    // 1% clamp
+    let funding_rate =
+        calculate_funding_rate_with_params(mark_price, oracle_price, 3600, 10_000, 0).unwrap();
+    assert_eq!(funding_rate, 20416666);
+
+    let funding_rate =
+        calculate_funding_rate_with_params(48 * PRICE_PRECISION, oracle_price, 3600, 10_000, 0)
+            .unwrap();
+    assert_eq!(funding_rate, -20416666);
+
+    // .01% interest rate on top of the premium
+    let funding_rate =
+        calculate_funding_rate_with_params(mark_price, oracle_price, 3600, 0, 100).unwrap();
+    assert_eq!(funding_rate, 41870833);
+
+    // interest rate alone when mark == oracle
+    let funding_rate =
+        calculate_funding_rate_with_params(49 * PRICE_PRECISION, oracle_price, 3600, 0, 100)
+            .unwrap();
+    assert_eq!(funding_rate, 204166);
+
+    // interest rate is clamped with the premium
+    let funding_rate =
+        calculate_funding_rate_with_params(49 * PRICE_PRECISION, oracle_price, 3600, 50, 100)
+            .unwrap();
+    assert_eq!(funding_rate, 102083);
+}
+
+#[test]
+fn predicted_funding_rate_test() {
+    let mut market = PerpMarket {
+        amm: AMM {
+            last_mark_price_twap: 50 * PRICE_PRECISION_U64,
+            historical_oracle_data: HistoricalOracleData {
+                last_oracle_price_twap: (49 * PRICE_PRECISION) as i64,
+                last_oracle_price: 990_000,
+                ..HistoricalOracleData::default()
+            },
+            funding_period: 3600,
+            ..AMM::default_test()
+        },
+        ..PerpMarket::default_test()
+    };
+
+    // same as the twap based funding rate
+    assert_eq!(
+        calculate_predicted_funding_rate(&market).unwrap(),
+        calculate_funding_rate(
+            market.amm.last_mark_price_twap as u128,
+            market.amm.historical_oracle_data.last_oracle_price_twap as i128,
+            market.amm.funding_period,
+        )
+        .unwrap() as i64
+    );
+
+    // reserve price of $1 vs last oracle price of $.99
+    market.funding_premium_sampling = FundingPremiumSampling::Last;
+    assert_eq!(calculate_predicted_funding_rate(&market).unwrap(), 416666);
+}
@@ -2,11 +2,18 @@ use solana_program::msg;
 
 use crate::error::{DriftResult, ErrorCode};
 use crate::math::casting::Cast;
+use crate::math::constants::{
+    IF_REWARD_PER_SHARE_PRECISION, INSURANCE_COVERAGE_MAX_STALENESS, PERCENTAGE_PRECISION,
+    SHARE_OF_IF_ESCROW_ALLOCATED_TO_PROTOCOL_DENOMINATOR,
+    SHARE_OF_IF_ESCROW_ALLOCATED_TO_PROTOCOL_NUMERATOR,
+};
 use crate::math::helpers::{get_proportion_u128, log10_iter};
+use crate::math::position::calculate_base_asset_value_with_oracle_price;
 use crate::math::safe_math::SafeMath;
 
 use crate::state::insurance_fund_stake::InsuranceFundStake;
-use crate::state::spot_market::SpotMarket;
+use crate::state::perp_market::PerpMarket;
+use crate::state::spot_market::InsuranceFundMarket;
 use crate::validate;
 
 #[cfg(test)]
@@ -83,21 +90,21 @@ pub fn calculate_rebase_info(
 
 pub fn calculate_if_shares_lost(
     insurance_fund_stake: &InsuranceFundStake,
-    spot_market: &SpotMarket,
+    market: &impl InsuranceFundMarket,
     insurance_fund_vault_balance: u64,
 ) -> DriftResult<u128> {
     let n_shares = insurance_fund_stake.last_withdraw_request_shares;
 
     let amount = if_shares_to_vault_amount(
         n_shares,
-        spot_market.insurance_fund.total_shares,
+        market.insurance_fund().total_shares,
         insurance_fund_vault_balance,
     )?;
 
     let if_shares_lost = if amount > insurance_fund_stake.last_withdraw_request_value {
         let new_n_shares = vault_amount_to_if_shares(
             insurance_fund_stake.last_withdraw_request_value,
-            spot_market.insurance_fund.total_shares - n_shares,
+            market.insurance_fund().total_shares - n_shares,
             insurance_fund_vault_balance - insurance_fund_stake.last_withdraw_request_value,
         )?;
 
@@ -116,3 +123,171 @@ pub fn calculate_if_shares_lost(
 
     Ok(if_shares_lost)
 }
+
+// returns (haircut shares, portion of the haircut allocated to the protocol)
+pub fn calculate_instant_unstake_haircut_shares(
+    n_shares: u128,
+    instant_unstake_haircut: u32,
+) -> DriftResult<(u128, u128)> {
+    let haircut_shares = get_proportion_u128(
+        n_shares,
+        instant_unstake_haircut.cast()?,
+        PERCENTAGE_PRECISION,
+    )?;
+
+    let protocol_shares = get_proportion_u128(
+        haircut_shares,
+        SHARE_OF_IF_ESCROW_ALLOCATED_TO_PROTOCOL_NUMERATOR,
+        SHARE_OF_IF_ESCROW_ALLOCATED_TO_PROTOCOL_DENOMINATOR,
+    )?;
+
+    Ok((haircut_shares, protocol_shares))
+}
+
+pub fn calculate_reward_per_share_delta(
+    reward_emission_rate: u64,
+    elapsed: i64,
+    user_if_shares: u128,
+) -> DriftResult<u128> {
+    if user_if_shares == 0 {
+        return Ok(0);
+    }
+
+    reward_emission_rate
+        .cast::<u128>()?
+        .safe_mul(elapsed.cast()?)?
+        .safe_mul(IF_REWARD_PER_SHARE_PRECISION)?
+        .safe_div(user_if_shares)
+}
+
+// rebase_divisor converts a checkpoint taken before the insurance fund rebased into the current share units
+pub fn calculate_if_stake_rewards(
+    if_shares: u128,
+    reward_per_share: u128,
+    reward_per_share_checkpoint: u128,
+    rebase_divisor: u128,
+) -> DriftResult<u64> {
+    let reward_per_share_delta =
+        reward_per_share.safe_sub(reward_per_share_checkpoint.safe_mul(rebase_divisor)?)?;
+
+    if_shares
+        .safe_mul(reward_per_share_delta)?
+        .safe_div(IF_REWARD_PER_SHARE_PRECISION)?
+        .safe_div(rebase_divisor)?
+        .cast()
+}
+
+// shares_mint tokens are if shares at shares_mint_base, so they need scaling up after each rebase
+fn get_shares_mint_precision_increase(
+    shares_base: u128,
+    shares_mint_base: u128,
+) -> DriftResult<u128> {
+    let expo_diff = shares_base.safe_sub(shares_mint_base)?.cast::<u32>()?;
+    Ok(10_u128.pow(expo_diff))
+}
+
+pub fn if_shares_to_shares_mint_amount(
+    n_shares: u128,
+    shares_base: u128,
+    shares_mint_base: u128,
+) -> DriftResult<u64> {
+    n_shares
+        .safe_mul(get_shares_mint_precision_increase(
+            shares_base,
+            shares_mint_base,
+        )?)?
+        .cast()
+}
+
+pub fn shares_mint_amount_to_if_shares(
+    amount: u64,
+    shares_base: u128,
+    shares_mint_base: u128,
+) -> DriftResult<u128> {
+    amount
+        .cast::<u128>()?
+        .safe_div(get_shares_mint_precision_increase(
+            shares_base,
+            shares_mint_base,
+        )?)
+}
+
+pub fn calculate_perp_market_weighted_open_interest(
+    perp_market: &PerpMarket,
+    oracle_price: i64,
+) -> DriftResult<u128> {
+    let open_interest_value = calculate_base_asset_value_with_oracle_price(
+        perp_market.get_open_interest().cast()?,
+        oracle_price,
+    )?;
+
+    get_proportion_u128(
+        open_interest_value,
+        perp_market.contract_tier.get_insurance_coverage_weight(),
+        PERCENTAGE_PRECISION,
+    )
+}
+
+// insurance a perp market can draw on: its own insurance fund plus what's left of its claim on the quote insurance fund
+pub fn calculate_perp_market_insurance_value(
+    perp_market: &PerpMarket,
+    insurance_fund_vault_balance: u64,
+    perp_market_insurance_fund_vault_balance: u64,
+) -> DriftResult<u128> {
+    let max_insurance_claim = perp_market
+        .insurance_claim
+        .quote_max_insurance
+        .saturating_sub(perp_market.insurance_claim.quote_settled_insurance);
+
+    perp_market_insurance_fund_vault_balance
+        .cast::<u128>()?
+        .safe_add(
+            insurance_fund_vault_balance
+                .min(max_insurance_claim)
+                .cast()?,
+        )
+}
+
+pub fn calculate_insurance_coverage_ratio(
+    insurance_value: u128,
+    weighted_open_interest: u128,
+) -> DriftResult<u32> {
+    if weighted_open_interest == 0 {
+        return Ok(u32::MAX);
+    }
+
+    insurance_value
+        .safe_mul(PERCENTAGE_PRECISION)?
+        .safe_div(weighted_open_interest)?
+        .min(u32::MAX as u128)
+        .cast()
+}
+
+// risk increasing orders need a recent coverage ratio at or above the market min, a market that
+// was never updated is stale
+pub fn validate_insurance_coverage_for_risk_increasing_order(
+    perp_market: &PerpMarket,
+    now: i64,
+) -> DriftResult {
+    if perp_market.min_insurance_coverage_ratio == 0 {
+        return Ok(());
+    }
+
+    validate!(
+        now.safe_sub(perp_market.last_insurance_coverage_update_ts)?
+            <= INSURANCE_COVERAGE_MAX_STALENESS,
+        ErrorCode::StaleInsuranceCoverage,
+        "market {} insurance coverage last updated at {}",
+        perp_market.market_index,
+        perp_market.last_insurance_coverage_update_ts
+    )?;
+
+    validate!(
+        perp_market.insurance_coverage_ratio >= perp_market.min_insurance_coverage_ratio,
+        ErrorCode::InsufficientInsuranceCoverage,
+        "market {} insurance coverage ratio {} below min {}",
+        perp_market.market_index,
+        perp_market.insurance_coverage_ratio,
+        perp_market.min_insurance_coverage_ratio
+    )
+}
@@ -1,9 +1,14 @@
 use anchor_lang::prelude::Pubkey;
 
-use crate::math::constants::{QUOTE_PRECISION, SPOT_CUMULATIVE_INTEREST_PRECISION};
+use crate::error::ErrorCode;
+use crate::math::constants::{
+    BASE_PRECISION_I128, IF_REWARD_PER_SHARE_PRECISION, INSURANCE_COVERAGE_MAX_STALENESS, ONE_HOUR,
+    PRICE_PRECISION_I64, QUOTE_PRECISION, QUOTE_PRECISION_U64, SPOT_CUMULATIVE_INTEREST_PRECISION,
+};
 use crate::math::helpers::log10;
 use crate::math::insurance::*;
-use crate::state::spot_market::InsuranceFund;
+use crate::state::perp_market::{ContractTier, InsuranceClaim, PerpMarket, AMM};
+use crate::state::spot_market::{InsuranceFund, SpotMarket};
 
 #[test]
 pub fn basic_stake_if_test() {
@@ -166,3 +171,164 @@ pub fn if_shares_lost_test() {
         true
     );
 }
+
+#[test]
+pub fn if_stake_reward_math() {
+    // no stakers, nothing accrues
+    let delta = calculate_reward_per_share_delta(100, 10, 0).unwrap();
+    assert_eq!(delta, 0);
+
+    let user_if_shares = 1000 * QUOTE_PRECISION;
+    let delta = calculate_reward_per_share_delta(100, 10, user_if_shares).unwrap();
+    assert_eq!(delta, 1000 * IF_REWARD_PER_SHARE_PRECISION / user_if_shares);
+
+    // a staker with half the shares earns half the emissions
+    let rewards = calculate_if_stake_rewards(user_if_shares / 2, delta, 0, 1).unwrap();
+    assert_eq!(rewards, 500);
+
+    // nothing owed at the checkpoint
+    let rewards = calculate_if_stake_rewards(user_if_shares / 2, delta, delta, 1).unwrap();
+    assert_eq!(rewards, 0);
+
+    // fund rebased by 100 since the stake last settled
+    let rebase_divisor = 100;
+    let rewards = calculate_if_stake_rewards(
+        user_if_shares / 2,
+        delta * 2 * rebase_divisor,
+        delta,
+        rebase_divisor,
+    )
+    .unwrap();
+    assert_eq!(rewards, 500);
+}
+
+#[test]
+pub fn shares_mint_amount_conversion() {
+    let n_shares = 1000 * QUOTE_PRECISION;
+
+    let amount = if_shares_to_shares_mint_amount(n_shares, 0, 0).unwrap();
+    assert_eq!(amount, 1000 * QUOTE_PRECISION as u64);
+    assert_eq!(
+        shares_mint_amount_to_if_shares(amount, 0, 0).unwrap(),
+        n_shares
+    );
+
+    // tokens minted before two rebases are worth 100x fewer shares
+    assert_eq!(
+        shares_mint_amount_to_if_shares(amount, 2, 0).unwrap(),
+        10 * QUOTE_PRECISION
+    );
+    assert_eq!(
+        if_shares_to_shares_mint_amount(10 * QUOTE_PRECISION, 2, 0).unwrap(),
+        amount
+    );
+    assert_eq!(shares_mint_amount_to_if_shares(99, 2, 0).unwrap(), 0);
+
+    // shares base can never be below the mint base
+    assert!(if_shares_to_shares_mint_amount(n_shares, 0, 1).is_err());
+}
+
+#[test]
+pub fn perp_market_insurance_coverage() {
+    let mut perp_market = PerpMarket {
+        amm: AMM {
+            base_asset_amount_long: 100 * BASE_PRECISION_I128,
+            base_asset_amount_short: -50 * BASE_PRECISION_I128,
+            ..AMM::default()
+        },
+        insurance_claim: InsuranceClaim {
+            quote_max_insurance: 10_000 * QUOTE_PRECISION_U64,
+            quote_settled_insurance: 4_000 * QUOTE_PRECISION_U64,
+            ..InsuranceClaim::default()
+        },
+        contract_tier: ContractTier::B,
+        ..PerpMarket::default()
+    };
+
+    // $10k of open interest, half weighted for tier B
+    let weighted_open_interest =
+        calculate_perp_market_weighted_open_interest(&perp_market, 100 * PRICE_PRECISION_I64)
+            .unwrap();
+    assert_eq!(weighted_open_interest, 5_000 * QUOTE_PRECISION);
+
+    // claim on the quote insurance fund capped at remaining max insurance
+    let insurance_value =
+        calculate_perp_market_insurance_value(&perp_market, 100_000 * QUOTE_PRECISION_U64, 0)
+            .unwrap();
+    assert_eq!(insurance_value, 6_000 * QUOTE_PRECISION);
+
+    let insurance_value = calculate_perp_market_insurance_value(
+        &perp_market,
+        1_000 * QUOTE_PRECISION_U64,
+        500 * QUOTE_PRECISION_U64,
+    )
+    .unwrap();
+    assert_eq!(insurance_value, 1_500 * QUOTE_PRECISION);
+
+    let coverage_ratio =
+        calculate_insurance_coverage_ratio(insurance_value, weighted_open_interest).unwrap();
+    assert_eq!(coverage_ratio, 300_000); // 30%
+
+    // speculative open interest doesnt need coverage
+    perp_market.contract_tier = ContractTier::Speculative;
+    let weighted_open_interest =
+        calculate_perp_market_weighted_open_interest(&perp_market, 100 * PRICE_PRECISION_I64)
+            .unwrap();
+    assert_eq!(weighted_open_interest, 0);
+    let coverage_ratio =
+        calculate_insurance_coverage_ratio(insurance_value, weighted_open_interest).unwrap();
+    assert_eq!(coverage_ratio, u32::MAX);
+}
+
+#[test]
+pub fn instant_unstake_haircut_shares() {
+    let (haircut_shares, protocol_shares) =
+        calculate_instant_unstake_haircut_shares(1000 * QUOTE_PRECISION, 0).unwrap();
+    assert_eq!(haircut_shares, 0);
+    assert_eq!(protocol_shares, 0);
+
+    let (haircut_shares, protocol_shares) =
+        calculate_instant_unstake_haircut_shares(1000 * QUOTE_PRECISION, 25_000).unwrap(); // 2.5%
+    assert_eq!(haircut_shares, 25 * QUOTE_PRECISION);
+    assert_eq!(protocol_shares, 12_500_000);
+}
+
+#[test]
+pub fn insurance_coverage_gates_risk_increasing_orders() {
+    let now = 10 * ONE_HOUR;
+    let mut perp_market = PerpMarket {
+        insurance_coverage_ratio: 500_000, // 50%
+        last_insurance_coverage_update_ts: now,
+        ..PerpMarket::default()
+    };
+
+    // no min set, never gated
+    assert!(validate_insurance_coverage_for_risk_increasing_order(&perp_market, now).is_ok());
+
+    perp_market.min_insurance_coverage_ratio = 250_000; // 25%
+    assert!(validate_insurance_coverage_for_risk_increasing_order(&perp_market, now).is_ok());
+
+    perp_market.min_insurance_coverage_ratio = 750_000; // 75%
+    assert_eq!(
+        validate_insurance_coverage_for_risk_increasing_order(&perp_market, now),
+        Err(ErrorCode::InsufficientInsuranceCoverage)
+    );
+
+    // coverage above min but not refreshed recently
+    perp_market.min_insurance_coverage_ratio = 250_000;
+    assert_eq!(
+        validate_insurance_coverage_for_risk_increasing_order(
+            &perp_market,
+            now + INSURANCE_COVERAGE_MAX_STALENESS + 1
+        ),
+        Err(ErrorCode::StaleInsuranceCoverage)
+    );
+
+    // never computed coverage is stale, not zero
+    perp_market.insurance_coverage_ratio = 0;
+    perp_market.last_insurance_coverage_update_ts = 0;
+    assert_eq!(
+        validate_insurance_coverage_for_risk_increasing_order(&perp_market, now),
+        Err(ErrorCode::StaleInsuranceCoverage)
+    );
+}
@@ -257,6 +257,26 @@ pub fn calculate_liquidation_multiplier(
     }
 }
 
+// dutch auction: the liquidator fee ramps linearly from zero to the max once a user is flagged,
+// so competing liquidators take over positions at the best price for the liquidatee
+pub fn calculate_liquidator_fee(
+    max_liquidator_fee: u32,
+    liquidator_fee_ramp_slots: u32,
+    slots_since_liquidation_start: u64,
+) -> DriftResult<u32> {
+    if liquidator_fee_ramp_slots == 0
+        || slots_since_liquidation_start >= liquidator_fee_ramp_slots.cast()?
+    {
+        return Ok(max_liquidator_fee);
+    }
+
+    max_liquidator_fee
+        .cast::<u64>()?
+        .safe_mul(slots_since_liquidation_start)?
+        .safe_div(liquidator_fee_ramp_slots.cast()?)?
+        .cast()
+}
+
 pub fn calculate_funding_rate_deltas_to_resolve_bankruptcy(
     loss: i128,
     market: &PerpMarket,
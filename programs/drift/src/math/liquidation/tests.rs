@@ -433,3 +433,40 @@ mod calculate_cumulative_deposit_interest_delta_to_resolve_bankruptcy {
         assert_eq!(delta, 916666666);
     }
 }
+
+mod calculate_liquidator_fee {
+    use crate::math::constants::LIQUIDATION_FEE_PRECISION;
+    use crate::math::liquidation::calculate_liquidator_fee;
+
+    #[test]
+    fn no_ramp() {
+        let max_liquidator_fee = LIQUIDATION_FEE_PRECISION / 50; // 2%
+
+        let fee = calculate_liquidator_fee(max_liquidator_fee, 0, 0).unwrap();
+        assert_eq!(fee, max_liquidator_fee);
+
+        let fee = calculate_liquidator_fee(max_liquidator_fee, 0, 100).unwrap();
+        assert_eq!(fee, max_liquidator_fee);
+    }
+
+    #[test]
+    fn ramp() {
+        let max_liquidator_fee = LIQUIDATION_FEE_PRECISION / 50; // 2%
+        let ramp_slots = 100;
+
+        let fee = calculate_liquidator_fee(max_liquidator_fee, ramp_slots, 0).unwrap();
+        assert_eq!(fee, 0);
+
+        let fee = calculate_liquidator_fee(max_liquidator_fee, ramp_slots, 25).unwrap();
+        assert_eq!(fee, LIQUIDATION_FEE_PRECISION / 200);
+
+        let fee = calculate_liquidator_fee(max_liquidator_fee, ramp_slots, 50).unwrap();
+        assert_eq!(fee, LIQUIDATION_FEE_PRECISION / 100);
+
+        let fee = calculate_liquidator_fee(max_liquidator_fee, ramp_slots, 100).unwrap();
+        assert_eq!(fee, max_liquidator_fee);
+
+        let fee = calculate_liquidator_fee(max_liquidator_fee, ramp_slots, 1000).unwrap();
+        assert_eq!(fee, max_liquidator_fee);
+    }
+}
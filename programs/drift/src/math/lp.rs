@@ -1,13 +1,19 @@
+use crate::controller::position::PositionDirection;
 use crate::error::DriftResult;
 use crate::math::amm::calculate_market_open_bids_asks;
 use crate::math::casting::Cast;
-use crate::math::constants::AMM_RESERVE_PRECISION_I128;
+use crate::math::constants::{
+    AMM_RESERVE_PRECISION, AMM_RESERVE_PRECISION_I128, LP_HEDGE_MAX_ORACLE_SLIPPAGE,
+    PERCENTAGE_PRECISION_U64,
+};
 use crate::math::helpers;
-use crate::math::orders::standardize_base_asset_amount_with_remainder_i128;
+use crate::math::orders::{
+    standardize_base_asset_amount, standardize_base_asset_amount_with_remainder_i128,
+    standardize_price,
+};
 use crate::math::safe_math::SafeMath;
 
 use crate::state::perp_market::PerpMarket;
-use crate::state::perp_market::AMM;
 use crate::state::user::PerpPosition;
 
 #[cfg(test)]
@@ -18,47 +24,91 @@ pub struct LPMetrics {
     pub base_asset_amount: i128,
     pub quote_asset_amount: i128,
     pub remainder_base_asset_amount: i32,
+    pub fee_amount: i128,
 }
 
-pub fn calculate_settle_lp_metrics(amm: &AMM, position: &PerpPosition) -> DriftResult<LPMetrics> {
-    let (base_asset_amount, quote_asset_amount) = calculate_settled_lp_base_quote(amm, position)?;
+pub fn calculate_settle_lp_metrics(
+    market: &PerpMarket,
+    position: &PerpPosition,
+) -> DriftResult<LPMetrics> {
+    let (base_asset_amount, quote_asset_amount) =
+        calculate_settled_lp_base_quote(market, position)?;
+    let fee_amount = calculate_settled_lp_fee(market, position)?;
 
     // stepsize it
     let (standardized_base_asset_amount, remainder_base_asset_amount) =
         standardize_base_asset_amount_with_remainder_i128(
             base_asset_amount,
-            amm.order_step_size.cast()?,
+            market.amm.order_step_size.cast()?,
         )?;
 
     let lp_metrics = LPMetrics {
         base_asset_amount: standardized_base_asset_amount,
         quote_asset_amount,
         remainder_base_asset_amount: remainder_base_asset_amount.cast()?,
+        fee_amount,
     };
 
     Ok(lp_metrics)
 }
 
+/// Per lp share base, quote and fee accumulators the position settles against. Ranged lps
+/// settle against their range's accumulators, which only grow while the range is active.
+pub fn get_lp_per_share_accumulators(
+    market: &PerpMarket,
+    position: &PerpPosition,
+) -> DriftResult<(i128, i128, u128)> {
+    if position.has_lp_range() {
+        let lp_range =
+            market.get_lp_range(position.lp_min_reserve_price, position.lp_max_reserve_price)?;
+
+        Ok((
+            lp_range.base_asset_amount_per_lp,
+            lp_range.quote_asset_amount_per_lp,
+            lp_range.fee_per_lp,
+        ))
+    } else {
+        Ok((
+            market.amm.base_asset_amount_per_lp,
+            market.amm.quote_asset_amount_per_lp,
+            market.fee_per_lp,
+        ))
+    }
+}
+
+/// Whether the position's lp shares are part of sqrt_k. Shares of an inactive range left it
+/// when the reserve price crossed out of the range.
+pub fn is_lp_position_active(market: &PerpMarket, position: &PerpPosition) -> DriftResult<bool> {
+    if !position.has_lp_range() {
+        return Ok(true);
+    }
+
+    Ok(market
+        .get_lp_range(position.lp_min_reserve_price, position.lp_max_reserve_price)?
+        .active)
+}
+
 pub fn calculate_settled_lp_base_quote(
-    amm: &AMM,
+    market: &PerpMarket,
     position: &PerpPosition,
 ) -> DriftResult<(i128, i128)> {
     let n_shares = position.lp_shares;
     let n_shares_i128 = n_shares.cast::<i128>()?;
 
     // give them slice of the damm market position
-    let amm_net_base_asset_amount_per_lp = amm
-        .base_asset_amount_per_lp
-        .safe_sub(position.last_net_base_asset_amount_per_lp.cast()?)?;
+    let (base_asset_amount_per_lp, quote_asset_amount_per_lp, _) =
+        get_lp_per_share_accumulators(market, position)?;
+
+    let amm_net_base_asset_amount_per_lp =
+        base_asset_amount_per_lp.safe_sub(position.last_net_base_asset_amount_per_lp.cast()?)?;
 
     let base_asset_amount = amm_net_base_asset_amount_per_lp
         .cast::<i128>()?
         .safe_mul(n_shares_i128)?
         .safe_div(AMM_RESERVE_PRECISION_I128)?;
 
-    let amm_net_quote_asset_amount_per_lp = amm
-        .quote_asset_amount_per_lp
-        .safe_sub(position.last_net_quote_asset_amount_per_lp.cast()?)?;
+    let amm_net_quote_asset_amount_per_lp =
+        quote_asset_amount_per_lp.safe_sub(position.last_net_quote_asset_amount_per_lp.cast()?)?;
 
     let quote_asset_amount = amm_net_quote_asset_amount_per_lp
         .cast::<i128>()?
@@ -68,10 +118,25 @@ pub fn calculate_settled_lp_base_quote(
     Ok((base_asset_amount, quote_asset_amount))
 }
 
+pub fn calculate_settled_lp_fee(market: &PerpMarket, position: &PerpPosition) -> DriftResult<i128> {
+    let (_, _, fee_per_lp) = get_lp_per_share_accumulators(market, position)?;
+    let fee_per_lp = fee_per_lp.safe_sub(position.last_fee_per_lp.cast()?)?;
+
+    fee_per_lp
+        .safe_mul(position.lp_shares.cast()?)?
+        .safe_div(AMM_RESERVE_PRECISION)?
+        .cast()
+}
+
 pub fn calculate_lp_open_bids_asks(
     market_position: &PerpPosition,
     market: &PerpMarket,
 ) -> DriftResult<(i64, i64)> {
+    // shares of an inactive range aren't part of sqrt_k
+    if !is_lp_position_active(market, market_position)? {
+        return Ok((0, 0));
+    }
+
     let total_lp_shares = market.amm.sqrt_k;
     let lp_shares = market_position.lp_shares;
 
@@ -81,3 +146,53 @@ pub fn calculate_lp_open_bids_asks(
 
     Ok((open_bids.cast()?, open_asks.cast()?))
 }
+
+pub fn is_reserve_price_in_range(
+    min_reserve_price: u64,
+    max_reserve_price: u64,
+    reserve_price: u64,
+) -> bool {
+    let above_min = min_reserve_price == 0 || reserve_price >= min_reserve_price;
+    let below_max = max_reserve_price == 0 || reserve_price <= max_reserve_price;
+
+    above_min && below_max
+}
+
+/// Base asset amount a keeper should close to flatten the lp inventory of a position that
+/// opted into hedging. Directional base isn't hedged. Returns 0 while the lp's base is within
+/// its hedge threshold.
+pub fn calculate_lp_hedge_base_asset_amount(
+    position: &PerpPosition,
+    order_step_size: u64,
+) -> DriftResult<u64> {
+    if !position.is_lp() || position.lp_hedge_threshold == 0 {
+        return Ok(0);
+    }
+
+    let base_asset_amount = position.get_lp_base_asset_amount().unsigned_abs();
+    if base_asset_amount <= position.lp_hedge_threshold {
+        return Ok(0);
+    }
+
+    standardize_base_asset_amount(base_asset_amount, order_step_size)
+}
+
+/// Limit price for a keeper hedge, at most LP_HEDGE_MAX_ORACLE_SLIPPAGE from the oracle
+pub fn calculate_lp_hedge_limit_price(
+    oracle_price: i64,
+    direction: PositionDirection,
+    tick_size: u64,
+) -> DriftResult<u64> {
+    let oracle_price = oracle_price.unsigned_abs();
+    let max_slippage = oracle_price
+        .safe_mul(LP_HEDGE_MAX_ORACLE_SLIPPAGE)?
+        .safe_div(PERCENTAGE_PRECISION_U64)?;
+
+    let limit_price = match direction {
+        PositionDirection::Long => oracle_price.safe_add(max_slippage)?,
+        PositionDirection::Short => oracle_price.safe_sub(max_slippage)?,
+    };
+
+    // rounds toward the oracle
+    standardize_price(limit_price, tick_size, direction)
+}
@@ -1,5 +1,6 @@
 use crate::math::constants::AMM_RESERVE_PRECISION;
 use crate::math::lp::*;
+use crate::state::perp_market::{PerpMarket, AMM};
 use crate::state::user::PerpPosition;
 
 mod calculate_get_proportion_u128 {
@@ -148,13 +149,16 @@ mod calculate_settled_lp_base_quote {
             ..PerpPosition::default()
         };
 
-        let amm = AMM {
-            base_asset_amount_per_lp: 10,
-            quote_asset_amount_per_lp: -10,
-            ..AMM::default_test()
+        let market = PerpMarket {
+            amm: AMM {
+                base_asset_amount_per_lp: 10,
+                quote_asset_amount_per_lp: -10,
+                ..AMM::default_test()
+            },
+            ..PerpMarket::default()
         };
 
-        let (baa, qaa) = calculate_settled_lp_base_quote(&amm, &position).unwrap();
+        let (baa, qaa) = calculate_settled_lp_base_quote(&market, &position).unwrap();
 
         assert_eq!(baa, 10 * 100);
         assert_eq!(qaa, -10 * 100);
@@ -167,13 +171,16 @@ mod calculate_settled_lp_base_quote {
             ..PerpPosition::default()
         };
 
-        let amm = AMM {
-            base_asset_amount_per_lp: -10,
-            quote_asset_amount_per_lp: 10,
-            ..AMM::default_test()
+        let market = PerpMarket {
+            amm: AMM {
+                base_asset_amount_per_lp: -10,
+                quote_asset_amount_per_lp: 10,
+                ..AMM::default_test()
+            },
+            ..PerpMarket::default()
         };
 
-        let (baa, qaa) = calculate_settled_lp_base_quote(&amm, &position).unwrap();
+        let (baa, qaa) = calculate_settled_lp_base_quote(&market, &position).unwrap();
 
         assert_eq!(baa, -10 * 100);
         assert_eq!(qaa, 10 * 100);
@@ -192,14 +199,17 @@ mod calculate_settle_lp_metrics {
             ..PerpPosition::default()
         };
 
-        let amm = AMM {
-            base_asset_amount_per_lp: 10,
-            quote_asset_amount_per_lp: -10,
-            order_step_size: 1,
-            ..AMM::default_test()
+        let market = PerpMarket {
+            amm: AMM {
+                base_asset_amount_per_lp: 10,
+                quote_asset_amount_per_lp: -10,
+                order_step_size: 1,
+                ..AMM::default_test()
+            },
+            ..PerpMarket::default()
         };
 
-        let lp_metrics = calculate_settle_lp_metrics(&amm, &position).unwrap();
+        let lp_metrics = calculate_settle_lp_metrics(&market, &position).unwrap();
 
         assert_eq!(lp_metrics.base_asset_amount, 10 * 100);
         assert_eq!(lp_metrics.quote_asset_amount, -10 * 100);
@@ -213,14 +223,17 @@ mod calculate_settle_lp_metrics {
             ..PerpPosition::default()
         };
 
-        let amm = AMM {
-            base_asset_amount_per_lp: 10,
-            quote_asset_amount_per_lp: -10,
-            order_step_size: 50 * 100,
-            ..AMM::default_test()
+        let market = PerpMarket {
+            amm: AMM {
+                base_asset_amount_per_lp: 10,
+                quote_asset_amount_per_lp: -10,
+                order_step_size: 50 * 100,
+                ..AMM::default_test()
+            },
+            ..PerpMarket::default()
         };
 
-        let lp_metrics = calculate_settle_lp_metrics(&amm, &position).unwrap();
+        let lp_metrics = calculate_settle_lp_metrics(&market, &position).unwrap();
 
         assert_eq!(lp_metrics.base_asset_amount, 0);
         assert_eq!(lp_metrics.quote_asset_amount, -10 * 100);
@@ -234,17 +247,259 @@ mod calculate_settle_lp_metrics {
             ..PerpPosition::default()
         };
 
-        let amm = AMM {
-            base_asset_amount_per_lp: 10,
-            quote_asset_amount_per_lp: -10,
-            order_step_size: 3,
-            ..AMM::default_test()
+        let market = PerpMarket {
+            amm: AMM {
+                base_asset_amount_per_lp: 10,
+                quote_asset_amount_per_lp: -10,
+                order_step_size: 3,
+                ..AMM::default_test()
+            },
+            ..PerpMarket::default()
         };
 
-        let lp_metrics = calculate_settle_lp_metrics(&amm, &position).unwrap();
+        let lp_metrics = calculate_settle_lp_metrics(&market, &position).unwrap();
 
         assert_eq!(lp_metrics.base_asset_amount, 9);
         assert_eq!(lp_metrics.quote_asset_amount, -10);
         assert_eq!(lp_metrics.remainder_base_asset_amount, 1);
     }
 }
+
+mod lp_range {
+    use crate::math::constants::PRICE_PRECISION_U64;
+    use crate::state::perp_market::LPRange;
+
+    use super::*;
+
+    #[test]
+    fn reserve_price_in_range() {
+        // unbounded
+        let lp_range = LPRange::default();
+        assert!(lp_range.is_reserve_price_in_range(1));
+        assert!(lp_range.is_reserve_price_in_range(u64::MAX));
+
+        let lp_range = LPRange {
+            min_reserve_price: PRICE_PRECISION_U64,
+            max_reserve_price: 2 * PRICE_PRECISION_U64,
+            ..LPRange::default()
+        };
+        assert!(!lp_range.is_reserve_price_in_range(PRICE_PRECISION_U64 - 1));
+        assert!(lp_range.is_reserve_price_in_range(PRICE_PRECISION_U64));
+        assert!(lp_range.is_reserve_price_in_range(2 * PRICE_PRECISION_U64));
+        assert!(!lp_range.is_reserve_price_in_range(2 * PRICE_PRECISION_U64 + 1));
+
+        // only a lower bound
+        let lp_range = LPRange {
+            min_reserve_price: PRICE_PRECISION_U64,
+            ..LPRange::default()
+        };
+        assert!(lp_range.is_reserve_price_in_range(u64::MAX));
+    }
+
+    #[test]
+    fn ranged_lp_settles_against_its_range() {
+        let position = PerpPosition {
+            lp_shares: 100 * AMM_RESERVE_PRECISION as u64,
+            lp_min_reserve_price: PRICE_PRECISION_U64,
+            ..PerpPosition::default()
+        };
+        let mut market = PerpMarket {
+            amm: AMM {
+                order_step_size: 1,
+                base_asset_amount_per_lp: 10,
+                quote_asset_amount_per_lp: -10,
+                ..AMM::default_test()
+            },
+            ..PerpMarket::default()
+        };
+        market.lp_ranges[0] = LPRange {
+            lp_shares: 100 * AMM_RESERVE_PRECISION,
+            min_reserve_price: PRICE_PRECISION_U64,
+            ..LPRange::default()
+        };
+
+        // trades while the range was inactive only moved the unranged accumulators
+        let lp_metrics = calculate_settle_lp_metrics(&market, &position).unwrap();
+        assert_eq!(lp_metrics.base_asset_amount, 0);
+        assert_eq!(lp_metrics.quote_asset_amount, 0);
+        assert_eq!(lp_metrics.remainder_base_asset_amount, 0);
+        assert!(!is_lp_position_active(&market, &position).unwrap());
+        assert_eq!(
+            calculate_lp_open_bids_asks(&position, &market).unwrap(),
+            (0, 0)
+        );
+
+        market.lp_ranges[0].active = true;
+        market.lp_ranges[0].base_asset_amount_per_lp = 20;
+        market.lp_ranges[0].quote_asset_amount_per_lp = -20;
+        let lp_metrics = calculate_settle_lp_metrics(&market, &position).unwrap();
+        assert_eq!(lp_metrics.base_asset_amount, 20 * 100);
+        assert_eq!(lp_metrics.quote_asset_amount, -20 * 100);
+
+        // a position without shares in any range can't settle
+        let position = PerpPosition {
+            lp_max_reserve_price: PRICE_PRECISION_U64,
+            ..position
+        };
+        assert!(calculate_settle_lp_metrics(&market, &position).is_err());
+    }
+}
+
+mod calculate_settled_lp_fee {
+    use crate::math::constants::{BASE_PRECISION_U64, QUOTE_PRECISION};
+    use crate::state::perp_market::LPRange;
+
+    use super::*;
+
+    #[test]
+    fn test_fee_since_last_settle() {
+        let position = PerpPosition {
+            lp_shares: 100 * BASE_PRECISION_U64,
+            last_fee_per_lp: QUOTE_PRECISION as u64,
+            ..PerpPosition::default()
+        };
+
+        let market = PerpMarket {
+            amm: AMM {
+                quote_asset_amount_per_lp: -10,
+                order_step_size: 1,
+                ..AMM::default_test()
+            },
+            fee_per_lp: 3 * QUOTE_PRECISION,
+            ..PerpMarket::default()
+        };
+
+        let fee = calculate_settled_lp_fee(&market, &position).unwrap();
+        assert_eq!(fee, 200 * QUOTE_PRECISION as i128);
+
+        // fees are reported apart from the inventory quote
+        let lp_metrics = calculate_settle_lp_metrics(&market, &position).unwrap();
+        assert_eq!(lp_metrics.quote_asset_amount, -10 * 100);
+        assert_eq!(lp_metrics.fee_amount, 200 * QUOTE_PRECISION as i128);
+
+        // ranged shares only earn their range's fees
+        let position = PerpPosition {
+            lp_min_reserve_price: 1,
+            ..position
+        };
+        let mut market = market;
+        market.lp_ranges[0] = LPRange {
+            lp_shares: 100 * AMM_RESERVE_PRECISION,
+            fee_per_lp: QUOTE_PRECISION,
+            min_reserve_price: 1,
+            ..LPRange::default()
+        };
+        assert_eq!(calculate_settled_lp_fee(&market, &position).unwrap(), 0);
+    }
+}
+
+mod calculate_lp_hedge_base_asset_amount {
+    use crate::math::constants::BASE_PRECISION_U64;
+
+    use super::*;
+
+    #[test]
+    fn test_hedge_threshold() {
+        // hedging disabled
+        let position = PerpPosition {
+            lp_shares: 100 * BASE_PRECISION_U64,
+            base_asset_amount: 5 * BASE_PRECISION_U64 as i64,
+            lp_base_asset_amount: 5 * BASE_PRECISION_U64 as i64,
+            ..PerpPosition::default()
+        };
+        assert_eq!(
+            calculate_lp_hedge_base_asset_amount(&position, 1).unwrap(),
+            0
+        );
+
+        // within threshold
+        let position = PerpPosition {
+            lp_hedge_threshold: 5 * BASE_PRECISION_U64,
+            ..position
+        };
+        assert_eq!(
+            calculate_lp_hedge_base_asset_amount(&position, 1).unwrap(),
+            0
+        );
+
+        // beyond threshold, flatten the lp base in step sizes
+        let position = PerpPosition {
+            base_asset_amount: -(5 * BASE_PRECISION_U64 as i64) - 1234,
+            lp_base_asset_amount: -(5 * BASE_PRECISION_U64 as i64) - 1234,
+            ..position
+        };
+        assert_eq!(
+            calculate_lp_hedge_base_asset_amount(&position, BASE_PRECISION_U64 / 1000).unwrap(),
+            5 * BASE_PRECISION_U64
+        );
+
+        // directional base on top of the lp base isn't hedged
+        let position = PerpPosition {
+            base_asset_amount: -20 * BASE_PRECISION_U64 as i64,
+            ..position
+        };
+        assert_eq!(
+            calculate_lp_hedge_base_asset_amount(&position, BASE_PRECISION_U64 / 1000).unwrap(),
+            5 * BASE_PRECISION_U64
+        );
+
+        // directional trades closed part of the lp base
+        let position = PerpPosition {
+            base_asset_amount: -(6 * BASE_PRECISION_U64 as i64),
+            lp_base_asset_amount: -(20 * BASE_PRECISION_U64 as i64),
+            ..position
+        };
+        assert_eq!(
+            calculate_lp_hedge_base_asset_amount(&position, BASE_PRECISION_U64 / 1000).unwrap(),
+            6 * BASE_PRECISION_U64
+        );
+
+        // directional trades flipped the position
+        let position = PerpPosition {
+            base_asset_amount: 20 * BASE_PRECISION_U64 as i64,
+            ..position
+        };
+        assert_eq!(
+            calculate_lp_hedge_base_asset_amount(&position, 1).unwrap(),
+            0
+        );
+
+        // not an lp
+        let position = PerpPosition {
+            lp_shares: 0,
+            ..position
+        };
+        assert_eq!(
+            calculate_lp_hedge_base_asset_amount(&position, 1).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_hedge_limit_price() {
+        use crate::controller::position::PositionDirection;
+        use crate::math::constants::PRICE_PRECISION_I64;
+
+        let oracle_price = 100 * PRICE_PRECISION_I64;
+
+        assert_eq!(
+            calculate_lp_hedge_limit_price(oracle_price, PositionDirection::Long, 1).unwrap(),
+            100_500_000
+        );
+        assert_eq!(
+            calculate_lp_hedge_limit_price(oracle_price, PositionDirection::Short, 1).unwrap(),
+            99_500_000
+        );
+
+        // rounded toward the oracle
+        assert_eq!(
+            calculate_lp_hedge_limit_price(oracle_price, PositionDirection::Long, 400_000).unwrap(),
+            100_400_000
+        );
+        assert_eq!(
+            calculate_lp_hedge_limit_price(oracle_price, PositionDirection::Short, 400_000)
+                .unwrap(),
+            99_600_000
+        );
+    }
+}
@@ -146,7 +146,7 @@ pub fn calculate_perp_position_value_and_pnl(
 
     let market_position = if market_position.is_lp() {
         // compute lp metrics
-        let lp_metrics = calculate_settle_lp_metrics(&market.amm, market_position)?;
+        let lp_metrics = calculate_settle_lp_metrics(market, market_position)?;
 
         // compute settled position
         let base_asset_amount = market_position
@@ -155,7 +155,8 @@ pub fn calculate_perp_position_value_and_pnl(
 
         let mut quote_asset_amount = market_position
             .quote_asset_amount
-            .safe_add(lp_metrics.quote_asset_amount.cast()?)?;
+            .safe_add(lp_metrics.quote_asset_amount.cast()?)?
+            .safe_add(lp_metrics.fee_amount.cast()?)?;
 
         // dust position in baa/qaa
         if lp_metrics.remainder_base_asset_amount != 0 {
@@ -253,7 +254,7 @@ pub fn calculate_margin_requirement_and_total_collateral_and_liability_info(
     let mut with_isolated_liability: bool = false;
 
     let user_custom_margin_ratio = if margin_requirement_type == MarginRequirementType::Initial {
-        user.max_margin_ratio
+        user.max_margin_ratio.max(user.liquidation_margin_ratio)
     } else {
         0_u32
     };
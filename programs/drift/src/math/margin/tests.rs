@@ -507,8 +507,9 @@ mod calculate_margin_requirement_and_total_collateral {
     use crate::create_anchor_account_info;
     use crate::math::constants::{
         AMM_RESERVE_PRECISION, BASE_PRECISION_I64, LIQUIDATION_FEE_PRECISION, MARGIN_PRECISION,
-        PEG_PRECISION, SPOT_BALANCE_PRECISION, SPOT_BALANCE_PRECISION_U64,
-        SPOT_CUMULATIVE_INTEREST_PRECISION, SPOT_WEIGHT_PRECISION,
+        MAX_OPEN_ORDERS, MAX_PERP_POSITIONS, MAX_SPOT_POSITIONS, PEG_PRECISION,
+        SPOT_BALANCE_PRECISION, SPOT_BALANCE_PRECISION_U64, SPOT_CUMULATIVE_INTEREST_PRECISION,
+        SPOT_WEIGHT_PRECISION,
     };
     use crate::math::margin::{
         calculate_margin_requirement_and_total_collateral, MarginRequirementType,
@@ -575,7 +576,7 @@ mod calculate_margin_requirement_and_total_collateral {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -590,8 +591,8 @@ mod calculate_margin_requirement_and_total_collateral {
             ..SpotPosition::default()
         };
         let user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); MAX_OPEN_ORDERS as usize],
+            perp_positions: [PerpPosition::default(); MAX_PERP_POSITIONS as usize],
             spot_positions,
             ..User::default()
         };
@@ -663,7 +664,7 @@ mod calculate_margin_requirement_and_total_collateral {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -678,8 +679,8 @@ mod calculate_margin_requirement_and_total_collateral {
             ..SpotPosition::default()
         };
         let user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); MAX_OPEN_ORDERS as usize],
+            perp_positions: [PerpPosition::default(); MAX_PERP_POSITIONS as usize],
             spot_positions,
             ..User::default()
         };
@@ -752,7 +753,7 @@ mod calculate_margin_requirement_and_total_collateral {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -767,8 +768,8 @@ mod calculate_margin_requirement_and_total_collateral {
             ..SpotPosition::default()
         };
         let user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); MAX_OPEN_ORDERS as usize],
+            perp_positions: [PerpPosition::default(); MAX_PERP_POSITIONS as usize],
             spot_positions,
             ..User::default()
         };
@@ -860,7 +861,7 @@ mod calculate_margin_requirement_and_total_collateral {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -875,7 +876,7 @@ mod calculate_margin_requirement_and_total_collateral {
         };
 
         let user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); MAX_OPEN_ORDERS as usize],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 base_asset_amount: 100 * BASE_PRECISION_I64,
@@ -963,8 +964,9 @@ mod calculate_max_withdrawable_amount {
     use crate::create_account_info;
     use crate::create_anchor_account_info;
     use crate::math::constants::{
-        LIQUIDATION_FEE_PRECISION, SPOT_BALANCE_PRECISION, SPOT_BALANCE_PRECISION_U64,
-        SPOT_CUMULATIVE_INTEREST_PRECISION, SPOT_WEIGHT_PRECISION,
+        LIQUIDATION_FEE_PRECISION, MAX_OPEN_ORDERS, MAX_PERP_POSITIONS, MAX_SPOT_POSITIONS,
+        SPOT_BALANCE_PRECISION, SPOT_BALANCE_PRECISION_U64, SPOT_CUMULATIVE_INTEREST_PRECISION,
+        SPOT_WEIGHT_PRECISION,
     };
     use crate::math::margin::calculate_max_withdrawable_amount;
     use crate::state::oracle::OracleSource;
@@ -1028,7 +1030,7 @@ mod calculate_max_withdrawable_amount {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -1043,8 +1045,8 @@ mod calculate_max_withdrawable_amount {
             ..SpotPosition::default()
         };
         let user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); MAX_OPEN_ORDERS as usize],
+            perp_positions: [PerpPosition::default(); MAX_PERP_POSITIONS as usize],
             spot_positions,
             ..User::default()
         };
@@ -1116,7 +1118,7 @@ mod calculate_max_withdrawable_amount {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Borrow,
@@ -1130,8 +1132,8 @@ mod calculate_max_withdrawable_amount {
             ..SpotPosition::default()
         };
         let user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); MAX_OPEN_ORDERS as usize],
+            perp_positions: [PerpPosition::default(); MAX_PERP_POSITIONS as usize],
             spot_positions,
             ..User::default()
         };
@@ -1160,8 +1162,9 @@ mod validate_spot_margin_trading {
     use crate::create_anchor_account_info;
     use crate::error::ErrorCode;
     use crate::math::constants::{
-        LIQUIDATION_FEE_PRECISION, SPOT_BALANCE_PRECISION, SPOT_BALANCE_PRECISION_U64,
-        SPOT_CUMULATIVE_INTEREST_PRECISION, SPOT_WEIGHT_PRECISION,
+        LIQUIDATION_FEE_PRECISION, MAX_OPEN_ORDERS, MAX_PERP_POSITIONS, MAX_SPOT_POSITIONS,
+        SPOT_BALANCE_PRECISION, SPOT_BALANCE_PRECISION_U64, SPOT_CUMULATIVE_INTEREST_PRECISION,
+        SPOT_WEIGHT_PRECISION,
     };
     use crate::math::margin::validate_spot_margin_trading;
     use crate::state::oracle::OracleSource;
@@ -1225,7 +1228,7 @@ mod validate_spot_margin_trading {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -1240,8 +1243,8 @@ mod validate_spot_margin_trading {
             ..SpotPosition::default()
         };
         let user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); MAX_OPEN_ORDERS as usize],
+            perp_positions: [PerpPosition::default(); MAX_PERP_POSITIONS as usize],
             spot_positions,
             ..User::default()
         };
@@ -1303,7 +1306,7 @@ mod validate_spot_margin_trading {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -1318,8 +1321,8 @@ mod validate_spot_margin_trading {
             ..SpotPosition::default()
         };
         let user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); MAX_OPEN_ORDERS as usize],
+            perp_positions: [PerpPosition::default(); MAX_PERP_POSITIONS as usize],
             spot_positions,
             ..User::default()
         };
@@ -1381,7 +1384,7 @@ mod validate_spot_margin_trading {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -1396,8 +1399,8 @@ mod validate_spot_margin_trading {
             ..SpotPosition::default()
         };
         let user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); MAX_OPEN_ORDERS as usize],
+            perp_positions: [PerpPosition::default(); MAX_PERP_POSITIONS as usize],
             spot_positions,
             ..User::default()
         };
@@ -1459,7 +1462,7 @@ mod validate_spot_margin_trading {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -1474,8 +1477,8 @@ mod validate_spot_margin_trading {
             ..SpotPosition::default()
         };
         let user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); MAX_OPEN_ORDERS as usize],
+            perp_positions: [PerpPosition::default(); MAX_PERP_POSITIONS as usize],
             spot_positions,
             ..User::default()
         };
@@ -1537,7 +1540,7 @@ mod validate_spot_margin_trading {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -1552,8 +1555,8 @@ mod validate_spot_margin_trading {
             ..SpotPosition::default()
         };
         let user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); MAX_OPEN_ORDERS as usize],
+            perp_positions: [PerpPosition::default(); MAX_PERP_POSITIONS as usize],
             spot_positions,
             ..User::default()
         };
@@ -1615,7 +1618,7 @@ mod validate_spot_margin_trading {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Borrow,
@@ -1630,8 +1633,8 @@ mod validate_spot_margin_trading {
             ..SpotPosition::default()
         };
         let user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); MAX_OPEN_ORDERS as usize],
+            perp_positions: [PerpPosition::default(); MAX_PERP_POSITIONS as usize],
             spot_positions,
             ..User::default()
         };
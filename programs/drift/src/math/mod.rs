@@ -28,3 +28,4 @@ pub mod serum;
 pub mod spot_balance;
 pub mod spot_withdraw;
 pub mod stats;
+pub mod token_2022;
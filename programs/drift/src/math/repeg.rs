@@ -9,7 +9,8 @@ use crate::math::bn;
 use crate::math::casting::Cast;
 use crate::math::constants::{
     AMM_RESERVE_PRECISION_I128, BID_ASK_SPREAD_PRECISION_U128, PEG_PRECISION_I128,
-    PRICE_TO_PEG_PRECISION_RATIO, SHARE_OF_FEES_ALLOCATED_TO_CLEARING_HOUSE_DENOMINATOR,
+    PERCENTAGE_PRECISION, PRICE_TO_PEG_PRECISION_RATIO,
+    SHARE_OF_FEES_ALLOCATED_TO_CLEARING_HOUSE_DENOMINATOR,
     SHARE_OF_FEES_ALLOCATED_TO_CLEARING_HOUSE_NUMERATOR,
 };
 use crate::math::cp_curve;
@@ -22,6 +23,7 @@ use crate::state::oracle::get_oracle_price;
 use crate::state::oracle::OraclePriceData;
 use crate::state::perp_market::{PerpMarket, AMM};
 use crate::state::state::OracleGuardRails;
+use crate::validate;
 
 #[cfg(test)]
 mod tests;
@@ -163,6 +165,51 @@ pub fn calculate_peg_from_target_price(
     Ok(new_peg.max(1))
 }
 
+// distance between the terminal price and the oracle, in PERCENTAGE_PRECISION
+pub fn calculate_terminal_oracle_divergence(
+    terminal_price: u64,
+    oracle_price: i64,
+) -> DriftResult<u64> {
+    let oracle_price = oracle_price.cast::<i128>()?;
+
+    validate!(
+        oracle_price > 0,
+        ErrorCode::InvalidOracle,
+        "oracle_price <= 0"
+    )?;
+
+    terminal_price
+        .cast::<i128>()?
+        .safe_sub(oracle_price)?
+        .unsigned_abs()
+        .safe_mul(PERCENTAGE_PRECISION)?
+        .safe_div(oracle_price.unsigned_abs())?
+        .cast()
+}
+
+pub fn calculate_oracle_pegged_peg(
+    amm: &AMM,
+    oracle_price_data: &OraclePriceData,
+) -> DriftResult<u128> {
+    // keep the reserve price inside the oracle confidence band, trades still move it within the band
+    let reserve_price = amm.reserve_price()?;
+    let oracle_price = oracle_price_data.price.cast::<u64>()?;
+    let lower_bound = oracle_price.saturating_sub(oracle_price_data.confidence);
+    let upper_bound = oracle_price.safe_add(oracle_price_data.confidence)?;
+
+    let target_price = reserve_price.clamp(lower_bound, upper_bound);
+
+    if target_price == reserve_price {
+        return Ok(amm.peg_multiplier);
+    }
+
+    calculate_peg_from_target_price(
+        amm.quote_asset_reserve,
+        amm.base_asset_reserve,
+        target_price,
+    )
+}
+
 pub fn calculate_amm_target_price(
     amm: &AMM,
     current_price: u64,
@@ -1,11 +1,17 @@
+#[cfg(test)]
+mod tests;
+
 use solana_program::msg;
 
 use crate::error::{DriftResult, ErrorCode};
 use crate::math::casting::Cast;
-use crate::math::constants::{ONE_YEAR, SPOT_RATE_PRECISION, SPOT_UTILIZATION_PRECISION};
+use crate::math::constants::{
+    MAX_BORROW_RATE_KINKS, ONE_YEAR, SPOT_RATE_PRECISION, SPOT_UTILIZATION_PRECISION,
+    SPOT_UTILIZATION_PRECISION_U32,
+};
 use crate::math::safe_math::SafeMath;
 use crate::state::oracle::OraclePriceData;
-use crate::state::spot_market::{SpotBalanceType, SpotMarket};
+use crate::state::spot_market::{BorrowRateKink, SpotBalanceType, SpotMarket};
 use crate::state::user::SpotPosition;
 use crate::validate;
 
@@ -101,6 +107,77 @@ pub fn calculate_utilization(
     Ok(utilization)
 }
 
+// curve starts at the min borrow rate, passes through the optimal point and any additional kinks
+// and ends at the max borrow rate at full utilization. unused kinks are filled with the end point
+pub fn get_borrow_rate_curve(
+    min_borrow_rate: u32,
+    optimal_utilization: u32,
+    optimal_borrow_rate: u32,
+    max_borrow_rate: u32,
+    borrow_rate_kinks: &[BorrowRateKink],
+) -> [BorrowRateKink; MAX_BORROW_RATE_KINKS + 3] {
+    let mut curve = [BorrowRateKink {
+        utilization: SPOT_UTILIZATION_PRECISION_U32,
+        borrow_rate: max_borrow_rate,
+    }; MAX_BORROW_RATE_KINKS + 3];
+
+    curve[0] = BorrowRateKink {
+        utilization: 0,
+        borrow_rate: min_borrow_rate,
+    };
+    curve[1] = BorrowRateKink {
+        utilization: optimal_utilization,
+        borrow_rate: optimal_borrow_rate,
+    };
+
+    let kinks = borrow_rate_kinks
+        .iter()
+        .filter(|kink| kink.utilization != 0)
+        .take(MAX_BORROW_RATE_KINKS);
+    for (point, kink) in curve[2..].iter_mut().zip(kinks) {
+        *point = *kink;
+    }
+
+    // ties at full utilization keep the lower rate first so the optimal point isnt skipped
+    curve[1..].sort_unstable_by_key(|kink| (kink.utilization, kink.borrow_rate));
+
+    curve
+}
+
+pub fn calculate_borrow_rate(spot_market: &SpotMarket, utilization: u128) -> DriftResult<u128> {
+    let curve = get_borrow_rate_curve(
+        spot_market.min_borrow_rate,
+        spot_market.optimal_utilization,
+        spot_market.optimal_borrow_rate,
+        spot_market.max_borrow_rate,
+        &spot_market.borrow_rate_kinks,
+    );
+
+    for segment in curve.windows(2) {
+        let (lower, upper) = (segment[0], segment[1]);
+
+        if upper.utilization == lower.utilization || utilization > upper.utilization.cast()? {
+            continue;
+        }
+
+        let borrow_rate_slope = upper
+            .borrow_rate
+            .cast::<u128>()?
+            .safe_sub(lower.borrow_rate.cast()?)?
+            .safe_mul(SPOT_UTILIZATION_PRECISION)?
+            .safe_div(upper.utilization.safe_sub(lower.utilization)?.cast()?)?;
+
+        return lower.borrow_rate.cast::<u128>()?.safe_add(
+            utilization
+                .safe_sub(lower.utilization.cast()?)?
+                .safe_mul(borrow_rate_slope)?
+                .safe_div(SPOT_UTILIZATION_PRECISION)?,
+        );
+    }
+
+    spot_market.max_borrow_rate.cast()
+}
+
 pub fn calculate_accumulated_interest(
     spot_market: &SpotMarket,
     now: i64,
@@ -125,34 +202,7 @@ pub fn calculate_accumulated_interest(
         });
     }
 
-    let borrow_rate = if utilization > spot_market.optimal_utilization.cast()? {
-        let surplus_utilization = utilization.safe_sub(spot_market.optimal_utilization.cast()?)?;
-
-        let borrow_rate_slope = spot_market
-            .max_borrow_rate
-            .cast::<u128>()?
-            .safe_sub(spot_market.optimal_borrow_rate.cast()?)?
-            .safe_mul(SPOT_UTILIZATION_PRECISION)?
-            .safe_div(
-                SPOT_UTILIZATION_PRECISION.safe_sub(spot_market.optimal_utilization.cast()?)?,
-            )?;
-
-        spot_market.optimal_borrow_rate.cast::<u128>()?.safe_add(
-            surplus_utilization
-                .safe_mul(borrow_rate_slope)?
-                .safe_div(SPOT_UTILIZATION_PRECISION)?,
-        )?
-    } else {
-        let borrow_rate_slope = spot_market
-            .optimal_borrow_rate
-            .cast::<u128>()?
-            .safe_mul(SPOT_UTILIZATION_PRECISION)?
-            .safe_div(spot_market.optimal_utilization.cast()?)?;
-
-        utilization
-            .safe_mul(borrow_rate_slope)?
-            .safe_div(SPOT_UTILIZATION_PRECISION)?
-    };
+    let borrow_rate = calculate_borrow_rate(spot_market, utilization)?;
 
     let time_since_last_update = now
         .cast::<u64>()
@@ -0,0 +1,158 @@
+use crate::math::spot_balance::calculate_borrow_rate;
+use crate::state::spot_market::{BorrowRateKink, SpotMarket};
+
+#[test]
+fn two_segment_curve() {
+    let spot_market = SpotMarket {
+        optimal_utilization: 800_000,
+        optimal_borrow_rate: 100_000,
+        max_borrow_rate: 1_000_000,
+        ..SpotMarket::default()
+    };
+
+    assert_eq!(
+        calculate_borrow_rate(&spot_market, 400_000).unwrap(),
+        50_000
+    );
+    assert_eq!(
+        calculate_borrow_rate(&spot_market, 800_000).unwrap(),
+        100_000
+    );
+    assert_eq!(
+        calculate_borrow_rate(&spot_market, 900_000).unwrap(),
+        550_000
+    );
+    assert_eq!(
+        calculate_borrow_rate(&spot_market, 1_000_000).unwrap(),
+        1_000_000
+    );
+}
+
+#[test]
+fn min_borrow_rate() {
+    let spot_market = SpotMarket {
+        optimal_utilization: 800_000,
+        optimal_borrow_rate: 100_000,
+        max_borrow_rate: 1_000_000,
+        min_borrow_rate: 20_000,
+        ..SpotMarket::default()
+    };
+
+    assert_eq!(calculate_borrow_rate(&spot_market, 1).unwrap(), 20_000);
+    assert_eq!(
+        calculate_borrow_rate(&spot_market, 400_000).unwrap(),
+        60_000
+    );
+    assert_eq!(
+        calculate_borrow_rate(&spot_market, 900_000).unwrap(),
+        550_000
+    );
+}
+
+#[test]
+fn multi_kink_curve() {
+    let mut spot_market = SpotMarket {
+        optimal_utilization: 800_000,
+        optimal_borrow_rate: 100_000,
+        max_borrow_rate: 1_000_000,
+        ..SpotMarket::default()
+    };
+    spot_market.borrow_rate_kinks[0] = BorrowRateKink {
+        utilization: 600_000,
+        borrow_rate: 24_000,
+    };
+    spot_market.borrow_rate_kinks[1] = BorrowRateKink {
+        utilization: 900_000,
+        borrow_rate: 200_000,
+    };
+
+    // 0 -> 60%: 0 -> 2.4%
+    assert_eq!(
+        calculate_borrow_rate(&spot_market, 300_000).unwrap(),
+        12_000
+    );
+    assert_eq!(
+        calculate_borrow_rate(&spot_market, 600_000).unwrap(),
+        24_000
+    );
+    // 60% -> 80%: 2.4% -> 10%
+    assert_eq!(
+        calculate_borrow_rate(&spot_market, 700_000).unwrap(),
+        62_000
+    );
+    // 80% -> 90%: 10% -> 20%
+    assert_eq!(
+        calculate_borrow_rate(&spot_market, 850_000).unwrap(),
+        150_000
+    );
+    // 90% -> 100%: 20% -> 100%
+    assert_eq!(
+        calculate_borrow_rate(&spot_market, 950_000).unwrap(),
+        600_000
+    );
+    assert_eq!(
+        calculate_borrow_rate(&spot_market, 1_000_000).unwrap(),
+        1_000_000
+    );
+}
+
+#[test]
+fn zero_optimal_utilization() {
+    let spot_market = SpotMarket {
+        optimal_utilization: 0,
+        optimal_borrow_rate: 100_000,
+        max_borrow_rate: 1_000_000,
+        ..SpotMarket::default()
+    };
+
+    assert_eq!(
+        calculate_borrow_rate(&spot_market, 500_000).unwrap(),
+        550_000
+    );
+}
+
+#[test]
+fn unordered_kinks() {
+    let mut spot_market = SpotMarket {
+        optimal_utilization: 800_000,
+        optimal_borrow_rate: 100_000,
+        max_borrow_rate: 1_000_000,
+        ..SpotMarket::default()
+    };
+    spot_market.borrow_rate_kinks[2] = BorrowRateKink {
+        utilization: 900_000,
+        borrow_rate: 200_000,
+    };
+    spot_market.borrow_rate_kinks[3] = BorrowRateKink {
+        utilization: 600_000,
+        borrow_rate: 24_000,
+    };
+
+    assert_eq!(
+        calculate_borrow_rate(&spot_market, 300_000).unwrap(),
+        12_000
+    );
+    assert_eq!(
+        calculate_borrow_rate(&spot_market, 850_000).unwrap(),
+        150_000
+    );
+    assert_eq!(
+        calculate_borrow_rate(&spot_market, 950_000).unwrap(),
+        600_000
+    );
+}
+
+#[test]
+fn optimal_at_full_utilization() {
+    let spot_market = SpotMarket {
+        optimal_utilization: 1_000_000,
+        optimal_borrow_rate: 100_000,
+        max_borrow_rate: 1_000_000,
+        ..SpotMarket::default()
+    };
+
+    assert_eq!(
+        calculate_borrow_rate(&spot_market, 500_000).unwrap(),
+        50_000
+    );
+}
@@ -0,0 +1,223 @@
+#[cfg(test)]
+mod tests;
+
+use crate::error::{DriftResult, ErrorCode};
+use crate::math::casting::Cast;
+use crate::math::safe_math::SafeMath;
+use crate::validate;
+use arrayref::array_ref;
+use solana_program::msg;
+
+// token-2022 accounts share the spl token base layout, extensions are appended as tlv entries
+// after the base account length and a single account type byte
+pub const BASE_ACCOUNT_LENGTH: usize = 165;
+pub const MINT_LENGTH: usize = 82;
+pub const MINT_DECIMALS_OFFSET: usize = 44;
+const ACCOUNT_TYPE_LENGTH: usize = 1;
+const TLV_HEADER_LENGTH: usize = 4;
+
+pub const ACCOUNT_TYPE_MINT: u8 = 1;
+pub const ACCOUNT_TYPE_ACCOUNT: u8 = 2;
+
+pub const EXTENSION_TYPE_UNINITIALIZED: u16 = 0;
+pub const EXTENSION_TYPE_TRANSFER_FEE_CONFIG: u16 = 1;
+pub const EXTENSION_TYPE_INTEREST_BEARING_CONFIG: u16 = 10;
+pub const EXTENSION_TYPE_METADATA_POINTER: u16 = 18;
+pub const EXTENSION_TYPE_TOKEN_METADATA: u16 = 19;
+
+// token account space required to hold the TransferFeeAmount extension
+pub const TRANSFER_FEE_ACCOUNT_LENGTH: usize =
+    BASE_ACCOUNT_LENGTH + ACCOUNT_TYPE_LENGTH + TLV_HEADER_LENGTH + 8;
+
+// TransferFeeConfig: two authorities, withheld amount, older fee, newer fee
+const TRANSFER_FEE_CONFIG_LENGTH: usize = 32 + 32 + 8 + 18 + 18;
+const OLDER_TRANSFER_FEE_OFFSET: usize = 32 + 32 + 8;
+const NEWER_TRANSFER_FEE_OFFSET: usize = OLDER_TRANSFER_FEE_OFFSET + 18;
+const MAX_FEE_BASIS_POINTS: u128 = 10_000;
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct TransferFee {
+    pub epoch: u64,
+    pub maximum_fee: u64,
+    pub transfer_fee_basis_points: u16,
+}
+
+impl TransferFee {
+    fn unpack(data: &[u8; 18]) -> TransferFee {
+        TransferFee {
+            epoch: u64::from_le_bytes(*array_ref![data, 0, 8]),
+            maximum_fee: u64::from_le_bytes(*array_ref![data, 8, 8]),
+            transfer_fee_basis_points: u16::from_le_bytes(*array_ref![data, 16, 2]),
+        }
+    }
+
+    pub fn calculate_fee(&self, amount: u64) -> DriftResult<u64> {
+        if self.transfer_fee_basis_points == 0 || amount == 0 {
+            return Ok(0);
+        }
+
+        let fee = amount
+            .cast::<u128>()?
+            .safe_mul(self.transfer_fee_basis_points.cast()?)?
+            .safe_add(MAX_FEE_BASIS_POINTS - 1)?
+            .safe_div(MAX_FEE_BASIS_POINTS)?
+            .cast::<u64>()?;
+
+        Ok(fee.min(self.maximum_fee))
+    }
+
+    // smallest amount that leaves post_fee_amount after the fee is withheld
+    pub fn calculate_pre_fee_amount(&self, post_fee_amount: u64) -> DriftResult<u64> {
+        if self.transfer_fee_basis_points == 0 || post_fee_amount == 0 {
+            return Ok(post_fee_amount);
+        }
+
+        if self.transfer_fee_basis_points.cast::<u128>()? >= MAX_FEE_BASIS_POINTS {
+            return post_fee_amount.safe_add(self.maximum_fee);
+        }
+
+        let pre_fee_amount = post_fee_amount
+            .cast::<u128>()?
+            .safe_mul(MAX_FEE_BASIS_POINTS)?
+            .safe_add(MAX_FEE_BASIS_POINTS - 1 - self.transfer_fee_basis_points.cast::<u128>()?)?
+            .safe_div(MAX_FEE_BASIS_POINTS - self.transfer_fee_basis_points.cast::<u128>()?)?
+            .cast::<u64>()?;
+
+        Ok(pre_fee_amount.min(post_fee_amount.safe_add(self.maximum_fee)?))
+    }
+}
+
+pub fn get_mint_decimals(mint_data: &[u8]) -> DriftResult<u8> {
+    validate!(
+        mint_data.len() >= MINT_LENGTH,
+        ErrorCode::InvalidTokenMint,
+        "mint data too short"
+    )?;
+
+    Ok(mint_data[MINT_DECIMALS_OFFSET])
+}
+
+pub fn get_mint_extension_types(mint_data: &[u8]) -> DriftResult<Vec<u16>> {
+    Ok(get_mint_extensions(mint_data)?
+        .iter()
+        .map(|(extension_type, _)| *extension_type)
+        .collect())
+}
+
+fn get_mint_extensions(mint_data: &[u8]) -> DriftResult<Vec<(u16, &[u8])>> {
+    // mints without extensions are plain spl token mints
+    if mint_data.len() <= BASE_ACCOUNT_LENGTH {
+        validate!(
+            mint_data.len() == MINT_LENGTH,
+            ErrorCode::InvalidTokenMint,
+            "unexpected mint data length {}",
+            mint_data.len()
+        )?;
+
+        return Ok(vec![]);
+    }
+
+    validate!(
+        mint_data[BASE_ACCOUNT_LENGTH] == ACCOUNT_TYPE_MINT,
+        ErrorCode::InvalidTokenMint,
+        "account is not a mint"
+    )?;
+
+    let mut extensions = vec![];
+    let mut offset = BASE_ACCOUNT_LENGTH + ACCOUNT_TYPE_LENGTH;
+    while offset + TLV_HEADER_LENGTH <= mint_data.len() {
+        let extension_type = u16::from_le_bytes(*array_ref![mint_data, offset, 2]);
+        if extension_type == EXTENSION_TYPE_UNINITIALIZED {
+            break;
+        }
+
+        let length = u16::from_le_bytes(*array_ref![mint_data, offset + 2, 2]) as usize;
+        let value_start = offset + TLV_HEADER_LENGTH;
+        let value_end = value_start.safe_add(length)?;
+        validate!(
+            value_end <= mint_data.len(),
+            ErrorCode::InvalidTokenMint,
+            "extension {} overflows mint data",
+            extension_type
+        )?;
+
+        extensions.push((extension_type, &mint_data[value_start..value_end]));
+        offset = value_end;
+    }
+
+    Ok(extensions)
+}
+
+pub fn validate_mint_extensions(mint_data: &[u8]) -> DriftResult {
+    for extension_type in get_mint_extension_types(mint_data)? {
+        validate!(
+            matches!(
+                extension_type,
+                EXTENSION_TYPE_TRANSFER_FEE_CONFIG
+                    | EXTENSION_TYPE_INTEREST_BEARING_CONFIG
+                    | EXTENSION_TYPE_METADATA_POINTER
+                    | EXTENSION_TYPE_TOKEN_METADATA
+            ),
+            ErrorCode::UnsupportedMintExtension,
+            "mint extension {} is not supported",
+            extension_type
+        )?;
+    }
+
+    Ok(())
+}
+
+pub fn get_transfer_fee(mint_data: &[u8], epoch: u64) -> DriftResult<Option<TransferFee>> {
+    let transfer_fee_config = get_mint_extensions(mint_data)?
+        .into_iter()
+        .find(|(extension_type, _)| *extension_type == EXTENSION_TYPE_TRANSFER_FEE_CONFIG);
+
+    let value = match transfer_fee_config {
+        Some((_, value)) => value,
+        None => return Ok(None),
+    };
+
+    validate!(
+        value.len() == TRANSFER_FEE_CONFIG_LENGTH,
+        ErrorCode::InvalidTokenMint,
+        "invalid transfer fee config length {}",
+        value.len()
+    )?;
+
+    let newer_transfer_fee = TransferFee::unpack(array_ref![value, NEWER_TRANSFER_FEE_OFFSET, 18]);
+    if epoch >= newer_transfer_fee.epoch {
+        Ok(Some(newer_transfer_fee))
+    } else {
+        Ok(Some(TransferFee::unpack(array_ref![
+            value,
+            OLDER_TRANSFER_FEE_OFFSET,
+            18
+        ])))
+    }
+}
+
+pub fn calculate_transfer_fee(mint_data: &[u8], epoch: u64, amount: u64) -> DriftResult<u64> {
+    match get_transfer_fee(mint_data, epoch)? {
+        Some(transfer_fee) => transfer_fee.calculate_fee(amount),
+        None => Ok(0),
+    }
+}
+
+pub fn calculate_pre_fee_amount(
+    mint_data: &[u8],
+    epoch: u64,
+    post_fee_amount: u64,
+) -> DriftResult<u64> {
+    match get_transfer_fee(mint_data, epoch)? {
+        Some(transfer_fee) => transfer_fee.calculate_pre_fee_amount(post_fee_amount),
+        None => Ok(post_fee_amount),
+    }
+}
+
+pub fn get_token_account_length(mint_data: &[u8]) -> DriftResult<usize> {
+    if get_mint_extension_types(mint_data)?.contains(&EXTENSION_TYPE_TRANSFER_FEE_CONFIG) {
+        Ok(TRANSFER_FEE_ACCOUNT_LENGTH)
+    } else {
+        Ok(BASE_ACCOUNT_LENGTH)
+    }
+}
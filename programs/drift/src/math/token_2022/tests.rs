@@ -0,0 +1,159 @@
+use crate::error::ErrorCode;
+use crate::math::token_2022::{
+    calculate_pre_fee_amount, calculate_transfer_fee, get_mint_decimals, get_token_account_length,
+    validate_mint_extensions, BASE_ACCOUNT_LENGTH, EXTENSION_TYPE_INTEREST_BEARING_CONFIG,
+    EXTENSION_TYPE_TRANSFER_FEE_CONFIG, MINT_LENGTH, TRANSFER_FEE_ACCOUNT_LENGTH,
+};
+
+const EXTENSION_TYPE_NON_TRANSFERABLE: u16 = 9;
+const EXTENSION_TYPE_PERMANENT_DELEGATE: u16 = 12;
+
+fn mint_data(decimals: u8, extensions: &[(u16, Vec<u8>)]) -> Vec<u8> {
+    let mut data = vec![0_u8; MINT_LENGTH];
+    data[44] = decimals;
+    data[45] = 1; // is_initialized
+
+    if extensions.is_empty() {
+        return data;
+    }
+
+    data.resize(BASE_ACCOUNT_LENGTH, 0);
+    data.push(1); // AccountType::Mint
+    for (extension_type, value) in extensions.iter() {
+        data.extend_from_slice(&extension_type.to_le_bytes());
+        data.extend_from_slice(&(value.len() as u16).to_le_bytes());
+        data.extend_from_slice(value);
+    }
+
+    data
+}
+
+fn transfer_fee_config(older: (u64, u64, u16), newer: (u64, u64, u16)) -> Vec<u8> {
+    let mut value = vec![0_u8; 72];
+    for (epoch, maximum_fee, basis_points) in [older, newer].iter() {
+        value.extend_from_slice(&epoch.to_le_bytes());
+        value.extend_from_slice(&maximum_fee.to_le_bytes());
+        value.extend_from_slice(&basis_points.to_le_bytes());
+    }
+    value
+}
+
+#[test]
+fn legacy_mint() {
+    let data = mint_data(6, &[]);
+
+    assert_eq!(get_mint_decimals(&data).unwrap(), 6);
+    assert!(validate_mint_extensions(&data).is_ok());
+    assert_eq!(calculate_transfer_fee(&data, 0, 1_000_000).unwrap(), 0);
+    assert_eq!(
+        get_token_account_length(&data).unwrap(),
+        BASE_ACCOUNT_LENGTH
+    );
+}
+
+#[test]
+fn transfer_fee_uses_epoch_and_maximum() {
+    let data = mint_data(
+        9,
+        &[(
+            EXTENSION_TYPE_TRANSFER_FEE_CONFIG,
+            transfer_fee_config((0, 1_000, 100), (10, 50_000, 50)),
+        )],
+    );
+
+    assert_eq!(get_mint_decimals(&data).unwrap(), 9);
+    assert!(validate_mint_extensions(&data).is_ok());
+    assert_eq!(
+        get_token_account_length(&data).unwrap(),
+        TRANSFER_FEE_ACCOUNT_LENGTH
+    );
+
+    // older fee: 1% capped at 1_000
+    assert_eq!(calculate_transfer_fee(&data, 9, 10_000).unwrap(), 100);
+    assert_eq!(calculate_transfer_fee(&data, 9, 1_000_000).unwrap(), 1_000);
+
+    // newer fee: 0.5% rounded up, capped at 50_000
+    assert_eq!(calculate_transfer_fee(&data, 10, 10_001).unwrap(), 51);
+    assert_eq!(calculate_transfer_fee(&data, 10, 1_000_000).unwrap(), 5_000);
+    assert_eq!(
+        calculate_transfer_fee(&data, 10, 100_000_000).unwrap(),
+        50_000
+    );
+    assert_eq!(calculate_transfer_fee(&data, 10, 0).unwrap(), 0);
+}
+
+#[test]
+fn pre_fee_amount_covers_transfer_fee() {
+    let data = mint_data(
+        9,
+        &[(
+            EXTENSION_TYPE_TRANSFER_FEE_CONFIG,
+            transfer_fee_config((0, 0, 0), (0, 50_000, 50)),
+        )],
+    );
+
+    for post_fee_amount in [1, 10_000, 1_000_000, 100_000_000].iter() {
+        let pre_fee_amount = calculate_pre_fee_amount(&data, 0, *post_fee_amount).unwrap();
+        let fee = calculate_transfer_fee(&data, 0, pre_fee_amount).unwrap();
+        assert_eq!(pre_fee_amount - fee, *post_fee_amount);
+    }
+
+    assert_eq!(calculate_pre_fee_amount(&data, 0, 10_000).unwrap(), 10_051);
+    // fee capped at maximum
+    assert_eq!(
+        calculate_pre_fee_amount(&data, 0, 100_000_000).unwrap(),
+        100_050_000
+    );
+    assert_eq!(calculate_pre_fee_amount(&data, 0, 0).unwrap(), 0);
+
+    let legacy = mint_data(6, &[]);
+    assert_eq!(
+        calculate_pre_fee_amount(&legacy, 0, 10_000).unwrap(),
+        10_000
+    );
+}
+
+#[test]
+fn unsupported_extensions_rejected() {
+    let interest_bearing = mint_data(6, &[(EXTENSION_TYPE_INTEREST_BEARING_CONFIG, vec![0; 52])]);
+    assert!(validate_mint_extensions(&interest_bearing).is_ok());
+
+    let non_transferable = mint_data(6, &[(EXTENSION_TYPE_NON_TRANSFERABLE, vec![])]);
+    assert_eq!(
+        validate_mint_extensions(&non_transferable),
+        Err(ErrorCode::UnsupportedMintExtension)
+    );
+
+    let permanent_delegate = mint_data(
+        6,
+        &[
+            (EXTENSION_TYPE_INTEREST_BEARING_CONFIG, vec![0; 52]),
+            (EXTENSION_TYPE_PERMANENT_DELEGATE, vec![1; 32]),
+        ],
+    );
+    assert_eq!(
+        validate_mint_extensions(&permanent_delegate),
+        Err(ErrorCode::UnsupportedMintExtension)
+    );
+}
+
+#[test]
+fn malformed_mint_data() {
+    assert_eq!(
+        get_mint_decimals(&[0; 10]),
+        Err(ErrorCode::InvalidTokenMint)
+    );
+
+    let mut truncated = mint_data(
+        6,
+        &[(
+            EXTENSION_TYPE_TRANSFER_FEE_CONFIG,
+            transfer_fee_config((0, 0, 0), (0, 0, 0)),
+        )],
+    );
+    truncated.truncate(truncated.len() - 1);
+    assert_eq!(
+        validate_mint_extensions(&truncated),
+        Err(ErrorCode::InvalidTokenMint)
+    );
+}
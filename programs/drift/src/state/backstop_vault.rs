@@ -0,0 +1,85 @@
+use anchor_lang::prelude::*;
+
+use crate::error::{DriftResult, ErrorCode};
+use crate::math::safe_math::SafeMath;
+use crate::state::user::User;
+use crate::validate;
+use solana_program::msg;
+
+#[account(zero_copy)]
+#[derive(Default, Eq, PartialEq, Debug)]
+#[repr(C)]
+pub struct BackstopVault {
+    pub pubkey: Pubkey,
+    pub user: Pubkey, // program owned user that holds the vault's deposits and absorbed positions
+    pub user_stats: Pubkey,
+    pub total_shares: u128,
+    pub liquidation_delay_slots: u64, // slots a user must be in liquidation before the backstop can take over
+    pub unstaking_period: i64, // seconds a staker must wait between requesting and removing stake
+}
+
+impl BackstopVault {
+    pub fn validate_can_liquidate(&self, user: &User, slot: u64) -> DriftResult {
+        validate!(
+            user.is_being_liquidated,
+            ErrorCode::BackstopLiquidationNotAvailable,
+            "user is not being liquidated"
+        )?;
+
+        let slots_since_liquidation_start = user.get_slots_since_liquidation_start(slot)?;
+        validate!(
+            slots_since_liquidation_start >= self.liquidation_delay_slots,
+            ErrorCode::BackstopLiquidationNotAvailable,
+            "user has only been in liquidation for {} slots, backstop waits {}",
+            slots_since_liquidation_start,
+            self.liquidation_delay_slots
+        )?;
+
+        Ok(())
+    }
+}
+
+#[account(zero_copy)]
+#[derive(Default, Eq, PartialEq, Debug)]
+#[repr(C)]
+pub struct BackstopVaultStake {
+    pub authority: Pubkey,
+    pub shares: u128,
+    pub cost_basis: i64,
+    pub last_stake_ts: i64,
+    pub last_withdraw_request_shares: u128, // 0 when no withdraw request is in progress
+    pub last_withdraw_request_value: u64,
+    pub last_withdraw_request_ts: i64,
+}
+
+impl BackstopVaultStake {
+    pub fn new(authority: Pubkey, now: i64) -> Self {
+        BackstopVaultStake {
+            authority,
+            shares: 0,
+            cost_basis: 0,
+            last_stake_ts: now,
+            last_withdraw_request_shares: 0,
+            last_withdraw_request_value: 0,
+            last_withdraw_request_ts: 0,
+        }
+    }
+
+    pub fn increase_shares(&mut self, delta: u128) -> DriftResult {
+        self.shares = self.shares.safe_add(delta)?;
+        Ok(())
+    }
+
+    pub fn decrease_shares(&mut self, delta: u128) -> DriftResult {
+        validate!(
+            self.shares >= delta,
+            ErrorCode::InsufficientBackstopShares,
+            "stake has {} shares, cant remove {}",
+            self.shares,
+            delta
+        )?;
+
+        self.shares = self.shares.safe_sub(delta)?;
+        Ok(())
+    }
+}
@@ -129,6 +129,21 @@ pub struct CurveRecord {
     pub fill_record: u128,
     pub number_of_users: u32,
     pub market_index: u16,
+    pub reason: CurveUpdateReason,
+}
+
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Eq, Debug)]
+pub enum CurveUpdateReason {
+    AdminRepeg,
+    AdminUpdateK,
+    FormulaicUpdateK,
+    KeeperRepeg, // terminal price drifted past KEEPER_REPEG_TERMINAL_DIVERGENCE from oracle
+}
+
+impl Default for CurveUpdateReason {
+    fn default() -> Self {
+        CurveUpdateReason::AdminRepeg
+    }
 }
 
 #[event]
@@ -288,6 +303,7 @@ pub struct LPRecord {
     pub delta_base_asset_amount: i64,
     pub delta_quote_asset_amount: i64,
     pub pnl: i64,
+    pub fee: i64, // exchange fees earned by the lp shares since they were last settled
 }
 
 #[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Eq)]
@@ -295,6 +311,7 @@ pub enum LPAction {
     AddLiquidity,
     RemoveLiquidity,
     SettleLiquidity,
+    RemoveLiquidityDerisk, // lp shares burned during liquidation
 }
 
 impl Default for LPAction {
@@ -303,6 +320,20 @@ impl Default for LPAction {
     }
 }
 
+#[event]
+#[derive(Default)]
+pub struct TransferPerpPositionRecord {
+    pub ts: i64,
+    pub user_authority: Pubkey,
+    pub from_user: Pubkey,
+    pub to_user: Pubkey,
+    pub market_index: u16,
+    pub base_asset_amount: i64,
+    pub quote_asset_amount: i64,
+    pub from_user_base_asset_amount_after: i64,
+    pub to_user_base_asset_amount_after: i64,
+}
+
 #[event]
 #[derive(Default)]
 pub struct LiquidationRecord {
@@ -323,7 +354,7 @@ pub struct LiquidationRecord {
     pub spot_bankruptcy: SpotBankruptcyRecord,
 }
 
-#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Eq)]
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Eq, Debug)]
 pub enum LiquidationType {
     LiquidatePerp,
     LiquidateSpot,
@@ -331,6 +362,7 @@ pub enum LiquidationType {
     LiquidatePerpPnlForDeposit,
     PerpBankruptcy,
     SpotBankruptcy,
+    LiquidatePerpWithAmm,
 }
 
 impl Default for LiquidationType {
@@ -390,6 +422,7 @@ pub struct PerpBankruptcyRecord {
     pub market_index: u16,
     pub pnl: i128,
     pub if_payment: u128,
+    pub perp_market_if_payment: u128,
     pub clawback_user: Option<Pubkey>,
     pub clawback_user_payment: Option<u128>,
     pub cumulative_funding_rate_delta: i128,
@@ -443,6 +476,18 @@ pub struct InsuranceFundRecord {
     pub total_if_shares_before: u128,
     pub total_if_shares_after: u128,
     pub amount: i64,
+    pub insurance_fund_market_type: MarketType, // whether the spot or perp market's insurance fund was used
+}
+
+#[event]
+#[derive(Default)]
+pub struct InsuranceCoverageRecord {
+    pub ts: i64,
+    pub perp_market_index: u16,
+    pub insurance_value: u128,
+    pub weighted_open_interest: u128,
+    pub insurance_coverage_ratio: u32,
+    pub min_insurance_coverage_ratio: u32,
 }
 
 #[event]
@@ -453,6 +498,7 @@ pub struct InsuranceFundStakeRecord {
     pub action: StakeAction,
     pub amount: u64,
     pub market_index: u16,
+    pub market_type: MarketType,
 
     pub insurance_vault_amount_before: u64,
     pub if_shares_before: u128,
@@ -463,12 +509,42 @@ pub struct InsuranceFundStakeRecord {
     pub total_if_shares_after: u128,
 }
 
+#[event]
+#[derive(Default)]
+pub struct BackstopVaultStakeRecord {
+    pub ts: i64,
+    pub user_authority: Pubkey,
+    pub action: StakeAction,
+    pub amount: u64,
+
+    pub vault_value_before: u64,
+    pub shares_before: u128,
+    pub total_shares_before: u128,
+    pub shares_after: u128,
+    pub total_shares_after: u128,
+}
+
+#[event]
+#[derive(Default)]
+pub struct InsuranceFundRewardRecord {
+    pub ts: i64,
+    pub user_authority: Pubkey,
+    pub market_index: u16,
+    pub market_type: MarketType,
+    pub amount: u64,
+    pub reward_per_share: u128,
+    pub unclaimed_rewards_after: u64,
+}
+
 #[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Eq)]
 pub enum StakeAction {
     Stake,
     UnstakeRequest,
     UnstakeCancelRequest,
     Unstake,
+    MintShares,
+    RedeemShares,
+    InstantUnstake,
 }
 
 impl Default for StakeAction {
@@ -3,7 +3,8 @@ use crate::error::ErrorCode;
 use crate::math_error;
 use crate::safe_decrement;
 use crate::safe_increment;
-use crate::state::spot_market::SpotMarket;
+use crate::state::spot_market::InsuranceFundMarket;
+use crate::state::user::MarketType;
 use crate::validate;
 use anchor_lang::prelude::*;
 
@@ -20,7 +21,10 @@ pub struct InsuranceFundStake {
     pub last_withdraw_request_ts: i64,
     pub market_index: u16,
     pub cost_basis: i64,
-    pub padding: [u8; 6],
+    pub market_type: MarketType,
+    pub padding: [u8; 5],
+    pub reward_per_share_checkpoint: u128, // insurance fund reward_per_share when rewards were last settled
+    pub unclaimed_rewards: u64,
 }
 
 impl InsuranceFundStake {
@@ -35,24 +39,37 @@ impl InsuranceFundStake {
             if_base: 0,
             last_valid_ts: now,
             if_shares: 0,
-            padding: [0; 6],
+            market_type: MarketType::Spot,
+            padding: [0; 5],
+            reward_per_share_checkpoint: 0,
+            unclaimed_rewards: 0,
         }
     }
 
-    fn validate_base(&self, spot_market: &SpotMarket) -> DriftResult {
+    fn validate_base(&self, market: &impl InsuranceFundMarket) -> DriftResult {
         validate!(
-            self.if_base == spot_market.insurance_fund.shares_base,
+            self.market_type == market.market_type() && self.market_index == market.market_index(),
+            ErrorCode::InvalidInsuranceFundStakeMarket,
+            "if stake is for {:?} market {}, not {:?} market {}",
+            self.market_type,
+            self.market_index,
+            market.market_type(),
+            market.market_index()
+        )?;
+
+        validate!(
+            self.if_base == market.insurance_fund().shares_base,
             ErrorCode::InvalidIFRebase,
             "if stake bases mismatch. user base: {} market base {}",
             self.if_base,
-            spot_market.insurance_fund.shares_base
+            market.insurance_fund().shares_base
         )?;
 
         Ok(())
     }
 
-    pub fn checked_if_shares(&self, spot_market: &SpotMarket) -> DriftResult<u128> {
-        self.validate_base(spot_market)?;
+    pub fn checked_if_shares(&self, market: &impl InsuranceFundMarket) -> DriftResult<u128> {
+        self.validate_base(market)?;
         Ok(self.if_shares)
     }
 
@@ -60,20 +77,32 @@ impl InsuranceFundStake {
         self.if_shares
     }
 
-    pub fn increase_if_shares(&mut self, delta: u128, spot_market: &SpotMarket) -> DriftResult {
-        self.validate_base(spot_market)?;
+    pub fn increase_if_shares(
+        &mut self,
+        delta: u128,
+        market: &impl InsuranceFundMarket,
+    ) -> DriftResult {
+        self.validate_base(market)?;
         safe_increment!(self.if_shares, delta);
         Ok(())
     }
 
-    pub fn decrease_if_shares(&mut self, delta: u128, spot_market: &SpotMarket) -> DriftResult {
-        self.validate_base(spot_market)?;
+    pub fn decrease_if_shares(
+        &mut self,
+        delta: u128,
+        market: &impl InsuranceFundMarket,
+    ) -> DriftResult {
+        self.validate_base(market)?;
         safe_decrement!(self.if_shares, delta);
         Ok(())
     }
 
-    pub fn update_if_shares(&mut self, new_shares: u128, spot_market: &SpotMarket) -> DriftResult {
-        self.validate_base(spot_market)?;
+    pub fn update_if_shares(
+        &mut self,
+        new_shares: u128,
+        market: &impl InsuranceFundMarket,
+    ) -> DriftResult {
+        self.validate_base(market)?;
         self.if_shares = new_shares;
 
         Ok(())
@@ -0,0 +1,97 @@
+use anchor_lang::prelude::*;
+
+use crate::error::DriftResult;
+use crate::math::constants::{MARGIN_PRECISION, TWENTY_FOUR_HOUR};
+use crate::math::safe_math::SafeMath;
+use crate::state::events::LiquidationType;
+
+#[cfg(test)]
+mod tests;
+
+pub const LIQUIDATION_HISTORY_LENGTH: usize = 8;
+// liquidations (not partial fills of one liquidation) within the lookback before the user's
+// initial margin is raised
+pub const REPEATED_LIQUIDATION_THRESHOLD: u8 = 3;
+pub const REPEATED_LIQUIDATION_LOOKBACK: i64 = TWENTY_FOUR_HOUR;
+pub const REPEATED_LIQUIDATION_MARGIN_RATIO: u32 = MARGIN_PRECISION / 5; // 5x max leverage
+
+#[account(zero_copy)]
+#[derive(Default, Eq, PartialEq, Debug)]
+#[repr(C)]
+pub struct UserLiquidationHistory {
+    pub authority: Pubkey,
+    pub records: [LiquidationHistoryRecord; LIQUIDATION_HISTORY_LENGTH],
+    pub next_record_index: u8, // ring buffer head, the oldest record gets overwritten next
+    pub padding: [u8; 7],
+}
+
+#[zero_copy]
+#[derive(Default, Eq, PartialEq, Debug)]
+#[repr(C)]
+pub struct LiquidationHistoryRecord {
+    pub ts: i64,
+    pub slot: u64,
+    pub liquidation_start_slot: u64, // shared by every record of one liquidation
+    pub price: i64,                  // oracle price of the liquidated market
+    pub amount: u64, // base for perp liquidations, liability/pnl transferred otherwise
+    pub fee: u64,    // explicit liquidator and insurance fund fees paid by the user
+    pub market_index: u16,
+    pub liquidation_type: LiquidationType,
+    pub padding: [u8; 5],
+}
+
+impl UserLiquidationHistory {
+    pub fn new(authority: Pubkey) -> Self {
+        UserLiquidationHistory {
+            authority,
+            ..UserLiquidationHistory::default()
+        }
+    }
+
+    pub fn add_record(&mut self, record: LiquidationHistoryRecord) -> DriftResult {
+        self.records[self.next_record_index as usize] = record;
+        self.next_record_index =
+            self.next_record_index.safe_add(1)? % LIQUIDATION_HISTORY_LENGTH as u8;
+        Ok(())
+    }
+
+    pub fn count_recent_liquidations(&self, now: i64) -> DriftResult<u8> {
+        let mut count = 0_u8;
+        for (i, record) in self.records.iter().enumerate() {
+            if !record.is_recent(now)? {
+                continue;
+            }
+
+            // a liquidation done over several instructions leaves one record per instruction
+            let mut counted = false;
+            for other in self.records[..i].iter() {
+                if other.liquidation_start_slot == record.liquidation_start_slot
+                    && other.is_recent(now)?
+                {
+                    counted = true;
+                    break;
+                }
+            }
+
+            if !counted {
+                count = count.safe_add(1)?;
+            }
+        }
+
+        Ok(count)
+    }
+
+    pub fn get_liquidation_margin_ratio(&self, now: i64) -> DriftResult<u32> {
+        if self.count_recent_liquidations(now)? >= REPEATED_LIQUIDATION_THRESHOLD {
+            Ok(REPEATED_LIQUIDATION_MARGIN_RATIO)
+        } else {
+            Ok(0)
+        }
+    }
+}
+
+impl LiquidationHistoryRecord {
+    pub fn is_recent(&self, now: i64) -> DriftResult<bool> {
+        Ok(self.ts != 0 && now.safe_sub(self.ts)? < REPEATED_LIQUIDATION_LOOKBACK)
+    }
+}
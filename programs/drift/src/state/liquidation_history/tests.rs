@@ -0,0 +1,105 @@
+mod add_record {
+    use crate::state::liquidation_history::{
+        LiquidationHistoryRecord, UserLiquidationHistory, LIQUIDATION_HISTORY_LENGTH,
+    };
+
+    #[test]
+    fn wraps_around() {
+        let mut history = UserLiquidationHistory::default();
+
+        for i in 0..LIQUIDATION_HISTORY_LENGTH + 2 {
+            history
+                .add_record(LiquidationHistoryRecord {
+                    ts: i as i64 + 1,
+                    ..LiquidationHistoryRecord::default()
+                })
+                .unwrap();
+        }
+
+        assert_eq!(history.next_record_index, 2);
+        assert_eq!(history.records[0].ts, 9);
+        assert_eq!(history.records[1].ts, 10);
+        assert_eq!(history.records[2].ts, 3);
+    }
+}
+
+mod get_liquidation_margin_ratio {
+    use crate::math::constants::TWENTY_FOUR_HOUR;
+    use crate::state::liquidation_history::{
+        LiquidationHistoryRecord, UserLiquidationHistory, REPEATED_LIQUIDATION_MARGIN_RATIO,
+    };
+
+    #[test]
+    fn raised_after_threshold() {
+        let mut history = UserLiquidationHistory::default();
+        let now = 2 * TWENTY_FOUR_HOUR;
+
+        for ts in [TWENTY_FOUR_HOUR - 1, now - 100, now - 10] {
+            history
+                .add_record(LiquidationHistoryRecord {
+                    ts,
+                    liquidation_start_slot: ts as u64,
+                    ..LiquidationHistoryRecord::default()
+                })
+                .unwrap();
+        }
+
+        // first liquidation is outside the lookback
+        assert_eq!(history.count_recent_liquidations(now).unwrap(), 2);
+        assert_eq!(history.get_liquidation_margin_ratio(now).unwrap(), 0);
+
+        history
+            .add_record(LiquidationHistoryRecord {
+                ts: now,
+                liquidation_start_slot: now as u64,
+                ..LiquidationHistoryRecord::default()
+            })
+            .unwrap();
+
+        assert_eq!(history.count_recent_liquidations(now).unwrap(), 3);
+        assert_eq!(
+            history.get_liquidation_margin_ratio(now).unwrap(),
+            REPEATED_LIQUIDATION_MARGIN_RATIO
+        );
+
+        // decays once the liquidations fall out of the lookback
+        let later = now + TWENTY_FOUR_HOUR;
+        assert_eq!(history.get_liquidation_margin_ratio(later).unwrap(), 0);
+    }
+
+    #[test]
+    fn counts_each_liquidation_once() {
+        let mut history = UserLiquidationHistory::default();
+        let now = 2 * TWENTY_FOUR_HOUR;
+
+        // one liquidation done in three instructions
+        for ts in [now - 30, now - 20, now - 10] {
+            history
+                .add_record(LiquidationHistoryRecord {
+                    ts,
+                    liquidation_start_slot: 100,
+                    ..LiquidationHistoryRecord::default()
+                })
+                .unwrap();
+        }
+
+        assert_eq!(history.count_recent_liquidations(now).unwrap(), 1);
+        assert_eq!(history.get_liquidation_margin_ratio(now).unwrap(), 0);
+
+        for (ts, liquidation_start_slot) in [(now - 5, 200), (now, 300)] {
+            history
+                .add_record(LiquidationHistoryRecord {
+                    ts,
+                    liquidation_start_slot,
+                    ..LiquidationHistoryRecord::default()
+                })
+                .unwrap();
+        }
+
+        assert_eq!(history.count_recent_liquidations(now).unwrap(), 3);
+        assert_eq!(
+            history.get_liquidation_margin_ratio(now).unwrap(),
+            REPEATED_LIQUIDATION_MARGIN_RATIO
+        );
+    }
+}
@@ -1,6 +1,8 @@
+pub mod backstop_vault;
 pub mod events;
 pub mod fulfillment;
 pub mod insurance_fund_stake;
+pub mod liquidation_history;
 pub mod oracle;
 pub mod oracle_map;
 pub mod perp_market;
@@ -7,9 +7,11 @@ use crate::error::{DriftResult, ErrorCode};
 use crate::math::amm;
 use crate::math::casting::Cast;
 use crate::math::constants::{
-    AMM_RESERVE_PRECISION, BID_ASK_SPREAD_PRECISION_U128, MARGIN_PRECISION_U128,
-    PRICE_PRECISION_I64, SPOT_WEIGHT_PRECISION, TWENTY_FOUR_HOUR,
+    AMM_RESERVE_PRECISION, BID_ASK_SPREAD_PRECISION_U128, MARGIN_PRECISION_U128, MAX_LP_RANGES,
+    PERCENTAGE_PRECISION, PRICE_PRECISION_I64, SPOT_WEIGHT_PRECISION, TWENTY_FOUR_HOUR,
 };
+use crate::math::liquidation::calculate_liquidator_fee;
+use crate::math::lp::is_reserve_price_in_range;
 use crate::math::margin::{
     calculate_size_discount_asset_weight, calculate_size_premium_liability_weight,
     MarginRequirementType,
@@ -18,7 +20,10 @@ use crate::math::safe_math::SafeMath;
 use crate::math::stats;
 
 use crate::state::oracle::{HistoricalOracleData, OracleSource};
-use crate::state::spot_market::{SpotBalance, SpotBalanceType};
+use crate::state::spot_market::{
+    InsuranceFund, InsuranceFundExtension, InsuranceFundMarket, SpotBalance, SpotBalanceType,
+};
+use crate::state::user::MarketType;
 use crate::{AMM_TO_QUOTE_PRECISION_RATIO, MAX_CONCENTRATION_COEFFICIENT, PRICE_PRECISION};
 use borsh::{BorshDeserialize, BorshSerialize};
 
@@ -62,12 +67,61 @@ pub enum ContractTier {
     Isolated,    // no insurance, only single position allowed
 }
 
+impl ContractTier {
+    // share of open interest the insurance fund is expected to back
+    pub fn get_insurance_coverage_weight(&self) -> u128 {
+        match self {
+            ContractTier::A => PERCENTAGE_PRECISION,
+            ContractTier::B => PERCENTAGE_PRECISION / 2,
+            ContractTier::C => PERCENTAGE_PRECISION / 4,
+            ContractTier::Speculative => 0,
+            ContractTier::Isolated => 0,
+        }
+    }
+}
+
 impl Default for ContractTier {
     fn default() -> Self {
         ContractTier::Speculative
     }
 }
 
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Debug, Eq)]
+pub enum SpreadModel {
+    Legacy,              // base spread, oracle retreat, inventory and leverage scaling
+    VolatilityIntensity, // legacy plus widening from realized volatility and one-sided flow
+}
+
+impl Default for SpreadModel {
+    fn default() -> Self {
+        SpreadModel::Legacy
+    }
+}
+
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Debug, Eq)]
+pub enum AmmMode {
+    Curve,        // reserve price follows the curve, repeg/update k move it toward the oracle
+    OraclePegged, // reserve price anchored to the oracle confidence band each update, curve only sets depth
+}
+
+impl Default for AmmMode {
+    fn default() -> Self {
+        AmmMode::Curve
+    }
+}
+
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Debug, Eq)]
+pub enum FundingPremiumSampling {
+    TimeWeighted, // mark twap vs oracle twap over the funding period
+    Last,         // latest execution premium price vs latest oracle price
+}
+
+impl Default for FundingPremiumSampling {
+    fn default() -> Self {
+        FundingPremiumSampling::TimeWeighted
+    }
+}
+
 #[account(zero_copy)]
 #[derive(Default, Eq, PartialEq, Debug)]
 #[repr(C)]
@@ -97,7 +151,28 @@ pub struct PerpMarket {
     pub status: MarketStatus,
     pub contract_type: ContractType,
     pub contract_tier: ContractTier,
-    pub padding: [u8; 3],
+    pub funding_premium_sampling: FundingPremiumSampling,
+    pub padding: [u8; 2],
+    // fields below were appended after launch, see resize_perp_market
+    pub liquidator_fee_ramp_slots: u32, // slots for the liquidator fee to ramp up to liquidator_fee
+    pub padding1: [u8; 4],
+    pub insurance_fund: InsuranceFund, // optional insurance fund dedicated to this market
+    pub insurance_fund_settled_fee_withdrawn: u128, // amm.total_fee_withdrawn at the last revenue settle to insurance_fund
+    pub insurance_fund_extension: InsuranceFundExtension,
+    pub insurance_coverage_ratio: u32, // PERCENTAGE_PRECISION, insurance available vs tier weighted open interest
+    pub min_insurance_coverage_ratio: u32, // PERCENTAGE_PRECISION, risk increasing orders blocked below this (0 disables)
+    pub last_insurance_coverage_update_ts: i64,
+    pub last_keeper_repeg_slot: u64, // slot of the last permissionless repeg, see KEEPER_REPEG_MIN_SLOTS
+    pub padding2: [u8; 8],
+    pub lp_ranges: [LPRange; MAX_LP_RANGES as usize], // lp shares restricted to a reserve price range, grouped by range
+    pub funding_max_price_spread: u32, // PERCENTAGE_PRECISION, clamp on the premium used for funding (0 = 1/33)
+    pub funding_interest_rate: i32, // PERCENTAGE_PRECISION per 24h, baseline rate added to the premium before clamping
+    pub volatility_spread_scale: u32, // BID_ASK_SPREAD_PRECISION, spread added per unit of std / price
+    pub intensity_spread_scale: u32, // BID_ASK_SPREAD_PRECISION, spread added at fully one-sided flow
+    pub spread_model: SpreadModel,
+    pub amm_mode: AmmMode,
+    pub padding3: [u8; 6],
+    pub fee_per_lp: u128, // cumulative exchange fees earned per lp share, settled separately from inventory
 }
 
 impl PerpMarket {
@@ -124,6 +199,14 @@ impl PerpMarket {
         })
     }
 
+    pub fn get_liquidator_fee(&self, slots_since_liquidation_start: u64) -> DriftResult<u32> {
+        calculate_liquidator_fee(
+            self.liquidator_fee,
+            self.liquidator_fee_ramp_slots,
+            slots_since_liquidation_start,
+        )
+    }
+
     pub fn get_margin_ratio(
         &self,
         size: u128,
@@ -162,6 +245,67 @@ impl PerpMarket {
         }
     }
 
+    pub fn has_insurance_fund(&self) -> bool {
+        self.insurance_fund.vault != Pubkey::default()
+    }
+
+    pub fn get_lp_range(
+        &self,
+        min_reserve_price: u64,
+        max_reserve_price: u64,
+    ) -> DriftResult<&LPRange> {
+        self.lp_ranges
+            .iter()
+            .find(|lp_range| lp_range.is_for(min_reserve_price, max_reserve_price))
+            .ok_or(ErrorCode::LPRangeNotFound)
+    }
+
+    pub fn get_lp_range_mut(
+        &mut self,
+        min_reserve_price: u64,
+        max_reserve_price: u64,
+    ) -> DriftResult<&mut LPRange> {
+        self.lp_ranges
+            .iter_mut()
+            .find(|lp_range| lp_range.is_for(min_reserve_price, max_reserve_price))
+            .ok_or(ErrorCode::LPRangeNotFound)
+    }
+
+    /// Finds the range, or takes over one without shares. A range without shares is reset and
+    /// starts active if the reserve price is in it.
+    pub fn force_get_lp_range_mut(
+        &mut self,
+        min_reserve_price: u64,
+        max_reserve_price: u64,
+    ) -> DriftResult<&mut LPRange> {
+        let lp_range_index = self
+            .lp_ranges
+            .iter()
+            .position(|lp_range| lp_range.is_for(min_reserve_price, max_reserve_price))
+            .or_else(|| {
+                self.lp_ranges
+                    .iter()
+                    .position(|lp_range| lp_range.is_available())
+            })
+            .ok_or(ErrorCode::MaxNumberOfLPRanges)?;
+
+        if self.lp_ranges[lp_range_index].is_available() {
+            let reserve_price = self.amm.reserve_price()?;
+            self.lp_ranges[lp_range_index] = LPRange {
+                min_reserve_price,
+                max_reserve_price,
+                active: is_reserve_price_in_range(
+                    min_reserve_price,
+                    max_reserve_price,
+                    reserve_price,
+                ),
+                ..LPRange::default()
+            };
+        }
+
+        Ok(&mut self.lp_ranges[lp_range_index])
+    }
+
     pub fn default_test() -> Self {
         let amm = AMM::default_test();
         PerpMarket {
@@ -241,6 +385,32 @@ impl PerpMarket {
     }
 }
 
+impl InsuranceFundMarket for PerpMarket {
+    fn market_index(&self) -> u16 {
+        self.market_index
+    }
+
+    fn market_type(&self) -> MarketType {
+        MarketType::Perp
+    }
+
+    fn insurance_fund(&self) -> &InsuranceFund {
+        &self.insurance_fund
+    }
+
+    fn insurance_fund_mut(&mut self) -> &mut InsuranceFund {
+        &mut self.insurance_fund
+    }
+
+    fn insurance_fund_extension(&self) -> &InsuranceFundExtension {
+        &self.insurance_fund_extension
+    }
+
+    fn insurance_fund_extension_mut(&mut self) -> &mut InsuranceFundExtension {
+        &mut self.insurance_fund_extension
+    }
+}
+
 #[zero_copy]
 #[derive(Default, Eq, PartialEq, Debug)]
 #[repr(C)]
@@ -289,6 +459,38 @@ impl SpotBalance for PoolBalance {
     }
 }
 
+#[zero_copy]
+#[derive(Default, Eq, PartialEq, Debug)]
+#[repr(C)]
+pub struct LPRange {
+    pub lp_shares: u128, // shares of the lps with this range, part of amm.sqrt_k only while active
+    pub base_asset_amount_per_lp: i128, // amm.base_asset_amount_per_lp, only accrued while active
+    pub quote_asset_amount_per_lp: i128,
+    pub fee_per_lp: u128,
+    pub min_reserve_price: u64, // (0 = no lower bound)
+    pub max_reserve_price: u64, // (0 = no upper bound)
+    pub active: bool, // reserve price was in range at the last update, see update_lp_ranges
+    pub padding: [u8; 15],
+}
+
+impl LPRange {
+    pub fn is_for(&self, min_reserve_price: u64, max_reserve_price: u64) -> bool {
+        self.min_reserve_price == min_reserve_price && self.max_reserve_price == max_reserve_price
+    }
+
+    pub fn is_available(&self) -> bool {
+        self.lp_shares == 0
+    }
+
+    pub fn is_reserve_price_in_range(&self, reserve_price: u64) -> bool {
+        is_reserve_price_in_range(
+            self.min_reserve_price,
+            self.max_reserve_price,
+            reserve_price,
+        )
+    }
+}
+
 #[zero_copy]
 #[derive(Default, Debug, PartialEq, Eq)]
 #[repr(C)]
@@ -62,19 +62,22 @@ impl<'a> PerpMarketMap<'a> {
                 break;
             }
 
-            // market index 8 bytes from the back of the account
-            let market_index = u16::from_le_bytes(*array_ref![data, expected_data_len - 8, 2]);
-
             let account_info = account_info_iter.next().unwrap();
 
             let is_writable = account_info.is_writable;
+            let account_loader: AccountLoader<PerpMarket> =
+                AccountLoader::try_from(account_info).or(Err(ErrorCode::InvalidMarketAccount))?;
+
+            // read through the account, fields appended after launch sit behind market_index
+            let market_index = account_loader
+                .load()
+                .or(Err(ErrorCode::CouldNotLoadMarketData))?
+                .market_index;
+
             if writable_markets.contains(&market_index) && !is_writable {
                 return Err(ErrorCode::MarketWrongMutability);
             }
 
-            let account_loader: AccountLoader<PerpMarket> =
-                AccountLoader::try_from(account_info).or(Err(ErrorCode::InvalidMarketAccount))?;
-
             perp_market_map.0.insert(market_index, account_loader);
         }
 
@@ -105,13 +108,16 @@ impl<'a> PerpMarketMap<'a> {
             return Err(ErrorCode::CouldNotLoadMarketData);
         }
 
-        // market index 8 bytes from back of account
-        let market_index = u16::from_le_bytes(*array_ref![data, expected_data_len - 8, 2]);
-
         let is_writable = account_info.is_writable;
         let account_loader: AccountLoader<PerpMarket> =
             AccountLoader::try_from(account_info).or(Err(ErrorCode::InvalidMarketAccount))?;
 
+        // read through the account, fields appended after launch sit behind market_index
+        let market_index = account_loader
+            .load()
+            .or(Err(ErrorCode::CouldNotLoadMarketData))?
+            .market_index;
+
         if must_be_writable && !is_writable {
             return Err(ErrorCode::MarketWrongMutability);
         }
@@ -147,13 +153,16 @@ impl<'a> PerpMarketMap<'a> {
                 return Err(ErrorCode::CouldNotLoadMarketData);
             }
 
-            // market index 8 bytes from back of account
-            let market_index = u16::from_le_bytes(*array_ref![data, expected_data_len - 8, 2]);
-
             let is_writable = account_info.is_writable;
             let account_loader: AccountLoader<PerpMarket> =
                 AccountLoader::try_from(account_info).or(Err(ErrorCode::InvalidMarketAccount))?;
 
+            // read through the account, fields appended after launch sit behind market_index
+            let market_index = account_loader
+                .load()
+                .or(Err(ErrorCode::CouldNotLoadMarketData))?
+                .market_index;
+
             if must_be_writable && !is_writable {
                 return Err(ErrorCode::MarketWrongMutability);
             }
@@ -173,6 +182,14 @@ pub fn get_writable_perp_market_set(market_index: u16) -> MarketSet {
     writable_markets
 }
 
+pub fn get_writable_perp_market_set_from_many(market_indexes: Vec<u16>) -> MarketSet {
+    let mut writable_markets = MarketSet::new();
+    for market_index in market_indexes {
+        writable_markets.insert(market_index);
+    }
+    writable_markets
+}
+
 pub fn get_market_set_from_list(market_indexes: [u16; 5]) -> MarketSet {
     let mut writable_markets = MarketSet::new();
     for market_index in market_indexes.iter() {
@@ -8,7 +8,10 @@ use crate::error::DriftResult;
 use crate::instructions::SpotFulfillmentType;
 #[cfg(test)]
 use crate::math::constants::SPOT_CUMULATIVE_INTEREST_PRECISION;
-use crate::math::constants::{AMM_RESERVE_PRECISION, MARGIN_PRECISION, SPOT_WEIGHT_PRECISION_U128};
+use crate::math::constants::{
+    AMM_RESERVE_PRECISION, MARGIN_PRECISION, MAX_BORROW_RATE_KINKS, SPOT_WEIGHT_PRECISION_U128,
+};
+use crate::math::liquidation::calculate_liquidator_fee;
 use crate::math::margin::{
     calculate_size_discount_asset_weight, calculate_size_premium_liability_weight,
     MarginRequirementType,
@@ -18,6 +21,7 @@ use crate::math::spot_balance::get_token_amount;
 
 use crate::state::oracle::{HistoricalIndexData, HistoricalOracleData, OracleSource};
 use crate::state::perp_market::{MarketStatus, PoolBalance};
+use crate::state::user::MarketType;
 
 #[account(zero_copy)]
 #[derive(Default, PartialEq, Eq, Debug)]
@@ -69,6 +73,11 @@ pub struct SpotMarket {
     pub status: MarketStatus,
     pub asset_tier: AssetTier,
     pub padding: [u8; 6],
+    // fields below were appended after launch, see resize_spot_market
+    pub min_borrow_rate: u32, // borrow rate at zero utilization
+    pub borrow_rate_kinks: [BorrowRateKink; MAX_BORROW_RATE_KINKS], // additional kinks, unused have zero utilization
+    pub liquidator_fee_ramp_slots: u32, // slots for the liquidator fee to ramp up to liquidator_fee
+    pub insurance_fund_extension: InsuranceFundExtension,
 }
 
 impl SpotMarket {
@@ -160,6 +169,14 @@ impl SpotMarket {
         liability_weight.safe_sub(MARGIN_PRECISION)
     }
 
+    pub fn get_liquidator_fee(&self, slots_since_liquidation_start: u64) -> DriftResult<u32> {
+        calculate_liquidator_fee(
+            self.liquidator_fee,
+            self.liquidator_fee_ramp_slots,
+            slots_since_liquidation_start,
+        )
+    }
+
     pub fn get_available_deposits(&self) -> DriftResult<u128> {
         let deposit_token_amount =
             get_token_amount(self.deposit_balance, self, &SpotBalanceType::Deposit)?;
@@ -292,6 +309,14 @@ impl Default for AssetTier {
     }
 }
 
+#[zero_copy]
+#[derive(Default, Eq, PartialEq, Debug, AnchorSerialize, AnchorDeserialize)]
+#[repr(C)]
+pub struct BorrowRateKink {
+    pub utilization: u32, // SPOT_UTILIZATION_PRECISION
+    pub borrow_rate: u32, // SPOT_RATE_PRECISION
+}
+
 #[zero_copy]
 #[derive(Default, Eq, PartialEq, Debug)]
 #[repr(C)]
@@ -306,3 +331,61 @@ pub struct InsuranceFund {
     pub total_factor: u32, // percentage of interest for total insurance
     pub user_factor: u32,  // percentage of interest for user staked insurance
 }
+
+// insurance fund fields added after launch, appended to the market layouts rather than growing
+// InsuranceFund, see resize_spot_market
+#[zero_copy]
+#[derive(Default, Eq, PartialEq, Debug)]
+#[repr(C)]
+pub struct InsuranceFundExtension {
+    pub reward_vault: Pubkey,
+    pub shares_mint: Pubkey,
+    pub reward_per_share: u128, // IF_REWARD_PER_SHARE_PRECISION, accumulated rewards per user share
+    pub shares_mint_base: u128, // shares_base when shares_mint was created, token amounts are shares at this base
+    pub tokenized_shares: u128, // user shares held as shares_mint tokens rather than in an InsuranceFundStake
+    pub reward_emission_rate: u64, // reward tokens streamed to user shares per second
+    pub reward_emission_end_ts: i64,
+    pub last_reward_update_ts: i64,
+    pub instant_unstake_haircut: u32, // PERCENTAGE_PRECISION, share forfeited to skip unstaking_period (0 disables)
+    pub padding: [u8; 4],
+}
+
+pub trait InsuranceFundMarket {
+    fn market_index(&self) -> u16;
+
+    fn market_type(&self) -> MarketType;
+
+    fn insurance_fund(&self) -> &InsuranceFund;
+
+    fn insurance_fund_mut(&mut self) -> &mut InsuranceFund;
+
+    fn insurance_fund_extension(&self) -> &InsuranceFundExtension;
+
+    fn insurance_fund_extension_mut(&mut self) -> &mut InsuranceFundExtension;
+}
+
+impl InsuranceFundMarket for SpotMarket {
+    fn market_index(&self) -> u16 {
+        self.market_index
+    }
+
+    fn market_type(&self) -> MarketType {
+        MarketType::Spot
+    }
+
+    fn insurance_fund(&self) -> &InsuranceFund {
+        &self.insurance_fund
+    }
+
+    fn insurance_fund_mut(&mut self) -> &mut InsuranceFund {
+        &mut self.insurance_fund
+    }
+
+    fn insurance_fund_extension(&self) -> &InsuranceFundExtension {
+        &self.insurance_fund_extension
+    }
+
+    fn insurance_fund_extension_mut(&mut self) -> &mut InsuranceFundExtension {
+        &mut self.insurance_fund_extension
+    }
+}
@@ -66,14 +66,18 @@ impl<'a> SpotMarketMap<'a> {
                 break;
             }
 
-            let market_index = u16::from_le_bytes(*array_ref![data, expected_data_len - 12, 2]);
-
             let account_info = account_info_iter.next().unwrap();
             let is_writable = account_info.is_writable;
             let account_loader: AccountLoader<SpotMarket> =
                 AccountLoader::try_from(account_info)
                     .or(Err(ErrorCode::InvalidSpotMarketAccount))?;
 
+            // read through the account, fields appended after launch sit behind market_index
+            let market_index = account_loader
+                .load()
+                .or(Err(ErrorCode::CouldNotLoadSpotMarketData))?
+                .market_index;
+
             if writable_spot_markets.contains(&market_index) && !is_writable {
                 return Err(ErrorCode::SpotMarketWrongMutability);
             }
@@ -108,12 +112,16 @@ impl<'a> SpotMarketMap<'a> {
             return Err(ErrorCode::CouldNotLoadSpotMarketData);
         }
 
-        let market_index = u16::from_le_bytes(*array_ref![data, expected_data_len - 12, 2]);
-
         let is_writable = account_info.is_writable;
         let account_loader: AccountLoader<SpotMarket> =
             AccountLoader::try_from(account_info).or(Err(ErrorCode::InvalidSpotMarketAccount))?;
 
+        // read through the account, fields appended after launch sit behind market_index
+        let market_index = account_loader
+            .load()
+            .or(Err(ErrorCode::CouldNotLoadSpotMarketData))?
+            .market_index;
+
         if must_be_writable && !is_writable {
             return Err(ErrorCode::SpotMarketWrongMutability);
         }
@@ -146,13 +154,17 @@ impl<'a> SpotMarketMap<'a> {
                 return Err(ErrorCode::CouldNotLoadSpotMarketData);
             }
 
-            let market_index = u16::from_le_bytes(*array_ref![data, expected_data_len - 12, 2]);
-
             let is_writable = account_info.is_writable;
             let account_loader: AccountLoader<SpotMarket> =
                 AccountLoader::try_from(account_info)
                     .or(Err(ErrorCode::InvalidSpotMarketAccount))?;
 
+            // read through the account, fields appended after launch sit behind market_index
+            let market_index = account_loader
+                .load()
+                .or(Err(ErrorCode::CouldNotLoadSpotMarketData))?
+                .market_index;
+
             if must_be_writable && !is_writable {
                 return Err(ErrorCode::SpotMarketWrongMutability);
             }
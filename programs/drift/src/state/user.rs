@@ -7,8 +7,9 @@ use crate::error::{DriftResult, ErrorCode};
 use crate::math::auction::{calculate_auction_price, is_auction_complete};
 use crate::math::casting::Cast;
 use crate::math::constants::{
-    AMM_TO_QUOTE_PRECISION_RATIO_I128, EPOCH_DURATION, PRICE_PRECISION_I128,
-    QUOTE_SPOT_MARKET_INDEX, THIRTY_DAY,
+    AMM_TO_QUOTE_PRECISION_RATIO_I128, EPOCH_DURATION, LEGACY_MAX_OPEN_ORDERS,
+    LEGACY_MAX_PERP_POSITIONS, LEGACY_MAX_SPOT_POSITIONS, MAX_OPEN_ORDERS, MAX_PERP_POSITIONS,
+    MAX_SPOT_POSITIONS, PRICE_PRECISION_I128, QUOTE_SPOT_MARKET_INDEX, THIRTY_DAY,
 };
 use crate::math::orders::standardize_price;
 use crate::math::position::calculate_base_asset_value_and_pnl_with_oracle_price;
@@ -19,21 +20,22 @@ use crate::math_error;
 use crate::safe_increment;
 use crate::state::oracle::OraclePriceData;
 use crate::state::spot_market::{SpotBalance, SpotBalanceType, SpotMarket};
+use crate::validate;
 use std::cmp::max;
 
 #[cfg(test)]
 mod tests;
 
 #[account(zero_copy)]
-#[derive(Default, Eq, PartialEq, Debug)]
+#[derive(Eq, PartialEq, Debug)]
 #[repr(C)]
 pub struct User {
     pub authority: Pubkey,
     pub delegate: Pubkey,
     pub name: [u8; 32],
-    pub spot_positions: [SpotPosition; 8],
-    pub perp_positions: [PerpPosition; 8],
-    pub orders: [Order; 32],
+    pub spot_positions: [SpotPosition; MAX_SPOT_POSITIONS as usize],
+    pub perp_positions: PerpPositions,
+    pub orders: [Order; MAX_OPEN_ORDERS as usize],
     pub last_add_perp_lp_shares_ts: i64,
     pub total_deposits: u64,
     pub total_withdraws: u64,
@@ -50,6 +52,39 @@ pub struct User {
     pub is_bankrupt: bool,
     pub is_margin_trading_enabled: bool,
     pub padding: [u8; 1],
+    pub liquidation_start_slot: u64, // slot the current liquidation began, used to ramp the liquidator fee
+    pub liquidation_margin_ratio: u32, // initial margin floor after repeated liquidations, see UserLiquidationHistory
+    pub padding1: [u8; 4],
+}
+
+impl Default for User {
+    fn default() -> Self {
+        User {
+            authority: Pubkey::default(),
+            delegate: Pubkey::default(),
+            name: [0; 32],
+            spot_positions: [SpotPosition::default(); MAX_SPOT_POSITIONS as usize],
+            perp_positions: [PerpPosition::default(); MAX_PERP_POSITIONS as usize],
+            orders: [Order::default(); MAX_OPEN_ORDERS as usize],
+            last_add_perp_lp_shares_ts: 0,
+            total_deposits: 0,
+            total_withdraws: 0,
+            settled_perp_pnl: 0,
+            cumulative_spot_fees: 0,
+            cumulative_perp_funding: 0,
+            next_order_id: 0,
+            max_margin_ratio: 0,
+            next_liquidation_id: 0,
+            sub_account_id: 0,
+            is_being_liquidated: false,
+            is_bankrupt: false,
+            is_margin_trading_enabled: false,
+            padding: [0; 1],
+            liquidation_start_slot: 0,
+            liquidation_margin_ratio: 0,
+            padding1: [0; 4],
+        }
+    }
 }
 
 impl User {
@@ -85,6 +120,14 @@ impl User {
         self.get_spot_position_mut(QUOTE_SPOT_MARKET_INDEX).unwrap()
     }
 
+    pub fn get_slots_since_liquidation_start(&self, slot: u64) -> DriftResult<u64> {
+        if !self.is_being_liquidated {
+            return Ok(0);
+        }
+
+        slot.safe_sub(self.liquidation_start_slot)
+    }
+
     pub fn add_spot_position(
         &mut self,
         market_index: u16,
@@ -201,6 +244,127 @@ impl User {
     }
 }
 
+// authority, delegate and name precede the position and order arrays
+const USER_HEADER_SIZE: usize = 32 + 32 + 32;
+
+/// Fields following the orders in the legacy user layout, fields added since are appended after them
+#[repr(C)]
+pub struct LegacyUserTail {
+    pub last_add_perp_lp_shares_ts: i64,
+    pub total_deposits: u64,
+    pub total_withdraws: u64,
+    pub settled_perp_pnl: i64,
+    pub cumulative_spot_fees: i64,
+    pub cumulative_perp_funding: i64,
+    pub next_order_id: u32,
+    pub max_margin_ratio: u32,
+    pub next_liquidation_id: u16,
+    pub sub_account_id: u16,
+    pub is_being_liquidated: bool,
+    pub is_bankrupt: bool,
+    pub is_margin_trading_enabled: bool,
+    pub padding: [u8; 1],
+}
+
+/// Perp position fields in the legacy user layout, fields added since are appended after them
+#[repr(C)]
+pub struct LegacyPerpPosition {
+    pub last_cumulative_funding_rate: i64,
+    pub base_asset_amount: i64,
+    pub quote_asset_amount: i64,
+    pub quote_break_even_amount: i64,
+    pub quote_entry_amount: i64,
+    pub open_bids: i64,
+    pub open_asks: i64,
+    pub settled_pnl: i64,
+    pub lp_shares: u64,
+    pub last_net_base_asset_amount_per_lp: i64,
+    pub last_net_quote_asset_amount_per_lp: i64,
+    pub remainder_base_asset_amount: i32,
+    pub market_index: u16,
+    pub open_orders: u8,
+    pub padding: [u8; 1],
+}
+
+fn user_tail_size() -> usize {
+    std::mem::size_of::<User>()
+        - USER_HEADER_SIZE
+        - std::mem::size_of::<SpotPosition>() * MAX_SPOT_POSITIONS as usize
+        - std::mem::size_of::<PerpPosition>() * MAX_PERP_POSITIONS as usize
+        - std::mem::size_of::<Order>() * MAX_OPEN_ORDERS as usize
+}
+
+/// Size of a user account (excluding the discriminator) created with the legacy capacities
+pub fn get_legacy_user_size() -> usize {
+    USER_HEADER_SIZE
+        + std::mem::size_of::<SpotPosition>() * LEGACY_MAX_SPOT_POSITIONS as usize
+        + std::mem::size_of::<LegacyPerpPosition>() * LEGACY_MAX_PERP_POSITIONS as usize
+        + std::mem::size_of::<Order>() * LEGACY_MAX_OPEN_ORDERS as usize
+        + std::mem::size_of::<LegacyUserTail>()
+}
+
+/// Rewrites legacy user data in place to the current layout. `data` must already be resized to
+/// size_of::<User>() and hold the legacy user (excluding the discriminator) at its start.
+/// Slots beyond the legacy capacity are left zeroed, same as a freshly initialized user.
+pub fn migrate_legacy_user_data(data: &mut [u8]) -> DriftResult {
+    let legacy_size = get_legacy_user_size();
+
+    validate!(
+        data.len() == std::mem::size_of::<User>() && legacy_size <= data.len(),
+        ErrorCode::InvalidUserAccountSize,
+        "invalid data len {} for user migration",
+        data.len()
+    )?;
+
+    let legacy_data = data[..legacy_size].to_vec();
+    data.fill(0);
+
+    // (legacy item size, item size, legacy capacity, capacity)
+    let sections = [
+        (USER_HEADER_SIZE, USER_HEADER_SIZE, 1, 1),
+        (
+            std::mem::size_of::<SpotPosition>(),
+            std::mem::size_of::<SpotPosition>(),
+            LEGACY_MAX_SPOT_POSITIONS as usize,
+            MAX_SPOT_POSITIONS as usize,
+        ),
+        (
+            std::mem::size_of::<LegacyPerpPosition>(),
+            std::mem::size_of::<PerpPosition>(),
+            LEGACY_MAX_PERP_POSITIONS as usize,
+            MAX_PERP_POSITIONS as usize,
+        ),
+        (
+            std::mem::size_of::<Order>(),
+            std::mem::size_of::<Order>(),
+            LEGACY_MAX_OPEN_ORDERS as usize,
+            MAX_OPEN_ORDERS as usize,
+        ),
+        (
+            std::mem::size_of::<LegacyUserTail>(),
+            user_tail_size(),
+            1,
+            1,
+        ),
+    ];
+
+    let mut legacy_offset = 0;
+    let mut offset = 0;
+    for &(legacy_item_size, item_size, legacy_capacity, capacity) in sections.iter() {
+        for i in 0..legacy_capacity {
+            let legacy_item_offset = legacy_offset + i * legacy_item_size;
+            let item_offset = offset + i * item_size;
+            data[item_offset..item_offset + legacy_item_size].copy_from_slice(
+                &legacy_data[legacy_item_offset..legacy_item_offset + legacy_item_size],
+            );
+        }
+        legacy_offset += legacy_item_size * legacy_capacity;
+        offset += item_size * capacity;
+    }
+
+    Ok(())
+}
+
 #[zero_copy]
 #[derive(Default, Eq, PartialEq, Debug)]
 #[repr(C)]
@@ -324,6 +488,12 @@ pub struct PerpPosition {
     pub market_index: u16,
     pub open_orders: u8,
     pub padding: [u8; 1],
+    pub lp_min_reserve_price: u64, // lp shares only active while reserve price >= this (0 = no lower bound)
+    pub lp_max_reserve_price: u64, // lp shares only active while reserve price <= this (0 = no upper bound)
+    pub last_fee_per_lp: u64,
+    pub lp_hedge_threshold: u64, // keepers can flatten the lp's base once it exceeds this (0 = disabled)
+    pub cumulative_funding: i64, // funding received (+) / paid (-) since the position was opened, reset on close and flip
+    pub lp_base_asset_amount: i64, // base taken on through lp settlement and not yet hedged
 }
 
 impl PerpPosition {
@@ -350,6 +520,29 @@ impl PerpPosition {
         self.lp_shares > 0
     }
 
+    /// Base taken on as an lp, capped by the position's base in the same direction so
+    /// directional trades since the lp settled aren't counted
+    pub fn get_lp_base_asset_amount(&self) -> i64 {
+        if self.lp_base_asset_amount.signum() != self.base_asset_amount.signum() {
+            return 0;
+        }
+
+        if self.lp_base_asset_amount.unsigned_abs() <= self.base_asset_amount.unsigned_abs() {
+            self.lp_base_asset_amount
+        } else {
+            self.base_asset_amount
+        }
+    }
+
+    pub fn has_lp_range(&self) -> bool {
+        self.lp_min_reserve_price != 0 || self.lp_max_reserve_price != 0
+    }
+
+    pub fn update_cumulative_funding(&mut self, amount: i64) -> DriftResult {
+        safe_increment!(self.cumulative_funding, amount);
+        Ok(())
+    }
+
     pub fn has_unsettled_pnl(&self) -> bool {
         self.base_asset_amount == 0 && self.quote_asset_amount != 0
     }
@@ -426,7 +619,7 @@ impl PerpPosition {
     }
 }
 
-pub type PerpPositions = [PerpPosition; 8];
+pub type PerpPositions = [PerpPosition; MAX_PERP_POSITIONS as usize];
 
 #[zero_copy]
 #[repr(C)]
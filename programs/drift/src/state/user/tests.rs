@@ -885,3 +885,119 @@ mod get_worst_case_token_amounts {
         assert_eq!(worst_case_quote_token_amount, 100 * QUOTE_PRECISION_I128);
     }
 }
+
+mod migrate_legacy_user_data {
+    use crate::math::constants::{
+        LEGACY_MAX_OPEN_ORDERS, LEGACY_MAX_PERP_POSITIONS, LEGACY_MAX_SPOT_POSITIONS,
+    };
+    use crate::state::user::{
+        get_legacy_user_size, migrate_legacy_user_data, LegacyPerpPosition, LegacyUserTail, Order,
+        OrderStatus, PerpPosition, SpotPosition, User,
+    };
+    use anchor_lang::prelude::Pubkey;
+
+    #[test]
+    fn legacy_positions_and_orders_keep_their_slots() {
+        let mut legacy_user = User {
+            authority: Pubkey::new_unique(),
+            delegate: Pubkey::new_unique(),
+            name: [1; 32],
+            next_order_id: 10,
+            next_liquidation_id: 3,
+            sub_account_id: 2,
+            total_deposits: 100,
+            is_margin_trading_enabled: true,
+            ..User::default()
+        };
+
+        let last_spot_index = LEGACY_MAX_SPOT_POSITIONS as usize - 1;
+        let last_perp_index = LEGACY_MAX_PERP_POSITIONS as usize - 1;
+        let last_order_index = LEGACY_MAX_OPEN_ORDERS as usize - 1;
+
+        legacy_user.spot_positions[last_spot_index] = SpotPosition {
+            market_index: 1,
+            scaled_balance: 1000,
+            ..SpotPosition::default()
+        };
+        legacy_user.perp_positions[last_perp_index] = PerpPosition {
+            market_index: 4,
+            base_asset_amount: 5,
+            quote_asset_amount: -5,
+            ..PerpPosition::default()
+        };
+        legacy_user.orders[last_order_index] = Order {
+            order_id: 9,
+            market_index: 4,
+            status: OrderStatus::Open,
+            base_asset_amount: 5,
+            ..Order::default()
+        };
+
+        let user_bytes = bytemuck::bytes_of(&legacy_user);
+        let header_size = 96;
+        let legacy_tail_size = std::mem::size_of::<LegacyUserTail>();
+        let tail_start = header_size
+            + std::mem::size_of_val(&legacy_user.spot_positions)
+            + std::mem::size_of_val(&legacy_user.perp_positions)
+            + std::mem::size_of_val(&legacy_user.orders);
+
+        let mut legacy_data: Vec<u8> = vec![];
+        legacy_data.extend_from_slice(&user_bytes[..header_size]);
+        legacy_data.extend_from_slice(bytemuck::cast_slice(
+            &legacy_user.spot_positions[..LEGACY_MAX_SPOT_POSITIONS as usize],
+        ));
+        // legacy perp positions end before the lp range fields
+        let legacy_perp_position_size = std::mem::size_of::<LegacyPerpPosition>();
+        for perp_position in legacy_user.perp_positions[..LEGACY_MAX_PERP_POSITIONS as usize].iter()
+        {
+            legacy_data
+                .extend_from_slice(&bytemuck::bytes_of(perp_position)[..legacy_perp_position_size]);
+        }
+        legacy_data.extend_from_slice(bytemuck::cast_slice(
+            &legacy_user.orders[..LEGACY_MAX_OPEN_ORDERS as usize],
+        ));
+        legacy_data.extend_from_slice(&user_bytes[tail_start..tail_start + legacy_tail_size]);
+        assert_eq!(legacy_data.len(), get_legacy_user_size());
+
+        let mut user = User::default();
+        let data = bytemuck::bytes_of_mut(&mut user);
+        data[..legacy_data.len()].copy_from_slice(&legacy_data);
+        migrate_legacy_user_data(data).unwrap();
+
+        assert_eq!(user.authority, legacy_user.authority);
+        assert_eq!(user.delegate, legacy_user.delegate);
+        assert_eq!(user.name, legacy_user.name);
+        assert_eq!(
+            user.spot_positions[last_spot_index],
+            legacy_user.spot_positions[last_spot_index]
+        );
+        assert_eq!(
+            user.perp_positions[last_perp_index],
+            legacy_user.perp_positions[last_perp_index]
+        );
+        assert_eq!(
+            user.orders[last_order_index],
+            legacy_user.orders[last_order_index]
+        );
+        assert!(user.spot_positions[last_spot_index + 1..]
+            .iter()
+            .all(|spot_position| spot_position.is_available()));
+        assert!(user.perp_positions[last_perp_index + 1..]
+            .iter()
+            .all(|perp_position| perp_position.is_available()));
+        assert!(user.orders[last_order_index + 1..]
+            .iter()
+            .all(|order| order.status == OrderStatus::Init));
+        assert_eq!(user.next_order_id, 10);
+        assert_eq!(user.next_liquidation_id, 3);
+        assert_eq!(user.sub_account_id, 2);
+        assert_eq!(user.total_deposits, 100);
+        assert!(user.is_margin_trading_enabled);
+    }
+
+    #[test]
+    fn wrong_size_fails() {
+        let mut data = vec![0_u8; get_legacy_user_size()];
+        assert!(migrate_legacy_user_data(&mut data).is_err());
+    }
+}
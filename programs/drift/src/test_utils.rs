@@ -4,22 +4,25 @@ use bytes::BytesMut;
 
 use pyth::pc::Price;
 
-use crate::state::user::{Order, PerpPosition, SpotPosition};
+use crate::math::constants::{MAX_OPEN_ORDERS, MAX_PERP_POSITIONS, MAX_SPOT_POSITIONS};
+use crate::state::user::{Order, PerpPosition, PerpPositions, SpotPosition};
 
-pub fn get_positions(position: PerpPosition) -> [PerpPosition; 8] {
-    let mut positions = [PerpPosition::default(); 8];
+pub fn get_positions(position: PerpPosition) -> PerpPositions {
+    let mut positions = [PerpPosition::default(); MAX_PERP_POSITIONS as usize];
     positions[0] = position;
     positions
 }
 
-pub fn get_orders(order: Order) -> [Order; 32] {
-    let mut orders = [Order::default(); 32];
+pub fn get_orders(order: Order) -> [Order; MAX_OPEN_ORDERS as usize] {
+    let mut orders = [Order::default(); MAX_OPEN_ORDERS as usize];
     orders[0] = order;
     orders
 }
 
-pub fn get_spot_positions(spot_position: SpotPosition) -> [SpotPosition; 8] {
-    let mut spot_positions = [SpotPosition::default(); 8];
+pub fn get_spot_positions(
+    spot_position: SpotPosition,
+) -> [SpotPosition; MAX_SPOT_POSITIONS as usize] {
+    let mut spot_positions = [SpotPosition::default(); MAX_SPOT_POSITIONS as usize];
     spot_positions[0] = spot_position;
     spot_positions
 }
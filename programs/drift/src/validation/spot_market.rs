@@ -1,5 +1,10 @@
+#[cfg(test)]
+mod tests;
+
 use crate::error::{DriftResult, ErrorCode};
-use crate::math::constants::SPOT_UTILIZATION_PRECISION_U32;
+use crate::math::constants::{MAX_BORROW_RATE_KINKS, SPOT_UTILIZATION_PRECISION_U32};
+use crate::math::spot_balance::get_borrow_rate_curve;
+use crate::state::spot_market::BorrowRateKink;
 use crate::validate;
 use solana_program::msg;
 
@@ -7,6 +12,8 @@ pub fn validate_borrow_rate(
     optimal_utilization: u32,
     optimal_borrow_rate: u32,
     max_borrow_rate: u32,
+    min_borrow_rate: u32,
+    borrow_rate_kinks: &[BorrowRateKink],
 ) -> DriftResult {
     validate!(
         optimal_utilization <= SPOT_UTILIZATION_PRECISION_U32,
@@ -23,5 +30,52 @@ pub fn validate_borrow_rate(
         max_borrow_rate
     )?;
 
+    validate!(
+        borrow_rate_kinks.len() <= MAX_BORROW_RATE_KINKS,
+        ErrorCode::InvalidSpotMarketInitialization,
+        "For spot market, at most {} borrow rate kinks",
+        MAX_BORROW_RATE_KINKS
+    )?;
+
+    for kink in borrow_rate_kinks.iter() {
+        validate!(
+            kink.utilization > 0 && kink.utilization < SPOT_UTILIZATION_PRECISION_U32,
+            ErrorCode::InvalidSpotMarketInitialization,
+            "For spot market, borrow rate kink utilization ({}) must be in (0, {})",
+            kink.utilization,
+            SPOT_UTILIZATION_PRECISION_U32
+        )?;
+    }
+
+    let curve = get_borrow_rate_curve(
+        min_borrow_rate,
+        optimal_utilization,
+        optimal_borrow_rate,
+        max_borrow_rate,
+        borrow_rate_kinks,
+    );
+
+    for segment in curve.windows(2) {
+        validate!(
+            segment[0].borrow_rate <= segment[1].borrow_rate,
+            ErrorCode::InvalidSpotMarketInitialization,
+            "For spot market, borrow rate curve must be monotonic: {} at utilization {} > {} at utilization {}",
+            segment[0].borrow_rate,
+            segment[0].utilization,
+            segment[1].borrow_rate,
+            segment[1].utilization
+        )?;
+
+        // only the optimal point may share utilization with the curve's end points
+        validate!(
+            segment[0].utilization < segment[1].utilization
+                || segment[0].utilization == 0
+                || segment[1].utilization == SPOT_UTILIZATION_PRECISION_U32,
+            ErrorCode::InvalidSpotMarketInitialization,
+            "For spot market, borrow rate kinks must have distinct utilization ({})",
+            segment[1].utilization
+        )?;
+    }
+
     Ok(())
 }
@@ -0,0 +1,77 @@
+use crate::error::ErrorCode;
+use crate::state::spot_market::BorrowRateKink;
+use crate::validation::spot_market::validate_borrow_rate;
+
+#[test]
+fn two_segment_curve() {
+    assert!(validate_borrow_rate(800_000, 100_000, 1_000_000, 0, &[]).is_ok());
+    assert!(validate_borrow_rate(1_000_000, 100_000, 1_000_000, 0, &[]).is_ok());
+    assert!(validate_borrow_rate(0, 100_000, 1_000_000, 0, &[]).is_ok());
+
+    assert_eq!(
+        validate_borrow_rate(800_000, 1_000_001, 1_000_000, 0, &[]),
+        Err(ErrorCode::InvalidSpotMarketInitialization)
+    );
+}
+
+#[test]
+fn multi_kink_curve() {
+    let kinks = [
+        BorrowRateKink {
+            utilization: 500_000,
+            borrow_rate: 40_000,
+        },
+        BorrowRateKink {
+            utilization: 900_000,
+            borrow_rate: 300_000,
+        },
+    ];
+
+    assert!(validate_borrow_rate(800_000, 100_000, 1_000_000, 10_000, &kinks).is_ok());
+
+    // min borrow rate above first kink
+    assert_eq!(
+        validate_borrow_rate(800_000, 100_000, 1_000_000, 50_000, &kinks),
+        Err(ErrorCode::InvalidSpotMarketInitialization)
+    );
+
+    // kink past optimal with lower rate than optimal
+    let decreasing_kinks = [BorrowRateKink {
+        utilization: 900_000,
+        borrow_rate: 50_000,
+    }];
+    assert_eq!(
+        validate_borrow_rate(800_000, 100_000, 1_000_000, 0, &decreasing_kinks),
+        Err(ErrorCode::InvalidSpotMarketInitialization)
+    );
+
+    // kink on the optimal utilization
+    let duplicate_kinks = [BorrowRateKink {
+        utilization: 800_000,
+        borrow_rate: 100_000,
+    }];
+    assert_eq!(
+        validate_borrow_rate(800_000, 100_000, 1_000_000, 0, &duplicate_kinks),
+        Err(ErrorCode::InvalidSpotMarketInitialization)
+    );
+
+    // kink at full utilization
+    let full_utilization_kink = [BorrowRateKink {
+        utilization: 1_000_000,
+        borrow_rate: 1_000_000,
+    }];
+    assert_eq!(
+        validate_borrow_rate(800_000, 100_000, 1_000_000, 0, &full_utilization_kink),
+        Err(ErrorCode::InvalidSpotMarketInitialization)
+    );
+
+    // too many kinks
+    let too_many_kinks = [BorrowRateKink {
+        utilization: 100_000,
+        borrow_rate: 0,
+    }; 5];
+    assert_eq!(
+        validate_borrow_rate(800_000, 100_000, 1_000_000, 0, &too_many_kinks),
+        Err(ErrorCode::InvalidSpotMarketInitialization)
+    );
+}